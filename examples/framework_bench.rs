@@ -0,0 +1,57 @@
+//! The plaintext and JSON tests of the TechEmpower framework benchmarks,
+//! with the performance settings
+//!
+//! ```sh
+//! cargo run --release --example framework_bench
+//! wrk -t 4 -c 256 -d 10 http://127.0.0.1:8080/plaintext
+//! ```
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+use std::io;
+
+use may_minihttp::{
+    ContentType, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+};
+use yarte::Serialize;
+
+#[derive(Serialize)]
+struct HelloMessage {
+    message: &'static str,
+}
+
+#[derive(Clone)]
+struct Bench;
+
+impl HttpService for Bench {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/plaintext" => {
+                rsp.content_type(ContentType::Text).body("Hello, World!");
+            }
+            "/json" => {
+                rsp.content_type(ContentType::Json);
+                // serialized straight into the response buffer
+                HelloMessage {
+                    message: "Hello, World!",
+                }
+                .to_bytes_mut(rsp.body_mut());
+            }
+            _ => {
+                rsp.status_code(404, "Not Found");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    may::config()
+        .set_workers(num_cpus::get())
+        .set_pool_capacity(1000)
+        .set_stack_size(0x1000);
+    let server = HttpServer(Bench)
+        .start_with_config("0.0.0.0:8080", HttpConfig::performance())
+        .unwrap();
+    server.wait().unwrap();
+}
@@ -23,5 +23,5 @@ impl HttpService for StatusService {
 fn main() {
     env_logger::init();
     let server = HttpServer(StatusService).start("127.0.0.1:8080").unwrap();
-    server.join().unwrap();
+    server.wait().unwrap();
 }
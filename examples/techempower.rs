@@ -312,6 +312,6 @@ mod __impl {
         };
 
         println!("Starting http server: 127.0.0.1:8081");
-        server.start("0.0.0.0:8081").unwrap().join().unwrap();
+        server.start("0.0.0.0:8081").unwrap().wait().unwrap();
     }
 }
@@ -47,5 +47,5 @@ fn main() {
     may::config().set_pool_capacity(500).set_stack_size(0x1000);
     let http_server = HttpServer {};
     let server = http_server.start("0.0.0.0:8081").unwrap();
-    server.join().unwrap();
+    server.wait().unwrap();
 }
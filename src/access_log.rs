@@ -0,0 +1,306 @@
+//! access logs written to a pluggable sink
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::date::HttpDate;
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// One request answered by an [`AccessLogService`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogRecord {
+    /// When the request was parsed
+    pub time: SystemTime,
+    /// The request method
+    pub method: String,
+    /// The request target, query included
+    pub path: String,
+    /// The response status code, `500` when the service failed
+    pub status: usize,
+    /// The response body bytes
+    pub body_len: usize,
+    /// The time the service took to answer
+    pub duration: Duration,
+    /// The `User-Agent` request header, if any
+    pub user_agent: Option<String>,
+}
+
+impl fmt::Display for AccessLogRecord {
+    /// a line like `[Sun, 06 Nov 1994 08:49:37 GMT] "GET /" 200 5 0.120ms "curl/8.0"`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{}] \"{} {}\" {} {} {:.3}ms \"{}\"",
+            HttpDate::from(self.time),
+            self.method,
+            self.path.escape_debug(),
+            self.status,
+            self.body_len,
+            self.duration.as_secs_f64() * 1000.0,
+            self.user_agent.as_deref().unwrap_or("-").escape_debug(),
+        )
+    }
+}
+
+/// Where an [`AccessLogService`] sends its records
+///
+/// `write` runs on the connection coroutine before the response goes out,
+/// so a sink should hand the record over rather than wait for slow I/O, as
+/// [`FileSink`] and [`ChannelSink`] do.
+pub trait AccessLogSink: Send + Sync {
+    /// Take the record of an answered request
+    fn write(&self, record: &AccessLogRecord);
+
+    /// Push out what was written so far, e.g. before the process exits
+    fn flush(&self) {}
+}
+
+/// Writes each record as a line to stderr
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl AccessLogSink for StderrSink {
+    fn write(&self, record: &AccessLogRecord) {
+        eprintln!("{record}");
+    }
+
+    fn flush(&self) {
+        io::stderr().flush().ok();
+    }
+}
+
+/// Sends each record to a channel, for the application to ship elsewhere
+#[derive(Debug, Clone)]
+pub struct ChannelSink(Sender<AccessLogRecord>);
+
+impl ChannelSink {
+    /// Create a sink and the receiving end of its channel
+    ///
+    /// The channel is unbounded, records pile up if nothing receives them.
+    /// Records written once the receiver is gone are dropped.
+    pub fn new() -> (Self, Receiver<AccessLogRecord>) {
+        let (tx, rx) = mpsc::channel();
+        (ChannelSink(tx), rx)
+    }
+}
+
+impl AccessLogSink for ChannelSink {
+    fn write(&self, record: &AccessLogRecord) {
+        self.0.send(record.clone()).ok();
+    }
+}
+
+/// what the file writer thread is told
+enum FileMsg {
+    Line(String),
+    Flush(SyncSender<()>),
+}
+
+/// Appends each record as a line to a file, rotating it by size
+///
+/// The lines go through a channel to a writer thread, which buffers them,
+/// so connections never wait for the disk. Once the file would grow past
+/// `max_bytes` it is renamed to `<path>.1`, the older ones shift to
+/// `<path>.2` and so on up to `<path>.<keep>`, and a new file is started.
+/// Write errors are logged and the records dropped.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    tx: Sender<FileMsg>,
+}
+
+impl FileSink {
+    /// Append to the file at `path`, rotating it past `max_bytes` and
+    /// keeping `keep` rotated files, `0` for `max_bytes` never rotates
+    ///
+    /// # Errors
+    ///
+    /// Returns the error opening the file.
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_log(&path)?;
+        let written = file.metadata()?.len();
+        let mut writer = FileWriter {
+            file: BufWriter::new(file),
+            written,
+            path,
+            max_bytes,
+            keep,
+        };
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("access-log".to_owned())
+            .spawn(move || writer.run(rx))?;
+        Ok(FileSink { tx })
+    }
+}
+
+impl AccessLogSink for FileSink {
+    fn write(&self, record: &AccessLogRecord) {
+        self.tx.send(FileMsg::Line(format!("{record}\n"))).ok();
+    }
+
+    /// Waits until the writer thread has written out the records so far
+    fn flush(&self) {
+        let (done, flushed) = mpsc::sync_channel(1);
+        if self.tx.send(FileMsg::Flush(done)).is_ok() {
+            flushed.recv().ok();
+        }
+    }
+}
+
+struct FileWriter {
+    file: BufWriter<File>,
+    written: u64,
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl FileWriter {
+    fn run(&mut self, rx: Receiver<FileMsg>) {
+        while let Ok(msg) = rx.recv() {
+            let result = match msg {
+                FileMsg::Line(line) => self.write(&line),
+                FileMsg::Flush(done) => {
+                    let result = self.file.flush();
+                    done.send(()).ok();
+                    result
+                }
+            };
+            if let Err(e) = result {
+                error!("can't write access log {}: {e}", self.path.display());
+            }
+        }
+        // all the senders are gone
+        self.file.flush().ok();
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.max_bytes != 0 && self.written != 0 && self.written + len > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for n in (1..self.keep).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = BufWriter::new(open_log(&self.path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn open_log(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `<path>.<n>`
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// `HttpService` wrapper writing a record of each request to an
+/// [`AccessLogSink`]
+///
+/// The record is written once the inner service returns, before the
+/// response goes out. Responses the inner service failed to make are
+/// logged as `500`, and the error is passed on.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{AccessLogService, FileSink, HttpServer, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("hello");
+///         Ok(())
+///     }
+/// }
+///
+/// // up to 6 files of 64 MiB
+/// let sink = FileSink::new("/var/log/app/access.log", 64 << 20, 5).unwrap();
+/// let server = HttpServer(AccessLogService::new(Hello, sink)).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AccessLogService<S> {
+    inner: S,
+    sink: Arc<dyn AccessLogSink>,
+}
+
+impl<S> AccessLogService<S> {
+    /// Wrap `inner`, writing its access log to `sink`
+    pub fn new(inner: S, sink: impl AccessLogSink + 'static) -> Self {
+        AccessLogService {
+            inner,
+            sink: Arc::new(sink),
+        }
+    }
+}
+
+impl<S: HttpService> HttpService for AccessLogService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let time = SystemTime::now();
+        let started = Instant::now();
+        let method = req.method().to_owned();
+        let path = req.path().to_owned();
+        let user_agent = req
+            .headers()
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("user-agent"))
+            .map(|h| String::from_utf8_lossy(h.value).into_owned());
+        let result = self.inner.call(req, rsp);
+        let status = match result {
+            Ok(()) => rsp.status().0,
+            Err(_) => 500,
+        };
+        self.sink.write(&AccessLogRecord {
+            time,
+            method,
+            path,
+            status,
+            body_len: rsp.body_len(),
+            duration: started.elapsed(),
+            user_agent,
+        });
+        result
+    }
+}
+
+/// Several services may share a sink, and the application keeps a handle
+/// to flush it
+impl<T: AccessLogSink + ?Sized> AccessLogSink for Arc<T> {
+    fn write(&self, record: &AccessLogRecord) {
+        (**self).write(record)
+    }
+
+    fn flush(&self) {
+        (**self).flush()
+    }
+}
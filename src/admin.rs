@@ -0,0 +1,217 @@
+//! a json admin endpoint for inspecting a running server
+
+use std::fmt::Write;
+use std::io;
+use std::sync::Arc;
+
+use log::LevelFilter;
+
+use crate::config::{BufGrowth, HeaderValidation, HttpConfig};
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+use crate::stats::ServerStats;
+
+/// longest log level name accepted by `PUT /log-level`
+const MAX_LEVEL_BODY: usize = 64;
+
+/// `HttpService` answering operator queries about a server as JSON
+///
+/// Run it on a listener of its own, next to the server it reports on, e.g.
+/// on a port that is only reachable from localhost: it has no
+/// authentication and can change the log level. It serves
+///
+/// | Route | Answer |
+/// |-------|--------|
+/// | `GET /stats` | the [`ServerStats`] counters |
+/// | `GET /config` | the [`HttpConfig`] the server runs with |
+/// | `GET /connections` | the open connections: peer, age and requests served |
+/// | `GET /log-level` | the `log` max level |
+/// | `PUT /log-level` | set the max level to the body, e.g. `debug` |
+///
+/// The log level is the global [`log::max_level`], a logger may filter
+/// further on its own.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use may_minihttp::{
+///     AdminService, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+///     ServerStats,
+/// };
+///
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("hello");
+///         Ok(())
+///     }
+/// }
+///
+/// let stats = Arc::new(ServerStats::new());
+/// let config = HttpConfig::new().with_stats(stats.clone());
+/// let server = HttpServer(Hello).start_with_config("0.0.0.0:8080", config.clone()).unwrap();
+/// let _admin = HttpServer(AdminService::new(stats, config))
+///     .start("127.0.0.1:9090")
+///     .unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AdminService {
+    stats: Arc<ServerStats>,
+    config: HttpConfig,
+}
+
+impl AdminService {
+    /// Report on the server counting into `stats` and started with `config`
+    pub fn new(stats: Arc<ServerStats>, config: HttpConfig) -> Self {
+        AdminService { stats, config }
+    }
+
+    fn stats_json(&self) -> String {
+        let s = &self.stats;
+        format!(
+            "{{\"accepted\":{},\"accept_errors\":{},\"active_connections\":{},\
+             \"keep_alive_connections\":{},\"requests\":{},\"bytes_read\":{},\
+             \"bytes_written\":{}}}",
+            s.accepted(),
+            s.accept_errors(),
+            s.active_connections(),
+            s.keep_alive_connections(),
+            s.requests(),
+            s.bytes_read(),
+            s.bytes_written()
+        )
+    }
+
+    fn config_json(&self) -> String {
+        let c = &self.config;
+        format!(
+            "{{\"max_headers\":{},\"batch_writes\":{},\"write_high_watermark\":{},\
+             \"max_conn_memory\":{},\"buf_initial_capacity\":{},\"buf_grow_by\":{},\
+             \"buf_growth\":\"{}\",\"buf_max_capacity\":{},\"max_body\":{},\
+             \"max_in_flight\":{},\"header_validation\":\"{}\",\
+             \"max_accept_backoff_ms\":{},\"backlog\":{},\"defer_accept_secs\":{},\
+             \"fastopen_queue\":{},\"ipv6_only\":{},\"linger_secs\":{},\"abortive_close\":{},\
+             \"tcp_keepalive_idle_secs\":{},\"tcp_keepalive_interval_secs\":{},\
+             \"tcp_keepalive_retries\":{},\
+             \"keep_alive_timeout_secs\":{},\"max_requests_per_conn\":{},\
+             \"evict_idle\":{},\"parse_error_log\":\"{}\",\"parse_error_hints\":{},\
+             \"on_parse_error\":{},\"on_connection_error\":{},\"on_request_complete\":{}}}",
+            c.max_headers.value(),
+            c.batch_writes,
+            c.write_high_watermark,
+            c.max_conn_memory,
+            c.buf_initial_capacity,
+            c.buf_grow_by,
+            growth_name(c.buf_growth),
+            c.buf_max_capacity,
+            c.max_body,
+            c.max_in_flight,
+            validation_name(c.header_validation),
+            c.max_accept_backoff_ms,
+            c.backlog,
+            c.defer_accept_secs,
+            c.fastopen_queue,
+            c.ipv6_only.map_or("null".to_owned(), |v| v.to_string()),
+            c.linger_secs.map_or("null".to_owned(), |s| s.to_string()),
+            c.abortive_close,
+            c.tcp_keepalive_idle_secs,
+            c.tcp_keepalive_interval_secs,
+            c.tcp_keepalive_retries,
+            c.keep_alive_timeout_secs,
+            c.max_requests_per_conn,
+            c.evict_idle,
+            level_name(c.parse_error_log),
+            c.parse_error_hints,
+            c.on_parse_error.is_some(),
+            c.on_connection_error.is_some(),
+            c.on_request_complete.is_some()
+        )
+    }
+
+    fn connections_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, conn) in self.stats.connections().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str("{\"peer\":");
+            match conn.peer_addr {
+                Some(peer) => write!(json, "\"{peer}\"").unwrap(),
+                None => json.push_str("null"),
+            }
+            write!(
+                json,
+                ",\"age_ms\":{},\"requests\":{}}}",
+                conn.age.as_millis(),
+                conn.requests
+            )
+            .unwrap();
+        }
+        json.push(']');
+        json
+    }
+}
+
+impl HttpService for AdminService {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let json = match (req.method(), req.path()) {
+            ("GET", "/stats") => self.stats_json(),
+            ("GET", "/config") => self.config_json(),
+            ("GET", "/connections") => self.connections_json(),
+            ("GET", "/log-level") => level_json(log::max_level()),
+            ("PUT", "/log-level") => {
+                let body = req.body_string(MAX_LEVEL_BODY)?;
+                match body.trim().parse::<LevelFilter>() {
+                    Ok(level) => {
+                        log::set_max_level(level);
+                        level_json(level)
+                    }
+                    Err(_) => {
+                        rsp.status_code(400, "Bad Request");
+                        let msg = "expected off, error, warn, info, debug or trace";
+                        format!("{{\"error\":\"invalid log level, {msg}\"}}")
+                    }
+                }
+            }
+            (_, "/stats" | "/config" | "/connections" | "/log-level") => {
+                rsp.status_code(405, "Method Not Allowed");
+                "{\"error\":\"method not allowed\"}".to_owned()
+            }
+            _ => {
+                rsp.status_code(404, "Not Found");
+                "{\"error\":\"not found\"}".to_owned()
+            }
+        };
+        rsp.header("Content-Type: application/json");
+        rsp.body_vec(json.into_bytes());
+        Ok(())
+    }
+}
+
+fn validation_name(validation: HeaderValidation) -> &'static str {
+    match validation {
+        HeaderValidation::Reject => "reject",
+        HeaderValidation::Sanitize => "sanitize",
+        HeaderValidation::Off => "off",
+    }
+}
+
+fn growth_name(growth: BufGrowth) -> &'static str {
+    match growth {
+        BufGrowth::Fixed => "fixed",
+        BufGrowth::Doubling => "doubling",
+    }
+}
+
+fn level_name(level: LevelFilter) -> String {
+    level.as_str().to_ascii_lowercase()
+}
+
+fn level_json(level: LevelFilter) -> String {
+    format!("{{\"level\":\"{}\"}}", level_name(level))
+}
@@ -0,0 +1,121 @@
+//! bounded admission in front of any `HttpService`
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use may::sync::Semphore;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that bounds the requests running and waiting to run
+///
+/// At most `max_concurrent` requests run the inner service at once, across
+/// all the clones of the wrapper and so across all the connections. Up to
+/// [`queue_depth`](Self::queue_depth) more wait for a turn, each for at most
+/// [`max_wait`](Self::max_wait). A request finding the queue full gets a
+/// `429 Too Many Requests` right away, one that waited too long a
+/// `503 Service Unavailable`, both with `Retry-After: 1`. Bursts then cost a
+/// bounded amount of work and memory instead of piling up coroutines.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use may_minihttp::{AdmissionService, HttpServer, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Report;
+///
+/// impl HttpService for Report {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("expensive");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = AdmissionService::new(Report, 64)
+///     .queue_depth(256)
+///     .max_wait(Duration::from_millis(500));
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AdmissionService<S> {
+    inner: S,
+    slots: Arc<Semphore>,
+    waiting: Arc<AtomicUsize>,
+    queue_depth: usize,
+    max_wait: Duration,
+}
+
+impl<S> AdmissionService<S> {
+    /// Wrap `inner`, running at most `max_concurrent` requests at once
+    ///
+    /// The queue holds `max_concurrent` requests for up to a second, change
+    /// that with the methods below.
+    pub fn new(inner: S, max_concurrent: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        AdmissionService {
+            inner,
+            slots: Arc::new(Semphore::new(max_concurrent)),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            queue_depth: max_concurrent,
+            max_wait: Duration::from_secs(1),
+        }
+    }
+
+    /// Set how many requests may wait for a turn, `0` rejects at once
+    pub fn queue_depth(mut self, queue_depth: usize) -> Self {
+        self.queue_depth = queue_depth;
+        self
+    }
+
+    /// Set how long a request waits for a turn before it gets a `503`
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// wait for a slot, `Err` with the answer for the client if there is none
+    fn admit(&self) -> Result<Admitted, (usize, &'static str)> {
+        if self.slots.try_wait() {
+            return Ok(Admitted(self.slots.clone()));
+        }
+        if self.waiting.fetch_add(1, Ordering::AcqRel) >= self.queue_depth {
+            self.waiting.fetch_sub(1, Ordering::AcqRel);
+            return Err((429, "Too Many Requests"));
+        }
+        let admitted = self.slots.wait_timeout(self.max_wait);
+        self.waiting.fetch_sub(1, Ordering::AcqRel);
+        if admitted {
+            Ok(Admitted(self.slots.clone()))
+        } else {
+            Err((503, "Service Unavailable"))
+        }
+    }
+}
+
+impl<S: HttpService> HttpService for AdmissionService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match self.admit() {
+            Ok(_slot) => self.inner.call(req, rsp),
+            Err((code, msg)) => {
+                debug!("{} {} not admitted: {code}", req.method(), req.path());
+                rsp.status_code(code, msg).header("Retry-After: 1");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// a taken slot, given back when the request is done or unwinds
+struct Admitted(Arc<Semphore>);
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        self.0.post();
+    }
+}
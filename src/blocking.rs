@@ -0,0 +1,115 @@
+//! thread pool for work that would stall a coroutine worker thread
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use once_cell::sync::Lazy;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// the pool never grows beyond this many threads, further jobs are queued
+const MAX_THREADS: usize = 512;
+
+struct Pool {
+    tx: mpsc::Sender<Job>,
+    rx: Mutex<mpsc::Receiver<Job>>,
+    // threads started so far
+    threads: AtomicUsize,
+    // threads waiting for a job that no submit has claimed yet
+    idle: AtomicUsize,
+}
+
+static POOL: Lazy<Pool> = Lazy::new(|| {
+    let (tx, rx) = mpsc::channel();
+    Pool {
+        tx,
+        rx: Mutex::new(rx),
+        threads: AtomicUsize::new(0),
+        idle: AtomicUsize::new(0),
+    }
+});
+
+impl Pool {
+    fn submit(&'static self, job: Job) {
+        self.tx
+            .send(job)
+            .expect("blocking pool receiver is never dropped");
+        // wake a waiting thread if there is one, each can only be claimed once
+        let claimed = self
+            .idle
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+        if claimed.is_ok() {
+            return;
+        }
+        let n = self.threads.fetch_add(1, Ordering::AcqRel);
+        if n >= MAX_THREADS {
+            // the job waits for the next thread that is done
+            self.threads.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+        let spawned = thread::Builder::new()
+            .name(format!("minihttp-blocking-{n}"))
+            .spawn(move || self.run());
+        if let Err(e) = spawned {
+            self.threads.fetch_sub(1, Ordering::AcqRel);
+            error!("failed to start a blocking pool thread: {e}");
+        }
+    }
+
+    fn run(&self) {
+        // a new thread is started for the job just queued
+        loop {
+            let job = self.rx.lock().unwrap().recv();
+            match job {
+                Ok(job) => job(),
+                Err(_) => return,
+            }
+            self.idle.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Run blocking or CPU heavy work on a dedicated thread pool
+///
+/// The calling coroutine is parked until `f` returns, so the `may` worker
+/// thread keeps serving other connections meanwhile. Use it for image
+/// processing, compression, synchronous database drivers or file system
+/// calls. The pool starts threads on demand, up to 512, and keeps them for
+/// later calls. A panic in `f` is resumed in the caller.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{spawn_blocking, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Thumbnail;
+///
+/// impl HttpService for Thumbnail {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         let digest = spawn_blocking(|| {
+///             // stands in for CPU heavy work
+///             (0..10_000_000u64).fold(0u64, |acc, x| acc.wrapping_mul(31).wrapping_add(x))
+///         });
+///         rsp.body_vec(digest.to_string().into_bytes());
+///         Ok(())
+///     }
+/// }
+/// ```
+pub fn spawn_blocking<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let (tx, rx) = may::sync::mpsc::channel();
+    POOL.submit(Box::new(move || {
+        let ret = panic::catch_unwind(AssertUnwindSafe(f));
+        let _ = tx.send(ret);
+    }));
+    match rx.recv() {
+        Ok(Ok(ret)) => ret,
+        Ok(Err(payload)) => panic::resume_unwind(payload),
+        Err(_) => unreachable!("the job always sends its result"),
+    }
+}
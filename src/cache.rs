@@ -0,0 +1,471 @@
+//! in-memory response cache on top of any `HttpService`
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use crate::date::HttpDate;
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+struct Entry {
+    seq: u64,
+    expires: Instant,
+    // the response `Vary` names with the request values they were stored for
+    vary: Vec<(String, Option<Vec<u8>>)>,
+    code: usize,
+    msg: Cow<'static, str>,
+    headers: Vec<&'static str>,
+    owned_headers: Vec<String>,
+    last_modified: Option<HttpDate>,
+    body: Bytes,
+}
+
+#[derive(Default)]
+struct Store {
+    map: HashMap<String, Entry>,
+    // the key of each entry by its expiry, soonest first
+    order: BTreeMap<(Instant, u64), String>,
+    bytes: usize,
+    seq: u64,
+}
+
+impl Store {
+    fn remove(&mut self, key: &str) {
+        if let Some(e) = self.map.remove(key) {
+            self.bytes -= e.body.len();
+            self.order.remove(&(e.expires, e.seq));
+        }
+    }
+
+    fn evict_one(&mut self) -> bool {
+        match self.order.pop_first() {
+            Some((_, key)) => {
+                if let Some(e) = self.map.remove(&key) {
+                    self.bytes -= e.body.len();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// drop the entries expired by `now`, not only the ones looked up
+    fn sweep(&mut self, now: Instant) {
+        while let Some(first) = self.order.first_entry() {
+            if first.key().0 > now {
+                break;
+            }
+            let key = first.remove();
+            if let Some(e) = self.map.remove(&key) {
+                self.bytes -= e.body.len();
+            }
+        }
+    }
+}
+
+/// Shared response cache with TTL and size bounds
+///
+/// Responses are keyed by method, path and the values of the configured
+/// `vary` request headers, plus the request headers named by the response
+/// `Vary`. Only `GET`/`HEAD` requests answered with `200` are stored.
+///
+/// Responses carrying `Cache-Control: no-store`/`no-cache`/`private`,
+/// `Set-Cookie` or `Vary: *` are never cached, and neither are responses to
+/// requests with `Authorization` or `Cookie` unless marked `public`. A
+/// response `max-age`/`s-maxage` shortens the entry lifetime below the
+/// cache ttl. Requests with `Cache-Control: no-cache` always reach the inner
+/// service, and the ones with `no-store` don't store its response.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use may_minihttp::{CacheService, HttpServer, HttpService, Request, Response, ResponseCache};
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.header("Content-Type: application/json");
+///         rsp.body("{\"message\":\"Hello, World!\"}");
+///         Ok(())
+///     }
+/// }
+///
+/// let cache = Arc::new(ResponseCache::new(Duration::from_secs(1)).vary("Accept-Encoding"));
+/// let server = HttpServer(CacheService::new(Hello, cache.clone()))
+///     .start("0.0.0.0:8080")
+///     .unwrap();
+/// server.wait().unwrap();
+/// println!("hits: {}, misses: {}", cache.hits(), cache.misses());
+/// ```
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    max_bytes: usize,
+    vary: Vec<&'static str>,
+    store: Mutex<Store>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// Create a cache whose entries live for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        ResponseCache {
+            ttl,
+            max_entries: 1024,
+            max_bytes: 16 * 1024 * 1024,
+            vary: Vec::new(),
+            store: Mutex::new(Store::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the maximum number of cached responses, default is 1024
+    pub fn max_entries(mut self, n: usize) -> Self {
+        self.max_entries = n;
+        self
+    }
+
+    /// Set the maximum total size of cached bodies in bytes, default is 16MB
+    pub fn max_bytes(mut self, n: usize) -> Self {
+        self.max_bytes = n;
+        self
+    }
+
+    /// Add a request header whose value becomes part of the cache key
+    pub fn vary(mut self, name: &'static str) -> Self {
+        self.vary.push(name);
+        self
+    }
+
+    /// Number of requests served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of cacheable requests that had to invoke the inner service
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Number of responses currently stored
+    pub fn len(&self) -> usize {
+        self.store.lock().unwrap().map.len()
+    }
+
+    /// Returns `true` if there is no stored response
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all the stored responses
+    pub fn clear(&self) {
+        let mut store = self.store.lock().unwrap();
+        store.map.clear();
+        store.order.clear();
+        store.bytes = 0;
+    }
+
+    fn key(&self, req: &Request) -> Option<String> {
+        let method = req.method();
+        if method != "GET" && method != "HEAD" {
+            return None;
+        }
+        let mut key = String::with_capacity(64);
+        key.push_str(method);
+        key.push(' ');
+        key.push_str(req.path());
+        for name in self.vary.iter() {
+            key.push('\n');
+            for h in req.headers() {
+                if h.name.eq_ignore_ascii_case(name) {
+                    key.push_str(std::str::from_utf8(h.value).ok()?);
+                    break;
+                }
+            }
+        }
+        Some(key)
+    }
+
+    fn lookup(&self, key: &str, req: &Request, rsp: &mut Response) -> bool {
+        let mut store = self.store.lock().unwrap();
+        let entry = match store.map.get(key) {
+            Some(e) => e,
+            None => return false,
+        };
+        if entry.expires <= Instant::now() {
+            store.remove(key);
+            return false;
+        }
+        // another variant, replaced once the inner service answers
+        if entry
+            .vary
+            .iter()
+            .any(|(name, value)| request_header(req, name) != value.as_deref())
+        {
+            return false;
+        }
+        rsp.status_with_reason(entry.code, entry.msg.clone());
+        for h in entry.headers.iter() {
+            rsp.header(h);
+        }
+        for h in entry.owned_headers.iter() {
+            rsp.header_owned(h.clone());
+        }
+        if let Some(date) = entry.last_modified {
+            rsp.last_modified(date.into());
+        }
+        rsp.body_mut().extend_from_slice(&entry.body);
+        true
+    }
+
+    fn insert(&self, key: String, req: &RequestInfo, rsp: &mut Response) {
+        let code = rsp.status().0;
+        // a flushed response is only partly in the buffer
+        if code != 200 || rsp.is_flushed() {
+            return;
+        }
+        let policy = ResponsePolicy::of(rsp);
+        if policy.no_store || (req.authorized && !policy.public) {
+            return;
+        }
+        let ttl = match policy.max_age {
+            Some(0) => return,
+            Some(age) => self.ttl.min(Duration::from_secs(age)),
+            None => self.ttl,
+        };
+        let vary = policy
+            .vary
+            .into_iter()
+            .map(|name| {
+                let value = req.header(&name).map(<[u8]>::to_vec);
+                (name, value)
+            })
+            .collect();
+        let msg = rsp.reason();
+        let headers = rsp.headers().to_vec();
+        let owned_headers = rsp.owned_headers().to_vec();
+        let last_modified = rsp.get_last_modified();
+        let body = Bytes::copy_from_slice(rsp.get_body());
+        if body.len() > self.max_bytes {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut store = self.store.lock().unwrap();
+        store.remove(&key);
+        store.sweep(now);
+        while store.map.len() >= self.max_entries || store.bytes + body.len() > self.max_bytes {
+            if !store.evict_one() {
+                break;
+            }
+        }
+        if store.map.len() >= self.max_entries {
+            return;
+        }
+        store.seq += 1;
+        let seq = store.seq;
+        let expires = now + ttl;
+        store.bytes += body.len();
+        store.order.insert((expires, seq), key.clone());
+        store.map.insert(
+            key,
+            Entry {
+                seq,
+                expires,
+                vary,
+                code,
+                msg,
+                headers,
+                owned_headers,
+                last_modified,
+                body,
+            },
+        );
+        debug_assert_eq!(store.order.len(), store.map.len());
+    }
+}
+
+fn request_header<'a>(req: &'a Request, name: &str) -> Option<&'a [u8]> {
+    req.headers()
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value)
+}
+
+/// the comma separated directives of a header value, lower cased
+fn directives(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(|d| d.trim().to_ascii_lowercase())
+        .filter(|d| !d.is_empty())
+}
+
+/// what a request lets the cache do, read before the request is consumed
+struct RequestInfo {
+    // `Cache-Control: no-cache` or `Pragma: no-cache`
+    no_cache: bool,
+    // `Cache-Control: no-store`
+    no_store: bool,
+    // `Authorization` or `Cookie`, the response may be for this client only
+    authorized: bool,
+    // the request headers, kept on a miss to find the values of the
+    // response `Vary` names once the request is consumed
+    headers: Vec<(String, Vec<u8>)>,
+}
+
+impl RequestInfo {
+    fn of(req: &Request) -> Self {
+        let mut info = RequestInfo {
+            no_cache: false,
+            no_store: false,
+            authorized: false,
+            headers: Vec::new(),
+        };
+        for h in req.headers() {
+            let name = h.name;
+            if name.eq_ignore_ascii_case("authorization") || name.eq_ignore_ascii_case("cookie") {
+                info.authorized = true;
+            }
+            let control = name.eq_ignore_ascii_case("cache-control");
+            if control || name.eq_ignore_ascii_case("pragma") {
+                for d in directives(&String::from_utf8_lossy(h.value)) {
+                    match d.as_str() {
+                        "no-cache" => info.no_cache = true,
+                        "no-store" if control => info.no_store = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        info
+    }
+
+    fn keep_headers(&mut self, req: &Request) {
+        let headers = req.headers().iter();
+        self.headers = headers
+            .map(|h| (h.name.to_owned(), h.value.to_vec()))
+            .collect();
+    }
+
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+}
+
+/// what the response headers let the cache store
+#[derive(Default)]
+struct ResponsePolicy {
+    // `no-store`, `no-cache`, `private`, `Set-Cookie` or `Vary: *`
+    no_store: bool,
+    public: bool,
+    // `s-maxage` if given, else `max-age`, in seconds
+    max_age: Option<u64>,
+    // the lower cased names of the response `Vary`
+    vary: Vec<String>,
+}
+
+impl ResponsePolicy {
+    fn of(rsp: &Response) -> Self {
+        let mut policy = ResponsePolicy::default();
+        let mut s_maxage = None;
+        let lines = rsp.headers().iter().copied();
+        for h in lines.chain(rsp.owned_headers().iter().map(String::as_str)) {
+            let (name, value) = match h.split_once(':') {
+                Some((name, value)) => (name.trim(), value),
+                None => continue,
+            };
+            if name.eq_ignore_ascii_case("set-cookie") {
+                policy.no_store = true;
+            } else if name.eq_ignore_ascii_case("cache-control") {
+                for d in directives(value) {
+                    let (d, arg) = match d.split_once('=') {
+                        Some((d, arg)) => (d, Some(arg)),
+                        None => (d.as_str(), None),
+                    };
+                    match d {
+                        "no-store" | "no-cache" | "private" => policy.no_store = true,
+                        "public" => policy.public = true,
+                        // an invalid age is stale, RFC 9111 section 4.2.1
+                        "max-age" => policy.max_age = Some(parse_age(arg)),
+                        "s-maxage" => s_maxage = Some(parse_age(arg)),
+                        _ => {}
+                    }
+                }
+            } else if name.eq_ignore_ascii_case("vary") {
+                for d in directives(value) {
+                    if d == "*" {
+                        policy.no_store = true;
+                    } else if !policy.vary.contains(&d) {
+                        policy.vary.push(d);
+                    }
+                }
+            }
+        }
+        if s_maxage.is_some() {
+            policy.max_age = s_maxage;
+        }
+        policy
+    }
+}
+
+fn parse_age(arg: Option<&str>) -> u64 {
+    arg.and_then(|a| a.trim().trim_matches('"').parse().ok())
+        .unwrap_or(0)
+}
+
+/// `HttpService` wrapper that serves repeated requests from a `ResponseCache`
+/// without invoking the inner service
+#[derive(Clone)]
+pub struct CacheService<S> {
+    inner: S,
+    cache: Arc<ResponseCache>,
+}
+
+impl<S> CacheService<S> {
+    /// Wrap `inner` with the shared `cache`
+    pub fn new(inner: S, cache: Arc<ResponseCache>) -> Self {
+        CacheService { inner, cache }
+    }
+
+    /// The cache used by this service
+    pub fn cache(&self) -> &Arc<ResponseCache> {
+        &self.cache
+    }
+}
+
+impl<S: HttpService> HttpService for CacheService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let key = match self.cache.key(&req) {
+            Some(key) => key,
+            None => return self.inner.call(req, rsp),
+        };
+        let mut info = RequestInfo::of(&req);
+        if !info.no_cache && self.cache.lookup(&key, &req, rsp) {
+            self.cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.cache.misses.fetch_add(1, Ordering::Relaxed);
+        if info.no_store {
+            return self.inner.call(req, rsp);
+        }
+        info.keep_headers(&req);
+        self.inner.call(req, rsp)?;
+        self.cache.insert(key, &info, rsp);
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! frequently sent headers as preformatted lines
+
+/// Frequent `Content-Type` values, see [`Response::content_type`](crate::Response::content_type)
+///
+/// Text types carry `charset=utf-8`. Use [`mime::from_path`](crate::mime::from_path)
+/// for types picked by file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ContentType {
+    /// `application/json`
+    Json,
+    /// `text/plain; charset=utf-8`
+    Text,
+    /// `text/html; charset=utf-8`
+    Html,
+    /// `text/css; charset=utf-8`
+    Css,
+    /// `text/javascript; charset=utf-8`
+    JavaScript,
+    /// `application/xml`
+    Xml,
+    /// `application/x-www-form-urlencoded`
+    Form,
+    /// `text/event-stream`
+    EventStream,
+    /// `application/octet-stream`
+    OctetStream,
+}
+
+/// `"Content-Type: ".len()`
+const CONTENT_TYPE_NAME_LEN: usize = 14;
+
+impl ContentType {
+    /// The whole header line, e.g. `Content-Type: application/json`
+    pub const fn header(self) -> &'static str {
+        match self {
+            ContentType::Json => "Content-Type: application/json",
+            ContentType::Text => "Content-Type: text/plain; charset=utf-8",
+            ContentType::Html => "Content-Type: text/html; charset=utf-8",
+            ContentType::Css => "Content-Type: text/css; charset=utf-8",
+            ContentType::JavaScript => "Content-Type: text/javascript; charset=utf-8",
+            ContentType::Xml => "Content-Type: application/xml",
+            ContentType::Form => "Content-Type: application/x-www-form-urlencoded",
+            ContentType::EventStream => "Content-Type: text/event-stream",
+            ContentType::OctetStream => "Content-Type: application/octet-stream",
+        }
+    }
+
+    /// The header value, e.g. `application/json`
+    pub fn as_str(self) -> &'static str {
+        &self.header()[CONTENT_TYPE_NAME_LEN..]
+    }
+}
+
+/// Other headers sent often, see [`Response::common_header`](crate::Response::common_header)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CommonHeader {
+    /// `Content-Type` with one of the frequent values
+    ContentType(ContentType),
+    /// `Connection: close`
+    ConnectionClose,
+    /// `Connection: keep-alive`
+    ConnectionKeepAlive,
+    /// `Connection: upgrade`
+    ConnectionUpgrade,
+    /// `Cache-Control: no-store`
+    NoStore,
+    /// `Cache-Control: no-cache`
+    NoCache,
+    /// `Access-Control-Allow-Origin: *`
+    AllowAnyOrigin,
+    /// `X-Content-Type-Options: nosniff`
+    NoSniff,
+}
+
+impl CommonHeader {
+    /// The whole header line, e.g. `Connection: close`
+    pub const fn header(self) -> &'static str {
+        match self {
+            CommonHeader::ContentType(content_type) => content_type.header(),
+            CommonHeader::ConnectionClose => "Connection: close",
+            CommonHeader::ConnectionKeepAlive => "Connection: keep-alive",
+            CommonHeader::ConnectionUpgrade => "Connection: upgrade",
+            CommonHeader::NoStore => "Cache-Control: no-store",
+            CommonHeader::NoCache => "Cache-Control: no-cache",
+            CommonHeader::AllowAnyOrigin => "Access-Control-Allow-Origin: *",
+            CommonHeader::NoSniff => "X-Content-Type-Options: nosniff",
+        }
+    }
+}
+
+impl From<ContentType> for CommonHeader {
+    fn from(content_type: ContentType) -> Self {
+        CommonHeader::ContentType(content_type)
+    }
+}
@@ -0,0 +1,81 @@
+//! conditional request handling on top of any `HttpService`
+
+use std::io;
+
+use crate::date::HttpDate;
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that answers conditional requests
+///
+/// When a `GET`/`HEAD` request carries `If-Modified-Since` and the inner
+/// service answers `200` with a [`Response::last_modified`] time that is not
+/// newer, the response is turned into a `304 Not Modified` without a body.
+/// Invalid dates are ignored, as is `If-Modified-Since` when the request
+/// also has `If-None-Match`.
+///
+/// # Example
+/// ```no_run
+/// use std::time::SystemTime;
+/// use may_minihttp::{ConditionalService, HttpServer, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Report {
+///     updated: SystemTime,
+/// }
+///
+/// impl HttpService for Report {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.last_modified(self.updated).body("all systems nominal");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = ConditionalService::new(Report { updated: SystemTime::now() });
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ConditionalService<S> {
+    inner: S,
+}
+
+impl<S> ConditionalService<S> {
+    /// Wrap `inner`
+    pub fn new(inner: S) -> Self {
+        ConditionalService { inner }
+    }
+}
+
+/// the `If-Modified-Since` date, if it applies to this request
+fn if_modified_since(req: &Request) -> Option<HttpDate> {
+    let method = req.method();
+    if method != "GET" && method != "HEAD" {
+        return None;
+    }
+    let mut since = None;
+    for h in req.headers() {
+        if h.name.eq_ignore_ascii_case("if-none-match") {
+            return None;
+        }
+        if h.name.eq_ignore_ascii_case("if-modified-since") {
+            since = std::str::from_utf8(h.value).ok()?.parse().ok();
+        }
+    }
+    since
+}
+
+impl<S: HttpService> HttpService for ConditionalService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let since = if_modified_since(&req);
+        self.inner.call(req, rsp)?;
+        if let (Some(since), Some(modified)) = (since, rsp.get_last_modified()) {
+            if rsp.status().0 == 200 && !rsp.is_flushed() && modified <= since {
+                rsp.status_code(304, "Not Modified");
+                rsp.clear_body();
+            }
+        }
+        Ok(())
+    }
+}
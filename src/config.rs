@@ -1,16 +1,339 @@
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::LevelFilter;
+
+use crate::drain::Drain;
+use crate::error::HttpError;
+#[cfg(feature = "ip-filter")]
+use crate::ip_filter::IpFilter;
+use crate::privileges::RunAs;
 use crate::request::MaxHeaders;
+use crate::stats::ServerStats;
+
+/// Called with every request that fails to parse and the peer address, when known
+pub type ParseErrorHook = fn(&HttpError, Option<SocketAddr>);
+
+/// Called with the peer address, when known, and the error that ended a connection
+pub type ConnectionErrorHook = fn(Option<SocketAddr>, &io::Error);
+
+/// Called after each request with its method, path, response status code,
+/// the time taken to answer it and the response body bytes
+pub type RequestCompleteHook = fn(&str, &str, usize, Duration, usize);
+
+/// What the server does with response headers that contain CR, LF or other
+/// control characters
+///
+/// A handler that copies user input into a header could otherwise split the
+/// response and inject headers or a whole response of its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config-file",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum HeaderValidation {
+    /// Answer with a `500` instead of the response
+    #[default]
+    Reject,
+    /// Replace the offending characters with spaces
+    Sanitize,
+    /// Send the headers as they are
+    Off,
+}
+
+impl FromStr for HeaderValidation {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(HeaderValidation::Reject),
+            "sanitize" => Ok(HeaderValidation::Sanitize),
+            "off" => Ok(HeaderValidation::Off),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid header validation: {s:?}, expected reject, sanitize or off"),
+            )),
+        }
+    }
+}
+
+/// How a connection buffer grows once it runs out of room
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "config-file",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum BufGrowth {
+    /// Add [`buf_grow_by`](HttpConfig::buf_grow_by) bytes each time, which
+    /// keeps the buffers of small requests small
+    #[default]
+    Fixed,
+    /// Double the capacity, adding at least `buf_grow_by` bytes, so large
+    /// request heads are copied a few times only
+    Doubling,
+}
+
+impl FromStr for BufGrowth {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fixed" => Ok(BufGrowth::Fixed),
+            "doubling" => Ok(BufGrowth::Doubling),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid buffer growth: {s:?}, expected fixed or doubling"),
+            )),
+        }
+    }
+}
 
 /// Configuration for HTTP server behavior
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "config-file",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct HttpConfig {
     /// Maximum number of headers to accept per request
     pub max_headers: MaxHeaders,
+    /// Write the responses to all pipelined requests found in one read with a
+    /// single write, instead of one write per response
+    ///
+    /// Responses are always sent in request order. Default is `true`.
+    pub batch_writes: bool,
+    /// Maximum bytes of encoded responses queued for a connection
+    ///
+    /// When a client doesn't read its responses fast enough, the connection
+    /// stops processing further requests until the socket drains below this
+    /// mark, which bounds the per-connection memory. A body written with
+    /// [`Response::body_mut`](crate::Response::body_mut) past the mark is
+    /// flushed as a chunk before it grows further. Default is 1MB.
+    pub write_high_watermark: usize,
+    /// Maximum bytes held by the read, write and body buffers of a connection,
+    /// `0` means no limit, which is the default
+    ///
+    /// Past the limit the connection first waits for the client to read the
+    /// queued responses, then closes with an [`HttpError::MemoryLimit`] if
+    /// the buffers are still too large, e.g. for a request head that never
+    /// ends. Buffers grown past four times
+    /// [`buf_initial_capacity`](Self::buf_initial_capacity) by a large
+    /// request or response are shrunk back once it is done, limit or not.
+    pub max_conn_memory: usize,
+    /// Capacity of the read and write buffers of a new connection, default
+    /// is 32KB
+    ///
+    /// Lower it for many connections sending small requests, raise it for
+    /// large request heads or responses.
+    pub buf_initial_capacity: usize,
+    /// Bytes a buffer grows by once less than 1KB of it is free, default is
+    /// 32KB, at least 1KB
+    pub buf_grow_by: usize,
+    /// How a full buffer grows, default is [`BufGrowth::Fixed`]
+    pub buf_growth: BufGrowth,
+    /// Largest capacity of the read buffer of a connection, `0` means no
+    /// limit, which is the default
+    ///
+    /// A request head that doesn't fit closes the connection with an
+    /// [`HttpError::MemoryLimit`]. The write buffers are bounded by
+    /// [`write_high_watermark`](Self::write_high_watermark) instead.
+    pub buf_max_capacity: usize,
+    /// Largest request body accepted, in bytes, `0` means no limit, which is
+    /// the default
+    ///
+    /// A request whose `Content-Length` is larger is answered with a
+    /// `413 Payload Too Large` and `Connection: close` before the service
+    /// sees it, then the connection closes with an
+    /// [`HttpError::BodyTooLarge`].
+    pub max_body: usize,
+    /// Maximum requests running at once in an
+    /// [`HttpServerConcurrent`](crate::HttpServerConcurrent), default is 1024
+    ///
+    /// Connections stop parsing further requests while all of them are taken.
+    pub max_in_flight: usize,
+    /// How response headers with CR, LF or other control characters are
+    /// handled, default is [`HeaderValidation::Reject`]
+    pub header_validation: HeaderValidation,
+    /// Longest pause of the accept loop after a failed accept, in
+    /// milliseconds, default is 1000
+    ///
+    /// When accepts keep failing, e.g. because the process is out of file
+    /// descriptors, the loop waits 5ms, then twice as long after each further
+    /// failure up to this limit, instead of spinning. `0` retries right away.
+    pub max_accept_backoff_ms: u64,
+    /// Length of the queue of connections the OS accepts before the server
+    /// takes them, `0` keeps the default of the standard library, 128
+    ///
+    /// Raise it, e.g. to 4096, for servers that see bursts of new clients.
+    /// Connections arriving while the queue is full are reset or retried,
+    /// depending on the OS. The OS caps it at its own limit, on Linux
+    /// `net.core.somaxconn`.
+    pub backlog: u32,
+    /// Seconds the OS waits for the first request bytes before it hands a
+    /// new connection to the server, `0` is off, which is the default
+    ///
+    /// Health checks and port scans that connect without sending anything
+    /// then never wake the accept loop. Linux only (`TCP_DEFER_ACCEPT`),
+    /// ignored elsewhere.
+    pub defer_accept_secs: u32,
+    /// Length of the queue of TCP Fast Open connections, `0` is off, which is
+    /// the default
+    ///
+    /// Returning clients send their first request in the SYN, saving a round
+    /// trip. Linux only (`TCP_FASTOPEN`), ignored elsewhere, and the
+    /// `net.ipv4.tcp_fastopen` sysctl must allow it for servers.
+    pub fastopen_queue: u32,
+    /// `IPV6_V6ONLY` of IPv6 listeners, default is `None`, the OS default
+    ///
+    /// With `Some(false)` a listener on `[::]` also takes IPv4 clients, as
+    /// `::ffff:a.b.c.d` peers. With `Some(true)` it takes IPv6 only, so a
+    /// second listener can bind `0.0.0.0` on the same port. The OS default
+    /// differs: Linux shares the port unless the `net.ipv6.bindv6only`
+    /// sysctl is set, Windows and OpenBSD never do. Ignored for IPv4 addresses.
+    pub ipv6_only: Option<bool>,
+    /// `SO_LINGER` of accepted connections in seconds, default is `None`,
+    /// the OS default
+    ///
+    /// Without it a close returns at once and the OS sends what is left in
+    /// the background. `Some(0)` resets every connection on close instead of
+    /// the FIN handshake, leaving no `TIME_WAIT` behind. A longer linger makes
+    /// the close wait for the client to take the last bytes, holding up the
+    /// worker thread meanwhile.
+    pub linger_secs: Option<u32>,
+    /// Reset connections closed for a malformed request or an exceeded
+    /// limit, default is `false`
+    ///
+    /// Connections ending with an [`HttpError`] are closed with a RST
+    /// instead of a FIN, so misbehaving clients can't hold server sockets in
+    /// `FIN_WAIT` or `TIME_WAIT` states. Other connections close normally.
+    pub abortive_close: bool,
+    /// Seconds a connection is silent before the OS sends TCP keepalive
+    /// probes, `0` is off, which is the default
+    ///
+    /// Clients that vanished behind a NAT or a crashed host never send a FIN,
+    /// their connections would wait for a request until the keep-alive
+    /// timeout of the server. With probes on, the OS resets them once
+    /// [`tcp_keepalive_retries`](Self::tcp_keepalive_retries) probes go
+    /// unanswered, and the connection closes with an error.
+    pub tcp_keepalive_idle_secs: u32,
+    /// Seconds between unanswered keepalive probes, `0` keeps the OS default
+    pub tcp_keepalive_interval_secs: u32,
+    /// Unanswered keepalive probes before the connection is reset, `0` keeps
+    /// the OS default, ignored on Windows
+    pub tcp_keepalive_retries: u32,
+    /// Seconds a keep-alive connection waits for its next request before
+    /// the server closes it, `0` waits forever, which is the default
+    ///
+    /// Responses announce it with `Keep-Alive: timeout=<secs>`, so clients
+    /// don't send a request on a connection about to close. The server closes
+    /// the connections of an [`HttpServer`](crate::HttpServer) or
+    /// [`HttpServerWithHeaders`](crate::HttpServerWithHeaders) on unix, up
+    /// to half a second late.
+    pub keep_alive_timeout_secs: u32,
+    /// Requests served on one connection before the server closes it, `0`
+    /// for no limit, which is the default
+    ///
+    /// Responses announce the requests left with `Keep-Alive: max=<n>`, the
+    /// last one carries `Connection: close`.
+    pub max_requests_per_conn: usize,
+    /// Close the longest idle keep-alive connection when an accept fails for
+    /// lack of file descriptors, default is `false`
+    ///
+    /// Once the process reaches its fd limit, clients holding idle
+    /// connections would otherwise keep new clients out. An evicted client
+    /// sees its connection close between requests, as after a keep-alive
+    /// timeout. Only the connections of an [`HttpServer`](crate::HttpServer)
+    /// or [`HttpServerWithHeaders`](crate::HttpServerWithHeaders) accepted
+    /// with this set are evicted, and only on unix.
+    pub evict_idle: bool,
+    /// The `log` level used for requests that fail to parse, default is `Warn`
+    ///
+    /// `Off` silences them, the hook below is still called.
+    pub parse_error_log: LevelFilter,
+    /// Log a hint along with each parse error that has one, e.g. the
+    /// [`MaxHeaders`] that fits a request with too many headers, default is
+    /// `false`
+    ///
+    /// Meant for debugging, the hint is formatted for every bad request.
+    pub parse_error_hints: bool,
+    /// Optional hook for requests that fail to parse, e.g. to count them
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub on_parse_error: Option<ParseErrorHook>,
+    /// Optional hook for failed accepts and for errors that end a connection
+    ///
+    /// This covers parse errors as well as read and write failures, including
+    /// clients that reset the connection. A clean close is not an error.
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub on_connection_error: Option<ConnectionErrorHook>,
+    /// Optional hook called once each response is ready, e.g. to feed a
+    /// latency histogram
+    ///
+    /// The time runs from the parsed request until the service returns, so it
+    /// includes reading the body but not writing out the response. A service
+    /// error is reported with the status it is answered with, a `500` or a
+    /// `413` for a body over the limit. Nothing is copied out of the request
+    /// unless a hook is set.
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub on_request_complete: Option<RequestCompleteHook>,
+    /// Optional counters of connections, requests and bytes
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub stats: Option<Arc<ServerStats>>,
+    /// Optional drain state, see [`ServerHandle::begin_drain`](crate::ServerHandle::begin_drain)
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub drain: Option<Arc<Drain>>,
+    /// Optional client allow and deny lists, connections from denied clients
+    /// are closed right after the accept
+    #[cfg(feature = "ip-filter")]
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub ip_filter: Option<Arc<IpFilter>>,
+    // set by `HttpServerBuilder::run_as`, the accept loops wait for it
+    #[cfg_attr(feature = "config-file", serde(skip))]
+    pub(crate) run_as: Option<Arc<RunAs>>,
 }
 
 impl Default for HttpConfig {
     fn default() -> Self {
         Self {
             max_headers: MaxHeaders::Default,
+            batch_writes: true,
+            write_high_watermark: 1024 * 1024,
+            max_conn_memory: 0,
+            buf_initial_capacity: 32 * 1024,
+            buf_grow_by: 32 * 1024,
+            buf_growth: BufGrowth::Fixed,
+            buf_max_capacity: 0,
+            max_body: 0,
+            max_in_flight: 1024,
+            header_validation: HeaderValidation::Reject,
+            max_accept_backoff_ms: 1000,
+            backlog: 0,
+            defer_accept_secs: 0,
+            fastopen_queue: 0,
+            ipv6_only: None,
+            linger_secs: None,
+            abortive_close: false,
+            tcp_keepalive_idle_secs: 0,
+            tcp_keepalive_interval_secs: 0,
+            tcp_keepalive_retries: 0,
+            keep_alive_timeout_secs: 0,
+            max_requests_per_conn: 0,
+            evict_idle: false,
+            parse_error_log: LevelFilter::Warn,
+            parse_error_hints: false,
+            on_parse_error: None,
+            on_connection_error: None,
+            on_request_complete: None,
+            stats: None,
+            drain: None,
+            #[cfg(feature = "ip-filter")]
+            ip_filter: None,
+            run_as: None,
         }
     }
 }
@@ -20,11 +343,512 @@ impl HttpConfig {
     pub fn new() -> Self {
         Self::default()
     }
-    
+
+    /// A configuration for benchmarks and other raw throughput workloads
+    ///
+    /// Starts from the defaults and turns off what costs time on every
+    /// response:
+    /// - pipelined responses go out in one write ([`batch_writes`](Self::batch_writes))
+    /// - response headers are sent without the control character scan
+    ///   ([`HeaderValidation::Off`]), so only static headers should be set
+    /// - malformed requests are not logged
+    /// - the smallest header buffer, 16 slots, is kept on the stack
+    ///
+    /// The rest of the fast path is always on: the `Date` value is formatted
+    /// twice a second and copied into each response, status codes and
+    /// `Content-Length` are formatted with `itoa`, small responses are
+    /// encoded on the stack and a request that sets no hook, stats or owned
+    /// header allocates nothing. Leave the hooks and [`stats`](Self::stats)
+    /// unset to keep it that way. See `examples/framework_bench.rs`.
+    pub fn performance() -> Self {
+        Self {
+            max_headers: MaxHeaders::Default,
+            batch_writes: true,
+            header_validation: HeaderValidation::Off,
+            parse_error_log: LevelFilter::Off,
+            ..Self::default()
+        }
+    }
+
+    /// Create a configuration from `MINIHTTP_*` environment variables
+    ///
+    /// Unset variables keep their default value. Recognized variables:
+    ///
+    /// | Variable | Setting | Example |
+    /// |----------|---------|---------|
+    /// | `MINIHTTP_MAX_HEADERS` | [`max_headers`](Self::max_headers) | `large`, `64` |
+    /// | `MINIHTTP_BATCH_WRITES` | [`batch_writes`](Self::batch_writes) | `true`, `0` |
+    /// | `MINIHTTP_WRITE_HIGH_WATERMARK` | [`write_high_watermark`](Self::write_high_watermark) | `262144` |
+    /// | `MINIHTTP_MAX_CONN_MEMORY` | [`max_conn_memory`](Self::max_conn_memory) | `4194304` |
+    /// | `MINIHTTP_BUF_INITIAL_CAPACITY` | [`buf_initial_capacity`](Self::buf_initial_capacity) | `4096` |
+    /// | `MINIHTTP_BUF_GROW_BY` | [`buf_grow_by`](Self::buf_grow_by) | `8192` |
+    /// | `MINIHTTP_BUF_GROWTH` | [`buf_growth`](Self::buf_growth) | `fixed`, `doubling` |
+    /// | `MINIHTTP_BUF_MAX_CAPACITY` | [`buf_max_capacity`](Self::buf_max_capacity) | `1048576` |
+    /// | `MINIHTTP_MAX_BODY` | [`max_body`](Self::max_body) | `10485760` |
+    /// | `MINIHTTP_MAX_IN_FLIGHT` | [`max_in_flight`](Self::max_in_flight) | `256` |
+    /// | `MINIHTTP_HEADER_VALIDATION` | [`header_validation`](Self::header_validation) | `sanitize`, `off` |
+    /// | `MINIHTTP_MAX_ACCEPT_BACKOFF_MS` | [`max_accept_backoff_ms`](Self::max_accept_backoff_ms) | `250` |
+    /// | `MINIHTTP_BACKLOG` | [`backlog`](Self::backlog) | `4096` |
+    /// | `MINIHTTP_DEFER_ACCEPT_SECS` | [`defer_accept_secs`](Self::defer_accept_secs) | `5` |
+    /// | `MINIHTTP_FASTOPEN_QUEUE` | [`fastopen_queue`](Self::fastopen_queue) | `256` |
+    /// | `MINIHTTP_IPV6_ONLY` | [`ipv6_only`](Self::ipv6_only) | `false` |
+    /// | `MINIHTTP_LINGER_SECS` | [`linger_secs`](Self::linger_secs) | `0` |
+    /// | `MINIHTTP_ABORTIVE_CLOSE` | [`abortive_close`](Self::abortive_close) | `true`, `0` |
+    /// | `MINIHTTP_TCP_KEEPALIVE_IDLE_SECS` | [`tcp_keepalive_idle_secs`](Self::tcp_keepalive_idle_secs) | `60` |
+    /// | `MINIHTTP_TCP_KEEPALIVE_INTERVAL_SECS` | [`tcp_keepalive_interval_secs`](Self::tcp_keepalive_interval_secs) | `10` |
+    /// | `MINIHTTP_TCP_KEEPALIVE_RETRIES` | [`tcp_keepalive_retries`](Self::tcp_keepalive_retries) | `5` |
+    /// | `MINIHTTP_KEEP_ALIVE_TIMEOUT_SECS` | [`keep_alive_timeout_secs`](Self::keep_alive_timeout_secs) | `5` |
+    /// | `MINIHTTP_MAX_REQUESTS_PER_CONN` | [`max_requests_per_conn`](Self::max_requests_per_conn) | `1000` |
+    /// | `MINIHTTP_EVICT_IDLE` | [`evict_idle`](Self::evict_idle) | `true`, `0` |
+    /// | `MINIHTTP_PARSE_ERROR_LOG` | [`parse_error_log`](Self::parse_error_log) | `debug`, `off` |
+    /// | `MINIHTTP_PARSE_ERROR_HINTS` | [`parse_error_hints`](Self::parse_error_hints) | `true`, `0` |
+    ///
+    /// The addresses to listen on, `MINIHTTP_LISTEN`, are read by
+    /// [`HttpServerBuilder::from_env`](crate::HttpServerBuilder::from_env).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a variable is set to a value that
+    /// can't be parsed.
+    pub fn from_env() -> io::Result<Self> {
+        let mut config = Self::default();
+        if let Some(v) = env_var("MINIHTTP_MAX_HEADERS")? {
+            config.max_headers = v.parse()?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BATCH_WRITES")? {
+            config.batch_writes = parse_bool("MINIHTTP_BATCH_WRITES", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_WRITE_HIGH_WATERMARK")? {
+            config.write_high_watermark = parse_usize("MINIHTTP_WRITE_HIGH_WATERMARK", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_MAX_CONN_MEMORY")? {
+            config.max_conn_memory = parse_usize("MINIHTTP_MAX_CONN_MEMORY", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BUF_INITIAL_CAPACITY")? {
+            config.buf_initial_capacity = parse_usize("MINIHTTP_BUF_INITIAL_CAPACITY", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BUF_GROW_BY")? {
+            config.buf_grow_by = parse_usize("MINIHTTP_BUF_GROW_BY", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BUF_GROWTH")? {
+            config.buf_growth = v.parse().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("MINIHTTP_BUF_GROWTH: {e}"),
+                )
+            })?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BUF_MAX_CAPACITY")? {
+            config.buf_max_capacity = parse_usize("MINIHTTP_BUF_MAX_CAPACITY", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_MAX_BODY")? {
+            config.max_body = parse_usize("MINIHTTP_MAX_BODY", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_MAX_IN_FLIGHT")? {
+            config.max_in_flight = parse_usize("MINIHTTP_MAX_IN_FLIGHT", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_HEADER_VALIDATION")? {
+            config.header_validation = v.parse().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("MINIHTTP_HEADER_VALIDATION: {e}"),
+                )
+            })?;
+        }
+        if let Some(v) = env_var("MINIHTTP_MAX_ACCEPT_BACKOFF_MS")? {
+            config.max_accept_backoff_ms = parse_u64("MINIHTTP_MAX_ACCEPT_BACKOFF_MS", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_BACKLOG")? {
+            config.backlog = parse_u32("MINIHTTP_BACKLOG", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_DEFER_ACCEPT_SECS")? {
+            config.defer_accept_secs = parse_u32("MINIHTTP_DEFER_ACCEPT_SECS", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_FASTOPEN_QUEUE")? {
+            config.fastopen_queue = parse_u32("MINIHTTP_FASTOPEN_QUEUE", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_IPV6_ONLY")? {
+            config.ipv6_only = Some(parse_bool("MINIHTTP_IPV6_ONLY", &v)?);
+        }
+        if let Some(v) = env_var("MINIHTTP_LINGER_SECS")? {
+            config.linger_secs = Some(parse_u32("MINIHTTP_LINGER_SECS", &v)?);
+        }
+        if let Some(v) = env_var("MINIHTTP_ABORTIVE_CLOSE")? {
+            config.abortive_close = parse_bool("MINIHTTP_ABORTIVE_CLOSE", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_TCP_KEEPALIVE_IDLE_SECS")? {
+            config.tcp_keepalive_idle_secs = parse_u32("MINIHTTP_TCP_KEEPALIVE_IDLE_SECS", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_TCP_KEEPALIVE_INTERVAL_SECS")? {
+            config.tcp_keepalive_interval_secs =
+                parse_u32("MINIHTTP_TCP_KEEPALIVE_INTERVAL_SECS", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_TCP_KEEPALIVE_RETRIES")? {
+            config.tcp_keepalive_retries = parse_u32("MINIHTTP_TCP_KEEPALIVE_RETRIES", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_KEEP_ALIVE_TIMEOUT_SECS")? {
+            config.keep_alive_timeout_secs = parse_u32("MINIHTTP_KEEP_ALIVE_TIMEOUT_SECS", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_MAX_REQUESTS_PER_CONN")? {
+            config.max_requests_per_conn = parse_usize("MINIHTTP_MAX_REQUESTS_PER_CONN", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_EVICT_IDLE")? {
+            config.evict_idle = parse_bool("MINIHTTP_EVICT_IDLE", &v)?;
+        }
+        if let Some(v) = env_var("MINIHTTP_PARSE_ERROR_LOG")? {
+            config.parse_error_log = v.trim().parse().map_err(|_| {
+                let msg = format!("MINIHTTP_PARSE_ERROR_LOG: invalid log level: {v:?}");
+                io::Error::new(io::ErrorKind::InvalidInput, msg)
+            })?;
+        }
+        if let Some(v) = env_var("MINIHTTP_PARSE_ERROR_HINTS")? {
+            config.parse_error_hints = parse_bool("MINIHTTP_PARSE_ERROR_HINTS", &v)?;
+        }
+        Ok(config)
+    }
+
     /// Set the maximum number of headers
     pub fn with_max_headers(mut self, max_headers: MaxHeaders) -> Self {
         self.max_headers = max_headers;
         self
     }
+
+    /// Set whether pipelined responses are written in one batch
+    pub fn with_batch_writes(mut self, batch_writes: bool) -> Self {
+        self.batch_writes = batch_writes;
+        self
+    }
+
+    /// Set the maximum bytes of responses queued for a connection
+    pub fn with_write_high_watermark(mut self, bytes: usize) -> Self {
+        self.write_high_watermark = bytes;
+        self
+    }
+
+    /// Set the maximum bytes of buffers held by a connection, `0` for no limit
+    pub fn with_max_conn_memory(mut self, bytes: usize) -> Self {
+        self.max_conn_memory = bytes;
+        self
+    }
+
+    /// Set the capacity of the buffers of a new connection
+    pub fn with_buf_initial_capacity(mut self, bytes: usize) -> Self {
+        self.buf_initial_capacity = bytes;
+        self
+    }
+
+    /// Set how a full buffer grows and the bytes it grows by at least
+    pub fn with_buf_growth(mut self, growth: BufGrowth, grow_by: usize) -> Self {
+        self.buf_growth = growth;
+        self.buf_grow_by = grow_by;
+        self
+    }
+
+    /// Set the largest capacity of the read buffer of a connection, `0` for
+    /// no limit
+    pub fn with_buf_max_capacity(mut self, bytes: usize) -> Self {
+        self.buf_max_capacity = bytes;
+        self
+    }
+
+    /// Set the largest request body accepted, `0` for no limit
+    pub fn with_max_body(mut self, bytes: usize) -> Self {
+        self.max_body = bytes;
+        self
+    }
+
+    /// Set the maximum requests running at once in a concurrent server
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Set how response headers with control characters are handled
+    pub fn with_header_validation(mut self, validation: HeaderValidation) -> Self {
+        self.header_validation = validation;
+        self
+    }
+
+    /// Set the longest pause after failed accepts, in milliseconds
+    pub fn with_max_accept_backoff_ms(mut self, ms: u64) -> Self {
+        self.max_accept_backoff_ms = ms;
+        self
+    }
+
+    /// Set the length of the listen queue, `0` for the default
+    pub fn with_backlog(mut self, backlog: u32) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Set how long the OS waits for request bytes before handing over a
+    /// connection, `0` for off
+    pub fn with_defer_accept_secs(mut self, secs: u32) -> Self {
+        self.defer_accept_secs = secs;
+        self
+    }
+
+    /// Set the length of the TCP Fast Open queue, `0` for off
+    pub fn with_fastopen_queue(mut self, queue: u32) -> Self {
+        self.fastopen_queue = queue;
+        self
+    }
+
+    /// Set whether IPv6 listeners take IPv6 clients only
+    pub fn with_ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.ipv6_only = Some(ipv6_only);
+        self
+    }
+
+    /// Set the `SO_LINGER` seconds of accepted connections
+    pub fn with_linger_secs(mut self, secs: u32) -> Self {
+        self.linger_secs = Some(secs);
+        self
+    }
+
+    /// Set whether connections closed for a bad request or a limit are reset
+    pub fn with_abortive_close(mut self, abortive_close: bool) -> Self {
+        self.abortive_close = abortive_close;
+        self
+    }
+
+    /// Turn on TCP keepalive probes after `idle_secs` of silence, sent every
+    /// `interval_secs` up to `retries` times, `0` keeps the OS default for the
+    /// last two
+    pub fn with_tcp_keepalive(mut self, idle_secs: u32, interval_secs: u32, retries: u32) -> Self {
+        self.tcp_keepalive_idle_secs = idle_secs;
+        self.tcp_keepalive_interval_secs = interval_secs;
+        self.tcp_keepalive_retries = retries;
+        self
+    }
+
+    /// Set how long a keep-alive connection waits for its next request, `0`
+    /// for no limit
+    pub fn with_keep_alive_timeout_secs(mut self, secs: u32) -> Self {
+        self.keep_alive_timeout_secs = secs;
+        self
+    }
+
+    /// Set the requests served on one connection, `0` for no limit
+    pub fn with_max_requests_per_conn(mut self, max: usize) -> Self {
+        self.max_requests_per_conn = max;
+        self
+    }
+
+    /// Set whether idle connections are closed when the process runs out of fds
+    pub fn with_evict_idle(mut self, evict_idle: bool) -> Self {
+        self.evict_idle = evict_idle;
+        self
+    }
+
+    /// Set the `log` level for requests that fail to parse
+    pub fn with_parse_error_log(mut self, level: LevelFilter) -> Self {
+        self.parse_error_log = level;
+        self
+    }
+
+    /// Set whether parse errors are logged with a hint how to avoid them
+    pub fn with_parse_error_hints(mut self, hints: bool) -> Self {
+        self.parse_error_hints = hints;
+        self
+    }
+
+    /// Set a hook called for each request that fails to parse
+    pub fn with_parse_error_hook(mut self, hook: ParseErrorHook) -> Self {
+        self.on_parse_error = Some(hook);
+        self
+    }
+
+    /// Set a hook called for accept failures and connection errors
+    pub fn with_connection_error_hook(mut self, hook: ConnectionErrorHook) -> Self {
+        self.on_connection_error = Some(hook);
+        self
+    }
+
+    /// Set a hook called after each request with its status and timing
+    pub fn with_request_complete_hook(mut self, hook: RequestCompleteHook) -> Self {
+        self.on_request_complete = Some(hook);
+        self
+    }
+
+    /// Set the counters the server keeps up to date
+    pub fn with_stats(mut self, stats: Arc<ServerStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Set the drain state the connections follow
+    pub fn with_drain(mut self, drain: Arc<Drain>) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    /// Set the client networks the listener accepts connections from
+    #[cfg(feature = "ip-filter")]
+    pub fn with_ip_filter(mut self, ip_filter: Arc<IpFilter>) -> Self {
+        self.ip_filter = Some(ip_filter);
+        self
+    }
+}
+
+fn env_var(name: &str) -> io::Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(v) => Ok(Some(v)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name}: {e}"),
+        )),
+    }
+}
+
+fn parse_bool(name: &str, v: &str) -> io::Result<bool> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name}: invalid boolean value: {v:?}"),
+        )),
+    }
+}
+
+fn parse_usize(name: &str, v: &str) -> io::Result<usize> {
+    v.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name}: invalid number: {v:?}"),
+        )
+    })
 }
 
+fn parse_u32(name: &str, v: &str) -> io::Result<u32> {
+    v.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name}: invalid number: {v:?}"),
+        )
+    })
+}
+
+fn parse_u64(name: &str, v: &str) -> io::Result<u64> {
+    v.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name}: invalid number: {v:?}"),
+        )
+    })
+}
+
+/// The comma separated addresses of `MINIHTTP_LISTEN`, empty when unset
+pub(crate) fn listen_from_env() -> io::Result<Vec<String>> {
+    let Some(v) = env_var("MINIHTTP_LISTEN")? else {
+        return Ok(Vec::new());
+    };
+    Ok(v.split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Server settings loaded from a TOML file
+///
+/// The file holds the [`HttpConfig`] fields at the top level plus the list
+/// of addresses to listen on:
+///
+/// ```toml
+/// listen = ["0.0.0.0:8080", "[::]:8080"]
+/// ipv6_only = true        # so both can bind port 8080
+/// max_headers = "large"   # or a header count, e.g. 96
+/// batch_writes = true
+/// write_high_watermark = 262144
+/// max_conn_memory = 4194304
+/// buf_initial_capacity = 4096
+/// buf_grow_by = 8192
+/// buf_growth = "doubling"
+/// buf_max_capacity = 1048576
+/// max_body = 10485760
+/// max_in_flight = 256
+/// header_validation = "sanitize"
+/// max_accept_backoff_ms = 250
+/// backlog = 4096
+/// defer_accept_secs = 5
+/// fastopen_queue = 256
+/// linger_secs = 5
+/// abortive_close = true
+/// tcp_keepalive_idle_secs = 60
+/// tcp_keepalive_interval_secs = 10
+/// tcp_keepalive_retries = 5
+/// keep_alive_timeout_secs = 5
+/// max_requests_per_conn = 1000
+/// evict_idle = true
+/// parse_error_log = "debug"
+/// parse_error_hints = true
+/// ```
+///
+/// Unknown keys, e.g. a misspelled setting, are an error.
+#[cfg(feature = "config-file")]
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(try_from = "toml::Table")]
+pub struct ServerConfig {
+    /// Addresses to listen on
+    pub listen: Vec<String>,
+    /// HTTP settings
+    pub http: HttpConfig,
+}
+
+// not `#[serde(flatten)]`, which hands `HttpConfig` the keys it knows only,
+// so it could never see a misspelled one
+#[cfg(feature = "config-file")]
+impl TryFrom<toml::Table> for ServerConfig {
+    type Error = toml::de::Error;
+
+    fn try_from(mut table: toml::Table) -> Result<Self, Self::Error> {
+        let listen = match table.remove("listen") {
+            Some(listen) => listen.try_into()?,
+            None => Vec::new(),
+        };
+        let http = toml::Value::Table(table).try_into()?;
+        Ok(ServerConfig { listen, http })
+    }
+}
+
+#[cfg(feature = "config-file")]
+impl ServerConfig {
+    /// Parse a TOML document
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the document is not valid.
+    pub fn from_toml(s: &str) -> io::Result<Self> {
+        toml::from_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Read and parse a TOML config file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or is not valid.
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// `MaxHeaders` accepts the same names and counts as `MINIHTTP_MAX_HEADERS`
+#[cfg(feature = "config-file")]
+impl<'de> serde::Deserialize<'de> for MaxHeaders {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Count(usize),
+            Name(String),
+        }
+        let v = match Repr::deserialize(d)? {
+            Repr::Count(n) => n.to_string().parse(),
+            Repr::Name(s) => s.parse(),
+        };
+        v.map_err(serde::de::Error::custom)
+    }
+}
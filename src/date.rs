@@ -1,13 +1,41 @@
+//! HTTP dates for headers like `Last-Modified`, `Expires` and `If-Modified-Since`
+//!
+//! # Example
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use may_minihttp::date::HttpDate;
+//!
+//! let date = HttpDate::from(UNIX_EPOCH + Duration::from_secs(784111777));
+//! assert_eq!(date.to_string(), "Sun, 06 Nov 1994 08:49:37 GMT");
+//!
+//! // the obsolete RFC 850 and asctime formats are accepted as well
+//! for s in [
+//!     "Sun, 06 Nov 1994 08:49:37 GMT",
+//!     "Sunday, 06-Nov-94 08:49:37 GMT",
+//!     "Sun Nov  6 08:49:37 1994",
+//! ] {
+//!     assert_eq!(s.parse::<HttpDate>().unwrap(), date);
+//! }
+//! ```
+
+#[cfg(feature = "date-header")]
 use std::cell::UnsafeCell;
-use std::fmt::{self, Write};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+#[cfg(feature = "date-header")]
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "date-header")]
 use bytes::BytesMut;
+#[cfg(feature = "date-header")]
 use once_cell::sync::Lazy;
 
 // "Sun, 06 Nov 1994 08:49:37 GMT".len()
 const DATE_VALUE_LENGTH: usize = 29;
 
+#[cfg(feature = "date-header")]
 static CURRENT_DATE: Lazy<Arc<DataWrap>> = Lazy::new(|| {
     let date = Arc::new(DataWrap(UnsafeCell::new(Date::new())));
     let date_clone = date.clone();
@@ -18,9 +46,12 @@ static CURRENT_DATE: Lazy<Arc<DataWrap>> = Lazy::new(|| {
     date
 });
 
+#[cfg(feature = "date-header")]
 struct DataWrap(UnsafeCell<Date>);
+#[cfg(feature = "date-header")]
 unsafe impl Sync for DataWrap {}
 
+#[cfg(feature = "date-header")]
 #[doc(hidden)]
 #[inline]
 pub fn append_date(dst: &mut BytesMut) {
@@ -28,10 +59,81 @@ pub fn append_date(dst: &mut BytesMut) {
     dst.extend_from_slice(date.as_bytes());
 }
 
+/// the cached date value, updated every 500ms
+#[cfg(feature = "date-header")]
+#[inline]
+pub(crate) fn current() -> &'static [u8] {
+    let date = unsafe { &*CURRENT_DATE.0.get() };
+    date.as_bytes()
+}
+
+/// The current time formatted as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+///
+/// This is the cached value used for the `Date` response header, so it is
+/// cheap but may lag behind the clock by up to half a second.
+#[cfg(feature = "date-header")]
+pub fn now_http_date() -> String {
+    String::from_utf8_lossy(current()).into_owned()
+}
+
+/// A point in time with the one second precision of HTTP dates
+///
+/// Formats as the RFC 7231 IMF-fixdate and parses all three formats an HTTP
+/// recipient must accept: IMF-fixdate, RFC 850 and asctime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate(httpdate::HttpDate);
+
+impl HttpDate {
+    /// The current time
+    pub fn now() -> Self {
+        HttpDate::from(SystemTime::now())
+    }
+
+    /// the IMF-fixdate form, without allocating
+    pub(crate) fn to_bytes(self) -> [u8; DATE_VALUE_LENGTH] {
+        imf_fixdate(SystemTime::from(self))
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    /// Sub-second precision is dropped
+    fn from(t: SystemTime) -> Self {
+        HttpDate(t.into())
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(d: HttpDate) -> Self {
+        d.0.into()
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s.trim().parse() {
+            Ok(d) => Ok(HttpDate(d)),
+            Err(_) => {
+                let msg = format!("invalid http date: {s:?}");
+                Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "date-header")]
 struct Date {
     bytes: [u8; DATE_VALUE_LENGTH],
 }
 
+#[cfg(feature = "date-header")]
 impl Date {
     fn new() -> Date {
         let mut date = Date {
@@ -47,15 +149,56 @@ impl Date {
     }
 
     fn update(&mut self) {
-        let t = std::time::SystemTime::now();
-        let date = httpdate::HttpDate::from(t);
-        write!(self, "{date}").unwrap();
+        self.bytes = imf_fixdate(SystemTime::now());
     }
 }
 
-impl fmt::Write for Date {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.bytes.copy_from_slice(s.as_bytes());
-        Ok(())
+/// format `t` as `Sun, 06 Nov 1994 08:49:37 GMT`, writing the digits
+/// directly instead of going through `core::fmt`
+fn imf_fixdate(t: SystemTime) -> [u8; DATE_VALUE_LENGTH] {
+    const WEEKDAYS: [&[u8; 3]; 7] = [b"Sun", b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat"];
+    const MONTHS: [&[u8; 3]; 12] = [
+        b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun", b"Jul", b"Aug", b"Sep", b"Oct", b"Nov",
+        b"Dec",
+    ];
+    let secs = t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+
+    let mut buf = *b"Sun, 00 Jan 0000 00:00:00 GMT";
+    // 1970-01-01 was a Thursday
+    buf[..3].copy_from_slice(WEEKDAYS[((days + 4) % 7) as usize]);
+    put_digits(&mut buf[5..7], day);
+    buf[8..11].copy_from_slice(MONTHS[month as usize - 1]);
+    put_digits(&mut buf[12..16], year);
+    put_digits(&mut buf[17..19], secs_of_day / 3600);
+    put_digits(&mut buf[20..22], secs_of_day / 60 % 60);
+    put_digits(&mut buf[23..25], secs_of_day % 60);
+    buf
+}
+
+/// the year, month and day of the `days`th day after 1970-01-01, from
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // counted from 0000-03-01, so the leap day ends the year
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// the last digits of `n`, zero padded to fill `dst`
+fn put_digits(dst: &mut [u8], mut n: u64) {
+    for b in dst.iter_mut().rev() {
+        *b = b'0' + (n % 10) as u8;
+        n /= 10;
     }
 }
@@ -0,0 +1,149 @@
+//! request body digest checks on top of any `HttpService`
+
+use std::io;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::http_server::HttpService;
+use crate::request::{OwnedRequest, Request};
+use crate::response::Response;
+
+/// `HttpService` wrapper that checks request bodies against their digest
+///
+/// The body is read into memory and compared with every `sha-256` and
+/// `sha-512` digest in the `Content-Digest` (RFC 9530) and `Digest`
+/// (RFC 3230) headers. On a mismatch, or a digest that can't be decoded, the
+/// client gets a `400 Bad Request` and the inner service is not called, so it
+/// never acts on a tampered body. Other algorithms are ignored. Requests
+/// without a digest are passed on unless [`require_digest`](Self::require_digest)
+/// is set.
+///
+/// Request bodies over [`max_body`](Self::max_body) bytes get a `413 Payload
+/// Too Large`.
+///
+/// # Example
+/// ```no_run
+/// use std::io::Read;
+/// use may_minihttp::{DigestService, HttpServer, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Webhook;
+///
+/// impl HttpService for Webhook {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         let mut event = Vec::new();
+///         req.body().read_to_end(&mut event)?;
+///         // the body matches its digest here
+///         rsp.status_code(204, "No Content");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = DigestService::new(Webhook).require_digest();
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct DigestService<S> {
+    inner: S,
+    max_body: usize,
+    required: bool,
+}
+
+impl<S> DigestService<S> {
+    /// Wrap `inner`, checking the digests of the requests that carry one
+    pub fn new(inner: S) -> Self {
+        DigestService {
+            inner,
+            max_body: 1024 * 1024,
+            required: false,
+        }
+    }
+
+    /// Set the largest request body read into memory, default is 1MB
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    /// Answer `400` to requests without a `sha-256` or `sha-512` digest too
+    pub fn require_digest(mut self) -> Self {
+        self.required = true;
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("sha-256") {
+            Some(Algorithm::Sha256)
+        } else if name.eq_ignore_ascii_case("sha-512") {
+            Some(Algorithm::Sha512)
+        } else {
+            None
+        }
+    }
+
+    fn matches(self, body: &[u8], digest: &[u8]) -> bool {
+        match self {
+            Algorithm::Sha256 => Sha256::digest(body)[..] == *digest,
+            Algorithm::Sha512 => Sha512::digest(body)[..] == *digest,
+        }
+    }
+}
+
+/// the supported digests claimed for the body, `None` if one can't be decoded
+fn claimed_digests(req: &OwnedRequest) -> Option<Vec<(Algorithm, Vec<u8>)>> {
+    let mut digests = Vec::new();
+    for (name, value) in req.headers() {
+        // Content-Digest wraps the base64 in colons, Digest does not
+        let structured = name.eq_ignore_ascii_case("content-digest");
+        if !structured && !name.eq_ignore_ascii_case("digest") {
+            continue;
+        }
+        let value = std::str::from_utf8(value).ok()?;
+        for item in value.split(',') {
+            let (alg, encoded) = item.trim().split_once('=')?;
+            let alg = match Algorithm::from_name(alg) {
+                Some(alg) => alg,
+                None => continue,
+            };
+            let encoded = if structured {
+                encoded.strip_prefix(':')?.strip_suffix(':')?
+            } else {
+                encoded
+            };
+            digests.push((alg, STANDARD.decode(encoded).ok()?));
+        }
+    }
+    Some(digests)
+}
+
+impl<S: HttpService> HttpService for DigestService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let req = req.into_owned(self.max_body)?;
+        let verified = match claimed_digests(&req) {
+            Some(digests) if digests.is_empty() => !self.required,
+            Some(digests) => digests
+                .iter()
+                .all(|(alg, digest)| alg.matches(req.body(), digest)),
+            None => false,
+        };
+        if !verified {
+            debug!("{} {} body digest mismatch", req.method(), req.path());
+            rsp.status_code(400, "Bad Request");
+            return Ok(());
+        }
+        // hand the checked body to the inner service
+        let mut headers = req.header_slots();
+        self.inner.call(req.as_request(&mut headers), rsp)
+    }
+}
@@ -0,0 +1,395 @@
+//! running each request on a coroutine of its own
+
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+
+use bytes::{BufMut, BytesMut};
+use may::net::TcpStream;
+use may::sync::{mpsc, Semphore};
+use may::{coroutine, go};
+
+use crate::config::HttpConfig;
+use crate::date::HttpDate;
+use crate::error::HttpError;
+use crate::http_server::{AcceptBackoff, ConnConfig, HttpService, Started, HEAP_HEADERS};
+use crate::listener;
+use crate::request::{self, OwnedRequest};
+use crate::response::{self, Response};
+use crate::server_handle::ServerHandle;
+
+/// what the service answered, sent back from its coroutine
+pub(crate) struct Answer {
+    code: usize,
+    msg: Cow<'static, str>,
+    headers: Vec<&'static str>,
+    owned_headers: Vec<String>,
+    last_modified: Option<HttpDate>,
+    body: Vec<u8>,
+}
+
+impl Answer {
+    /// the `413` of a body over the limit, the connection closes after it
+    fn too_large() -> Self {
+        Answer {
+            code: 413,
+            msg: Cow::Borrowed("Payload Too Large"),
+            headers: Vec::new(),
+            owned_headers: Vec::new(),
+            last_modified: None,
+            body: Vec::new(),
+        }
+    }
+
+    pub(crate) fn apply(self, rsp: &mut Response) {
+        rsp.status_with_reason(self.code, self.msg);
+        for h in self.headers {
+            rsp.header(h);
+        }
+        for h in self.owned_headers {
+            rsp.header_owned(h);
+        }
+        if let Some(date) = self.last_modified {
+            rsp.last_modified(date.into());
+        }
+        rsp.body_vec(self.body);
+    }
+}
+
+fn call_owned<S: HttpService>(inner: &mut S, req: &OwnedRequest) -> io::Result<Answer> {
+    let mut headers = req.header_slots();
+    let req = req.as_request(&mut headers);
+    let mut body = BytesMut::new();
+    let mut rsp = Response::new(&mut body);
+    inner.call(req, &mut rsp)?;
+    if rsp.take_hijack().is_some() {
+        debug!("a dispatched request can't hijack the connection");
+    }
+    let code = rsp.status().0;
+    let msg = rsp.reason();
+    Ok(Answer {
+        code,
+        msg,
+        headers: rsp.headers().to_vec(),
+        owned_headers: rsp.owned_headers().to_vec(),
+        last_modified: rsp.get_last_modified(),
+        body: rsp.get_body().to_vec(),
+    })
+}
+
+/// run `service` for `req` on a new coroutine
+///
+/// `guard` is dropped once the service returns, or unwinds
+pub(crate) fn spawn_call<S, G>(
+    mut service: S,
+    req: OwnedRequest,
+    guard: G,
+) -> (
+    coroutine::JoinHandle<()>,
+    mpsc::Receiver<io::Result<Answer>>,
+)
+where
+    S: HttpService + Send + 'static,
+    G: Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let worker = go!(move || {
+        let ret = call_owned(&mut service, &req);
+        drop(guard);
+        let _ = tx.send(ret);
+    });
+    (worker, rx)
+}
+
+/// a taken slot of the in-flight limit, given back on drop
+struct Slot(Arc<Semphore>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.post();
+    }
+}
+
+/// HTTP server that runs each request on a coroutine of its own
+///
+/// Requests on a keep-alive or pipelined connection are parsed as they
+/// arrive and dispatched right away, so a slow request doesn't hold up the
+/// ones behind it; the responses are still sent in request order. At most
+/// [`HttpConfig::max_in_flight`] requests run at once across all the
+/// connections, when they are all taken connections wait before parsing more.
+///
+/// Each request is read into memory first, a body over
+/// [`HttpConfig::max_body`] bytes gets a `413` and closes the connection.
+/// Set it, without a limit a body of any size is read. [`Response::flush`] has no effect, the response is sent once
+/// the service returns.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpConfig, HttpServerConcurrent, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Report;
+///
+/// impl HttpService for Report {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("done");
+///         Ok(())
+///     }
+/// }
+///
+/// let config = HttpConfig::new().with_max_in_flight(256);
+/// let server = HttpServerConcurrent::new(Report)
+///     .start_with_config("0.0.0.0:8080", config)
+///     .unwrap();
+/// server.wait().unwrap();
+/// ```
+pub struct HttpServerConcurrent<T> {
+    service: T,
+}
+
+impl<T: HttpService + Clone + Send + Sync + 'static> HttpServerConcurrent<T> {
+    /// Create a server for `service`, each request runs on a clone of it
+    pub fn new(service: T) -> Self {
+        HttpServerConcurrent { service }
+    }
+
+    /// Spawns the http service, binding to the given address
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        self.start_with_config(addr, HttpConfig::default())
+    }
+
+    /// Spawns the http service with the given configuration, binding to the given address
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    pub fn start_with_config<L: ToSocketAddrs>(
+        self,
+        addr: L,
+        config: HttpConfig,
+    ) -> io::Result<ServerHandle> {
+        let listener = listener::bind(addr, &config)?;
+        let conn = ConnConfig::new(&config);
+        let slots = Arc::new(Semphore::new(config.max_in_flight.max(1)));
+        ServerHandle::spawn(
+            "TcpServerConcurrent",
+            listener,
+            conn,
+            move |listener, state| {
+                let mut backoff = AcceptBackoff::new(&state.conn());
+                state.set_ready();
+                for stream in listener.incoming() {
+                    let mut stream = match stream {
+                        Ok(s) => s,
+                        Err(e) => {
+                            state.conn().report_accept_error(&e);
+                            backoff.wait(&e);
+                            continue;
+                        }
+                    };
+                    backoff.reset();
+                    let conn = state.conn();
+                    if !conn.accepts(|| stream.peer_addr().ok()) {
+                        continue;
+                    }
+                    conn.set_options(&stream);
+                    let service = self.service.clone();
+                    let slots = slots.clone();
+                    go!(move || if let Err(e) =
+                        dispatch_connection(&mut stream, service, &conn, slots)
+                    {
+                        conn.report_error(&e, stream.peer_addr().ok());
+                        conn.close_after_error(&stream, &e);
+                    });
+                }
+            },
+        )
+    }
+}
+
+/// an answer to come, with the request for the request complete hook,
+/// whether the connection stays open after it and the requests answered
+/// before it
+type Pending = (
+    mpsc::Receiver<io::Result<Answer>>,
+    Option<Started>,
+    bool,
+    usize,
+);
+
+/// parse and dispatch on this coroutine, write the responses from another
+fn dispatch_connection<T: HttpService + Clone + Send + 'static>(
+    stream: &mut TcpStream,
+    service: T,
+    conn: &Arc<ConnConfig>,
+    slots: Arc<Semphore>,
+) -> io::Result<()> {
+    let (order_tx, order_rx) = mpsc::channel();
+    let writer_stream = stream.try_clone()?;
+    let writer_conn = Arc::clone(conn);
+    let writer = go!(move || write_in_order(writer_stream, order_rx, writer_conn));
+
+    let ret = match conn.max_headers {
+        0..=16 => dispatch_loop::<T, 16>(stream, service, conn, &slots, order_tx),
+        17..=32 => dispatch_loop::<T, 32>(stream, service, conn, &slots, order_tx),
+        33..=64 => dispatch_loop::<T, 64>(stream, service, conn, &slots, order_tx),
+        65..=128 => dispatch_loop::<T, 128>(stream, service, conn, &slots, order_tx),
+        _ => dispatch_loop::<T, HEAP_HEADERS>(stream, service, conn, &slots, order_tx),
+    };
+    // the writer ends once the dispatched responses are out
+    let written = writer
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("response writer panicked")));
+    ret.and(written)
+}
+
+fn dispatch_loop<T: HttpService + Clone + Send + 'static, const N: usize>(
+    stream: &mut TcpStream,
+    service: T,
+    conn: &ConnConfig,
+    slots: &Arc<Semphore>,
+    order: mpsc::Sender<Pending>,
+) -> io::Result<()> {
+    let mut stats = conn.open_stats(|| stream.peer_addr().ok());
+    let mut stream = Counted { stream, conn };
+    let max_headers = conn.max_headers;
+    // the part of req_buf already searched for the end of the headers
+    let mut scanned = 0;
+    // requests answered on the connection
+    let mut served = 0;
+    let mut req_buf = conn.bufs.alloc();
+    loop {
+        conn.bufs.reserve_read(&mut req_buf)?;
+        let read_buf: &mut [u8] = unsafe { std::mem::transmute(&mut *req_buf.chunk_mut()) };
+        let read_cnt = stream.read(read_buf)?;
+        if read_cnt == 0 {
+            //connection was closed
+            return Ok(());
+        }
+        unsafe { req_buf.advance_mut(read_cnt) };
+
+        loop {
+            let mut stack = [MaybeUninit::uninit(); N];
+            // a stack buffer that large would not fit small coroutine stacks
+            let mut heap = Vec::new();
+            let headers = match N {
+                HEAP_HEADERS => {
+                    heap.resize(max_headers, MaybeUninit::uninit());
+                    &mut heap[..]
+                }
+                _ => &mut stack[..max_headers],
+            };
+            let req = match request::decode(headers, &mut req_buf, &mut stream, &mut scanned)? {
+                Some(req) => req,
+                None => break,
+            };
+            let keep_alive = conn.keeps_alive(req.is_keep_alive(), served);
+            let started = conn.request_started(&req);
+            let answer = match req.into_owned(conn.body_limit()) {
+                Ok(req) => {
+                    slots.wait();
+                    let slot = Slot(slots.clone());
+                    spawn_call(service.clone(), req, slot).1
+                }
+                Err(e) => {
+                    // the unread body makes the rest of the stream unusable
+                    let answer = match HttpError::from_io(&e) {
+                        Some(HttpError::BodyTooLarge { .. }) => Ok(Answer::too_large()),
+                        _ => Err(e),
+                    };
+                    let (tx, rx) = mpsc::channel();
+                    let _ = tx.send(answer);
+                    let _ = order.send((rx, started, false, served));
+                    return Ok(());
+                }
+            };
+            if order.send((answer, started, keep_alive, served)).is_err() {
+                // the writer failed, it has the error
+                return Ok(());
+            }
+            served += 1;
+            stats.served(keep_alive);
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+        conn.bufs.shrink(&mut req_buf);
+        conn.check_memory(&[&req_buf])?;
+    }
+}
+
+/// the connection as read by the dispatcher, counting the bytes
+struct Counted<'a> {
+    stream: &'a mut TcpStream,
+    conn: &'a ConnConfig,
+}
+
+impl Read for Counted<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stream.read(buf)?;
+        self.conn.count_read(n);
+        Ok(n)
+    }
+}
+
+fn write_in_order(
+    mut stream: TcpStream,
+    order: mpsc::Receiver<Pending>,
+    conn: Arc<ConnConfig>,
+) -> io::Result<()> {
+    let mut rsp_buf = conn.bufs.alloc();
+    let mut body_buf = conn.bufs.alloc();
+    loop {
+        let (pending, started, keep_alive, served) = match order.try_recv() {
+            Ok(pending) => pending,
+            Err(mpsc::TryRecvError::Empty) => {
+                // nothing else dispatched yet, send what is done
+                write_out(&mut stream, &mut rsp_buf, &conn)?;
+                match order.recv() {
+                    Ok(pending) => pending,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                return write_out(&mut stream, &mut rsp_buf, &conn)
+            }
+        };
+        let answer = match pending.try_recv() {
+            Ok(answer) => answer,
+            Err(_) => {
+                // don't hold back the finished responses while waiting
+                write_out(&mut stream, &mut rsp_buf, &conn)?;
+                pending
+                    .recv()
+                    .unwrap_or_else(|_| Err(io::Error::other("service panicked")))
+            }
+        };
+        conn.bufs.reserve(&mut rsp_buf);
+        let mut rsp = Response::new(&mut body_buf);
+        let ret = answer.and_then(|answer| {
+            answer.apply(&mut rsp);
+            rsp.check_headers(conn.header_validation)
+        });
+        conn.request_completed(started, &ret, &rsp);
+        let lines = conn.conn_lines(keep_alive, served);
+        match ret {
+            Ok(()) => response::encode_with(&rsp, lines, &mut rsp_buf),
+            Err(e) => response::encode_error(&e, lines, &mut rsp_buf),
+        }
+        drop(rsp);
+        conn.bufs.shrink(&mut body_buf);
+        if conn.over_memory(&[&rsp_buf, &body_buf]) {
+            write_out(&mut stream, &mut rsp_buf, &conn)?;
+        }
+    }
+}
+
+fn write_out(stream: &mut TcpStream, rsp_buf: &mut BytesMut, conn: &ConnConfig) -> io::Result<()> {
+    if !rsp_buf.is_empty() {
+        stream.write_all(rsp_buf)?;
+        conn.count_written(rsp_buf.len());
+        rsp_buf.clear();
+        conn.bufs.shrink(rsp_buf);
+    }
+    Ok(())
+}
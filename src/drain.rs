@@ -0,0 +1,66 @@
+//! taking a server out of a load balancer pool before it stops
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Drain state shared by servers and their readiness endpoints
+///
+/// Pass one to [`HttpConfig::with_drain`](crate::HttpConfig::with_drain)
+/// and call [`ServerHandle::begin_drain`](crate::ServerHandle::begin_drain)
+/// when the instance is about to go away. From then on every response gets
+/// `Connection: close` and the connection is closed after it, and the
+/// readiness endpoint should answer `503` so the load balancer stops sending
+/// new clients. Several servers may share one.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use may_minihttp::{
+///     Drain, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+/// };
+///
+/// #[derive(Clone)]
+/// struct App(Arc<Drain>);
+///
+/// impl HttpService for App {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         match req.path() {
+///             "/ready" if self.0.is_draining() => {
+///                 rsp.status_code(503, "Service Unavailable");
+///             }
+///             "/ready" => rsp.body("ready"),
+///             _ => rsp.body("hello"),
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let drain = Arc::new(Drain::new());
+/// let config = HttpConfig::new().with_drain(drain.clone());
+/// let server = HttpServer(App(drain)).start_with_config("0.0.0.0:8080", config).unwrap();
+/// // on SIGTERM: fail the health checks for a while, then stop accepting
+/// server.begin_drain(Duration::from_secs(10));
+/// server.wait().unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct Drain {
+    draining: AtomicBool,
+}
+
+impl Drain {
+    /// Create a drain state that is not draining, usable in a `static`
+    pub const fn new() -> Self {
+        Drain {
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether a drain has begun, readiness endpoints should fail from then on
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn begin(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+}
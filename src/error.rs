@@ -0,0 +1,166 @@
+//! errors reported for malformed requests
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::request::Charset;
+
+/// Why a request could not be decoded or read
+///
+/// The connection loop reports it through `log` and the optional
+/// [`HttpConfig::on_parse_error`](crate::HttpConfig::on_parse_error) hook.
+/// It is also the source of the `io::Error` returned by the `decode_*`
+/// functions, see [`HttpError::from_io`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HttpError {
+    /// The request has more header lines than the configured limit
+    TooManyHeaders {
+        /// header lines in the request
+        received: usize,
+        /// the configured limit
+        limit: usize,
+    },
+    /// The request is not valid HTTP/1.x
+    Parse(httparse::Error),
+    /// A `Content-Length` header is not a decimal number, or repeated
+    /// `Content-Length` headers disagree
+    InvalidContentLength,
+    /// The request body is bigger than the caller allows
+    BodyTooLarge {
+        /// the `Content-Length` of the request
+        length: usize,
+        /// the limit passed by the caller
+        limit: usize,
+    },
+    /// The connection buffers grew over
+    /// [`HttpConfig::max_conn_memory`](crate::HttpConfig::max_conn_memory),
+    /// or a request head over
+    /// [`HttpConfig::buf_max_capacity`](crate::HttpConfig::buf_max_capacity)
+    MemoryLimit {
+        /// bytes held by the buffers
+        held: usize,
+        /// the configured limit
+        limit: usize,
+    },
+    /// The `Content-Type` charset is not one
+    /// [`Request::body_text`](crate::Request::body_text) decodes
+    UnsupportedCharset,
+    /// The body is not valid text in its charset
+    InvalidText {
+        /// the charset of the body
+        charset: Charset,
+        /// the length of the valid text before the first invalid byte
+        valid_up_to: usize,
+    },
+}
+
+impl HttpError {
+    /// classify a parse failure of `buf`, counting the headers if needed
+    pub(crate) fn new(e: httparse::Error, buf: &[u8], limit: usize) -> Self {
+        if e != httparse::Error::TooManyHeaders {
+            return HttpError::Parse(e);
+        }
+        // count the header lines of this request only, not the body or the
+        // pipelined requests after it
+        let head = match memchr::memmem::find(buf, b"\r\n\r\n") {
+            Some(end) => &buf[..end + 2],
+            None => buf,
+        };
+        let received = memchr::memchr_iter(b'\n', head).count().saturating_sub(1);
+        HttpError::TooManyHeaders { received, limit }
+    }
+
+    /// how to avoid the error, see
+    /// [`HttpConfig::parse_error_hints`](crate::HttpConfig::parse_error_hints)
+    pub(crate) fn hint(&self) -> Option<Hint> {
+        match *self {
+            HttpError::TooManyHeaders { received, .. } => Some(Hint::MaxHeaders(received)),
+            HttpError::MemoryLimit { .. } => Some(Hint::MemoryLimit),
+            _ => None,
+        }
+    }
+
+    /// The `HttpError` behind an `io::Error` returned while decoding, if any
+    pub fn from_io(e: &io::Error) -> Option<&HttpError> {
+        e.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpError::TooManyHeaders { received, limit } => {
+                let over_by = received.saturating_sub(limit);
+                write!(
+                    f,
+                    "TooManyHeaders: received {received} headers, limit is {limit} (over by {over_by})"
+                )
+            }
+            HttpError::Parse(e) => write!(f, "failed to parse http request: {e:?}"),
+            HttpError::InvalidContentLength => f.write_str("invalid Content-Length header"),
+            HttpError::BodyTooLarge { length, limit } => {
+                write!(f, "body length {length} exceeds the limit {limit}")
+            }
+            HttpError::MemoryLimit { held, limit } => {
+                write!(
+                    f,
+                    "connection buffers hold {held} bytes, over the limit {limit}"
+                )
+            }
+            HttpError::UnsupportedCharset => f.write_str("unsupported charset of the body"),
+            HttpError::InvalidText {
+                charset,
+                valid_up_to,
+            } => write!(f, "invalid {charset} in the body at byte {valid_up_to}"),
+        }
+    }
+}
+
+impl Error for HttpError {}
+
+/// a hint logged with a parse error, formatted only when it is logged
+pub(crate) enum Hint {
+    MaxHeaders(usize),
+    MemoryLimit,
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hint::MaxHeaders(received) if received > 256 => {
+                write!(
+                    f,
+                    "hint: at most 256 headers are accepted, the request has {received}"
+                )
+            }
+            Hint::MaxHeaders(received) if received > 128 => {
+                write!(
+                    f,
+                    "hint: HttpConfig::with_max_headers(MaxHeaders::Custom({received})) accepts {received} headers"
+                )
+            }
+            Hint::MaxHeaders(received) => {
+                let fits = match received {
+                    0..=32 => "MaxHeaders::Standard (32)",
+                    33..=64 => "MaxHeaders::Large (64)",
+                    _ => "MaxHeaders::XLarge (128)",
+                };
+                write!(
+                    f,
+                    "hint: HttpConfig::with_max_headers({fits}) accepts {received} headers"
+                )
+            }
+            Hint::MemoryLimit => f.write_str(
+                "hint: raise HttpConfig::max_conn_memory or buf_max_capacity for larger requests",
+            ),
+        }
+    }
+}
+
+impl From<HttpError> for io::Error {
+    fn from(e: HttpError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
@@ -0,0 +1,170 @@
+//! closing idle keep-alive connections when the process runs out of fds or
+//! after the keep-alive timeout
+
+use std::collections::BTreeMap;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::net::Shutdown;
+use std::os::fd::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+/// the connections that may be closed while idle, across all servers as
+/// they share the fd limit of the process
+static TRACKED: Mutex<BTreeMap<u64, Arc<Tracked>>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+// `Tracked::idle_since` while serving and after an eviction, other values
+// are the milliseconds since `START` plus one
+const BUSY: u64 = 0;
+const EVICTED: u64 = u64::MAX;
+
+struct Tracked {
+    fd: RawFd,
+    idle_since: AtomicU64,
+    // may be closed for lack of fds
+    evictable: bool,
+    // idle milliseconds before the sweeper closes it, 0 for never
+    timeout_ms: u64,
+}
+
+/// A connection the accept loop or the sweeper may close while it waits for
+/// a request
+///
+/// Does nothing unless [`HttpConfig::evict_idle`](crate::HttpConfig::evict_idle)
+/// or [`HttpConfig::keep_alive_timeout_secs`](crate::HttpConfig::keep_alive_timeout_secs)
+/// is set.
+pub(crate) struct IdleTracker(Option<(u64, Arc<Tracked>)>);
+
+impl IdleTracker {
+    pub(crate) fn new(evictable: bool, timeout: Option<Duration>, fd: RawFd) -> Self {
+        if !evictable && timeout.is_none() {
+            return IdleTracker(None);
+        }
+        let timeout_ms = timeout.map_or(0, |t| t.as_millis() as u64);
+        if timeout_ms != 0 {
+            Lazy::force(&SWEEPER);
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let tracked = Arc::new(Tracked {
+            fd,
+            idle_since: AtomicU64::new(BUSY),
+            evictable,
+            timeout_ms,
+        });
+        lock().insert(id, tracked.clone());
+        IdleTracker(Some((id, tracked)))
+    }
+
+    /// the connection waits for the next request
+    pub(crate) fn idle(&self) {
+        if let Some((_, tracked)) = &self.0 {
+            let now = START.elapsed().as_millis() as u64 + 1;
+            let _ = tracked.idle_since.compare_exchange(
+                BUSY,
+                now.min(EVICTED - 1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        }
+    }
+
+    /// whether the accept loop shut the connection down while it was idle
+    pub(crate) fn is_evicted(&self) -> bool {
+        match &self.0 {
+            Some((_, tracked)) => tracked.idle_since.load(Ordering::Acquire) == EVICTED,
+            None => false,
+        }
+    }
+
+    /// the connection got data to serve, `false` if it was evicted meanwhile
+    pub(crate) fn busy(&self) -> bool {
+        match &self.0 {
+            Some((_, tracked)) => tracked.idle_since.swap(BUSY, Ordering::AcqRel) != EVICTED,
+            None => true,
+        }
+    }
+}
+
+impl Drop for IdleTracker {
+    fn drop(&mut self) {
+        // runs before the stream closes, so an fd is never shut down once reused
+        if let Some((id, _)) = &self.0 {
+            lock().remove(id);
+        }
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, BTreeMap<u64, Arc<Tracked>>> {
+    TRACKED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// whether a failed accept ran out of file descriptors
+pub(crate) fn out_of_fds(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENFILE | libc::EMFILE))
+}
+
+/// shut down the connection idle for the longest time, `false` if none is
+///
+/// the connection wakes up, sees it was evicted and closes its fd
+pub(crate) fn evict_idle() -> bool {
+    let tracked = lock();
+    loop {
+        let oldest = tracked
+            .values()
+            .filter(|t| t.evictable)
+            .map(|t| (t.idle_since.load(Ordering::Acquire), t))
+            .filter(|(since, _)| *since != BUSY && *since != EVICTED)
+            .min_by_key(|(since, _)| *since);
+        let Some((since, oldest)) = oldest else {
+            return false;
+        };
+        if oldest.shut_down(since) {
+            return true;
+        }
+    }
+}
+
+/// closes the connections idle past their keep-alive timeout, checking
+/// twice a second
+static SWEEPER: Lazy<()> = Lazy::new(|| {
+    may::go!(|| loop {
+        may::coroutine::sleep(Duration::from_millis(500));
+        let now = START.elapsed().as_millis() as u64 + 1;
+        for tracked in lock().values() {
+            let since = tracked.idle_since.load(Ordering::Acquire);
+            if tracked.timeout_ms != 0
+                && since != BUSY
+                && since != EVICTED
+                && now.saturating_sub(since) >= tracked.timeout_ms
+            {
+                tracked.shut_down(since);
+            }
+        }
+    });
+});
+
+impl Tracked {
+    /// shut down the connection if it is still idle since `since`, called
+    /// with the registry locked
+    fn shut_down(&self, since: u64) -> bool {
+        // it may just have got a request
+        if self
+            .idle_since
+            .compare_exchange(since, EVICTED, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return false;
+        }
+        // the connection is still registered, so the fd is still its own
+        let stream = ManuallyDrop::new(unsafe { std::net::TcpStream::from_raw_fd(self.fd) });
+        if let Err(e) = stream.shutdown(Shutdown::Both) {
+            debug!("can't shut down idle connection: {e}");
+        }
+        true
+    }
+}
@@ -0,0 +1,127 @@
+//! owned, case-insensitive header storage
+
+use std::fmt;
+
+/// Owned HTTP headers that outlive the request buffer
+///
+/// Header names keep their original case but are compared
+/// case-insensitively. Repeated headers are kept in arrival order.
+///
+/// # Example
+/// ```
+/// use may_minihttp::HeaderMap;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.append("Accept", "text/html");
+/// headers.append("accept", "application/json");
+///
+/// assert_eq!(headers.get_str("ACCEPT"), Some("text/html"));
+/// assert_eq!(headers.get_all("accept").count(), 2);
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl HeaderMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty map with room for `n` headers
+    pub fn with_capacity(n: usize) -> Self {
+        HeaderMap {
+            entries: Vec::with_capacity(n),
+        }
+    }
+
+    /// Copy parsed headers into an owned map
+    pub fn from_headers(headers: &[httparse::Header<'_>]) -> Self {
+        let entries = headers
+            .iter()
+            .map(|h| (h.name.to_owned(), h.value.to_vec()))
+            .collect();
+        HeaderMap { entries }
+    }
+
+    /// Number of header lines
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no headers
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if the header is present
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The value of the first header with this name
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// The value of the first header with this name, if it is valid UTF-8
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// The values of all the headers with this name
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Add a header, keeping existing ones with the same name
+    pub fn append<N: Into<String>, V: Into<Vec<u8>>>(&mut self, name: N, value: V) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// Set a header, replacing all existing ones with the same name
+    pub fn insert<N: Into<String>, V: Into<Vec<u8>>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    /// Remove all the headers with this name, returning the first value
+    pub fn remove(&mut self, name: &str) -> Option<Vec<u8>> {
+        let mut first = None;
+        let mut i = 0;
+        while i < self.entries.len() {
+            if self.entries[i].0.eq_ignore_ascii_case(name) {
+                let (_, v) = self.entries.remove(i);
+                first.get_or_insert(v);
+            } else {
+                i += 1;
+            }
+        }
+        first
+    }
+
+    /// Iterate over `(name, value)` pairs in arrival order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries.iter().map(|(n, v)| (n.as_str(), v.as_slice()))
+    }
+}
+
+impl fmt::Debug for HeaderMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.entries
+                    .iter()
+                    .map(|(n, v)| (n, String::from_utf8_lossy(v))),
+            )
+            .finish()
+    }
+}
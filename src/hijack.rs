@@ -0,0 +1,74 @@
+//! connections taken over from the http server
+
+use std::io::{self, Read, Write};
+
+use bytes::{Buf, BytesMut};
+use may::net::TcpStream;
+
+/// what `Response::hijack` runs with the connection
+pub(crate) type Handler = Box<dyn FnOnce(Hijacked)>;
+
+/// A connection the http server handed over, see [`Response::hijack`](crate::Response::hijack)
+///
+/// Reading returns the bytes the client sent after the request first, then
+/// reads from the stream. The connection is closed once this and any
+/// [`into_parts`](Self::into_parts) stream are dropped.
+pub struct Hijacked {
+    stream: TcpStream,
+    buffered: BytesMut,
+}
+
+impl Hijacked {
+    fn new(stream: TcpStream, buffered: BytesMut) -> Self {
+        Hijacked { stream, buffered }
+    }
+
+    /// The bytes read from the client but not yet returned by `read`
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffered
+    }
+
+    /// The raw stream, reading it skips the buffered bytes
+    pub fn stream(&mut self) -> &mut TcpStream {
+        &mut self.stream
+    }
+
+    /// Split into the raw stream and the bytes read ahead from it
+    pub fn into_parts(self) -> (TcpStream, BytesMut) {
+        (self.stream, self.buffered)
+    }
+}
+
+impl Read for Hijacked {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffered.is_empty() {
+            return self.stream.read(buf);
+        }
+        let n = buf.len().min(self.buffered.len());
+        buf[..n].copy_from_slice(&self.buffered[..n]);
+        self.buffered.advance(n);
+        Ok(n)
+    }
+}
+
+impl Write for Hijacked {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// a hijack waiting for a stream of its own
+pub(crate) struct Handoff {
+    pub(crate) handler: Handler,
+    pub(crate) buffered: BytesMut,
+}
+
+impl Handoff {
+    pub(crate) fn run(self, stream: TcpStream) {
+        (self.handler)(Hijacked::new(stream, self.buffered))
+    }
+}
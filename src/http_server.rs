@@ -1,15 +1,36 @@
 //! http server implementation on top of `MAY`
 
+use std::cell::RefCell;
 use std::io::{self, Read, Write};
 use std::mem::MaybeUninit;
-use std::net::ToSocketAddrs;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::config::{
+    BufGrowth, ConnectionErrorHook, HeaderValidation, HttpConfig, ParseErrorHook,
+    RequestCompleteHook,
+};
+use crate::drain::Drain;
+use crate::error::HttpError;
+#[cfg(unix)]
+use crate::evict::{self, IdleTracker};
+use crate::hijack::Handoff;
+#[cfg(feature = "ip-filter")]
+use crate::ip_filter::IpFilter;
+use crate::listener;
+use crate::privileges::RunAs;
 use crate::request::{self, Request};
-use crate::response::{self, Response};
+use crate::response::{self, ConnLines, Flush, Response};
+use crate::server_handle::ServerHandle;
+use crate::stats::{ConnStats, ServerStats};
 
 #[cfg(unix)]
 use bytes::Buf;
 use bytes::{BufMut, BytesMut};
+use log::LevelFilter;
 #[cfg(unix)]
 use may::io::WaitIo;
 use may::net::{TcpListener, TcpStream};
@@ -47,45 +68,545 @@ pub trait HttpService {
     fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()>;
 }
 
+/// information about an accepted connection
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ConnectionInfo {
+    /// the remote address of the client
+    pub peer_addr: SocketAddr,
+    /// the local address the connection was accepted on
+    pub local_addr: SocketAddr,
+    /// the connection id, same as the one passed to `new_service`
+    pub conn_id: usize,
+    /// whether the connection is TLS encrypted, always `false` for plain TCP listeners
+    pub tls: bool,
+}
+
 pub trait HttpServiceFactory: Send + Sized + 'static {
     type Service: HttpService + Send;
     // create a new http service for each connection
     fn new_service(&self, id: usize) -> Self::Service;
 
+    /// create a new http service for a connection, with details about the connection
+    ///
+    /// the default implementation calls `new_service` with `info.conn_id`
+    fn new_service_with_info(&self, info: &ConnectionInfo) -> Self::Service {
+        self.new_service(info.conn_id)
+    }
+
+    /// called once inside the accept coroutine, before the first connection is accepted
+    ///
+    /// use it to set up shared resources such as connection pools or caches
+    fn on_server_start(&self) {}
+
+    /// called once when the accept coroutine is stopped, e.g. by `ServerHandle::stop`
+    ///
+    /// use it to flush or release what `on_server_start` set up
+    fn on_server_stop(&self) {}
+
     /// Spawns the http service, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        let listener = TcpListener::bind(addr)?;
-        go!(
-            coroutine::Builder::new().name("TcpServerFac".to_owned()),
-            move || {
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
+        self.start_with_config(addr, HttpConfig::default())
+    }
+
+    /// Spawns the http service with the given configuration, binding to the given address
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    fn start_with_config<L: ToSocketAddrs>(
+        self,
+        addr: L,
+        config: HttpConfig,
+    ) -> io::Result<ServerHandle> {
+        let listener = listener::bind(addr, &config)?;
+        let conn = ConnConfig::new(&config);
+        ServerHandle::spawn("TcpServerFac", listener, conn, move |listener, state| {
+            #[cfg(unix)]
+            use std::os::fd::AsRawFd;
+            #[cfg(windows)]
+            use std::os::windows::io::AsRawSocket;
+            self.on_server_start();
+            let _stop = StopGuard(&self);
+            let mut backoff = AcceptBackoff::new(&state.conn());
+            state.set_ready();
+            loop {
+                let (mut stream, peer_addr) = match listener.accept() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        state.conn().report_accept_error(&e);
+                        backoff.wait(&e);
+                        continue;
+                    }
+                };
+                backoff.reset();
+                let conn = state.conn();
+                if !conn.accepts(|| Some(peer_addr)) {
+                    continue;
+                }
+                conn.set_options(&stream);
                 #[cfg(unix)]
-                use std::os::fd::AsRawFd;
+                let id = stream.as_raw_fd() as usize;
                 #[cfg(windows)]
-                use std::os::windows::io::AsRawSocket;
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    #[cfg(unix)]
-                    let id = stream.as_raw_fd() as usize;
-                    #[cfg(windows)]
-                    let id = stream.as_raw_socket() as usize;
-                    // t_c!(stream.set_nodelay(true));
-                    let service = self.new_service(id);
-                    let builder = may::coroutine::Builder::new().id(id);
-                    go!(
-                        builder,
-                        move || if let Err(e) = each_connection_loop(&mut stream, service) {
-                            // Only log actual errors, not normal client disconnects
-                            if !is_client_disconnect(&e) {
-                                error!("service err = {e:?}");
-                            }
-                            stream.shutdown(std::net::Shutdown::Both).ok();
-                        }
-                    )
-                    .unwrap();
+                let id = stream.as_raw_socket() as usize;
+                // t_c!(stream.set_nodelay(true));
+                let info = ConnectionInfo {
+                    peer_addr,
+                    local_addr: t_c!(stream.local_addr()),
+                    conn_id: id,
+                    tls: false,
+                };
+                let service = self.new_service_with_info(&info);
+                let builder = may::coroutine::Builder::new().id(id);
+                go!(
+                    builder,
+                    move || if let Err(e) = serve_connection(&mut stream, service, &conn) {
+                        conn.report_error(&e, Some(peer_addr));
+                        conn.close_after_error(&stream, &e);
+                    }
+                )
+                .unwrap();
+            }
+        })
+    }
+}
+
+/// calls `on_server_stop` when the accept loop unwinds
+struct StopGuard<'a, F: HttpServiceFactory>(&'a F);
+
+impl<F: HttpServiceFactory> Drop for StopGuard<'_, F> {
+    fn drop(&mut self) {
+        self.0.on_server_stop();
+    }
+}
+
+/// share one factory between several listeners
+impl<F: HttpServiceFactory + Sync> HttpServiceFactory for Arc<F> {
+    type Service = F::Service;
+
+    fn new_service(&self, id: usize) -> Self::Service {
+        (**self).new_service(id)
+    }
+
+    fn new_service_with_info(&self, info: &ConnectionInfo) -> Self::Service {
+        (**self).new_service_with_info(info)
+    }
+
+    fn on_server_start(&self) {
+        (**self).on_server_start()
+    }
+
+    fn on_server_stop(&self) {
+        (**self).on_server_stop()
+    }
+}
+
+/// a cloneable service can be used as a factory, each connection gets a clone
+impl<T: HttpService + Clone + Send + Sync + 'static> HttpServiceFactory for HttpServer<T> {
+    type Service = T;
+
+    fn new_service(&self, _id: usize) -> T {
+        self.0.clone()
+    }
+}
+
+/// the per connection settings derived from `HttpConfig`
+#[derive(Clone)]
+pub(crate) struct ConnConfig {
+    // the header slots used by the connection loop
+    pub(crate) max_headers: usize,
+    // write all the pipelined responses at once
+    batch_writes: bool,
+    // max queued response bytes before the connection stops taking requests
+    write_high_watermark: usize,
+    // max bytes held by the connection buffers, 0 for no limit
+    max_memory: usize,
+    // max Content-Length of a request, 0 for no limit
+    max_body: usize,
+    pub(crate) header_validation: HeaderValidation,
+    pub(crate) bufs: BufPolicy,
+    max_accept_backoff: Duration,
+    // let the accept loop close the connection while it is idle
+    evict_idle: bool,
+    linger: Option<Duration>,
+    // reset the connection when it ends with an `HttpError`
+    abortive_close: bool,
+    // idle time before keepalive probes, `None` for no probes
+    keepalive_idle: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    parse_error_log: LevelFilter,
+    parse_error_hints: bool,
+    on_parse_error: Option<ParseErrorHook>,
+    on_connection_error: Option<ConnectionErrorHook>,
+    on_request_complete: Option<RequestCompleteHook>,
+    stats: Option<Arc<ServerStats>>,
+    pub(crate) drain: Option<Arc<Drain>>,
+    #[cfg(feature = "ip-filter")]
+    ip_filter: Option<Arc<IpFilter>>,
+    // idle time before a keep-alive connection is closed
+    keep_alive_timeout: Option<Duration>,
+    // requests served before the connection is closed, 0 for no limit
+    max_requests: usize,
+    // the accept loop waits for the privileges to be dropped
+    pub(crate) run_as: Option<Arc<RunAs>>,
+}
+
+/// a request the request complete hook will be told about
+pub(crate) struct Started {
+    method: String,
+    path: String,
+    at: Instant,
+}
+
+impl ConnConfig {
+    pub(crate) fn new(config: &HttpConfig) -> Self {
+        ConnConfig {
+            max_headers: max_headers_limit(config),
+            batch_writes: config.batch_writes,
+            write_high_watermark: config.write_high_watermark,
+            max_memory: config.max_conn_memory,
+            max_body: config.max_body,
+            header_validation: config.header_validation,
+            bufs: BufPolicy::new(config),
+            max_accept_backoff: Duration::from_millis(config.max_accept_backoff_ms),
+            evict_idle: config.evict_idle,
+            linger: config.linger_secs.map(|s| Duration::from_secs(s.into())),
+            abortive_close: config.abortive_close,
+            keepalive_idle: secs(config.tcp_keepalive_idle_secs),
+            keepalive_interval: secs(config.tcp_keepalive_interval_secs),
+            keepalive_retries: (config.tcp_keepalive_retries != 0)
+                .then_some(config.tcp_keepalive_retries),
+            keep_alive_timeout: secs(config.keep_alive_timeout_secs),
+            max_requests: config.max_requests_per_conn,
+            parse_error_log: config.parse_error_log,
+            parse_error_hints: config.parse_error_hints,
+            on_parse_error: config.on_parse_error,
+            on_connection_error: config.on_connection_error,
+            on_request_complete: config.on_request_complete,
+            stats: config.stats.clone(),
+            drain: config.drain.clone(),
+            #[cfg(feature = "ip-filter")]
+            ip_filter: config.ip_filter.clone(),
+            run_as: config.run_as.clone(),
+        }
+    }
+
+    /// the limits, timeouts and parse error logging of `config`, for a
+    /// reload; the hooks and the shared stats, drain, ip filter and
+    /// privilege gate stay the ones the server started with
+    pub(crate) fn reloaded(&self, config: &HttpConfig) -> Self {
+        ConnConfig {
+            on_parse_error: self.on_parse_error,
+            on_connection_error: self.on_connection_error,
+            on_request_complete: self.on_request_complete,
+            stats: self.stats.clone(),
+            drain: self.drain.clone(),
+            #[cfg(feature = "ip-filter")]
+            ip_filter: self.ip_filter.clone(),
+            run_as: self.run_as.clone(),
+            ..Self::new(config)
+        }
+    }
+
+    /// whether the server is draining, the connection then closes after the
+    /// current request
+    #[inline]
+    pub(crate) fn draining(&self) -> bool {
+        self.drain.as_deref().is_some_and(Drain::is_draining)
+    }
+
+    /// whether the connection stays open after its `served`-th request,
+    /// counting from 0, when the request asks for it with `keep_alive`
+    #[inline]
+    pub(crate) fn keeps_alive(&self, keep_alive: bool, served: usize) -> bool {
+        keep_alive && !self.draining() && !self.is_last(served)
+    }
+
+    fn is_last(&self, served: usize) -> bool {
+        self.max_requests != 0 && served + 1 >= self.max_requests
+    }
+
+    /// tell the client whether and for how long the connection stays open
+    /// after its `served`-th response, `keep_alive` is what
+    /// [`keeps_alive`](Self::keeps_alive) decided for it
+    #[inline]
+    pub(crate) fn conn_lines(&self, keep_alive: bool, served: usize) -> ConnLines {
+        if !keep_alive {
+            if self.draining() || self.is_last(served) {
+                return ConnLines::Close;
+            }
+            return ConnLines::None;
+        }
+        let timeout = self.keep_alive_timeout.map(|t| t.as_secs());
+        let max = (self.max_requests != 0).then(|| self.max_requests.saturating_sub(served + 1));
+        match (timeout, max) {
+            (None, None) => ConnLines::None,
+            (timeout, max) => ConnLines::KeepAlive { timeout, max },
+        }
+    }
+
+    /// track when the connection is idle, for the accept loop to evict it
+    /// and to close it after the keep-alive timeout
+    #[cfg(unix)]
+    pub(crate) fn track_idle(&self, stream: &TcpStream) -> IdleTracker {
+        IdleTracker::new(self.evict_idle, self.keep_alive_timeout, stream.as_raw_fd())
+    }
+
+    /// set the socket options of a newly accepted connection
+    pub(crate) fn set_options(&self, stream: &TcpStream) {
+        if let Some(linger) = self.linger {
+            if let Err(e) = listener::set_linger(stream, Some(linger)) {
+                debug!("can't set SO_LINGER: {e}");
+            }
+        }
+        if let Some(idle) = self.keepalive_idle {
+            let keepalive = self.keepalive_interval;
+            if let Err(e) = listener::set_keepalive(stream, idle, keepalive, self.keepalive_retries)
+            {
+                debug!("can't set SO_KEEPALIVE: {e}");
+            }
+        }
+    }
+
+    /// close a connection that ended with `e`, resetting it if `e` is a
+    /// protocol violation or a limit and abortive close is on
+    pub(crate) fn close_after_error(&self, stream: &TcpStream, e: &io::Error) {
+        // with a zero linger the close sends a RST
+        let abort = self.abortive_close && HttpError::from_io(e).is_some();
+        if !abort || listener::set_linger(stream, Some(Duration::ZERO)).is_err() {
+            stream.shutdown(std::net::Shutdown::Both).ok();
+        }
+    }
+
+    /// whether the connection buffers hold more than the memory limit
+    pub(crate) fn over_memory(&self, bufs: &[&BytesMut]) -> bool {
+        self.check_memory(bufs).is_err()
+    }
+
+    /// error out if the connection buffers hold more than the memory limit
+    pub(crate) fn check_memory(&self, bufs: &[&BytesMut]) -> io::Result<()> {
+        let held = bufs.iter().map(|buf| buf.capacity()).sum();
+        if self.max_memory != 0 && held > self.max_memory {
+            let limit = self.max_memory;
+            return err(HttpError::MemoryLimit { held, limit }.into());
+        }
+        Ok(())
+    }
+
+    /// the largest request body, `usize::MAX` without a limit
+    pub(crate) fn body_limit(&self) -> usize {
+        match self.max_body {
+            0 => usize::MAX,
+            max_body => max_body,
+        }
+    }
+
+    /// error out if `req` announces a body over the body limit
+    pub(crate) fn check_body(&self, req: &Request) -> io::Result<()> {
+        let length = req.content_length();
+        if length > self.body_limit() {
+            let limit = self.max_body;
+            return err(HttpError::BodyTooLarge { length, limit }.into());
+        }
+        Ok(())
+    }
+
+    /// whether the ip filter lets a connection from `peer` in, the address
+    /// is only looked up for a filter
+    #[cfg(feature = "ip-filter")]
+    pub(crate) fn accepts(&self, peer_addr: impl FnOnce() -> Option<SocketAddr>) -> bool {
+        let Some(filter) = &self.ip_filter else {
+            return true;
+        };
+        match peer_addr() {
+            Some(peer) if filter.accepts(peer.ip()) => true,
+            peer => {
+                debug!("connection from {peer:?} denied");
+                false
+            }
+        }
+    }
+
+    #[cfg(not(feature = "ip-filter"))]
+    #[inline]
+    pub(crate) fn accepts(&self, _peer_addr: impl FnOnce() -> Option<SocketAddr>) -> bool {
+        true
+    }
+
+    /// count the connection as open until the result is dropped, the peer
+    /// address is only looked up for the stats
+    pub(crate) fn open_stats(&self, peer_addr: impl FnOnce() -> Option<SocketAddr>) -> ConnStats {
+        let peer_addr = self.stats.as_ref().and_then(|_| peer_addr());
+        ConnStats::open(self.stats.clone(), peer_addr)
+    }
+
+    #[inline]
+    pub(crate) fn count_read(&self, n: usize) {
+        if let Some(stats) = &self.stats {
+            stats.add_read(n);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn count_written(&self, n: usize) {
+        if let Some(stats) = &self.stats {
+            stats.add_written(n);
+        }
+    }
+
+    /// note a request for the request complete hook, if there is one
+    pub(crate) fn request_started(&self, req: &Request) -> Option<Started> {
+        self.on_request_complete.map(|_| Started {
+            method: req.method().to_owned(),
+            path: req.path().to_owned(),
+            at: Instant::now(),
+        })
+    }
+
+    /// call the request complete hook with the outcome of the service
+    pub(crate) fn request_completed(
+        &self,
+        started: Option<Started>,
+        ret: &io::Result<()>,
+        rsp: &Response,
+    ) {
+        if let (Some(hook), Some(started)) = (self.on_request_complete, started) {
+            let (status, bytes) = match ret {
+                Ok(()) => (rsp.status().0, rsp.total_body_len()),
+                Err(e) => (response::error_status(e), 0),
+            };
+            hook(
+                &started.method,
+                &started.path,
+                status,
+                started.at.elapsed(),
+                bytes,
+            );
+        }
+    }
+
+    /// log a failed accept and call the connection error hook
+    pub(crate) fn report_accept_error(&self, e: &io::Error) {
+        error!("accept err = {e:?}");
+        if let Some(stats) = &self.stats {
+            stats.add_accept_error();
+        }
+        if let Some(hook) = self.on_connection_error {
+            hook(None, e);
+        }
+    }
+
+    /// log the error that ended a connection and call the hooks
+    pub(crate) fn report_error(&self, e: &io::Error, peer: Option<SocketAddr>) {
+        // Only log actual errors, not normal client disconnects
+        if !self.report_parse_error(e, peer) && !is_client_disconnect(e) {
+            error!("service err = {e:?}");
+        }
+        if let Some(hook) = self.on_connection_error {
+            hook(peer, e);
+        }
+    }
+
+    /// log a request parse error at the configured level and call the hook
+    ///
+    /// returns `false` if `e` is not a parse error
+    fn report_parse_error(&self, e: &io::Error, peer: Option<SocketAddr>) -> bool {
+        let http_err = match HttpError::from_io(e) {
+            Some(http_err) => http_err,
+            None => return false,
+        };
+        if let Some(level) = self.parse_error_log.to_level() {
+            match peer {
+                Some(peer) => log!(level, "{http_err}, peer = {peer}"),
+                None => log!(level, "{http_err}"),
+            }
+            if self.parse_error_hints {
+                if let Some(hint) = http_err.hint() {
+                    log!(level, "{hint}");
                 }
             }
-        )
+        }
+        if let Some(hook) = self.on_parse_error {
+            hook(http_err, peer);
+        }
+        true
+    }
+
+    fn with_max_headers(max_headers: usize) -> Self {
+        ConnConfig {
+            max_headers,
+            ..Self::new(&HttpConfig::default())
+        }
+    }
+}
+
+/// the pause of the accept loop after an error, doubling while the errors go on
+pub(crate) struct AcceptBackoff {
+    delay: Duration,
+    max: Duration,
+}
+
+impl AcceptBackoff {
+    pub(crate) fn new(conn: &ConnConfig) -> Self {
+        AcceptBackoff {
+            delay: Duration::ZERO,
+            max: conn.max_accept_backoff,
+        }
+    }
+
+    /// the listener works again
+    pub(crate) fn reset(&mut self) {
+        self.delay = Duration::ZERO;
+    }
+
+    /// pause before the next accept, unless `e` is about a single connection
+    pub(crate) fn wait(&mut self, e: &io::Error) {
+        use io::ErrorKind::*;
+        // the client went away before it was accepted, the listener is fine
+        if matches!(
+            e.kind(),
+            ConnectionAborted | ConnectionReset | Interrupted | WouldBlock
+        ) {
+            return;
+        }
+        // make room for the next accept
+        #[cfg(unix)]
+        if evict::out_of_fds(e) && evict::evict_idle() {
+            info!("out of file descriptors, closing the longest idle connection");
+        }
+        self.delay = (self.delay * 2).max(MIN_ACCEPT_BACKOFF).min(self.max);
+        if !self.delay.is_zero() {
+            coroutine::sleep(self.delay);
+        }
+    }
+}
+
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+
+/// the header slots used by the connection loop for the given config
+fn max_headers_limit(config: &HttpConfig) -> usize {
+    config.max_headers.value()
+}
+
+/// the connection loops take the header slots from the heap for `N == 0`
+pub(crate) const HEAP_HEADERS: usize = 0;
+
+/// a config value in seconds, `None` for `0`
+fn secs(secs: u32) -> Option<Duration> {
+    (secs != 0).then(|| Duration::from_secs(secs.into()))
+}
+
+/// run the connection loop with the smallest header buffer that fits the limit
+fn serve_connection<T: HttpService>(
+    stream: &mut TcpStream,
+    service: T,
+    conn: &ConnConfig,
+) -> io::Result<()> {
+    match conn.max_headers {
+        0..=16 => each_connection_loop_with_headers::<T, 16>(stream, service, conn),
+        17..=32 => each_connection_loop_with_headers::<T, 32>(stream, service, conn),
+        33..=64 => each_connection_loop_with_headers::<T, 64>(stream, service, conn),
+        65..=128 => each_connection_loop_with_headers::<T, 128>(stream, service, conn),
+        _ => each_connection_loop_with_headers::<T, HEAP_HEADERS>(stream, service, conn),
     }
 }
 
@@ -95,31 +616,62 @@ pub(crate) fn err<T>(e: io::Error) -> io::Result<T> {
     Err(e)
 }
 
+/// where a nonblocking read of the connection stopped
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadEnd {
+    /// the buffer is full, there may be more to read
+    Full,
+    /// nothing more to read for now
+    Blocked,
+    /// the client shut down its side, no more requests come
+    Closed,
+}
+
 #[cfg(unix)]
 #[inline]
-fn nonblock_read(stream: &mut impl Read, req_buf: &mut BytesMut) -> io::Result<bool> {
-    reserve_buf(req_buf);
+fn nonblock_read(
+    stream: &mut impl Read,
+    req_buf: &mut BytesMut,
+    bufs: &BufPolicy,
+) -> io::Result<ReadEnd> {
+    bufs.reserve_read(req_buf)?;
     let read_buf: &mut [u8] = unsafe { std::mem::transmute(req_buf.chunk_mut()) };
     let len = read_buf.len();
 
     let mut read_cnt = 0;
+    let mut end = ReadEnd::Full;
     while read_cnt < len {
         match stream.read(unsafe { read_buf.get_unchecked_mut(read_cnt..) }) {
-            Ok(0) => return err(io::Error::new(io::ErrorKind::BrokenPipe, "read closed")),
+            // the requests read so far are still answered
+            Ok(0) => {
+                end = ReadEnd::Closed;
+                break;
+            }
             Ok(n) => read_cnt += n,
-            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                end = ReadEnd::Blocked;
+                break;
+            }
             Err(e) => return err(e),
         }
     }
 
     unsafe { req_buf.advance_mut(read_cnt) };
-    Ok(read_cnt < len)
+    Ok(end)
 }
 
 #[cfg(unix)]
 #[inline]
 fn nonblock_write(stream: &mut impl Write, rsp_buf: &mut BytesMut) -> io::Result<usize> {
-    let write_buf = rsp_buf.chunk();
+    let write_cnt = nonblock_write_slice(stream, rsp_buf.chunk())?;
+    rsp_buf.advance(write_cnt);
+    Ok(write_cnt)
+}
+
+#[cfg(unix)]
+#[inline]
+fn nonblock_write_slice(stream: &mut impl Write, write_buf: &[u8]) -> io::Result<usize> {
     let len = write_buf.len();
     let mut write_cnt = 0;
     while write_cnt < len {
@@ -130,19 +682,192 @@ fn nonblock_write(stream: &mut impl Write, rsp_buf: &mut BytesMut) -> io::Result
             Err(e) => return err(e),
         }
     }
-    rsp_buf.advance(write_cnt);
     Ok(write_cnt)
 }
 
-const BUF_LEN: usize = 4096 * 8;
+/// write a small response straight from the stack, `false` if it doesn't fit
+#[cfg(unix)]
+#[inline]
+fn write_small(io: &mut ConnIo<TcpStream>, rsp: &Response, lines: ConnLines) -> io::Result<bool> {
+    let mut small = [0u8; response::SMALL_RSP_LEN];
+    let len = match response::encode_small(rsp, lines, &mut small) {
+        Some(len) => len,
+        None => return Ok(false),
+    };
+    let n = nonblock_write_slice(io.stream.inner_mut(), &small[..len])?;
+    io.conn.count_written(n);
+    // keep what the socket didn't take for the next write
+    io.rsp_buf.extend_from_slice(&small[n..len]);
+    Ok(true)
+}
+
+/// write out the responses, parking the coroutine until the socket has taken
+/// enough of them to get below the high watermark
+///
+/// returns the bytes written
+#[cfg(unix)]
+fn drain_to_watermark(
+    stream: &mut TcpStream,
+    rsp_buf: &mut BytesMut,
+    high_watermark: usize,
+) -> io::Result<usize> {
+    let mut written = nonblock_write(stream.inner_mut(), rsp_buf)?;
+    while rsp_buf.len() > high_watermark {
+        stream.wait_io();
+        written += nonblock_write(stream.inner_mut(), rsp_buf)?;
+    }
+    Ok(written)
+}
+
+/// the connection stream and its queued responses, for one request
+struct ConnIo<'a, S> {
+    stream: &'a mut S,
+    rsp_buf: &'a mut BytesMut,
+    conn: &'a ConnConfig,
+    // requests answered on the connection before this one
+    served: usize,
+    // the client closed its side while the body was read
+    read_closed: bool,
+}
+
+/// shares the connection between the request body and `Response::flush`
+struct SharedConn<'c, 'a, S>(&'c RefCell<ConnIo<'a, S>>);
+
+impl<S: Read> Read for SharedConn<'_, '_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut io = self.0.borrow_mut();
+        let n = io.stream.read(buf)?;
+        io.conn.count_read(n);
+        io.read_closed |= n == 0 && !buf.is_empty();
+        Ok(n)
+    }
+}
+
+/// a peek that would block means the client is still there
+#[cfg(unix)]
+impl request::ConnProbe for SharedConn<'_, '_, TcpStream> {
+    fn is_connected(&self) -> bool {
+        let Ok(io) = self.0.try_borrow() else {
+            return true;
+        };
+        match io.stream.inner().peek(&mut [0; 1]) {
+            Ok(n) => n > 0,
+            Err(e) => e.kind() == io::ErrorKind::WouldBlock,
+        }
+    }
+}
+
+impl<S: Write> Flush for SharedConn<'_, '_, S> {
+    fn flush_bytes(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut io = self.0.borrow_mut();
+        let io = &mut *io;
+        // the responses queued before this one go first
+        io.rsp_buf.extend_from_slice(data);
+        io.stream.write_all(io.rsp_buf)?;
+        io.conn.count_written(io.rsp_buf.len());
+        io.rsp_buf.clear();
+        Ok(())
+    }
+
+    fn conn_lines(&self, keep_alive: bool) -> ConnLines {
+        let io = self.0.borrow();
+        let keep_alive = io.conn.keeps_alive(keep_alive, io.served);
+        io.conn.conn_lines(keep_alive, io.served)
+    }
+}
+
+pub(crate) const BUF_LEN: usize = 4096 * 8;
+/// the free space a buffer is grown for before a read
+const MIN_FREE: usize = 1024;
+
+/// make room for a read into a buffer that is nearly full, for the body
+/// reads of a parsed request
 #[inline]
 pub(crate) fn reserve_buf(buf: &mut BytesMut) {
     let rem = buf.capacity() - buf.len();
-    if rem < 1024 {
+    if rem < MIN_FREE {
         buf.reserve(BUF_LEN - rem);
     }
 }
 
+/// how the buffers of a connection are sized
+#[derive(Clone, Copy)]
+pub(crate) struct BufPolicy {
+    initial: usize,
+    grow_by: usize,
+    growth: BufGrowth,
+    // largest read buffer, 0 for no limit
+    max: usize,
+}
+
+impl BufPolicy {
+    pub(crate) fn new(config: &HttpConfig) -> Self {
+        BufPolicy {
+            initial: config.buf_initial_capacity,
+            grow_by: config.buf_grow_by.max(MIN_FREE),
+            growth: config.buf_growth,
+            max: config.buf_max_capacity,
+        }
+    }
+
+    /// a buffer for a new connection
+    pub(crate) fn alloc(&self) -> BytesMut {
+        BytesMut::with_capacity(self.initial)
+    }
+
+    /// make room for a read into `buf`, failing once it holds the largest
+    /// allowed unparsed request
+    #[inline]
+    pub(crate) fn reserve_read(&self, buf: &mut BytesMut) -> io::Result<()> {
+        if self.max == 0 {
+            self.reserve(buf);
+            return Ok(());
+        }
+        if buf.len() >= self.max {
+            let (held, limit) = (buf.len(), self.max);
+            return err(HttpError::MemoryLimit { held, limit }.into());
+        }
+        self.grow(buf, self.max - buf.len());
+        Ok(())
+    }
+
+    /// make room in a write buffer once it is nearly full
+    #[inline]
+    pub(crate) fn reserve(&self, buf: &mut BytesMut) {
+        self.grow(buf, usize::MAX);
+    }
+
+    fn grow(&self, buf: &mut BytesMut, max_free: usize) {
+        let rem = buf.capacity() - buf.len();
+        if rem >= MIN_FREE {
+            return;
+        }
+        let free = match self.growth {
+            BufGrowth::Fixed => self.grow_by,
+            BufGrowth::Doubling => buf.capacity().max(self.grow_by),
+        }
+        .min(max_free);
+        if free <= rem || buf.try_reclaim(free) {
+            return;
+        }
+        // `BytesMut::reserve` would at least double the allocation
+        let mut bigger = BytesMut::with_capacity(buf.len() + free);
+        bigger.extend_from_slice(buf);
+        *buf = bigger;
+    }
+
+    /// go back to an initial buffer after a large request or response, once
+    /// the buffer grew past four times its initial capacity and is mostly empty
+    pub(crate) fn shrink(&self, buf: &mut BytesMut) {
+        let initial = self.initial.max(MIN_FREE);
+        if buf.capacity() > 4 * initial && buf.len() <= initial / 2 {
+            let mut small = self.alloc();
+            small.extend_from_slice(buf);
+            *buf = small;
+        }
+    }
+}
+
 /// this is the generic type http server
 /// with a type parameter that impl `HttpService` trait
 ///
@@ -160,152 +885,411 @@ pub struct HttpServer<T>(pub T);
 /// ```
 pub struct HttpServerWithHeaders<T, const N: usize>(pub T);
 
-#[cfg(unix)]
-fn each_connection_loop<T: HttpService>(stream: &mut TcpStream, service: T) -> io::Result<()> {
-    each_connection_loop_with_headers::<T, { request::MAX_HEADERS }>(stream, service)
-}
-
 #[cfg(unix)]
 fn each_connection_loop_with_headers<T: HttpService, const N: usize>(
     stream: &mut TcpStream,
     mut service: T,
+    conn: &ConnConfig,
 ) -> io::Result<()> {
-    let mut req_buf = BytesMut::with_capacity(BUF_LEN);
-    let mut rsp_buf = BytesMut::with_capacity(BUF_LEN);
+    let max_headers = conn.max_headers;
+    // the part of req_buf already searched for the end of the headers
+    let mut scanned = 0;
+    // requests answered on the connection
+    let mut served = 0;
+    let mut req_buf = conn.bufs.alloc();
+    let mut rsp_buf = conn.bufs.alloc();
     let mut body_buf = BytesMut::with_capacity(4096);
+    let mut stats = conn.open_stats(|| stream.peer_addr().ok());
+    let idle = conn.track_idle(stream);
 
     loop {
-        let read_blocked = nonblock_read(stream.inner_mut(), &mut req_buf)?;
+        let buffered = req_buf.len();
+        let read_end = nonblock_read(stream.inner_mut(), &mut req_buf, &conn.bufs)?;
+        conn.count_read(req_buf.len() - buffered);
+        if req_buf.len() > buffered && !idle.busy() {
+            return Ok(());
+        }
 
         // prepare the requests, we should make sure the request is fully read
         loop {
-            let mut headers = [MaybeUninit::uninit(); N];
-            let req = match request::decode(&mut headers, &mut req_buf, stream)? {
+            let conn_io = RefCell::new(ConnIo {
+                stream: &mut *stream,
+                rsp_buf: &mut rsp_buf,
+                conn,
+                served,
+                read_closed: false,
+            });
+            let mut body_src = SharedConn(&conn_io);
+            let mut flush_dst = SharedConn(&conn_io);
+            let probe = SharedConn(&conn_io);
+            let mut stack = [MaybeUninit::uninit(); N];
+            // a stack buffer that large would not fit small coroutine stacks
+            let mut heap = Vec::new();
+            let headers = match N {
+                HEAP_HEADERS => {
+                    heap.resize(max_headers, MaybeUninit::uninit());
+                    &mut heap[..]
+                }
+                _ => &mut stack[..max_headers],
+            };
+            let mut req = match request::decode(headers, &mut req_buf, &mut body_src, &mut scanned)?
+            {
                 Some(req) => req,
                 None => break,
             };
-            reserve_buf(&mut rsp_buf);
-            let mut rsp = Response::new(&mut body_buf);
-            match service.call(req, &mut rsp) {
-                Ok(()) => response::encode(rsp, &mut rsp_buf),
+            if let Err(e) = conn.check_body(&req) {
+                // the body is left unread, the connection ends after the 413
+                let mut io = conn_io.borrow_mut();
+                let io = &mut *io;
+                conn.bufs.reserve(io.rsp_buf);
+                response::encode_error(&e, ConnLines::Close, io.rsp_buf);
+                conn.count_written(drain_to_watermark(io.stream, io.rsp_buf, 0)?);
+                return Err(e);
+            }
+            req.set_probe(&probe);
+            let wants_keep_alive = req.is_keep_alive();
+            let started = conn.request_started(&req);
+            let mut rsp = Response::with_flush(
+                &mut body_buf,
+                &mut flush_dst,
+                conn.header_validation,
+                conn.write_high_watermark,
+                &req,
+            );
+            let ret = service
+                .call(req, &mut rsp)
+                .and_then(|()| rsp.take_write_error())
+                .and_then(|()| rsp.check_headers(conn.header_validation));
+            conn.request_completed(started, &ret, &rsp);
+            let hijack = ret.as_ref().ok().and_then(|()| rsp.take_hijack());
+            let mut io = conn_io.borrow_mut();
+            let io = &mut *io;
+            // decided now, a drain may have begun during the call; a
+            // truncated body leaves nothing to read the next request from,
+            // a body flushed to an HTTP/1.0 client ends with the connection
+            let keep_alive = conn.keeps_alive(wants_keep_alive, served)
+                && !io.read_closed
+                && !rsp.ends_with_close();
+            let lines = conn.conn_lines(keep_alive, served);
+            served += 1;
+            match ret {
+                Ok(()) => {
+                    // a small response with nothing queued before or after it
+                    // is written straight from the stack
+                    let direct =
+                        io.rsp_buf.is_empty() && (req_buf.is_empty() || !conn.batch_writes);
+                    if !direct || !write_small(io, &rsp, lines)? {
+                        conn.bufs.reserve(io.rsp_buf);
+                        response::encode_with(&rsp, lines, io.rsp_buf);
+                    }
+                }
+                // the head is already out, the client has to see the connection close
+                Err(e) if rsp.is_flushed() => return Err(e),
                 Err(e) => {
-                    eprintln!("service err = {e:?}");
-                    response::encode_error(e, &mut rsp_buf);
+                    conn.bufs.reserve(io.rsp_buf);
+                    response::encode_error(&e, lines, io.rsp_buf);
                 }
             }
+            stats.served(keep_alive);
+            if let Some(handler) = hijack {
+                conn.count_written(drain_to_watermark(io.stream, io.rsp_buf, 0)?);
+                let buffered = req_buf.split();
+                Handoff { handler, buffered }.run(io.stream.try_clone()?);
+                return Ok(());
+            }
             // here need to use no_delay tcp option
-            // nonblock_write(stream.inner_mut(), &mut rsp_buf)?;
+            if !conn.batch_writes {
+                conn.count_written(nonblock_write(io.stream.inner_mut(), io.rsp_buf)?);
+            }
+            // don't queue more responses for a client that is not reading
+            if io.rsp_buf.len() > conn.write_high_watermark {
+                let high = conn.write_high_watermark;
+                conn.count_written(drain_to_watermark(io.stream, io.rsp_buf, high)?);
+            }
+            if !keep_alive {
+                conn.count_written(drain_to_watermark(io.stream, io.rsp_buf, 0)?);
+                return Ok(());
+            }
         }
 
-        // write out the responses
-        nonblock_write(stream.inner_mut(), &mut rsp_buf)?;
+        // write out the responses, in request order
+        let high = conn.write_high_watermark;
+        conn.count_written(drain_to_watermark(stream, &mut rsp_buf, high)?);
+        conn.bufs.shrink(&mut req_buf);
+        conn.bufs.shrink(&mut rsp_buf);
+        conn.bufs.shrink(&mut body_buf);
+        if conn.over_memory(&[&req_buf, &rsp_buf, &body_buf]) {
+            // wait for the client to take the queued responses first
+            conn.count_written(drain_to_watermark(stream, &mut rsp_buf, 0)?);
+            conn.bufs.shrink(&mut rsp_buf);
+            conn.check_memory(&[&req_buf, &rsp_buf, &body_buf])?;
+        }
 
-        if read_blocked {
+        if read_end == ReadEnd::Closed {
+            // a half closed connection still takes the responses
+            conn.count_written(drain_to_watermark(stream, &mut rsp_buf, 0)?);
+            return Ok(());
+        }
+        if read_end == ReadEnd::Blocked {
+            // nothing half read or unsent, the next request may never come
+            if req_buf.is_empty() && rsp_buf.is_empty() {
+                idle.idle();
+            }
             stream.wait_io();
+            if idle.is_evicted() {
+                debug!("idle connection evicted");
+                return Ok(());
+            }
         }
     }
 }
 
-#[cfg(not(unix))]
-fn each_connection_loop<T: HttpService>(stream: &mut TcpStream, service: T) -> io::Result<()> {
-    each_connection_loop_with_headers::<T, { request::MAX_HEADERS }>(stream, service)
-}
-
 #[cfg(not(unix))]
 fn each_connection_loop_with_headers<T: HttpService, const N: usize>(
     stream: &mut TcpStream,
-    mut service: T,
+    service: T,
+    conn: &ConnConfig,
+) -> io::Result<()> {
+    if let Some(handoff) = stream_loop::<_, T, N>(stream, service, conn)? {
+        handoff.run(stream.try_clone()?);
+    }
+    Ok(())
+}
+
+/// serve http requests over any blocking `Read + Write` stream
+///
+/// requests are read from the stream, dispatched to the service and the responses
+/// are written back until the peer closes the stream. This runs the same decode and
+/// encode path as the built-in TCP server, so alternative transports such as unix
+/// sockets or TLS wrappers can be plugged in
+pub fn serve_stream<S: Read + Write, T: HttpService>(
+    stream: &mut S,
+    service: T,
+    config: &HttpConfig,
 ) -> io::Result<()> {
-    let mut req_buf = BytesMut::with_capacity(BUF_LEN);
-    let mut rsp_buf = BytesMut::with_capacity(BUF_LEN);
-    let mut body_buf = BytesMut::with_capacity(BUF_LEN);
+    let conn = ConnConfig::new(config);
+    let ret = match conn.max_headers {
+        0..=16 => stream_loop::<S, T, 16>(stream, service, &conn),
+        17..=32 => stream_loop::<S, T, 32>(stream, service, &conn),
+        33..=64 => stream_loop::<S, T, 64>(stream, service, &conn),
+        65..=128 => stream_loop::<S, T, 128>(stream, service, &conn),
+        _ => stream_loop::<S, T, HEAP_HEADERS>(stream, service, &conn),
+    };
+    if let Err(e) = &ret {
+        conn.report_error(e, None);
+    }
+    if let Ok(Some(_)) = ret {
+        debug!("serve_stream can't hand over the connection, closing it");
+    }
+    ret.map(drop)
+}
+
+fn stream_loop<S: Read + Write, T: HttpService, const N: usize>(
+    stream: &mut S,
+    mut service: T,
+    conn: &ConnConfig,
+) -> io::Result<Option<Handoff>> {
+    let max_headers = conn.max_headers;
+    // the part of req_buf already searched for the end of the headers
+    let mut scanned = 0;
+    // requests answered on the connection
+    let mut served = 0;
+    let mut req_buf = conn.bufs.alloc();
+    let mut rsp_buf = conn.bufs.alloc();
+    let mut body_buf = conn.bufs.alloc();
+    let mut stats = conn.open_stats(|| None);
     loop {
-        // read the socket for requests
-        reserve_buf(&mut req_buf);
+        // read the stream for requests
+        conn.bufs.reserve_read(&mut req_buf)?;
         let read_buf: &mut [u8] = unsafe { std::mem::transmute(&mut *req_buf.chunk_mut()) };
         let read_cnt = stream.read(read_buf)?;
         if read_cnt == 0 {
             //connection was closed
-            return err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"));
+            return Ok(None);
         }
         unsafe { req_buf.advance_mut(read_cnt) };
+        conn.count_read(read_cnt);
 
         // prepare the requests
-        if read_cnt > 0 {
-            loop {
-                let mut headers = [MaybeUninit::uninit(); N];
-                let req = match request::decode(&mut headers, &mut req_buf, stream)? {
-                    Some(req) => req,
-                    None => break,
-                };
-                let mut rsp = Response::new(&mut body_buf);
-                match service.call(req, &mut rsp) {
-                    Ok(()) => response::encode(rsp, &mut rsp_buf),
-                    Err(e) => {
-                        eprintln!("service err = {:?}", e);
-                        response::encode_error(e, &mut rsp_buf);
-                    }
+        loop {
+            let conn_io = RefCell::new(ConnIo {
+                stream: &mut *stream,
+                rsp_buf: &mut rsp_buf,
+                conn,
+                served,
+                read_closed: false,
+            });
+            let mut body_src = SharedConn(&conn_io);
+            let mut flush_dst = SharedConn(&conn_io);
+            let mut stack = [MaybeUninit::uninit(); N];
+            // a stack buffer that large would not fit small coroutine stacks
+            let mut heap = Vec::new();
+            let headers = match N {
+                HEAP_HEADERS => {
+                    heap.resize(max_headers, MaybeUninit::uninit());
+                    &mut heap[..]
                 }
+                _ => &mut stack[..max_headers],
+            };
+            let req = match request::decode(headers, &mut req_buf, &mut body_src, &mut scanned)? {
+                Some(req) => req,
+                None => break,
+            };
+            if let Err(e) = conn.check_body(&req) {
+                // the body is left unread, the connection ends after the 413
+                let mut io = conn_io.borrow_mut();
+                let io = &mut *io;
+                response::encode_error(&e, ConnLines::Close, io.rsp_buf);
+                io.stream.write_all(io.rsp_buf)?;
+                conn.count_written(io.rsp_buf.len());
+                return Err(e);
+            }
+            let wants_keep_alive = req.is_keep_alive();
+            let started = conn.request_started(&req);
+            let mut rsp = Response::with_flush(
+                &mut body_buf,
+                &mut flush_dst,
+                conn.header_validation,
+                conn.write_high_watermark,
+                &req,
+            );
+            let ret = service
+                .call(req, &mut rsp)
+                .and_then(|()| rsp.take_write_error())
+                .and_then(|()| rsp.check_headers(conn.header_validation));
+            conn.request_completed(started, &ret, &rsp);
+            let hijack = ret.as_ref().ok().and_then(|()| rsp.take_hijack());
+            let mut io = conn_io.borrow_mut();
+            let io = &mut *io;
+            // decided now, a drain may have begun during the call; a
+            // truncated body leaves nothing to read the next request from,
+            // a body flushed to an HTTP/1.0 client ends with the connection
+            let keep_alive = conn.keeps_alive(wants_keep_alive, served)
+                && !io.read_closed
+                && !rsp.ends_with_close();
+            let lines = conn.conn_lines(keep_alive, served);
+            served += 1;
+            match ret {
+                Ok(()) => response::encode_with(&rsp, lines, io.rsp_buf),
+                // the head is already out, the client has to see the connection close
+                Err(e) if rsp.is_flushed() => return Err(e),
+                Err(e) => response::encode_error(&e, lines, io.rsp_buf),
+            }
+            stats.served(keep_alive);
+            if let Some(handler) = hijack {
+                io.stream.write_all(io.rsp_buf)?;
+                conn.count_written(io.rsp_buf.len());
+                let buffered = req_buf.split();
+                return Ok(Some(Handoff { handler, buffered }));
+            }
+            if !conn.batch_writes || !keep_alive || io.rsp_buf.len() > conn.write_high_watermark {
+                io.stream.write_all(io.rsp_buf)?;
+                conn.count_written(io.rsp_buf.len());
+                io.rsp_buf.clear();
+            }
+            if !keep_alive {
+                return Ok(None);
             }
         }
 
-        // send the result back to client
+        // send the result back to client, in request order
         stream.write_all(&rsp_buf)?;
+        conn.count_written(rsp_buf.len());
+        rsp_buf.clear();
+        conn.bufs.shrink(&mut req_buf);
+        conn.bufs.shrink(&mut rsp_buf);
+        conn.bufs.shrink(&mut body_buf);
+        conn.check_memory(&[&req_buf, &rsp_buf, &body_buf])?;
     }
 }
 
 impl<T: HttpService + Clone + Send + Sync + 'static> HttpServer<T> {
     /// Spawns the http service, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
         let listener = TcpListener::bind(addr)?;
         let service = self.0;
-        go!(
-            coroutine::Builder::new().name("TcpServer".to_owned()),
-            move || {
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    // t_c!(stream.set_nodelay(true));
-                    let service = service.clone();
-                    go!(
-                        move || if let Err(e) = each_connection_loop(&mut stream, service) {
-                            // Only log actual errors, not normal client disconnects
-                            if !is_client_disconnect(&e) {
-                                error!("service err = {e:?}");
-                            }
-                            stream.shutdown(std::net::Shutdown::Both).ok();
-                        }
-                    );
+        let conn = ConnConfig::with_max_headers(request::MAX_HEADERS);
+        ServerHandle::spawn("TcpServer", listener, conn, move |listener, state| {
+            let mut backoff = AcceptBackoff::new(&state.conn());
+            state.set_ready();
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        state.conn().report_accept_error(&e);
+                        backoff.wait(&e);
+                        continue;
+                    }
+                };
+                backoff.reset();
+                let conn = state.conn();
+                if !conn.accepts(|| stream.peer_addr().ok()) {
+                    continue;
                 }
+                conn.set_options(&stream);
+                // t_c!(stream.set_nodelay(true));
+                let service = service.clone();
+                go!(
+                    move || if let Err(e) = serve_connection(&mut stream, service, &conn) {
+                        conn.report_error(&e, stream.peer_addr().ok());
+                        conn.close_after_error(&stream, &e);
+                    }
+                );
             }
-        )
+        })
     }
 }
 
 impl<T: HttpService + Clone + Send + Sync + 'static, const N: usize> HttpServerWithHeaders<T, N> {
     /// Spawns the http service with custom max headers, binding to the given address
-    /// return a coroutine that you can cancel it when need to stop the service
-    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
+    /// return a [`ServerHandle`] to stop the service or wait for it
+    pub fn start<L: ToSocketAddrs>(self, addr: L) -> io::Result<ServerHandle> {
         let listener = TcpListener::bind(addr)?;
         let service = self.0;
-        go!(
-            coroutine::Builder::new().name("TcpServer".to_owned()),
-            move || {
-                for stream in listener.incoming() {
-                    let mut stream = t_c!(stream);
-                    // t_c!(stream.set_nodelay(true));
-                    let service = service.clone();
-                    go!(move || if let Err(e) =
-                        each_connection_loop_with_headers::<T, N>(&mut stream, service)
-                    {
-                        // Only log actual errors, not normal client disconnects
-                        if !is_client_disconnect(&e) {
-                            error!("service err = {e:?}");
-                        }
-                        stream.shutdown(std::net::Shutdown::Both).ok();
-                    });
+        let conn = ConnConfig::with_max_headers(N);
+        ServerHandle::spawn("TcpServer", listener, conn, move |listener, state| {
+            let mut backoff = AcceptBackoff::new(&state.conn());
+            // the header buffer is fixed, a reload can't change it
+            let pin = |shared: &ConnConfig| {
+                Arc::new(ConnConfig {
+                    max_headers: N,
+                    ..shared.clone()
+                })
+            };
+            let mut shared = state.conn();
+            let mut pinned = pin(&shared);
+            state.set_ready();
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        state.conn().report_accept_error(&e);
+                        backoff.wait(&e);
+                        continue;
+                    }
+                };
+                backoff.reset();
+                // only a reload makes a new copy
+                let current = state.conn();
+                if !Arc::ptr_eq(&current, &shared) {
+                    pinned = pin(&current);
+                    shared = current;
+                }
+                let conn = pinned.clone();
+                if !conn.accepts(|| stream.peer_addr().ok()) {
+                    continue;
                 }
+                conn.set_options(&stream);
+                // t_c!(stream.set_nodelay(true));
+                let service = service.clone();
+                go!(move || if let Err(e) =
+                    each_connection_loop_with_headers::<T, N>(&mut stream, service, &conn)
+                {
+                    conn.report_error(&e, stream.peer_addr().ok());
+                    conn.close_after_error(&stream, &e);
+                });
             }
-        )
+        })
     }
 }
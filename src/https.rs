@@ -0,0 +1,146 @@
+//! https redirect and HSTS on top of any `HttpService`
+
+use std::io;
+use std::time::Duration;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that sends plain http clients to https
+///
+/// The server itself speaks plain http, TLS is terminated by a proxy in
+/// front of it that sets `X-Forwarded-Proto`. Requests it marks as `https`
+/// go to the inner service and the response gets a
+/// `Strict-Transport-Security` header. Any other request is redirected to the
+/// same host and path over https, with `301 Moved Permanently` for `GET` and
+/// `HEAD` and `308 Permanent Redirect` for other methods so the method and
+/// body are kept. A request without a `Host` header gets `400 Bad Request`.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use may_minihttp::{HttpServer, HttpService, HttpsRedirect, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Account;
+///
+/// impl HttpService for Account {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("balance: 42");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = HttpsRedirect::new(Account)
+///     .hsts_max_age(Duration::from_secs(2 * 365 * 24 * 3600))
+///     .include_subdomains();
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct HttpsRedirect<S> {
+    inner: S,
+    max_age: Duration,
+    include_subdomains: bool,
+    preload: bool,
+    // the Strict-Transport-Security header built from the settings above
+    hsts: String,
+}
+
+impl<S> HttpsRedirect<S> {
+    /// Wrap `inner`, sending HSTS with a `max-age` of one year
+    pub fn new(inner: S) -> Self {
+        HttpsRedirect {
+            inner,
+            max_age: Duration::from_secs(365 * 24 * 3600),
+            include_subdomains: false,
+            preload: false,
+            hsts: String::new(),
+        }
+        .with_hsts()
+    }
+
+    /// Set how long browsers should only use https, zero makes them forget it
+    pub fn hsts_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self.with_hsts()
+    }
+
+    /// Apply the HSTS policy to all subdomains too
+    pub fn include_subdomains(mut self) -> Self {
+        self.include_subdomains = true;
+        self.with_hsts()
+    }
+
+    /// Ask to be included in the browsers' HSTS preload lists
+    pub fn preload(mut self) -> Self {
+        self.preload = true;
+        self.with_hsts()
+    }
+
+    fn with_hsts(mut self) -> Self {
+        self.hsts = format!(
+            "Strict-Transport-Security: max-age={}",
+            self.max_age.as_secs()
+        );
+        if self.include_subdomains {
+            self.hsts.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            self.hsts.push_str("; preload");
+        }
+        self
+    }
+}
+
+/// whether the proxy in front received the request over https
+fn is_secure(req: &Request) -> bool {
+    req.headers()
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("x-forwarded-proto"))
+        // a chain of proxies lists the client facing one first
+        .and_then(|h| h.value.split(|&b| b == b',').next())
+        .is_some_and(|proto| proto.trim_ascii().eq_ignore_ascii_case(b"https"))
+}
+
+fn host<'r>(req: &'r Request) -> Option<&'r str> {
+    let h = req
+        .headers()
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("host"))?;
+    std::str::from_utf8(h.value)
+        .ok()
+        .filter(|host| !host.is_empty())
+}
+
+impl<S: HttpService> HttpService for HttpsRedirect<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if is_secure(&req) {
+            self.inner.call(req, rsp)?;
+            // too late once the head is out
+            if !rsp.is_flushed() {
+                rsp.header_owned(self.hsts.clone());
+            }
+            return Ok(());
+        }
+        let location = host(&req).map(|host| format!("https://{host}{}", req.path()));
+        let keep_method = !matches!(req.method(), "GET" | "HEAD");
+        // skip the body so the next request on the connection can be read
+        drop(req.body());
+        match location {
+            Some(location) if keep_method => {
+                rsp.status_code(308, "Permanent Redirect")
+                    .header_kv("Location", location);
+            }
+            Some(location) => {
+                rsp.status_code(301, "Moved Permanently")
+                    .header_kv("Location", location);
+            }
+            None => {
+                rsp.status_code(400, "Bad Request");
+            }
+        }
+        Ok(())
+    }
+}
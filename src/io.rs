@@ -0,0 +1,75 @@
+//! io helpers for connections taken over from the http server
+
+use std::io;
+use std::net::Shutdown;
+
+use may::coroutine::JoinHandle;
+use may::go;
+use may::net::TcpStream;
+
+/// Copy data both ways between `a` and `b` until both directions end
+///
+/// Each direction runs on a coroutine of its own. When one side closes its
+/// write half, the end of the stream is passed on by shutting down the
+/// write half of the other side, which may keep sending the other way. If a
+/// direction fails both streams are shut down and the error is returned.
+/// Returns the bytes copied from `a` to `b` and from `b` to `a`.
+///
+/// This is the building block for tunnels, e.g. after answering a `CONNECT`
+/// with a [hijacked](crate::Response::hijack) connection. Write any
+/// [buffered](crate::Hijacked::buffered) bytes to the upstream first.
+///
+/// # Example
+/// ```no_run
+/// use std::io::Write;
+/// use may::net::TcpStream;
+/// use may_minihttp::{HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Tunnel;
+///
+/// impl HttpService for Tunnel {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         if req.method() != "CONNECT" {
+///             rsp.status_code(405, "Method Not Allowed");
+///             return Ok(());
+///         }
+///         let mut upstream = TcpStream::connect(req.path())?;
+///         rsp.hijack(move |conn| {
+///             let (client, buffered) = conn.into_parts();
+///             if upstream.write_all(&buffered).is_ok() {
+///                 let _ = may_minihttp::io::copy_bidirectional(&client, &upstream);
+///             }
+///         });
+///         Ok(())
+///     }
+/// }
+/// ```
+pub fn copy_bidirectional(a: &TcpStream, b: &TcpStream) -> io::Result<(u64, u64)> {
+    let a_to_b = pipe(a.try_clone()?, b.try_clone()?);
+    let b_to_a = pipe(b.try_clone()?, a.try_clone()?);
+    let a_to_b = joined(a_to_b);
+    let b_to_a = joined(b_to_a);
+    Ok((a_to_b?, b_to_a?))
+}
+
+/// copy `from` to `to` on a new coroutine
+fn pipe(mut from: TcpStream, mut to: TcpStream) -> JoinHandle<io::Result<u64>> {
+    go!(move || {
+        let ret = io::copy(&mut from, &mut to);
+        if ret.is_ok() {
+            // the peer of `to` sees the end, and may still answer
+            let _ = to.shutdown(Shutdown::Write);
+        } else {
+            // stop the other direction as well
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+        }
+        ret
+    })
+}
+
+fn joined(pipe: JoinHandle<io::Result<u64>>) -> io::Result<u64> {
+    pipe.join()
+        .unwrap_or_else(|_| Err(io::Error::other("copy coroutine panicked")))
+}
@@ -0,0 +1,253 @@
+//! client ip allow and deny lists
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// Allow and deny lists of client networks
+///
+/// A client is let in unless a [`deny`](Self::deny) network holds its
+/// address, or [`allow`](Self::allow) networks are set and none of them does.
+/// Networks are written in CIDR notation, e.g. `10.0.0.0/8` or
+/// `2001:db8::/32`, or as a single address. IPv4 clients of a dual stack
+/// listener match the IPv4 networks.
+///
+/// Set it on the listener with
+/// [`HttpConfig::with_ip_filter`](crate::HttpConfig::with_ip_filter) to close
+/// connections from denied clients right after the accept. Behind a proxy
+/// the connection comes from the proxy: mark it with
+/// [`trust_proxy`](Self::trust_proxy) and check each request with an
+/// [`IpFilterService`], which takes the client from `X-Forwarded-For`.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    proxies: Vec<Cidr>,
+}
+
+impl IpFilter {
+    /// Create a filter that lets everyone in
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let in only the clients of this and any other allowed network
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if `cidr` is not a network.
+    pub fn allow(mut self, cidr: &str) -> io::Result<Self> {
+        self.allow.push(cidr.parse()?);
+        Ok(self)
+    }
+
+    /// Keep out the clients of a network, even if they are allowed
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if `cidr` is not a network.
+    pub fn deny(mut self, cidr: &str) -> io::Result<Self> {
+        self.deny.push(cidr.parse()?);
+        Ok(self)
+    }
+
+    /// Trust the `X-Forwarded-For` header of requests from a proxy network
+    ///
+    /// Connections from trusted proxies are let in at accept time, their
+    /// requests are checked against the client the proxy forwarded them for.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if `cidr` is not a network.
+    pub fn trust_proxy(mut self, cidr: &str) -> io::Result<Self> {
+        self.proxies.push(cidr.parse()?);
+        Ok(self)
+    }
+
+    /// Whether the lists let `ip` in
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        !self.deny.iter().any(|net| net.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip)))
+    }
+
+    /// The client of a request that came in over a connection from `peer`
+    ///
+    /// Trusted proxies are skipped from the end of `X-Forwarded-For`, the
+    /// first address that is not one is the client. `None` if the header
+    /// holds something else than addresses.
+    pub fn client_ip(&self, peer: IpAddr, req: &Request) -> Option<IpAddr> {
+        let mut client = canonical(peer);
+        if !self.is_proxy(client) {
+            return Some(client);
+        }
+        let forwarded = req
+            .headers()
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case("x-forwarded-for"));
+        // the last header was added by the nearest proxy
+        let mut hops = Vec::new();
+        for h in forwarded {
+            let value = std::str::from_utf8(h.value).ok()?;
+            hops.extend(value.split(',').map(str::trim).filter(|s| !s.is_empty()));
+        }
+        for hop in hops.iter().rev() {
+            client = canonical(parse_hop(hop)?);
+            if !self.is_proxy(client) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    /// whether a connection from `peer` may go on to its requests
+    pub(crate) fn accepts(&self, peer: IpAddr) -> bool {
+        self.is_proxy(canonical(peer)) || self.is_allowed(peer)
+    }
+
+    fn is_proxy(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// `HttpService` wrapper answering `403 Forbidden` to clients an [`IpFilter`] denies
+///
+/// The request doesn't know the connection it came from, so create one per
+/// connection in
+/// [`new_service_with_info`](crate::HttpServiceFactory::new_service_with_info).
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use may_minihttp::{
+///     ConnectionInfo, HttpServer, HttpService, HttpServiceFactory, IpFilter, IpFilterService,
+///     Request, Response,
+/// };
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("hello");
+///         Ok(())
+///     }
+/// }
+///
+/// struct Internal(Arc<IpFilter>);
+///
+/// impl HttpServiceFactory for Internal {
+///     type Service = IpFilterService<Hello>;
+///
+///     fn new_service(&self, _id: usize) -> Self::Service {
+///         unreachable!("the server passes the connection info")
+///     }
+///
+///     fn new_service_with_info(&self, info: &ConnectionInfo) -> Self::Service {
+///         IpFilterService::new(Hello, self.0.clone(), info.peer_addr)
+///     }
+/// }
+///
+/// let filter = IpFilter::new()
+///     .allow("10.0.0.0/8").unwrap()
+///     .trust_proxy("192.168.1.10").unwrap();
+/// let server = Internal(Arc::new(filter)).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct IpFilterService<S> {
+    inner: S,
+    filter: Arc<IpFilter>,
+    peer: IpAddr,
+}
+
+impl<S> IpFilterService<S> {
+    /// Wrap `inner` for the connection from `peer`
+    pub fn new(inner: S, filter: Arc<IpFilter>, peer: SocketAddr) -> Self {
+        IpFilterService {
+            inner,
+            filter,
+            peer: peer.ip(),
+        }
+    }
+}
+
+impl<S: HttpService> HttpService for IpFilterService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match self.filter.client_ip(self.peer, &req) {
+            Some(ip) if self.filter.is_allowed(ip) => self.inner.call(req, rsp),
+            client => {
+                debug!("{} {} forbidden for {client:?}", req.method(), req.path());
+                rsp.status_code(403, "Forbidden");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// a network in CIDR notation
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Cidr {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid network: {s:?}, expected e.g. 10.0.0.0/8"),
+            )
+        };
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse().ok().filter(|&p| p <= max).ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+/// IPv4 clients of a dual stack listener show up as mapped IPv6 addresses
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    }
+}
+
+/// an `X-Forwarded-For` entry, proxies may add the port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|a| a.ip()))
+        .ok()
+}
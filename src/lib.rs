@@ -1,13 +1,117 @@
 #[macro_use]
-extern crate log;
+mod logging;
 
-mod date;
+#[cfg(feature = "access-log")]
+mod access_log;
+#[cfg(feature = "admin")]
+mod admin;
+#[cfg(feature = "admission")]
+mod admission;
+#[cfg(feature = "blocking")]
+mod blocking;
+#[cfg(feature = "cache")]
+mod cache;
+mod common_header;
+#[cfg(feature = "conditional")]
+mod conditional;
+mod config;
+pub mod date;
+#[cfg(feature = "digest")]
+mod digest;
+mod dispatch;
+mod drain;
+mod error;
+#[cfg(unix)]
+mod evict;
+#[cfg(feature = "header-map")]
+mod header_map;
+mod hijack;
 mod http_server;
+#[cfg(feature = "https-redirect")]
+mod https;
+pub mod io;
+#[cfg(feature = "ip-filter")]
+mod ip_filter;
+mod listener;
+pub mod mime;
+#[cfg(feature = "otel")]
+mod otel;
+mod post_process;
+mod privileges;
 mod request;
 mod response;
+mod server_builder;
+#[cfg(feature = "server-group")]
+mod server_group;
+mod server_handle;
+#[cfg(unix)]
+mod signals;
+#[cfg(feature = "spool")]
+mod spool;
+mod stats;
+pub mod test;
+#[cfg(feature = "timeout")]
+mod timeout;
+#[cfg(feature = "trace")]
+mod trace;
+#[cfg(feature = "tus")]
+mod tus;
 
-pub use http_server::{HttpServer, HttpServerWithHeaders, HttpService, HttpServiceFactory};
+#[cfg(feature = "access-log")]
+pub use access_log::{
+    AccessLogRecord, AccessLogService, AccessLogSink, ChannelSink, FileSink, StderrSink,
+};
+#[cfg(feature = "admin")]
+pub use admin::AdminService;
+#[cfg(feature = "admission")]
+pub use admission::AdmissionService;
+#[cfg(feature = "blocking")]
+pub use blocking::spawn_blocking;
+#[cfg(feature = "cache")]
+pub use cache::{CacheService, ResponseCache};
+pub use common_header::{CommonHeader, ContentType};
+#[cfg(feature = "conditional")]
+pub use conditional::ConditionalService;
+#[cfg(feature = "config-file")]
+pub use config::ServerConfig;
+pub use config::{
+    BufGrowth, ConnectionErrorHook, HeaderValidation, HttpConfig, ParseErrorHook,
+    RequestCompleteHook,
+};
+#[cfg(feature = "digest")]
+pub use digest::DigestService;
+pub use dispatch::HttpServerConcurrent;
+pub use drain::Drain;
+pub use error::HttpError;
+#[cfg(feature = "header-map")]
+pub use header_map::HeaderMap;
+pub use hijack::Hijacked;
+pub use http_server::{
+    serve_stream, ConnectionInfo, HttpServer, HttpServerWithHeaders, HttpService,
+    HttpServiceFactory,
+};
+#[cfg(feature = "https-redirect")]
+pub use https::HttpsRedirect;
+#[cfg(feature = "ip-filter")]
+pub use ip_filter::{IpFilter, IpFilterService};
+#[cfg(feature = "otel")]
+pub use otel::OtelService;
+pub use post_process::PostProcess;
 pub use request::{
-    decode_default, decode_large, decode_standard, decode_xlarge, BodyReader, MaxHeaders, Request,
+    decode_default, decode_large, decode_standard, decode_xlarge, BodyReader, Charset, MaxHeaders,
+    OwnedRequest, ReplayBody, Request,
 };
-pub use response::Response;
+pub use response::{encode, Response};
+pub use server_builder::HttpServerBuilder;
+#[cfg(feature = "server-group")]
+pub use server_group::{ServerGroup, ServerGroupBuilder};
+pub use server_handle::ServerHandle;
+#[cfg(feature = "spool")]
+pub use spool::SpooledBody;
+pub use stats::{ConnectionStats, ServerStats};
+#[cfg(feature = "timeout")]
+pub use timeout::TimeoutService;
+#[cfg(feature = "trace")]
+pub use trace::TraceContext;
+#[cfg(feature = "tus")]
+pub use tus::TusService;
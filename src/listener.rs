@@ -0,0 +1,136 @@
+//! listening sockets and accepted connections set up from an `HttpConfig`
+
+use std::io;
+use std::mem::ManuallyDrop;
+use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, FromRawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, FromRawSocket};
+use std::time::Duration;
+
+use may::net::{TcpListener, TcpStream};
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+
+use crate::config::HttpConfig;
+
+/// bind a listener to the first address of `addr` that works
+///
+/// a plain bind unless the config asks for listener options
+pub(crate) fn bind<A: ToSocketAddrs>(addr: A, config: &HttpConfig) -> io::Result<TcpListener> {
+    let plain = config.backlog == 0 && config.defer_accept_secs == 0 && config.fastopen_queue == 0;
+    if plain && config.ipv6_only.is_none() {
+        return TcpListener::bind(addr);
+    }
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match bind_addr(addr, config) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        let msg = "could not resolve to any addresses";
+        io::Error::new(io::ErrorKind::InvalidInput, msg)
+    }))
+}
+
+fn bind_addr(addr: SocketAddr, config: &HttpConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // as in std, a restarted server binds while old connections are in TIME_WAIT
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if let (true, Some(ipv6_only)) = (addr.is_ipv6(), config.ipv6_only) {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    socket.bind(&addr.into())?;
+    set_tcp_options(&socket, config);
+    // the OS caps it at its own limit, e.g. net.core.somaxconn on Linux
+    let backlog = match config.backlog {
+        0 => 128,
+        n => n.min(i32::MAX as u32) as i32,
+    };
+    socket.listen(backlog)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// the Linux only options, a listener without them still works
+#[cfg(target_os = "linux")]
+fn set_tcp_options(socket: &Socket, config: &HttpConfig) {
+    let options = [
+        (
+            "TCP_DEFER_ACCEPT",
+            libc::TCP_DEFER_ACCEPT,
+            config.defer_accept_secs,
+        ),
+        ("TCP_FASTOPEN", libc::TCP_FASTOPEN, config.fastopen_queue),
+    ];
+    for (name, option, value) in options {
+        if value == 0 {
+            continue;
+        }
+        if let Err(e) = set_tcp_option(socket, option, value) {
+            warn!("can't set {name} on the listener: {e}");
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_tcp_options(_socket: &Socket, config: &HttpConfig) {
+    if config.defer_accept_secs != 0 || config.fastopen_queue != 0 {
+        debug!("defer_accept_secs and fastopen_queue are only supported on Linux");
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_tcp_option(socket: &Socket, option: libc::c_int, value: u32) -> io::Result<()> {
+    let value = value.min(i32::MAX as u32) as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            (&value as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// set `SO_LINGER` on an accepted connection
+pub(crate) fn set_linger(stream: &TcpStream, linger: Option<Duration>) -> io::Result<()> {
+    borrow_socket(stream).set_linger(linger)
+}
+
+/// turn on TCP keepalive probes on an accepted connection, `None` values
+/// keep the OS defaults
+pub(crate) fn set_keepalive(
+    stream: &TcpStream,
+    idle: Duration,
+    interval: Option<Duration>,
+    retries: Option<u32>,
+) -> io::Result<()> {
+    let mut keepalive = TcpKeepalive::new().with_time(idle);
+    if let Some(interval) = interval {
+        keepalive = keepalive.with_interval(interval);
+    }
+    #[cfg(not(windows))]
+    if let Some(retries) = retries {
+        keepalive = keepalive.with_retries(retries);
+    }
+    #[cfg(windows)]
+    let _ = retries;
+    borrow_socket(stream).set_tcp_keepalive(&keepalive)
+}
+
+/// the socket of `stream` for setting options, it stays open when dropped
+fn borrow_socket(stream: &TcpStream) -> ManuallyDrop<Socket> {
+    #[cfg(unix)]
+    let socket = unsafe { Socket::from_raw_fd(stream.as_raw_fd()) };
+    #[cfg(windows)]
+    let socket = unsafe { Socket::from_raw_socket(stream.as_raw_socket()) };
+    ManuallyDrop::new(socket)
+}
@@ -0,0 +1,43 @@
+//! the log macros of the crate, compiled out without the `logging` feature
+
+#[cfg(feature = "logging")]
+macro_rules! log {
+    ($($arg:tt)+) => {
+        ::log::log!($($arg)+)
+    };
+}
+
+// the arguments are still type checked, so they count as used
+#[cfg(not(feature = "logging"))]
+macro_rules! log {
+    ($lvl:expr, $($arg:tt)+) => {{
+        let _ = $lvl;
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => {
+        log!(::log::Level::Error, $($arg)+)
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        log!(::log::Level::Warn, $($arg)+)
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => {
+        log!(::log::Level::Info, $($arg)+)
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        log!(::log::Level::Debug, $($arg)+)
+    };
+}
@@ -0,0 +1,103 @@
+//! file extension to `Content-Type` mapping
+//!
+//! # Example
+//! ```
+//! use may_minihttp::mime;
+//!
+//! assert_eq!(mime::from_path("static/app.js"), "text/javascript; charset=utf-8");
+//! assert_eq!(mime::from_path("archive.unknown"), mime::OCTET_STREAM);
+//!
+//! mime::register("wasm2", "application/wasm");
+//! assert_eq!(mime::from_extension("WASM2"), Some("application/wasm"));
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+/// The fallback type for unknown extensions
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+static BUILTIN: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("br", "application/x-brotli"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+static CUSTOM: Lazy<RwLock<HashMap<String, &'static str>>> = Lazy::new(Default::default);
+// fast check to skip the lock when nothing was registered
+static HAS_CUSTOM: AtomicBool = AtomicBool::new(false);
+
+/// Register (or override) the `Content-Type` for an extension
+///
+/// The extension is matched case-insensitively and without the leading dot.
+pub fn register(ext: &str, content_type: &'static str) {
+    let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+    CUSTOM.write().unwrap().insert(ext, content_type);
+    HAS_CUSTOM.store(true, Ordering::Release);
+}
+
+/// Look up the `Content-Type` for an extension, `None` if it's unknown
+pub fn from_extension(ext: &str) -> Option<&'static str> {
+    let ext = ext.trim_start_matches('.');
+    if HAS_CUSTOM.load(Ordering::Acquire) {
+        let custom = CUSTOM.read().unwrap();
+        if let Some(t) = custom.get(ext) {
+            return Some(t);
+        }
+        if let Some(t) = custom.get(&ext.to_ascii_lowercase()) {
+            return Some(t);
+        }
+    }
+    BUILTIN
+        .iter()
+        .find(|(e, _)| e.eq_ignore_ascii_case(ext))
+        .map(|(_, t)| *t)
+}
+
+/// Guess the `Content-Type` of a path from its extension
+///
+/// Returns [`OCTET_STREAM`] when the extension is missing or unknown.
+pub fn from_path<P: AsRef<Path>>(path: P) -> &'static str {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(from_extension)
+        .unwrap_or(OCTET_STREAM)
+}
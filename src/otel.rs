@@ -0,0 +1,143 @@
+//! OpenTelemetry server spans on top of any `HttpService`
+
+use std::io;
+use std::sync::Arc;
+
+use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::KeyValue;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that records a server span for every request
+///
+/// The parent context is extracted from the request headers with the
+/// global text map propagator, see
+/// [`set_text_map_propagator`](opentelemetry::global::set_text_map_propagator),
+/// so the span joins the caller's trace. It carries the `http.method`,
+/// `http.target` and `http.status_code` attributes, and `http.route` when
+/// [`route`](Self::route) names one for the path. The span is named
+/// `{method} {route}`, or just the method without a route to keep the
+/// names few. A `5xx` answer or an error from the inner service sets the
+/// error status.
+///
+/// The span is not made the current context: a coroutine may resume on
+/// another thread, where the thread local context would be wrong. Spans are
+/// exported by whatever the tracer's provider is built with.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpServer, HttpService, OtelService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Users;
+///
+/// impl HttpService for Users {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("[]");
+///         Ok(())
+///     }
+/// }
+///
+/// fn route(path: &str) -> Option<&'static str> {
+///     path.starts_with("/users/").then_some("/users/{id}")
+/// }
+///
+/// // install a provider with an exporter first, e.g. from opentelemetry_sdk
+/// let tracer = opentelemetry::global::tracer("my-app");
+/// let service = OtelService::new(Users, tracer).route(route);
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+pub struct OtelService<S, T> {
+    inner: S,
+    tracer: Arc<T>,
+    route: Option<fn(&str) -> Option<&'static str>>,
+}
+
+impl<S, T: Tracer> OtelService<S, T> {
+    /// Wrap `inner`, recording its requests with `tracer`
+    pub fn new(inner: S, tracer: T) -> Self {
+        OtelService {
+            inner,
+            tracer: Arc::new(tracer),
+            route: None,
+        }
+    }
+
+    /// Name the route template a path matches, e.g. `/users/{id}`, for
+    /// `http.route` and the span name
+    pub fn route(mut self, route: fn(&str) -> Option<&'static str>) -> Self {
+        self.route = Some(route);
+        self
+    }
+}
+
+// the tracer is shared, it need not be `Clone` itself
+impl<S: Clone, T> Clone for OtelService<S, T> {
+    fn clone(&self) -> Self {
+        OtelService {
+            inner: self.inner.clone(),
+            tracer: self.tracer.clone(),
+            route: self.route,
+        }
+    }
+}
+
+impl<S: HttpService, T: Tracer> HttpService for OtelService<S, T> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let parent =
+            global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+        let method = req.method().to_owned();
+        let route = self.route.and_then(|route| route(req.path()));
+        let name = match route {
+            Some(route) => format!("{method} {route}"),
+            None => method.clone(),
+        };
+        let mut attributes = vec![
+            KeyValue::new("http.method", method),
+            KeyValue::new("http.target", req.path().to_owned()),
+        ];
+        if let Some(route) = route {
+            attributes.push(KeyValue::new("http.route", route));
+        }
+        let mut span = self
+            .tracer
+            .span_builder(name)
+            .with_kind(SpanKind::Server)
+            .with_attributes(attributes)
+            .start_with_context(&*self.tracer, &parent);
+
+        let result = self.inner.call(req, rsp);
+        let status = match &result {
+            Ok(()) => rsp.status().0,
+            Err(e) => {
+                span.record_error(e);
+                500
+            }
+        };
+        span.set_attribute(KeyValue::new("http.status_code", status as i64));
+        if status >= 500 {
+            span.set_status(Status::error(""));
+        }
+        span.end();
+        result
+    }
+}
+
+/// the request headers as the propagator reads them
+struct HeaderExtractor<'a>(&'a [httparse::Header<'a>]);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        let header = self.0.iter().find(|h| h.name.eq_ignore_ascii_case(key))?;
+        std::str::from_utf8(header.value).ok()
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|h| h.name).collect()
+    }
+}
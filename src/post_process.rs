@@ -0,0 +1,61 @@
+//! response post-processing on top of any `HttpService`
+
+use std::io;
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that runs a hook on every successful response
+///
+/// The hook is called after the inner service filled the `Response` and
+/// before it is encoded, so it can inspect the status, headers and body
+/// length and add headers centrally. It is not called when the inner
+/// service returns an error.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpServer, HttpService, PostProcess, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("Hello, World!");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = PostProcess::new(Hello, |rsp: &mut Response| {
+///     rsp.header("X-Content-Type-Options: nosniff");
+///     if rsp.status().0 >= 500 {
+///         rsp.header("Cache-Control: no-store");
+///     }
+/// });
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct PostProcess<S, F> {
+    inner: S,
+    hook: F,
+}
+
+impl<S, F> PostProcess<S, F>
+where
+    F: Fn(&mut Response),
+{
+    /// Wrap `inner`, running `hook` on each response it produces
+    pub fn new(inner: S, hook: F) -> Self {
+        PostProcess { inner, hook }
+    }
+}
+
+impl<S: HttpService, F: Fn(&mut Response)> HttpService for PostProcess<S, F> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        self.inner.call(req, rsp)?;
+        (self.hook)(rsp);
+        Ok(())
+    }
+}
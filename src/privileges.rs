@@ -0,0 +1,171 @@
+//! giving up root once the listeners are bound
+
+#[cfg(unix)]
+use std::ffi::{CStr, CString};
+use std::io;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use may::coroutine;
+
+// `RunAs::state`
+const PENDING: u8 = 0;
+const DROPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// The user and group the accept loops run as
+///
+/// The loops started with it wait until the privileges are dropped, so no
+/// connection is ever served as root.
+#[derive(Debug)]
+pub(crate) struct RunAs {
+    user: String,
+    group: String,
+    state: AtomicU8,
+}
+
+impl RunAs {
+    pub(crate) fn new(user: &str, group: &str) -> Self {
+        RunAs {
+            user: user.to_owned(),
+            group: group.to_owned(),
+            state: AtomicU8::new(PENDING),
+        }
+    }
+
+    /// switch the process to the user and group, then let the loops go on
+    pub(crate) fn drop_privileges(&self) -> io::Result<()> {
+        let result = self.switch();
+        let state = if result.is_ok() { DROPPED } else { FAILED };
+        self.state.store(state, Ordering::Release);
+        result
+    }
+
+    #[cfg(unix)]
+    fn switch(&self) -> io::Result<()> {
+        let uid = lookup_user(&self.user)?;
+        let gid = lookup_group(&self.group)?;
+        if unsafe { libc::geteuid() == uid && libc::getegid() == gid } {
+            return Ok(());
+        }
+        // the group first, a process that gave up root can't change it anymore
+        let groups = [gid];
+        check(unsafe { libc::setgroups(1, groups.as_ptr()) })?;
+        check(unsafe { libc::setgid(gid) })?;
+        check(unsafe { libc::setuid(uid) })?;
+        if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            let msg = "root privileges could be regained after dropping them";
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+        }
+        info!("running as user {} and group {}", self.user, self.group);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn switch(&self) -> io::Result<()> {
+        let msg = "running as another user is only supported on Unix";
+        Err(io::Error::new(io::ErrorKind::Unsupported, msg))
+    }
+
+    /// let the loops go without serving, a listener could not be bound
+    fn abort(&self) {
+        self.state.store(FAILED, Ordering::Release);
+    }
+
+    /// wait for [`drop_privileges`](Self::drop_privileges), `false` if it failed
+    pub(crate) fn wait(&self) -> bool {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                PENDING => coroutine::sleep(Duration::from_millis(1)),
+                state => return state == DROPPED,
+            }
+        }
+    }
+}
+
+/// drop the privileges once all the listeners of `started` are bound,
+/// their accept loops end if that fails
+pub(crate) fn after_start<T>(run_as: Option<&RunAs>, started: io::Result<T>) -> io::Result<T> {
+    let Some(run_as) = run_as else {
+        return started;
+    };
+    match started {
+        Ok(servers) => run_as.drop_privileges().map(|()| servers),
+        Err(e) => {
+            run_as.abort();
+            Err(e)
+        }
+    }
+}
+
+/// the uid of a user name, or of a number
+#[cfg(unix)]
+fn lookup_user(user: &str) -> io::Result<libc::uid_t> {
+    let name = c_name(user)?;
+    let mut buf = vec![0; 16 * 1024];
+    let mut pwd = unsafe { std::mem::zeroed::<libc::passwd>() };
+    let mut found = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    if !found.is_null() {
+        return Ok(pwd.pw_uid);
+    }
+    user.parse().map_err(|_| not_found("user", &name))
+}
+
+/// the gid of a group name, or of a number
+#[cfg(unix)]
+fn lookup_group(group: &str) -> io::Result<libc::gid_t> {
+    let name = c_name(group)?;
+    let mut buf = vec![0; 16 * 1024];
+    let mut grp = unsafe { std::mem::zeroed::<libc::group>() };
+    let mut found = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    if !found.is_null() {
+        return Ok(grp.gr_gid);
+    }
+    group.parse().map_err(|_| not_found("group", &name))
+}
+
+#[cfg(unix)]
+fn c_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        let msg = format!("invalid user or group name: {name:?}");
+        io::Error::new(io::ErrorKind::InvalidInput, msg)
+    })
+}
+
+#[cfg(unix)]
+fn not_found(what: &str, name: &CStr) -> io::Error {
+    let msg = format!("no such {what}: {}", name.to_string_lossy());
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
+
+#[cfg(unix)]
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 use std::io::{self, BufRead, Read};
 use std::mem::MaybeUninit;
+use std::time::SystemTime;
 
 /// Maximum header buffer size configurations.
 ///
@@ -117,6 +118,9 @@ pub enum MaxHeaders {
     /// - Minimum: 16 (if 0 is specified)
     /// - Maximum: 256
     ///
+    /// Limits over 128 take their header slots from the heap, allocated for
+    /// each request, rather than from the coroutine stack.
+    ///
     /// # Example
     /// ```rust
     /// use may_minihttp::MaxHeaders;
@@ -156,75 +160,195 @@ impl MaxHeaders {
     }
 }
 
+impl std::str::FromStr for MaxHeaders {
+    type Err = io::Error;
+
+    /// Parse a variant name (`default`, `standard`, `large`, `xlarge`) or a
+    /// header count, e.g. the value of `MINIHTTP_MAX_HEADERS`
+    fn from_str(s: &str) -> io::Result<Self> {
+        let s = s.trim();
+        let v = if s.eq_ignore_ascii_case("default") {
+            MaxHeaders::Default
+        } else if s.eq_ignore_ascii_case("standard") {
+            MaxHeaders::Standard
+        } else if s.eq_ignore_ascii_case("large") {
+            MaxHeaders::Large
+        } else if s.eq_ignore_ascii_case("xlarge") {
+            MaxHeaders::XLarge
+        } else {
+            match s.parse::<usize>() {
+                Ok(16) => MaxHeaders::Default,
+                Ok(32) => MaxHeaders::Standard,
+                Ok(64) => MaxHeaders::Large,
+                Ok(128) => MaxHeaders::XLarge,
+                Ok(n) => MaxHeaders::Custom(n),
+                Err(_) => {
+                    let msg = format!("invalid max headers value: {s:?}");
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+                }
+            }
+        };
+        Ok(v)
+    }
+}
+
 /// Default maximum number of HTTP headers (backwards compatible)
 pub(crate) const MAX_HEADERS: usize = MaxHeaders::Default.value();
 
-use bytes::{Buf, BufMut, BytesMut};
-use may::net::TcpStream;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+use crate::date::HttpDate;
+use crate::error::HttpError;
 use crate::http_server::err;
+#[cfg(feature = "spool")]
+use crate::spool::SpooledBody;
+#[cfg(feature = "trace")]
+use crate::trace::{self, TraceContext};
+
+/// A text encoding [`Request::body_text`] decodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Charset {
+    /// `utf-8`, the default without a `charset` parameter
+    Utf8,
+    /// `iso-8859-1`, also labeled `latin1`
+    Latin1,
+    /// `us-ascii`
+    Ascii,
+}
+
+impl Charset {
+    /// the charset of a `Content-Type` label, case-insensitively
+    fn from_label(label: &str) -> Option<Self> {
+        const LABELS: &[(&str, Charset)] = &[
+            ("utf-8", Charset::Utf8),
+            ("utf8", Charset::Utf8),
+            ("iso-8859-1", Charset::Latin1),
+            ("iso8859-1", Charset::Latin1),
+            ("iso_8859-1", Charset::Latin1),
+            ("latin1", Charset::Latin1),
+            ("l1", Charset::Latin1),
+            ("cp819", Charset::Latin1),
+            ("us-ascii", Charset::Ascii),
+            ("ascii", Charset::Ascii),
+        ];
+        LABELS
+            .iter()
+            .find(|(l, _)| l.eq_ignore_ascii_case(label))
+            .map(|&(_, charset)| charset)
+    }
+
+    /// decode `bytes`, failing with the length of the valid start
+    fn decode(self, bytes: Vec<u8>) -> Result<String, usize> {
+        match self {
+            Charset::Utf8 => String::from_utf8(bytes).map_err(|e| e.utf8_error().valid_up_to()),
+            Charset::Ascii => match bytes.iter().position(|b| !b.is_ascii()) {
+                Some(invalid) => Err(invalid),
+                None => Ok(String::from_utf8(bytes).expect("ascii is utf-8")),
+            },
+            // the bytes are the code points
+            Charset::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+        }
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Latin1 => "iso-8859-1",
+            Charset::Ascii => "us-ascii",
+        })
+    }
+}
+
+// where the body bytes come from
+enum BodySource<'buf, 'stream> {
+    // the connection buffer, refilled from the stream
+    Conn {
+        req_buf: &'buf mut BytesMut,
+        stream: &'stream mut dyn Read,
+    },
+    // the rest of a parsed slice
+    Slice(&'buf [u8]),
+}
+
+/// tells whether the client of a connection is still there
+pub(crate) trait ConnProbe {
+    fn is_connected(&self) -> bool;
+}
 
 pub struct BodyReader<'buf, 'stream> {
-    // remaining bytes for body
-    req_buf: &'buf mut BytesMut,
+    src: BodySource<'buf, 'stream>,
     // the max body length limit
     body_limit: usize,
     // total read count
     total_read: usize,
-    // used to read extra body bytes
-    stream: &'stream mut TcpStream,
 }
 
-impl BodyReader<'_, '_> {
-    fn read_more_data(&mut self) -> io::Result<usize> {
-        crate::http_server::reserve_buf(self.req_buf);
-        let read_buf: &mut [u8] = unsafe { std::mem::transmute(self.req_buf.chunk_mut()) };
-        let n = self.stream.read(read_buf)?;
-        unsafe { self.req_buf.advance_mut(n) };
-        Ok(n)
-    }
+fn read_more_data(req_buf: &mut BytesMut, stream: &mut dyn Read) -> io::Result<usize> {
+    crate::http_server::reserve_buf(req_buf);
+    let read_buf: &mut [u8] = unsafe { std::mem::transmute(req_buf.chunk_mut()) };
+    let n = stream.read(read_buf)?;
+    unsafe { req_buf.advance_mut(n) };
+    Ok(n)
 }
 
 impl Read for BodyReader<'_, '_> {
     // the user should control the body reading, don't exceeds the body!
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if self.total_read >= self.body_limit {
-            return Ok(0);
-        }
-
-        loop {
-            if !self.req_buf.is_empty() {
-                let min_len = buf.len().min(self.body_limit - self.total_read);
-                let n = self.req_buf.reader().read(&mut buf[..min_len])?;
-                self.total_read += n;
-                return Ok(n);
-            }
-
-            if self.read_more_data()? == 0 {
-                return Ok(0);
-            }
+        let n = self.fill_buf()?.read(buf)?;
+        self.total_read += n;
+        match self.src {
+            BodySource::Conn {
+                ref mut req_buf, ..
+            } => req_buf.advance(n),
+            BodySource::Slice(ref mut s) => *s = &s[n..],
         }
+        Ok(n)
     }
 }
 
+/// the body ended before its `Content-Length`, e.g. the client went away
+fn short_body(read: usize, len: usize) -> io::Error {
+    let msg = format!("request body ended after {read} of {len} bytes");
+    io::Error::new(io::ErrorKind::UnexpectedEof, msg)
+}
+
 impl BufRead for BodyReader<'_, '_> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         let remain = self.body_limit - self.total_read;
         if remain == 0 {
             return Ok(&[]);
         }
-        if self.req_buf.is_empty() {
-            self.read_more_data()?;
+        match self.src {
+            BodySource::Conn {
+                ref mut req_buf,
+                ref mut stream,
+            } => {
+                if req_buf.is_empty() && read_more_data(req_buf, *stream)? == 0 {
+                    return Err(short_body(self.total_read, self.body_limit));
+                }
+                let n = req_buf.len().min(remain);
+                Ok(&req_buf[..n])
+            }
+            BodySource::Slice([]) => Err(short_body(self.total_read, self.body_limit)),
+            BodySource::Slice(s) => Ok(&s[..s.len().min(remain)]),
         }
-        let n = self.req_buf.len().min(remain);
-        Ok(&self.req_buf.chunk()[0..n])
     }
 
     fn consume(&mut self, amt: usize) {
         assert!(amt <= self.body_limit - self.total_read);
-        assert!(amt <= self.req_buf.len());
         self.total_read += amt;
-        self.req_buf.advance(amt)
+        match self.src {
+            BodySource::Conn {
+                ref mut req_buf, ..
+            } => {
+                assert!(amt <= req_buf.len());
+                req_buf.advance(amt)
+            }
+            BodySource::Slice(ref mut s) => *s = &s[amt..],
+        }
     }
 }
 
@@ -241,14 +365,69 @@ impl Drop for BodyReader<'_, '_> {
     }
 }
 
-// we should hold the mut ref of req_buf
+// we should hold the mut ref of req_buf (or the parsed slice)
 // before into body, this req_buf is only for holding headers
 // after into body, this req_buf is mutable to read extra body bytes
 // and the headers buf can be reused
 pub struct Request<'buf, 'header, 'stream> {
     req: httparse::Request<'header, 'buf>,
-    req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    // the request line and headers as received
+    head: &'buf [u8],
+    // checked when the request is parsed
+    content_length: usize,
+    src: BodySource<'buf, 'stream>,
+    probe: Option<&'stream dyn ConnProbe>,
+}
+
+impl<'buf, 'header> Request<'buf, 'header, 'static> {
+    /// Parse a request from a byte slice, without any stream behind it
+    ///
+    /// The body is read from the bytes following the headers, up to the
+    /// `Content-Length`. Useful for fuzzing, benchmarks and replaying
+    /// captured traffic.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request is malformed, has an invalid
+    /// `Content-Length` or has more headers than `headers` can hold.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Read;
+    /// use may_minihttp::Request;
+    ///
+    /// let buf = b"POST /echo HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+    /// let mut headers = [httparse::EMPTY_HEADER; 16];
+    /// let req = match Request::parse(buf, &mut headers).unwrap() {
+    ///     httparse::Status::Complete(req) => req,
+    ///     httparse::Status::Partial => unreachable!(),
+    /// };
+    /// assert_eq!(req.path(), "/echo");
+    ///
+    /// let mut body = String::new();
+    /// req.body().read_to_string(&mut body).unwrap();
+    /// assert_eq!(body, "hello");
+    /// ```
+    pub fn parse(
+        buf: &'buf [u8],
+        headers: &'header mut [httparse::Header<'buf>],
+    ) -> io::Result<httparse::Status<Self>> {
+        let header_limit = headers.len();
+        let mut req = httparse::Request::new(headers);
+        let len = match req.parse(buf) {
+            Ok(httparse::Status::Complete(amt)) => amt,
+            Ok(httparse::Status::Partial) => return Ok(httparse::Status::Partial),
+            Err(e) => return Err(HttpError::new(e, buf, header_limit).into()),
+        };
+        let content_length = content_length(req.headers)?;
+        Ok(httparse::Status::Complete(Request {
+            req,
+            head: &buf[..len],
+            content_length,
+            src: BodySource::Slice(&buf[len..]),
+            probe: None,
+        }))
+    }
 }
 
 impl<'buf, 'stream> Request<'buf, '_, 'stream> {
@@ -268,37 +447,639 @@ impl<'buf, 'stream> Request<'buf, '_, 'stream> {
         self.req.headers
     }
 
+    /// The headers as `(name, value)` pairs, in the order they were received
+    ///
+    /// Values are the raw bytes, they need not be UTF-8.
+    pub fn headers_iter(&self) -> impl Iterator<Item = (&'buf str, &'buf [u8])> + '_ {
+        self.req.headers.iter().map(|h| (h.name, h.value))
+    }
+
+    /// The header lines exactly as received
+    ///
+    /// Every line keeps its line ending, the request line and the empty line
+    /// ending the headers are left out, so a request without headers gives an
+    /// empty slice. For checking signatures over the on-wire bytes, e.g. HTTP
+    /// Message Signatures or AWS SigV4, where the parsed values may not match
+    /// what was signed.
+    ///
+    /// # Example
+    /// ```
+    /// use may_minihttp::Request;
+    ///
+    /// let buf = b"GET / HTTP/1.1\r\nHost: a\r\nX-Sig:  v \r\n\r\n";
+    /// let mut headers = [httparse::EMPTY_HEADER; 16];
+    /// let req = match Request::parse(buf, &mut headers).unwrap() {
+    ///     httparse::Status::Complete(req) => req,
+    ///     httparse::Status::Partial => unreachable!(),
+    /// };
+    /// assert_eq!(req.raw_header_block(), b"Host: a\r\nX-Sig:  v \r\n");
+    /// ```
+    pub fn raw_header_block(&self) -> &'buf [u8] {
+        let head = self.head;
+        let start = memchr::memchr(b'\n', head).map_or(head.len(), |i| i + 1);
+        let end = if head.ends_with(b"\r\n") {
+            head.len() - 2
+        } else {
+            head.len().saturating_sub(1)
+        };
+        &head[start.min(end)..end]
+    }
+
+    /// Whether the connection stays open after this request
+    ///
+    /// `Connection: close` or `keep-alive` decide if present, otherwise
+    /// HTTP/1.1 defaults to keep-alive and HTTP/1.0 to close. The server
+    /// closes the connection after answering a request for which this is
+    /// `false`.
+    pub fn is_keep_alive(&self) -> bool {
+        if self.has_token("connection", "close") {
+            return false;
+        }
+        self.version() >= 1 || self.has_token("connection", "keep-alive")
+    }
+
+    /// Whether the client asks to switch protocols, e.g. to WebSocket
+    ///
+    /// True when there is an `Upgrade` header and `Connection` lists `upgrade`.
+    pub fn is_upgrade(&self) -> bool {
+        self.has_token("connection", "upgrade")
+            && self
+                .headers()
+                .iter()
+                .any(|h| h.name.eq_ignore_ascii_case("upgrade"))
+    }
+
+    /// Whether the client is still there to take the response
+    ///
+    /// Peeks at the connection without reading from it, and turns false
+    /// once the client closed it or it failed. A long-running handler can
+    /// poll this to give up early, rather than compute a response only to
+    /// fail writing it. A client that just shut down its sending side looks
+    /// gone as well, the two can't be told apart. Always true when there is
+    /// no TCP connection behind the request, e.g. for [`parse`](Self::parse)
+    /// and [`serve_stream`](crate::serve_stream).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Report;
+    ///
+    /// impl HttpService for Report {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         let mut report = String::new();
+    ///         for part in 0..1000 {
+    ///             if !req.is_client_connected() {
+    ///                 return Err(std::io::ErrorKind::ConnectionAborted.into());
+    ///             }
+    ///             report.push_str(&format!("part {part}\n"));
+    ///         }
+    ///         rsp.body_vec(report.into_bytes());
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    pub fn is_client_connected(&self) -> bool {
+        self.probe.map_or(true, |p| p.is_connected())
+    }
+
+    /// the connection loop watches the client through `probe`
+    pub(crate) fn set_probe(&mut self, probe: &'stream dyn ConnProbe) {
+        self.probe = Some(probe);
+    }
+
+    /// the `Content-Length` of the request, `0` without one
+    pub(crate) fn content_length(&self) -> usize {
+        self.content_length
+    }
+
+    /// Whether an HTTP/1.1 client waits for `100 Continue` before sending the body
+    pub fn expects_continue(&self) -> bool {
+        self.version() >= 1 && self.has_token("expect", "100-continue")
+    }
+
+    /// Whether the `If-Match` and `If-Unmodified-Since` preconditions hold
+    ///
+    /// Pass the current entity tag of the resource, quotes included, and its
+    /// last modification time, `None` for what is not known. `If-Match`
+    /// needs a strong match with `etag`, or `*` with any `etag`, so it fails
+    /// when there is none. Without `If-Match`, a valid `If-Unmodified-Since`
+    /// fails when the resource changed after that date. When this returns
+    /// `false` answer `412 Precondition Failed` and leave the resource as is.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// struct Document {
+    ///     version: u64,
+    /// }
+    ///
+    /// impl HttpService for Document {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         let etag = format!("\"v{}\"", self.version);
+    ///         if !req.preconditions_hold(Some(&etag), None) {
+    ///             rsp.status_code(412, "Precondition Failed");
+    ///             return Ok(());
+    ///         }
+    ///         self.version += 1;
+    ///         rsp.header_kv("ETag", format!("\"v{}\"", self.version));
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    pub fn preconditions_hold(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> bool {
+        let mut if_match = None;
+        let mut unmodified_since = None;
+        for h in self.headers() {
+            if h.name.eq_ignore_ascii_case("if-match") {
+                // several lines form one list
+                let tags = if_match.get_or_insert(false);
+                *tags |= etag.is_some_and(|etag| strong_match(h.value, etag));
+            } else if h.name.eq_ignore_ascii_case("if-unmodified-since") {
+                unmodified_since = std::str::from_utf8(h.value)
+                    .ok()
+                    .and_then(|v| v.parse::<HttpDate>().ok());
+            }
+        }
+        if let Some(matched) = if_match {
+            return matched;
+        }
+        match (unmodified_since, last_modified) {
+            (Some(since), Some(modified)) => HttpDate::from(modified) <= since,
+            _ => true,
+        }
+    }
+
+    /// The trace context the caller propagated, if any
+    ///
+    /// Read from the W3C `traceparent` and `tracestate` headers, or the
+    /// Zipkin `b3` or `X-B3-*` headers when there is no `traceparent`. `None`
+    /// when there is no trace or it is malformed, in which case a new trace
+    /// should be started.
+    #[cfg(feature = "trace")]
+    pub fn trace_context(&self) -> Option<TraceContext> {
+        trace::extract(self.headers())
+    }
+
+    /// whether any `name` header lists `token`, case-insensitively
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.headers()
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(name))
+            .flat_map(|h| h.value.split(|&b| b == b','))
+            .any(|t| t.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+    }
+
+    /// Copy the headers into an owned map that can outlive the request
+    #[cfg(feature = "header-map")]
+    pub fn header_map(&self) -> crate::HeaderMap {
+        crate::HeaderMap::from_headers(self.req.headers)
+    }
+
     pub fn body(self) -> BodyReader<'buf, 'stream> {
         BodyReader {
-            body_limit: self.content_length(),
+            body_limit: self.content_length,
             total_read: 0,
-            stream: self.stream,
-            req_buf: self.req_buf,
+            src: self.src,
         }
     }
 
-    fn content_length(&self) -> usize {
-        let mut len = 0;
-        for header in self.req.headers.iter() {
-            if header.name.eq_ignore_ascii_case("content-length") {
-                len = std::str::from_utf8(header.value).unwrap().parse().unwrap();
-                break;
+    /// Detach the request from the connection buffer
+    ///
+    /// Copies the method, path and headers and reads the whole body, so the
+    /// result can be sent to another coroutine and answered later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the `Content-Length` is bigger than
+    /// `max_body`, or any error from reading the body.
+    pub fn into_owned(self, max_body: usize) -> io::Result<OwnedRequest> {
+        let len = self.checked_body_len(max_body)?;
+        let method = self.method().to_owned();
+        let path = self.path().to_owned();
+        let version = self.version();
+        let headers = self
+            .headers()
+            .iter()
+            .map(|h| (h.name.to_owned(), h.value.to_vec()))
+            .collect();
+        let head = self.head.to_vec();
+        let mut body = Vec::with_capacity(len);
+        self.body().read_to_end(&mut body)?;
+        Ok(OwnedRequest {
+            method,
+            path,
+            version,
+            headers,
+            head,
+            body,
+        })
+    }
+
+    /// Read the whole body into memory so it can be read more than once
+    ///
+    /// Useful for retry or proxy logic that has to resend the same body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the `Content-Length` is bigger than
+    /// `max_body`, or any error from reading the body.
+    pub fn buffered_body(self, max_body: usize) -> io::Result<ReplayBody> {
+        Ok(ReplayBody {
+            data: self.body_bytes(max_body)?.into(),
+            pos: 0,
+        })
+    }
+
+    /// Read the whole body, refusing bodies over `max_body` bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error carrying [`HttpError::BodyTooLarge`] if
+    /// the `Content-Length` is bigger than `max_body`, or any error from
+    /// reading the body.
+    pub fn body_bytes(self, max_body: usize) -> io::Result<Vec<u8>> {
+        let len = self.checked_body_len(max_body)?;
+        let mut body = Vec::with_capacity(len);
+        self.body().read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    /// Read the whole body, into a temporary file if it is over `threshold`
+    /// bytes, refusing bodies over `max_body` bytes
+    ///
+    /// Large uploads then take disk space instead of memory, they are copied
+    /// in small chunks and synced to disk before this returns. The file is
+    /// created in [`std::env::temp_dir`], e.g. set by `TMPDIR`, and removed
+    /// when the result is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error carrying [`HttpError::BodyTooLarge`] if
+    /// the `Content-Length` is bigger than `max_body`, or any error from
+    /// reading the body or writing the file.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// struct Upload;
+    ///
+    /// impl HttpService for Upload {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         let mut body = req.spooled_body(1024 * 1024, 1024 * 1024 * 1024)?;
+    ///         let mut out = std::fs::File::create("/srv/uploads/latest")?;
+    ///         let n = std::io::copy(&mut body, &mut out)?;
+    ///         rsp.body_vec(format!("stored {n} bytes").into_bytes());
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "spool")]
+    pub fn spooled_body(self, threshold: usize, max_body: usize) -> io::Result<SpooledBody> {
+        let len = self.checked_body_len(max_body)?;
+        SpooledBody::read(self.body(), len, threshold)
+    }
+
+    /// Read the whole body as UTF-8 text, refusing bodies over `max_body` bytes
+    ///
+    /// # Errors
+    ///
+    /// Same as [`body_bytes`](Self::body_bytes), and an `InvalidData` error
+    /// carrying the `Utf8Error` if the body is not valid UTF-8.
+    pub fn body_string(self, max_body: usize) -> io::Result<String> {
+        String::from_utf8(self.body_bytes(max_body)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.utf8_error()))
+    }
+
+    /// Read the whole body as text in the `Content-Type` charset, refusing
+    /// bodies over `max_body` bytes
+    ///
+    /// Decodes UTF-8, ISO-8859-1 (Latin-1) and US-ASCII, UTF-8 when there is
+    /// no `charset` parameter, so form posts from legacy clients come out
+    /// right. Use [`body_string`](Self::body_string) to insist on UTF-8.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`body_bytes`](Self::body_bytes), and an `InvalidData` error
+    /// carrying [`HttpError::UnsupportedCharset`] for any other charset, in
+    /// which case the body is not read, or [`HttpError::InvalidText`] if the
+    /// body is not valid in its charset.
+    ///
+    /// # Example
+    /// ```
+    /// use may_minihttp::Request;
+    ///
+    /// let buf = b"POST /form HTTP/1.1\r\n\
+    ///             Content-Type: application/x-www-form-urlencoded; charset=ISO-8859-1\r\n\
+    ///             Content-Length: 9\r\n\r\nname=Jos\xe9";
+    /// let mut headers = [httparse::EMPTY_HEADER; 16];
+    /// let req = match Request::parse(buf, &mut headers).unwrap() {
+    ///     httparse::Status::Complete(req) => req,
+    ///     httparse::Status::Partial => unreachable!(),
+    /// };
+    /// assert_eq!(req.body_text(1024).unwrap(), "name=José");
+    /// ```
+    pub fn body_text(self, max_body: usize) -> io::Result<String> {
+        let charset = self.charset()?;
+        let body = self.body_bytes(max_body)?;
+        charset.decode(body).map_err(|valid_up_to| {
+            HttpError::InvalidText {
+                charset,
+                valid_up_to,
             }
+            .into()
+        })
+    }
+
+    /// the `charset` parameter of the `Content-Type`, UTF-8 without one
+    fn charset(&self) -> io::Result<Charset> {
+        let Some(content_type) = self
+            .headers()
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case("content-type"))
+        else {
+            return Ok(Charset::Utf8);
+        };
+        let params = content_type.value.split(|&b| b == b';').skip(1);
+        for param in params {
+            let Some((name, value)) = std::str::from_utf8(param)
+                .ok()
+                .and_then(|p| p.split_once('='))
+            else {
+                continue;
+            };
+            if name.trim().eq_ignore_ascii_case("charset") {
+                let label = value.trim().trim_matches('"');
+                return Charset::from_label(label)
+                    .ok_or_else(|| HttpError::UnsupportedCharset.into());
+            }
+        }
+        Ok(Charset::Utf8)
+    }
+
+    /// Deserialize the body as JSON while it is read from the connection
+    ///
+    /// Unlike reading the body with [`body_bytes`](Self::body_bytes) first,
+    /// no copy of the whole payload is kept in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error carrying [`HttpError::BodyTooLarge`] if
+    /// the `Content-Length` is bigger than `max_body`, or the `serde_json`
+    /// error converted to `io::Error` if the body is not a valid `T`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// struct Ingest;
+    ///
+    /// impl HttpService for Ingest {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         let events: Vec<serde_json::Value> = req.json_streaming(16 * 1024 * 1024)?;
+    ///         rsp.body_vec(format!("{} events", events.len()).into_bytes());
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json_streaming<T: serde::de::DeserializeOwned>(self, max_body: usize) -> io::Result<T> {
+        self.checked_body_len(max_body)?;
+        let mut de = serde_json::Deserializer::from_reader(self.body());
+        let value = T::deserialize(&mut de)?;
+        // only whitespace may follow the value
+        de.end()?;
+        Ok(value)
+    }
+
+    fn checked_body_len(&self, max_body: usize) -> io::Result<usize> {
+        let length = self.content_length;
+        if length > max_body {
+            let limit = max_body;
+            return Err(HttpError::BodyTooLarge { length, limit }.into());
         }
-        len
+        Ok(length)
     }
 }
 
+/// the body length given by the `Content-Length` headers, 0 without one
+///
+/// every `Content-Length` must be a decimal number, and repeated ones must
+/// agree, RFC 9112 section 6.3
+fn content_length(headers: &[httparse::Header]) -> Result<usize, HttpError> {
+    let mut len = None;
+    for header in headers {
+        if !header.name.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        let value = header.value.trim_ascii();
+        if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+            return Err(HttpError::InvalidContentLength);
+        }
+        // only digits, so valid UTF-8; too many of them overflow
+        let n = std::str::from_utf8(value)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .ok_or(HttpError::InvalidContentLength)?;
+        if len.is_some_and(|len| len != n) {
+            return Err(HttpError::InvalidContentLength);
+        }
+        len = Some(n);
+    }
+    Ok(len.unwrap_or(0))
+}
+
+/// whether the `If-Match` list has `*` or a strong entity tag equal to `etag`
+fn strong_match(list: &[u8], etag: &str) -> bool {
+    list.split(|&b| b == b',').any(|tag| {
+        let tag = tag.trim_ascii();
+        // weak tags never match strongly
+        tag == b"*" || (!tag.starts_with(b"W/") && tag == etag.as_bytes())
+    })
+}
+
 impl fmt::Debug for Request<'_, '_, '_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "<HTTP Request {} {}>", self.method(), self.path())
     }
 }
 
-pub fn decode<'header, 'buf, 'stream, const N: usize>(
-    headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; N],
+/// A request body held in memory that can be read repeatedly
+///
+/// Created by [`Request::buffered_body`]. Cloning is cheap and each clone
+/// reads from its own position.
+///
+/// # Example
+/// ```no_run
+/// use std::io::Read;
+/// use may_minihttp::{HttpService, Request, Response};
+///
+/// struct Retry;
+///
+/// impl HttpService for Retry {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         let mut body = req.buffered_body(1024 * 1024)?;
+///         let mut first = Vec::new();
+///         body.read_to_end(&mut first)?;
+///         // the first attempt failed, send the same bytes again
+///         body.rewind();
+///         let mut second = Vec::new();
+///         body.read_to_end(&mut second)?;
+///         assert_eq!(first, second);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ReplayBody {
+    data: Bytes,
+    pos: usize,
+}
+
+impl ReplayBody {
+    /// Start reading from the beginning again
+    pub fn rewind(&mut self) {
+        self.pos = 0;
+    }
+
+    /// The whole body, regardless of the read position
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The whole body as a cheaply cloneable buffer
+    pub fn into_bytes(self) -> Bytes {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl Read for ReplayBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl BufRead for ReplayBody {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.data[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.data.len());
+    }
+}
+
+/// A fully read request that doesn't borrow the connection
+///
+/// Created by [`Request::into_owned`].
+#[derive(Clone)]
+pub struct OwnedRequest {
+    method: String,
+    path: String,
+    version: u8,
+    headers: Vec<(String, Vec<u8>)>,
+    // the request line and headers as received
+    head: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl OwnedRequest {
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// All the headers as `(name, value)` pairs in arrival order
+    pub fn headers(&self) -> &[(String, Vec<u8>)] {
+        &self.headers
+    }
+
+    /// The value of the first header with this name, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_slice())
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    /// the header slots of [`as_request`](Self::as_request)
+    pub(crate) fn header_slots(&self) -> Vec<httparse::Header<'_>> {
+        self.headers
+            .iter()
+            .map(|(name, value)| httparse::Header { name, value })
+            .collect()
+    }
+
+    /// the request again, for calling a service with it, the body is read
+    /// from memory
+    pub(crate) fn as_request<'buf, 'header>(
+        &'buf self,
+        headers: &'header mut [httparse::Header<'buf>],
+    ) -> Request<'buf, 'header, 'static> {
+        Request {
+            req: httparse::Request {
+                method: Some(&self.method),
+                path: Some(&self.path),
+                version: Some(self.version),
+                headers,
+            },
+            head: &self.head,
+            content_length: self.body.len(),
+            src: BodySource::Slice(&self.body),
+            probe: None,
+        }
+    }
+}
+
+impl fmt::Debug for OwnedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<HTTP Request {} {}>", self.method, self.path)
+    }
+}
+
+/// decode a request from the head of `req_buf`
+///
+/// `scanned` remembers how much of the buffer is known to not hold the end
+/// of the headers, so a slowly arriving request is not rescanned from the
+/// start on every read. The connection loop keeps it across calls, it is
+/// reset once the headers are complete.
+pub(crate) fn decode<'header, 'buf, 'stream, S: Read>(
+    headers: &'header mut [MaybeUninit<httparse::Header<'buf>>],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: &'stream mut S,
+    scanned: &mut usize,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
     let mut req = httparse::Request::new(&mut []);
     // safety: don't hold the reference of req_buf
@@ -308,64 +1089,41 @@ pub fn decode<'header, 'buf, 'stream, const N: usize>(
     // Wait for complete headers before parsing to prevent token errors
     // This fixes issue #18 where headers arriving in multiple TCP packets
     // would cause "Token" parsing errors
-    // The \r\n\r\n sequence marks the end of HTTP headers
-    if !buf.windows(4).any(|window| window == b"\r\n\r\n") {
+    // The \r\n\r\n sequence marks the end of HTTP headers, it may straddle
+    // the previously scanned part, so back up 3 bytes
+    let start = scanned.saturating_sub(3).min(buf.len());
+    if memchr::memmem::find(&buf[start..], b"\r\n\r\n").is_none() {
+        *scanned = buf.len();
         return Ok(None); // Need more data
     }
+    *scanned = 0;
 
     // Get the header limit before parsing (to avoid borrow issues)
     let header_limit = headers.len();
 
     let status = match req.parse_with_uninit_headers(buf, headers) {
         Ok(s) => s,
-        Err(e) => {
-            // Provide detailed error message for TooManyHeaders
-            let msg = if e == httparse::Error::TooManyHeaders {
-                // Count how many headers were actually sent
-                let header_count = buf
-                    .split(|&b| b == b'\n')
-                    .filter(|line| {
-                        !line.is_empty() && line.contains(&b':') && !line.starts_with(b"\r\n")
-                    })
-                    .count();
-
-                let over_by = header_count.saturating_sub(header_limit);
-
-                let error_msg = format!(
-                    "TooManyHeaders: received {header_count} headers, limit is {header_limit} (over by {over_by})"
-                );
-
-                // Log the error
-                eprintln!("{error_msg}");
-
-                // Log the suggestion on a separate line for clarity
-                eprintln!(
-                    "Suggestion: Consider using MaxHeaders::Standard (32), \
-                     MaxHeaders::Large (64), or MaxHeaders::XLarge (128) for production deployments."
-                );
-
-                error_msg
-            } else {
-                let error_msg = format!("failed to parse http request: {e:?}");
-                eprintln!("{error_msg}");
-                error_msg
-            };
-
-            return err(io::Error::other(msg));
-        }
+        // reported by the connection loop
+        Err(e) => return err(HttpError::new(e, buf, header_limit).into()),
     };
 
     let len = match status {
         httparse::Status::Complete(amt) => amt,
         httparse::Status::Partial => return Ok(None),
     };
+    let content_length = match content_length(req.headers) {
+        Ok(n) => n,
+        Err(e) => return err(e.into()),
+    };
     req_buf.advance(len);
 
     // println!("req: {:?}", std::str::from_utf8(req_buf).unwrap());
     Ok(Some(Request {
         req,
-        req_buf,
-        stream,
+        head: &buf[..len],
+        content_length,
+        src: BodySource::Conn { req_buf, stream },
+        probe: None,
     }))
 }
 
@@ -374,15 +1132,15 @@ pub fn decode<'header, 'buf, 'stream, const N: usize>(
 /// # Errors
 ///
 /// Returns an error if:
-/// - The TCP stream cannot be read
+/// - The stream cannot be read
 /// - The HTTP request is malformed
 /// - The number of headers exceeds 16
-pub fn decode_default<'header, 'buf, 'stream>(
+pub fn decode_default<'header, 'buf, 'stream, S: Read>(
     headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; 16],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: &'stream mut S,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, stream, &mut 0)
 }
 
 /// Decode HTTP request with Standard (32) headers
@@ -390,15 +1148,15 @@ pub fn decode_default<'header, 'buf, 'stream>(
 /// # Errors
 ///
 /// Returns an error if:
-/// - The TCP stream cannot be read
+/// - The stream cannot be read
 /// - The HTTP request is malformed
 /// - The number of headers exceeds 32
-pub fn decode_standard<'header, 'buf, 'stream>(
+pub fn decode_standard<'header, 'buf, 'stream, S: Read>(
     headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; 32],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: &'stream mut S,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, stream, &mut 0)
 }
 
 /// Decode HTTP request with Large (64) headers
@@ -406,15 +1164,15 @@ pub fn decode_standard<'header, 'buf, 'stream>(
 /// # Errors
 ///
 /// Returns an error if:
-/// - The TCP stream cannot be read
+/// - The stream cannot be read
 /// - The HTTP request is malformed
 /// - The number of headers exceeds 64
-pub fn decode_large<'header, 'buf, 'stream>(
+pub fn decode_large<'header, 'buf, 'stream, S: Read>(
     headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; 64],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: &'stream mut S,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, stream, &mut 0)
 }
 
 /// Decode HTTP request with `XLarge` (128) headers
@@ -422,13 +1180,13 @@ pub fn decode_large<'header, 'buf, 'stream>(
 /// # Errors
 ///
 /// Returns an error if:
-/// - The TCP stream cannot be read
+/// - The stream cannot be read
 /// - The HTTP request is malformed
 /// - The number of headers exceeds 128
-pub fn decode_xlarge<'header, 'buf, 'stream>(
+pub fn decode_xlarge<'header, 'buf, 'stream, S: Read>(
     headers: &'header mut [MaybeUninit<httparse::Header<'buf>>; 128],
     req_buf: &'buf mut BytesMut,
-    stream: &'stream mut TcpStream,
+    stream: &'stream mut S,
 ) -> io::Result<Option<Request<'buf, 'header, 'stream>>> {
-    decode(headers, req_buf, stream)
+    decode(headers, req_buf, stream, &mut 0)
 }
@@ -1,14 +1,101 @@
+use std::borrow::Cow;
 use std::io;
+use std::time::SystemTime;
 
-use crate::request::MAX_HEADERS;
+use crate::common_header::{CommonHeader, ContentType};
+use crate::config::HeaderValidation;
+use crate::date::HttpDate;
+use crate::error::HttpError;
+use crate::hijack::{Handler, Hijacked};
+use crate::request::{Request, MAX_HEADERS};
 
 use bytes::BytesMut;
 pub struct Response<'a> {
     headers: [&'static str; MAX_HEADERS],
     headers_len: usize,
+    // headers built at runtime, sent after the static ones
+    owned_headers: Vec<String>,
     status_message: StatusMessage,
     body: Body,
+    last_modified: Option<HttpDate>,
     rsp_buf: &'a mut BytesMut,
+    // the connection, for writing out the response before `call` returns
+    flush: Option<&'a mut dyn Flush>,
+    // the status line and headers already went out with `flush`
+    head_sent: bool,
+    // body bytes already sent by `flush`
+    body_sent: usize,
+    // applied to the headers before `flush` sends them
+    header_check: HeaderValidation,
+    // takes over the connection once the response is sent
+    hijack: Option<Handler>,
+    // the request is HTTP/1.1, the client understands 1xx responses and
+    // chunked bodies
+    http11: bool,
+    // the request is a `HEAD`, a flushed body is not sent
+    head_request: bool,
+    // the request asks to keep the connection open
+    keep_alive: bool,
+    // a body growing past it is flushed by `body_mut`
+    high_watermark: usize,
+    // the failed flush of a body past the high watermark
+    write_err: Option<io::Error>,
+}
+
+/// the connection side of [`Response::flush`]
+pub(crate) trait Flush {
+    /// write `data` after the responses already queued on the connection
+    fn flush_bytes(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// the connection lines of the response, for a request that asks to
+    /// keep the connection open with `keep_alive`
+    fn conn_lines(&self, keep_alive: bool) -> ConnLines;
+}
+
+/// the lines the server adds to the head to tell the client whether and
+/// for how long the connection stays open, they are not part of the
+/// response headers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ConnLines {
+    /// nothing to announce
+    #[default]
+    None,
+    /// `Connection: close`
+    Close,
+    /// `Keep-Alive` with the idle timeout in seconds and the requests left
+    KeepAlive {
+        timeout: Option<u64>,
+        max: Option<usize>,
+    },
+}
+
+impl ConnLines {
+    /// room for the longest lines
+    const MAX_LEN: usize = 64;
+
+    fn encode<B: EncodeBuf>(self, buf: &mut B) {
+        let (timeout, max) = match self {
+            ConnLines::None => return,
+            ConnLines::Close => {
+                buf.push(b"\r\nConnection: close");
+                return;
+            }
+            ConnLines::KeepAlive { timeout, max } => (timeout, max),
+        };
+        let mut n = itoa::Buffer::new();
+        buf.push(b"\r\nKeep-Alive: ");
+        if let Some(timeout) = timeout {
+            buf.push(b"timeout=");
+            buf.push(n.format(timeout).as_bytes());
+            if max.is_some() {
+                buf.push(b", ");
+            }
+        }
+        if let Some(max) = max {
+            buf.push(b"max=");
+            buf.push(n.format(max).as_bytes());
+        }
+    }
 }
 
 enum Body {
@@ -19,27 +106,103 @@ enum Body {
 
 struct StatusMessage {
     code: usize,
-    msg: &'static str,
+    msg: Cow<'static, str>,
 }
 
 impl<'a> Response<'a> {
-    pub(crate) fn new(rsp_buf: &'a mut BytesMut) -> Response<'a> {
+    /// Create an empty `200 Ok` response that writes its body into `rsp_buf`
+    ///
+    /// The server creates one for every request; this is for rendering
+    /// responses outside of a connection, see [`encode`].
+    pub fn new(rsp_buf: &'a mut BytesMut) -> Response<'a> {
         let headers: [&'static str; 16] = [""; 16];
 
         Response {
             headers,
             headers_len: 0,
+            owned_headers: Vec::new(),
             body: Body::Dummy,
             status_message: StatusMessage {
                 code: 200,
-                msg: "Ok",
+                msg: Cow::Borrowed("Ok"),
             },
+            last_modified: None,
             rsp_buf,
+            flush: None,
+            head_sent: false,
+            body_sent: 0,
+            header_check: HeaderValidation::Reject,
+            hijack: None,
+            http11: true,
+            head_request: false,
+            keep_alive: false,
+            high_watermark: usize::MAX,
+            write_err: None,
         }
     }
 
+    /// the response of a connection, `flush` writes to `conn` in the way
+    /// `req` understands
+    pub(crate) fn with_flush(
+        rsp_buf: &'a mut BytesMut,
+        conn: &'a mut dyn Flush,
+        header_check: HeaderValidation,
+        high_watermark: usize,
+        req: &Request,
+    ) -> Response<'a> {
+        let mut rsp = Response::new(rsp_buf);
+        rsp.flush = Some(conn);
+        rsp.header_check = header_check;
+        rsp.high_watermark = high_watermark;
+        rsp.http11 = req.version() >= 1;
+        rsp.head_request = req.method() == "HEAD";
+        rsp.keep_alive = req.is_keep_alive();
+        rsp
+    }
+
     #[inline]
     pub fn status_code(&mut self, code: usize, msg: &'static str) -> &mut Self {
+        self.status_message = StatusMessage {
+            code,
+            msg: Cow::Borrowed(msg),
+        };
+        self
+    }
+
+    /// Set the status code with any reason phrase, also one built at runtime
+    ///
+    /// Codes without a registered meaning, e.g. `599`, are sent as they are.
+    /// An empty `reason` sends the standard phrase of the code, or none for
+    /// an unknown code. Control characters in `reason` are replaced with
+    /// spaces. A code outside `100..=999` can't be encoded, the response is
+    /// sent as a `500` instead.
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use may_minihttp::{encode, Response};
+    ///
+    /// let mut body = BytesMut::new();
+    /// let mut rsp = Response::new(&mut body);
+    /// rsp.status_with_reason(599, format!("Upstream {}", "Scream"));
+    /// assert_eq!(rsp.status(), (599, "Upstream Scream"));
+    ///
+    /// let mut buf = BytesMut::new();
+    /// encode(&rsp, &mut buf);
+    /// assert!(buf.starts_with(b"HTTP/1.1 599 Upstream Scream\r\n"));
+    /// ```
+    pub fn status_with_reason(
+        &mut self,
+        code: usize,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> &mut Self {
+        let mut msg = reason.into();
+        if !is_valid_header(&msg) {
+            let clean = msg
+                .chars()
+                .map(|c| if is_invalid_char(c) { ' ' } else { c })
+                .collect();
+            msg = Cow::Owned(clean);
+        }
         self.status_message = StatusMessage { code, msg };
         self
     }
@@ -51,6 +214,62 @@ impl<'a> Response<'a> {
         self
     }
 
+    /// Add a header built at runtime, e.g. `format!("ETag: \"{tag}\"")`
+    ///
+    /// Owned headers are sent after the ones added with
+    /// [`header`](Self::header) and don't count towards its limit of 16.
+    #[inline]
+    pub fn header_owned(&mut self, header: String) -> &mut Self {
+        self.owned_headers.push(header);
+        self
+    }
+
+    /// Add a header from its name and value, e.g. `header_kv("X-Request-Id", &id)`
+    ///
+    /// The `Name: value` line is built in one allocation and sent like an
+    /// [owned header](Self::header_owned).
+    #[inline]
+    pub fn header_kv(&mut self, name: &str, value: impl AsRef<str>) -> &mut Self {
+        let value = value.as_ref();
+        let mut header = String::with_capacity(name.len() + 2 + value.len());
+        header.push_str(name);
+        header.push_str(": ");
+        header.push_str(value);
+        self.header_owned(header)
+    }
+
+    /// Add a `Content-Type` header from the preformatted ones
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use may_minihttp::{ContentType, Response};
+    ///
+    /// let mut body = BytesMut::new();
+    /// let mut rsp = Response::new(&mut body);
+    /// rsp.content_type(ContentType::Json);
+    /// assert_eq!(rsp.headers(), ["Content-Type: application/json"]);
+    /// ```
+    #[inline]
+    pub fn content_type(&mut self, content_type: ContentType) -> &mut Self {
+        self.header(content_type.header())
+    }
+
+    /// Add one of the preformatted [`CommonHeader`]s, copied as is
+    #[inline]
+    pub fn common_header(&mut self, header: impl Into<CommonHeader>) -> &mut Self {
+        self.header(header.into().header())
+    }
+
+    /// Send a `Last-Modified` header with the given time
+    ///
+    /// Wrap the service in a [`ConditionalService`](crate::ConditionalService)
+    /// to answer `If-Modified-Since` requests with `304 Not Modified`.
+    #[inline]
+    pub fn last_modified(&mut self, t: SystemTime) -> &mut Self {
+        self.last_modified = Some(HttpDate::from(t));
+        self
+    }
+
     #[inline]
     pub fn body(&mut self, s: &'static str) {
         self.body = Body::Str(s);
@@ -61,6 +280,14 @@ impl<'a> Response<'a> {
         self.body = Body::Vec(v);
     }
 
+    /// The body buffer, to write the body in place
+    ///
+    /// On a connection, a body that grew past the
+    /// [`write_high_watermark`](crate::HttpConfig::write_high_watermark) is
+    /// [flushed](Self::flush) first, waiting for the client to take it, so a
+    /// handler streaming a large body in many writes holds at most about the
+    /// watermark in memory. A failed flush is returned once `call` returns
+    /// and the body written after it is dropped.
     #[inline]
     pub fn body_mut(&mut self) -> &mut BytesMut {
         match self.body {
@@ -74,11 +301,315 @@ impl<'a> Response<'a> {
                 self.body = Body::Dummy;
             }
         }
+        if self.rsp_buf.len() > self.high_watermark {
+            self.flush_past_watermark();
+        }
         self.rsp_buf
     }
 
+    #[cold]
+    fn flush_past_watermark(&mut self) {
+        if self.write_err.is_none() {
+            self.write_err = self.flush().err();
+        }
+        // nothing written after a failed flush is sent
+        if self.write_err.is_some() {
+            self.clear_body();
+        }
+    }
+
+    /// the error of a flush by `body_mut`, if one failed
+    #[inline]
+    pub(crate) fn take_write_error(&mut self) -> io::Result<()> {
+        match self.write_err.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Write out the response so far, without waiting for `call` to return
+    ///
+    /// The first flush sends the status line and the headers with
+    /// `Transfer-Encoding: chunked` in place of `Content-Length`, and each
+    /// flush sends the body written so far as a chunk. The status and the
+    /// headers can't be changed afterwards; the rest of the body is sent,
+    /// followed by the last chunk, when `call` returns.
+    ///
+    /// An HTTP/1.0 client doesn't know chunks, it gets the body as it is,
+    /// ended by closing the connection after the response. The body of a
+    /// `HEAD` request, and of a status without one such as `204` and
+    /// `304`, is dropped instead of sent.
+    ///
+    /// Outside of a connection, e.g. for a response made with
+    /// [`Response::new`], this does nothing.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Progress;
+    ///
+    /// impl HttpService for Progress {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         rsp.header("Content-Type: text/plain");
+    ///         for step in 1..=3 {
+    ///             rsp.body_mut().extend_from_slice(format!("step {step}\n").as_bytes());
+    ///             rsp.flush()?;
+    ///         }
+    ///         rsp.body("done\n");
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    pub fn flush(&mut self) -> io::Result<()> {
+        let conn = match self.flush.take() {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        let framing = self.flushed_framing();
+        let mut buf = BytesMut::with_capacity(256 + self.body_len());
+        if !self.head_sent {
+            if let Err(e) = self.check_headers(self.header_check) {
+                self.flush = Some(conn);
+                return Err(e);
+            }
+            let lines = match framing {
+                Framing::Close => ConnLines::Close,
+                _ => conn.conn_lines(self.keep_alive),
+            };
+            encode_head(self, &mut buf, framing, lines);
+        }
+        let sent = self.sends_body();
+        if sent {
+            encode_body(self.get_body(), framing, &mut buf);
+        }
+        let ret = conn.flush_bytes(&buf);
+        self.flush = Some(conn);
+        ret?;
+        self.head_sent = true;
+        if sent {
+            self.body_sent += self.body_len();
+        }
+        self.clear_body();
+        Ok(())
+    }
+
+    /// Send an interim `1xx` response right away, ahead of the final one
+    ///
+    /// `headers` are whole header lines, as for [`header`](Self::header),
+    /// and go through the same [`HeaderValidation`]. Any number of interim
+    /// responses may precede the final response, which is built and sent as
+    /// usual afterwards, so the connection is kept or closed as without
+    /// them. They are allowed for any method, `HEAD` included.
+    ///
+    /// HTTP/1.0 clients don't know interim responses, nothing is sent to
+    /// them, nor outside of a connection, as for [`flush`](Self::flush).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error for a status outside of `1xx`, for
+    /// `101 Switching Protocols`, which is a final response (see
+    /// [`hijack`](Self::hijack)), and once `flush` sent the final head.
+    /// Otherwise returns the error writing to the connection.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::io::Read;
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Upload;
+    ///
+    /// impl HttpService for Upload {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         rsp.send_interim(103, &["Link: </style.css>; rel=preload; as=style"])?;
+    ///         if req.expects_continue() {
+    ///             rsp.send_interim(100, &[])?;
+    ///         }
+    ///         let mut body = Vec::new();
+    ///         req.body().read_to_end(&mut body)?;
+    ///         rsp.body_vec(body);
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    pub fn send_interim(&mut self, code: usize, headers: &[&str]) -> io::Result<()> {
+        if !(100..200).contains(&code) || code == 101 {
+            let msg = format!("{code} is not an interim status");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        if self.head_sent {
+            let msg = "the final response head was already sent";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let len = headers.iter().map(|h| h.len() + 2).sum::<usize>();
+        let mut buf = BytesMut::with_capacity(64 + len);
+        buf.extend_from_slice(b"HTTP/1.1 ");
+        buf.extend_from_slice(itoa::Buffer::new().format(code).as_bytes());
+        buf.extend_from_slice(b" ");
+        buf.extend_from_slice(canonical_reason(code).unwrap_or("").as_bytes());
+        for h in headers {
+            buf.extend_from_slice(b"\r\n");
+            match self.header_check {
+                HeaderValidation::Reject if !is_valid_header(h) => {
+                    let msg = format!("invalid response header: {h:?}");
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+                HeaderValidation::Sanitize if !is_valid_header(h) => {
+                    for c in h.chars() {
+                        let c = if is_invalid_char(c) { ' ' } else { c };
+                        buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                }
+                _ => buf.extend_from_slice(h.as_bytes()),
+            }
+        }
+        buf.extend_from_slice(b"\r\n\r\n");
+        match self.flush.as_mut() {
+            Some(conn) if self.http11 => conn.flush_bytes(&buf),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the status line and headers were already sent by [`flush`](Self::flush)
+    #[inline]
+    pub fn is_flushed(&self) -> bool {
+        self.head_sent
+    }
+
+    /// Take over the connection once this response is sent
+    ///
+    /// After the service returns, the server writes out the response,
+    /// usually a `101 Switching Protocols`, stops speaking http on the
+    /// connection and calls `handler` with it on the connection's coroutine.
+    /// Bytes the client sent after the request are handed over too. The
+    /// handler is not called when the service returns an error. The
+    /// concurrent server and `serve_stream` can't hand over their
+    /// connections, they close them instead.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// use may_minihttp::{HttpService, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Echo;
+    ///
+    /// impl HttpService for Echo {
+    ///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         if !req.is_upgrade() {
+    ///             rsp.status_code(426, "Upgrade Required");
+    ///             return Ok(());
+    ///         }
+    ///         rsp.status_code(101, "Switching Protocols")
+    ///             .header("Connection: Upgrade")
+    ///             .header("Upgrade: echo");
+    ///         rsp.hijack(|mut conn| {
+    ///             let mut buf = [0; 1024];
+    ///             while let Ok(n @ 1..) = conn.read(&mut buf) {
+    ///                 if conn.write_all(&buf[..n]).is_err() {
+    ///                     break;
+    ///                 }
+    ///             }
+    ///         });
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    pub fn hijack(&mut self, handler: impl FnOnce(Hijacked) + 'static) -> &mut Self {
+        self.hijack = Some(Box::new(handler));
+        self
+    }
+
+    /// the hijack handler, if the service set one
     #[inline]
-    fn body_len(&self) -> usize {
+    pub(crate) fn take_hijack(&mut self) -> Option<Handler> {
+        self.hijack.take()
+    }
+
+    /// The status code and reason phrase set so far
+    #[inline]
+    pub fn status(&self) -> (usize, &str) {
+        (self.status_message.code, &self.status_message.msg)
+    }
+
+    /// the reason phrase, without copying a static one
+    #[inline]
+    pub(crate) fn reason(&self) -> Cow<'static, str> {
+        self.status_message.msg.clone()
+    }
+
+    /// the status code and reason phrase as they go on the wire
+    #[inline]
+    fn status_line(&self) -> (usize, &str) {
+        let StatusMessage { code, ref msg } = self.status_message;
+        if !(100..=999).contains(&code) {
+            return (500, "Internal Server Error");
+        }
+        if msg.is_empty() {
+            return (code, canonical_reason(code).unwrap_or(""));
+        }
+        (code, msg)
+    }
+
+    /// whether the status allows a body, 1xx, 204 and 304 responses never
+    /// have one whatever the handler wrote
+    #[inline]
+    fn has_body(&self) -> bool {
+        let (code, _) = self.status_line();
+        !(100..200).contains(&code) && code != 204 && code != 304
+    }
+
+    /// whether the body goes on the wire, never for a `HEAD` request
+    #[inline]
+    fn sends_body(&self) -> bool {
+        self.has_body() && !self.head_request
+    }
+
+    /// how a flushed body is delimited, HTTP/1.0 clients don't know chunks
+    #[inline]
+    fn flushed_framing(&self) -> Framing {
+        match self.http11 {
+            true => Framing::Chunked,
+            false => Framing::Close,
+        }
+    }
+
+    /// whether the connection has to be closed to end the response, for a
+    /// body flushed to an HTTP/1.0 client
+    #[inline]
+    pub(crate) fn ends_with_close(&self) -> bool {
+        self.head_sent && self.flushed_framing() == Framing::Close && self.sends_body()
+    }
+
+    /// The headers added so far
+    #[inline]
+    pub fn headers(&self) -> &[&'static str] {
+        &self.headers[..self.headers_len]
+    }
+
+    /// The headers added with [`header_owned`](Self::header_owned) so far
+    #[inline]
+    pub fn owned_headers(&self) -> &[String] {
+        &self.owned_headers
+    }
+
+    /// The time set by [`last_modified`](Self::last_modified)
+    #[inline]
+    pub fn get_last_modified(&self) -> Option<HttpDate> {
+        self.last_modified
+    }
+
+    /// the body bytes of the whole response, including the flushed ones
+    #[inline]
+    pub(crate) fn total_body_len(&self) -> usize {
+        self.body_sent + self.body_len()
+    }
+
+    /// The length of the body written so far
+    #[inline]
+    pub fn body_len(&self) -> usize {
         match self.body {
             Body::Dummy => self.rsp_buf.len(),
             Body::Str(s) => s.len(),
@@ -86,8 +617,60 @@ impl<'a> Response<'a> {
         }
     }
 
+    /// apply `mode` to the headers before they are encoded
+    ///
+    /// returns an `InvalidData` error for a rejected header
+    pub(crate) fn check_headers(&mut self, mode: HeaderValidation) -> io::Result<()> {
+        match mode {
+            HeaderValidation::Off => Ok(()),
+            HeaderValidation::Reject => {
+                let owned = self.owned_headers.iter().map(|h| h.as_str());
+                match self
+                    .headers()
+                    .iter()
+                    .copied()
+                    .chain(owned)
+                    .find(|h| !is_valid_header(h))
+                {
+                    Some(h) => {
+                        let msg = format!("invalid response header: {h:?}");
+                        Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+                    }
+                    None => Ok(()),
+                }
+            }
+            HeaderValidation::Sanitize => {
+                // the static headers can't be changed in place, move the
+                // first bad one and those after it to the owned ones
+                if let Some(i) = self.headers().iter().position(|h| !is_valid_header(h)) {
+                    let mut moved: Vec<String> =
+                        self.headers()[i..].iter().map(|h| h.to_string()).collect();
+                    moved.append(&mut self.owned_headers);
+                    self.owned_headers = moved;
+                    self.headers_len = i;
+                }
+                for h in self.owned_headers.iter_mut() {
+                    if !is_valid_header(h) {
+                        *h = h
+                            .chars()
+                            .map(|c| if is_invalid_char(c) { ' ' } else { c })
+                            .collect();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// drop the body set or written so far
     #[inline]
-    fn get_body(&mut self) -> &[u8] {
+    pub(crate) fn clear_body(&mut self) {
+        self.body = Body::Dummy;
+        self.rsp_buf.clear();
+    }
+
+    #[inline]
+    pub(crate) fn get_body(&self) -> &[u8] {
         match self.body {
             Body::Dummy => self.rsp_buf.as_ref(),
             Body::Str(s) => s.as_bytes(),
@@ -96,50 +679,321 @@ impl<'a> Response<'a> {
     }
 }
 
+/// the registered reason phrase of a status code
+fn canonical_reason(code: usize) -> Option<&'static str> {
+    let reason = match code {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        103 => "Early Hints",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
+        204 => "No Content",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Content",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        511 => "Network Authentication Required",
+        _ => return None,
+    };
+    Some(reason)
+}
+
+// control characters other than tab can split or corrupt the response
+#[inline]
+fn is_invalid_char(c: char) -> bool {
+    (c < ' ' && c != '\t') || c == '\x7f'
+}
+
+#[inline]
+fn is_valid_header(h: &str) -> bool {
+    !h.bytes().any(|b| is_invalid_char(b as char))
+}
+
 impl Drop for Response<'_> {
     fn drop(&mut self) {
         self.rsp_buf.clear();
     }
 }
 
-pub(crate) fn encode(mut rsp: Response, buf: &mut BytesMut) {
-    if rsp.status_message.code == 200 {
-        buf.extend_from_slice(b"HTTP/1.1 200 Ok\r\nServer: M\r\nDate: ");
+/// Serialize a response to `buf` the way the server writes it to the wire
+///
+/// Appends the status line, the `Server`, `Date`, `Last-Modified` (when set)
+/// and `Content-Length` headers, the user headers and the body. Useful for pre-rendering responses, for
+/// checking the exact output in tests and for custom transports.
+///
+/// For a response that was [flushed](Response::flush) only the rest of the
+/// body is appended, as the final chunks.
+///
+/// # Example
+/// ```
+/// use bytes::BytesMut;
+/// use may_minihttp::{encode, Response};
+///
+/// let mut body = BytesMut::new();
+/// let mut rsp = Response::new(&mut body);
+/// rsp.status_code(404, "Not Found").header("Content-Type: text/plain");
+/// rsp.body("gone");
+///
+/// let mut buf = BytesMut::new();
+/// encode(&rsp, &mut buf);
+/// assert!(buf.starts_with(b"HTTP/1.1 404 Not Found\r\nServer: M\r\n"));
+/// assert!(buf.ends_with(b"\r\nContent-Type: text/plain\r\n\r\ngone"));
+/// ```
+pub fn encode(rsp: &Response, buf: &mut BytesMut) {
+    encode_to(rsp, ConnLines::None, buf);
+}
+
+/// [`encode`] a response of a connection, with its connection lines
+#[inline]
+pub(crate) fn encode_with(rsp: &Response, lines: ConnLines, buf: &mut BytesMut) {
+    encode_to(rsp, lines, buf);
+}
+
+/// responses up to this size are encoded on the stack by the connection loop
+pub(crate) const SMALL_RSP_LEN: usize = 512;
+
+/// Encode a response into a fixed buffer, skipping the `BytesMut` bookkeeping
+///
+/// Returns the encoded length, or `None` if the response may not fit.
+#[inline]
+pub(crate) fn encode_small(rsp: &Response, lines: ConnLines, buf: &mut [u8]) -> Option<usize> {
+    // status line, Server, Date, Last-Modified, Content-Length and the
+    // connection lines plus the final CRLFs
+    let mut len = 192 + ConnLines::MAX_LEN + rsp.status_line().1.len() + rsp.body_len();
+    for h in rsp.headers() {
+        len += h.len() + 2;
+    }
+    for h in rsp.owned_headers.iter() {
+        len += h.len() + 2;
+    }
+    if len > buf.len() {
+        return None;
+    }
+    let mut dst = SliceBuf { buf, len: 0 };
+    encode_to(rsp, lines, &mut dst);
+    Some(dst.len)
+}
+
+// where the encoded bytes go
+trait EncodeBuf {
+    fn push(&mut self, b: &[u8]);
+}
+
+impl EncodeBuf for BytesMut {
+    #[inline]
+    fn push(&mut self, b: &[u8]) {
+        self.extend_from_slice(b);
+    }
+}
+
+// the caller makes sure the slice is big enough
+struct SliceBuf<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl EncodeBuf for SliceBuf<'_> {
+    #[inline]
+    fn push(&mut self, b: &[u8]) {
+        self.buf[self.len..self.len + b.len()].copy_from_slice(b);
+        self.len += b.len();
+    }
+}
+
+#[inline]
+fn encode_to<B: EncodeBuf>(rsp: &Response, lines: ConnLines, buf: &mut B) {
+    if rsp.head_sent {
+        // the rest of a flushed body, a body where there is none would be
+        // read as the start of the next response
+        if rsp.sends_body() {
+            let framing = rsp.flushed_framing();
+            encode_body(rsp.get_body(), framing, buf);
+            if framing == Framing::Chunked {
+                // the last chunk
+                buf.push(b"0\r\n\r\n");
+            }
+        }
+        return;
+    }
+    // a `HEAD` gets the `Content-Length` of its body but not the body
+    encode_head(rsp, buf, Framing::Length, lines);
+    if rsp.sends_body() {
+        buf.push(rsp.get_body());
+    }
+}
+
+/// how the end of a body is found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// `Content-Length`
+    Length,
+    /// `Transfer-Encoding: chunked`
+    Chunked,
+    /// closing the connection
+    Close,
+}
+
+/// the lines after the status line of every response, the `Date` value
+/// follows
+#[cfg(feature = "date-header")]
+const SERVER_LINES: &[u8] = b"\r\nServer: M\r\nDate: ";
+#[cfg(not(feature = "date-header"))]
+const SERVER_LINES: &[u8] = b"\r\nServer: M";
+
+#[inline]
+fn encode_head<B: EncodeBuf>(rsp: &Response, buf: &mut B, framing: Framing, lines: ConnLines) {
+    let (code, msg) = rsp.status_line();
+    if code == 200 && msg == "Ok" {
+        buf.push(b"HTTP/1.1 200 Ok");
     } else {
-        buf.extend_from_slice(b"HTTP/1.1 ");
-        let mut code = itoa::Buffer::new();
-        buf.extend_from_slice(code.format(rsp.status_message.code).as_bytes());
-        buf.extend_from_slice(b" ");
-        buf.extend_from_slice(rsp.status_message.msg.as_bytes());
-        buf.extend_from_slice(b"\r\nServer: M\r\nDate: ");
+        if code != rsp.status_message.code {
+            warn!(
+                "invalid status code {}, sending 500",
+                rsp.status_message.code
+            );
+        }
+        buf.push(b"HTTP/1.1 ");
+        let mut code_buf = itoa::Buffer::new();
+        buf.push(code_buf.format(code).as_bytes());
+        buf.push(b" ");
+        buf.push(msg.as_bytes());
     }
-    crate::date::append_date(buf);
-    buf.extend_from_slice(b"\r\nContent-Length: ");
-    let mut length = itoa::Buffer::new();
-    buf.extend_from_slice(length.format(rsp.body_len()).as_bytes());
+    buf.push(SERVER_LINES);
+    #[cfg(feature = "date-header")]
+    buf.push(crate::date::current());
+    if let Some(date) = rsp.last_modified {
+        buf.push(b"\r\nLast-Modified: ");
+        buf.push(&date.to_bytes());
+    }
+    // nothing to frame without a body, a 304 Content-Length would describe
+    // the omitted one
+    if rsp.has_body() {
+        match framing {
+            Framing::Length => {
+                buf.push(b"\r\nContent-Length: ");
+                let mut length = itoa::Buffer::new();
+                buf.push(length.format(rsp.body_len()).as_bytes());
+            }
+            Framing::Chunked => buf.push(b"\r\nTransfer-Encoding: chunked"),
+            Framing::Close => {}
+        }
+    }
+    lines.encode(buf);
 
     // SAFETY: we already have bound check when insert headers
     let headers = unsafe { rsp.headers.get_unchecked(..rsp.headers_len) };
     for h in headers {
-        buf.extend_from_slice(b"\r\n");
-        buf.extend_from_slice(h.as_bytes());
+        buf.push(b"\r\n");
+        buf.push(h.as_bytes());
+    }
+    for h in rsp.owned_headers.iter() {
+        buf.push(b"\r\n");
+        buf.push(h.as_bytes());
     }
 
-    buf.extend_from_slice(b"\r\n\r\n");
-    buf.extend_from_slice(rsp.get_body());
+    buf.push(b"\r\n\r\n");
 }
 
+/// write a part of a flushed body
+fn encode_body<B: EncodeBuf>(data: &[u8], framing: Framing, buf: &mut B) {
+    match framing {
+        Framing::Chunked => encode_chunk(data, buf),
+        _ => buf.push(data),
+    }
+}
+
+/// write `data` as one chunk, an empty one would end the body
+fn encode_chunk<B: EncodeBuf>(data: &[u8], buf: &mut B) {
+    if data.is_empty() {
+        return;
+    }
+    let mut hex = [0u8; 16];
+    let mut i = hex.len();
+    let mut n = data.len();
+    loop {
+        i -= 1;
+        hex[i] = b"0123456789abcdef"[n & 0xf];
+        n >>= 4;
+        if n == 0 {
+            break;
+        }
+    }
+    buf.push(&hex[i..]);
+    buf.push(b"\r\n");
+    buf.push(data);
+    buf.push(b"\r\n");
+}
+
+/// the status code a failed request is answered with, `413` for a body over
+/// the limit, `500` for anything else
+pub(crate) fn error_status(e: &io::Error) -> usize {
+    match HttpError::from_io(e) {
+        Some(HttpError::BodyTooLarge { .. }) => 413,
+        _ => 500,
+    }
+}
+
+/// answer a failed request with the connection `lines` and the status of
+/// [`error_status`], the error of a `500` is logged
 #[cold]
-pub(crate) fn encode_error(e: io::Error, buf: &mut BytesMut) {
-    error!("error in service: err = {e:?}");
+pub(crate) fn encode_error(e: &io::Error, lines: ConnLines, buf: &mut BytesMut) {
+    let status: &[u8] = match error_status(e) {
+        413 => b"HTTP/1.1 413 Payload Too Large",
+        _ => {
+            error!("error in service: err = {e:?}");
+            b"HTTP/1.1 500 Internal Server Error"
+        }
+    };
     let msg_string = e.to_string();
     let msg = msg_string.as_bytes();
 
-    buf.extend_from_slice(b"HTTP/1.1 500 Internal Server Error\r\nServer: M\r\nDate: ");
+    buf.extend_from_slice(status);
+    buf.extend_from_slice(SERVER_LINES);
+    #[cfg(feature = "date-header")]
     crate::date::append_date(buf);
     buf.extend_from_slice(b"\r\nContent-Length: ");
     let mut length = itoa::Buffer::new();
     buf.extend_from_slice(length.format(msg.len()).as_bytes());
+    lines.encode(buf);
 
     buf.extend_from_slice(b"\r\n\r\n");
     buf.extend_from_slice(msg);
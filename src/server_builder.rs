@@ -1,16 +1,18 @@
-use crate::config::HttpConfig;
+use crate::config::{ConnectionErrorHook, HttpConfig};
 use crate::http_server::HttpServiceFactory;
+use crate::privileges::{self, RunAs};
 use crate::request::MaxHeaders;
-use may::coroutine;
+use crate::server_handle::ServerHandle;
 use std::io;
-use std::net::ToSocketAddrs;
+use std::net::{Ipv6Addr, ToSocketAddrs};
+use std::sync::Arc;
 
 /// Builder for creating and configuring HTTP servers
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use may_minihttp::{HttpServer, HttpService, Request, Response, MaxHeaders};
+/// use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response, MaxHeaders};
 /// use std::io;
 ///
 /// #[derive(Clone)]
@@ -24,42 +26,223 @@ use std::net::ToSocketAddrs;
 /// }
 ///
 /// // Start server with custom MaxHeaders
-/// let server = HttpServer::new(MyService)
+/// let server = HttpServerBuilder::new(HttpServer(MyService))
 ///     .max_headers(MaxHeaders::Large)
 ///     .bind("127.0.0.1:8080")
 ///     .unwrap();
 /// ```
-pub struct HttpServer<F> {
+pub struct HttpServerBuilder<F> {
     factory: F,
     config: HttpConfig,
+    listen: Vec<String>,
+    run_as: Option<(String, String)>,
 }
 
-impl<F: HttpServiceFactory> HttpServer<F> {
+impl<F: HttpServiceFactory> HttpServerBuilder<F> {
     /// Create a new HTTP server with the given service factory
     pub fn new(factory: F) -> Self {
         Self {
             factory,
             config: HttpConfig::default(),
+            listen: Vec::new(),
+            run_as: None,
         }
     }
-    
+
+    /// Create a server from a TOML config file
+    ///
+    /// See [`ServerConfig`](crate::ServerConfig) for the file format.
+    #[cfg(feature = "config-file")]
+    pub fn from_config_file<P: AsRef<std::path::Path>>(factory: F, path: P) -> io::Result<Self> {
+        let file = crate::config::ServerConfig::from_file(path)?;
+        Ok(Self {
+            factory,
+            config: file.http,
+            listen: file.listen,
+            run_as: None,
+        })
+    }
+
+    /// Create a server from `MINIHTTP_*` environment variables
+    ///
+    /// The settings are read by [`HttpConfig::from_env`], the addresses to
+    /// listen on from `MINIHTTP_LISTEN`, separated by commas, e.g.
+    /// `0.0.0.0:8080,[::]:8080`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a variable is set to a value that
+    /// can't be parsed.
+    pub fn from_env(factory: F) -> io::Result<Self> {
+        Ok(Self {
+            factory,
+            config: HttpConfig::from_env()?,
+            listen: crate::config::listen_from_env()?,
+            run_as: None,
+        })
+    }
+
     /// Set the maximum number of headers to accept
     pub fn max_headers(mut self, max_headers: MaxHeaders) -> Self {
         self.config.max_headers = max_headers;
         self
     }
-    
+
+    /// Set the length of the listen queue, see [`HttpConfig::backlog`]
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response};
+    /// # #[derive(Clone)]
+    /// # struct MyService;
+    /// # impl HttpService for MyService {
+    /// #     fn call(&mut self, _req: Request, _rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// // ramp-ups of load tests open thousands of connections at once
+    /// let server = HttpServerBuilder::new(HttpServer(MyService))
+    ///     .backlog(4096)
+    ///     .bind("0.0.0.0:8080")
+    ///     .unwrap();
+    /// ```
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.config.backlog = backlog;
+        self
+    }
+
+    /// Call `hook` for accept failures and errors that end a connection
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response};
+    /// # #[derive(Clone)]
+    /// # struct MyService;
+    /// # impl HttpService for MyService {
+    /// #     fn call(&mut self, _req: Request, _rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static CONN_ERRORS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let server = HttpServerBuilder::new(HttpServer(MyService))
+    ///     .on_connection_error(|peer, err| {
+    ///         CONN_ERRORS.fetch_add(1, Ordering::Relaxed);
+    ///         eprintln!("connection error from {peer:?}: {err}");
+    ///     })
+    ///     .bind("127.0.0.1:8080")
+    ///     .unwrap();
+    /// ```
+    pub fn on_connection_error(mut self, hook: ConnectionErrorHook) -> Self {
+        self.config.on_connection_error = Some(hook);
+        self
+    }
+
+    /// Switch to `user` and `group` once the listeners are bound
+    ///
+    /// Start the server as root to bind ports below 1024, e.g. `:80` and
+    /// `:443`, the process then gives up root before the first connection
+    /// is accepted, so no handler ever runs with it. Names or numeric ids
+    /// are taken. The switch applies to the whole process, other threads
+    /// included. Starting fails when the process can't change its ids, e.g.
+    /// when started as another user, and on platforms other than Unix.
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response};
+    /// # #[derive(Clone)]
+    /// # struct MyService;
+    /// # impl HttpService for MyService {
+    /// #     fn call(&mut self, _req: Request, _rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// let servers = HttpServerBuilder::new(HttpServer(MyService))
+    ///     .listen("0.0.0.0:80")
+    ///     .listen("0.0.0.0:443")
+    ///     .run_as("www-data", "www-data")
+    ///     .start()
+    ///     .unwrap();
+    /// ```
+    pub fn run_as(mut self, user: &str, group: &str) -> Self {
+        self.run_as = Some((user.to_owned(), group.to_owned()));
+        self
+    }
+
+    /// the gate the accept loops wait at until the privileges are dropped
+    fn take_run_as(&mut self) -> Option<Arc<RunAs>> {
+        let (user, group) = self.run_as.take()?;
+        let run_as = Arc::new(RunAs::new(&user, &group));
+        self.config.run_as = Some(run_as.clone());
+        Some(run_as)
+    }
+
     /// Set the full HTTP configuration
     pub fn config(mut self, config: HttpConfig) -> Self {
         self.config = config;
         self
     }
-    
+
+    /// Add an address for [`start`](Self::start) to listen on
+    pub fn listen<A: Into<String>>(mut self, addr: A) -> Self {
+        self.listen.push(addr.into());
+        self
+    }
+
     /// Bind to the given address and start the server
-    pub fn bind<L: ToSocketAddrs>(self, addr: L) -> io::Result<coroutine::JoinHandle<()>> {
-        // For now, we'll just use the factory's start method
-        // TODO: Pass config through to control header limits
-        self.factory.start(addr)
+    pub fn bind<L: ToSocketAddrs>(mut self, addr: L) -> io::Result<ServerHandle> {
+        let run_as = self.take_run_as();
+        let started = self.factory.start_with_config(addr, self.config);
+        privileges::after_start(run_as.as_deref(), started)
+    }
+
+    /// Start the server on `port` for both IPv4 and IPv6 clients
+    ///
+    /// Binds `[::]` with [`HttpConfig::ipv6_only`] off, whatever the OS
+    /// default is, so a single listener takes clients of either family. IPv4
+    /// peers show up as `::ffff:a.b.c.d` addresses. Fails where the OS can't
+    /// share the port between the two families, e.g. OpenBSD, or has no IPv6:
+    /// use [`listen`](Self::listen) with `0.0.0.0` and `[::]` and
+    /// [`HttpConfig::with_ipv6_only`] there.
+    ///
+    /// ```no_run
+    /// # use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response};
+    /// # #[derive(Clone)]
+    /// # struct MyService;
+    /// # impl HttpService for MyService {
+    /// #     fn call(&mut self, _req: Request, _rsp: &mut Response) -> std::io::Result<()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// let server = HttpServerBuilder::new(HttpServer(MyService))
+    ///     .bind_dual_stack(8080)
+    ///     .unwrap();
+    /// ```
+    pub fn bind_dual_stack(mut self, port: u16) -> io::Result<ServerHandle> {
+        self.config.ipv6_only = Some(false);
+        self.bind((Ipv6Addr::UNSPECIFIED, port))
     }
 }
 
+impl<F: HttpServiceFactory + Sync> HttpServerBuilder<F> {
+    /// Start the server on every configured listen address
+    ///
+    /// All the listeners share the same service factory.
+    pub fn start(mut self) -> io::Result<Vec<ServerHandle>> {
+        if self.listen.is_empty() {
+            let msg = "no listen address configured";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let run_as = self.take_run_as();
+        let factory = Arc::new(self.factory);
+        let started = self
+            .listen
+            .iter()
+            .map(|addr| {
+                factory
+                    .clone()
+                    .start_with_config(addr.as_str(), self.config.clone())
+            })
+            .collect();
+        privileges::after_start(run_as.as_deref(), started)
+    }
+}
@@ -0,0 +1,200 @@
+//! several servers with their own services, run as one
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::HttpConfig;
+use crate::http_server::HttpServiceFactory;
+use crate::privileges::{self, RunAs};
+use crate::server_handle::ServerHandle;
+
+/// starts one server of the group, once the privileges gate is known
+type Start = Box<dyn FnOnce(Option<Arc<RunAs>>) -> io::Result<ServerHandle>>;
+
+/// Builder for servers that each run their own service on their own
+/// address, sharing the `may` runtime and one [`ServerGroup`] handle
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpConfig, HttpServer, HttpService, Request, Response, ServerGroupBuilder};
+///
+/// #[derive(Clone)]
+/// struct Api;
+///
+/// impl HttpService for Api {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("api");
+///         Ok(())
+///     }
+/// }
+///
+/// #[derive(Clone)]
+/// struct Admin;
+///
+/// impl HttpService for Admin {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("admin");
+///         Ok(())
+///     }
+/// }
+///
+/// let admin_config = HttpConfig::new().with_max_in_flight(16);
+/// let servers = ServerGroupBuilder::new()
+///     .serve("0.0.0.0:8080", HttpServer(Api))
+///     .serve_with_config("127.0.0.1:9090", HttpServer(Admin), admin_config)
+///     .start()
+///     .unwrap();
+/// // stops both
+/// servers.stop();
+/// servers.wait().unwrap();
+/// ```
+#[derive(Default)]
+pub struct ServerGroupBuilder {
+    starts: Vec<Start>,
+    run_as: Option<(String, String)>,
+}
+
+impl ServerGroupBuilder {
+    /// Create an empty group
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `factory` on `addr` with the default configuration
+    pub fn serve<F: HttpServiceFactory, A: Into<String>>(self, addr: A, factory: F) -> Self {
+        self.serve_with_config(addr, factory, HttpConfig::default())
+    }
+
+    /// Serve `factory` on `addr` with `config`
+    pub fn serve_with_config<F: HttpServiceFactory, A: Into<String>>(
+        mut self,
+        addr: A,
+        factory: F,
+        mut config: HttpConfig,
+    ) -> Self {
+        let addr = addr.into();
+        self.starts.push(Box::new(move |run_as| {
+            config.run_as = run_as;
+            factory.start_with_config(addr.as_str(), config)
+        }));
+        self
+    }
+
+    /// Switch to `user` and `group` once all the listeners are bound, see
+    /// [`HttpServerBuilder::run_as`](crate::HttpServerBuilder::run_as)
+    pub fn run_as(mut self, user: &str, group: &str) -> Self {
+        self.run_as = Some((user.to_owned(), group.to_owned()));
+        self
+    }
+
+    /// Start all the servers
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if no server was added, or the first
+    /// error binding a listener, in which case the servers already started
+    /// are stopped again.
+    pub fn start(self) -> io::Result<ServerGroup> {
+        if self.starts.is_empty() {
+            let msg = "no server added to the group";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let run_as = self
+            .run_as
+            .map(|(user, group)| Arc::new(RunAs::new(&user, &group)));
+        let mut servers = Vec::with_capacity(self.starts.len());
+        let mut started = Ok(());
+        for start in self.starts {
+            match start(run_as.clone()) {
+                Ok(server) => servers.push(server),
+                Err(e) => {
+                    started = Err(e);
+                    break;
+                }
+            }
+        }
+        if started.is_err() {
+            servers.iter().for_each(ServerHandle::stop);
+        }
+        let started = started.map(|()| ServerGroup { servers });
+        privileges::after_start(run_as.as_deref(), started)
+    }
+}
+
+/// The servers started by a [`ServerGroupBuilder`], stopped and waited for
+/// together
+///
+/// Dropping the group leaves the servers running in the background.
+pub struct ServerGroup {
+    servers: Vec<ServerHandle>,
+}
+
+impl ServerGroup {
+    /// The servers, in the order they were added
+    ///
+    /// For what applies to one of them, e.g. its
+    /// [`local_addr`](ServerHandle::local_addr) or a
+    /// [`reload`](ServerHandle::reload) of its configuration.
+    pub fn servers(&self) -> &[ServerHandle] {
+        &self.servers
+    }
+
+    /// The addresses the servers listen on, in the order they were added
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.servers.iter().map(ServerHandle::local_addr).collect()
+    }
+
+    /// Stop accepting connections on all the servers, see
+    /// [`ServerHandle::stop`]
+    pub fn stop(&self) {
+        self.servers.iter().for_each(ServerHandle::stop);
+    }
+
+    /// Drain all the servers and stop them after `grace`, see
+    /// [`ServerHandle::begin_drain`]
+    pub fn begin_drain(&self, grace: Duration) {
+        for server in &self.servers {
+            server.begin_drain(grace);
+        }
+    }
+
+    /// Wait until all the servers stop
+    ///
+    /// Returns an error if an accept loop panicked, after waiting for the
+    /// others.
+    pub fn wait(self) -> io::Result<()> {
+        let mut ret = Ok(());
+        for server in self.servers {
+            let waited = server.wait();
+            ret = ret.and(waited);
+        }
+        ret
+    }
+
+    /// Wait up to `timeout` for all the servers to stop
+    ///
+    /// Returns whether they stopped, [`wait`](Self::wait) then returns at
+    /// once.
+    pub fn wait_for(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.servers.iter().all(|server| {
+            let left = deadline.saturating_duration_since(Instant::now());
+            server.wait_for(left)
+        })
+    }
+
+    /// Wait until all the servers accept connections, see
+    /// [`ServerHandle::wait_until_ready`]
+    pub fn wait_until_ready(&self) -> io::Result<()> {
+        self.servers
+            .iter()
+            .try_for_each(ServerHandle::wait_until_ready)
+    }
+
+    /// Whether any of the servers still accepts connections
+    pub fn is_running(&self) -> bool {
+        self.servers.iter().any(ServerHandle::is_running)
+    }
+}
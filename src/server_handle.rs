@@ -0,0 +1,364 @@
+//! the handle of a started server
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use may::coroutine;
+use may::go;
+use may::net::TcpListener;
+
+use crate::config::HttpConfig;
+use crate::http_server::ConnConfig;
+#[cfg(unix)]
+use crate::signals::Signals;
+
+/// how often the waits look at the accept loop
+const POLL: Duration = Duration::from_millis(1);
+
+/// how often a wait for signals looks for one, it may last the whole process
+#[cfg(unix)]
+const SIGNAL_POLL: Duration = Duration::from_millis(20);
+
+/// A running server, returned by the `start` methods
+///
+/// Dropping the handle leaves the server running in the background.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpServer, HttpService, Request, Response};
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("hello");
+///         Ok(())
+///     }
+/// }
+///
+/// let server = HttpServer(Hello).start("0.0.0.0:8080").unwrap();
+/// assert!(server.is_running());
+/// // ...
+/// server.stop();
+/// server.wait().unwrap();
+/// ```
+pub struct ServerHandle {
+    accept: coroutine::JoinHandle<()>,
+    local_addr: SocketAddr,
+    state: AcceptState,
+    stopped: Arc<AtomicBool>,
+}
+
+/// what the accept loop shares with its handle
+#[derive(Clone)]
+pub(crate) struct AcceptState(Arc<Shared>);
+
+struct Shared {
+    ready: AtomicBool,
+    // the settings for newly accepted connections, each one shares them
+    conn: Mutex<Arc<ConnConfig>>,
+}
+
+impl AcceptState {
+    /// tell the handle that the loop is accepting
+    pub(crate) fn set_ready(&self) {
+        self.0.ready.store(true, Ordering::Release);
+    }
+
+    fn is_ready(&self) -> bool {
+        self.0.ready.load(Ordering::Acquire)
+    }
+
+    /// the settings for a connection accepted now
+    pub(crate) fn conn(&self) -> Arc<ConnConfig> {
+        self.0
+            .conn
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn set_conn(&self, conn: ConnConfig) {
+        *self.0.conn.lock().unwrap_or_else(|e| e.into_inner()) = Arc::new(conn);
+    }
+}
+
+impl ServerHandle {
+    /// run `accept_loop` for `listener` on a new coroutine named `name`
+    pub(crate) fn spawn<F>(
+        name: &str,
+        listener: TcpListener,
+        conn: ConnConfig,
+        accept_loop: F,
+    ) -> io::Result<Self>
+    where
+        F: FnOnce(TcpListener, AcceptState) + Send + 'static,
+    {
+        let local_addr = listener.local_addr()?;
+        let run_as = conn.run_as.clone();
+        let state = AcceptState(Arc::new(Shared {
+            ready: AtomicBool::new(false),
+            conn: Mutex::new(Arc::new(conn)),
+        }));
+        let looping = state.clone();
+        let accept = go!(coroutine::Builder::new().name(name.to_owned()), move || {
+            // never serve a connection as root
+            if run_as.is_some_and(|run_as| !run_as.wait()) {
+                return;
+            }
+            accept_loop(listener, looping)
+        })?;
+        Ok(ServerHandle {
+            accept,
+            local_addr,
+            state,
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Stop accepting connections
+    ///
+    /// The accept loop ends, which runs
+    /// [`on_server_stop`](crate::HttpServiceFactory::on_server_stop) for a
+    /// factory. Connections already accepted are served until they close.
+    /// Use [`wait`](Self::wait) to know when the accept loop is gone.
+    pub fn stop(&self) {
+        cancel_accept(self.accept.coroutine(), &self.stopped);
+    }
+
+    /// Start taking the server out of service, and stop it after `grace`
+    ///
+    /// The [`Drain`] set with
+    /// [`HttpConfig::with_drain`](crate::HttpConfig::with_drain) reports the
+    /// drain at once, for readiness endpoints to fail, and connections close
+    /// after their next response. New connections are accepted until the
+    /// grace period is over, long enough for the load balancer to notice,
+    /// then the server [stops](Self::stop). Without a `Drain` only the stop
+    /// is delayed.
+    pub fn begin_drain(&self, grace: Duration) {
+        if let Some(drain) = &self.state.conn().drain {
+            drain.begin();
+        }
+        let accept = self.accept.coroutine().clone();
+        let stopped = self.stopped.clone();
+        go!(move || {
+            coroutine::sleep(grace);
+            cancel_accept(&accept, &stopped);
+        });
+    }
+
+    /// Use the limits and timeouts of `config` for the connections accepted
+    /// from now on
+    ///
+    /// Open connections keep the settings they were accepted with. The
+    /// hooks, [`ServerStats`](crate::ServerStats), [`Drain`](crate::Drain),
+    /// [`IpFilter`](crate::IpFilter) and `run_as` the server started with
+    /// stay, whatever `config` holds, so a config read from a file or the
+    /// environment doesn't lose them. What the accept loop sets up at the
+    /// start stays too: [`max_in_flight`](HttpConfig::max_in_flight),
+    /// [`max_accept_backoff_ms`](HttpConfig::max_accept_backoff_ms) and the
+    /// header limit of an [`HttpServerWithHeaders`](crate::HttpServerWithHeaders).
+    /// See [`wait_for_signals_with_reload`](Self::wait_for_signals_with_reload)
+    /// to reload on SIGHUP.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use may_minihttp::{HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Hello;
+    ///
+    /// impl HttpService for Hello {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         rsp.body("hello");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let config = HttpConfig::from_env().unwrap();
+    /// let server = HttpServer(Hello).start_with_config("0.0.0.0:8080", config).unwrap();
+    /// // e.g. after the environment of an admin command changed
+    /// server.reload(HttpConfig::from_env().unwrap());
+    /// ```
+    pub fn reload(&self, config: HttpConfig) {
+        let conn = self.state.conn().reloaded(&config);
+        self.state.set_conn(conn);
+    }
+
+    /// Wait until the server stops, e.g. after [`stop`](Self::stop)
+    ///
+    /// Returns an error if the accept loop panicked.
+    pub fn wait(self) -> io::Result<()> {
+        match self.accept.join() {
+            Ok(()) => Ok(()),
+            // a cancelled coroutine reports its unwinding
+            Err(_) if self.stopped.load(Ordering::Acquire) => Ok(()),
+            Err(_) => Err(io::Error::other("the server accept loop panicked")),
+        }
+    }
+
+    /// Drain the server on SIGTERM or SIGINT and wait until it stops
+    ///
+    /// Catches both signals from now on, they no longer end the process. The
+    /// first one [begins a drain](Self::begin_drain) of `grace`, so readiness
+    /// endpoints fail at once and the server stops after the grace period. A
+    /// second one, e.g. another Ctrl-C, stops it right away.
+    ///
+    /// Returns an error if the signals can't be caught or the accept loop
+    /// panicked.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use may_minihttp::{Drain, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Hello;
+    ///
+    /// impl HttpService for Hello {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         rsp.body("hello");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let config = HttpConfig::new().with_drain(Arc::new(Drain::new()));
+    /// let server = HttpServer(Hello).start_with_config("0.0.0.0:8080", config).unwrap();
+    /// server.wait_for_signals(Duration::from_secs(10)).unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn wait_for_signals(self, grace: Duration) -> io::Result<()> {
+        let signals = Signals::install()?;
+        self.watch_signals(signals, grace, |_| {})
+    }
+
+    /// Same as [`wait_for_signals`](Self::wait_for_signals), and
+    /// [reload](Self::reload) the config returned by `load` on SIGHUP
+    ///
+    /// SIGHUP is caught from now on too. If `load` fails, the error is logged
+    /// and the server keeps its settings.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use may_minihttp::{HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response};
+    ///
+    /// #[derive(Clone)]
+    /// struct Hello;
+    ///
+    /// impl HttpService for Hello {
+    ///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+    ///         rsp.body("hello");
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let config = HttpConfig::from_env().unwrap();
+    /// let server = HttpServer(Hello).start_with_config("0.0.0.0:8080", config).unwrap();
+    /// server
+    ///     .wait_for_signals_with_reload(Duration::from_secs(10), HttpConfig::from_env)
+    ///     .unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn wait_for_signals_with_reload<F>(self, grace: Duration, mut load: F) -> io::Result<()>
+    where
+        F: FnMut() -> io::Result<HttpConfig>,
+    {
+        let mut signals = Signals::install()?;
+        signals.catch_hangup()?;
+        self.watch_signals(signals, grace, |server| match load() {
+            Ok(config) => {
+                info!("SIGHUP received, config reloaded");
+                server.reload(config);
+            }
+            Err(e) => error!("SIGHUP received, config not reloaded: {e}"),
+        })
+    }
+
+    /// drain and stop on the signals, `on_hangup` runs for each SIGHUP
+    #[cfg(unix)]
+    fn watch_signals(
+        self,
+        mut signals: Signals,
+        grace: Duration,
+        mut on_hangup: impl FnMut(&Self),
+    ) -> io::Result<()> {
+        let mut draining = false;
+        while self.is_running() {
+            if signals.hangup() {
+                on_hangup(&self);
+                continue;
+            }
+            match signals.poll() {
+                Some(signal) if !draining => {
+                    info!("signal {signal} received, draining for {grace:?}");
+                    self.begin_drain(grace);
+                    draining = true;
+                }
+                Some(signal) => {
+                    info!("signal {signal} received while draining, stopping");
+                    self.stop();
+                }
+                None => coroutine::sleep(SIGNAL_POLL),
+            }
+        }
+        self.wait()
+    }
+
+    /// Wait up to `timeout` for the server to stop
+    ///
+    /// Returns whether it stopped, [`wait`](Self::wait) then returns at once.
+    pub fn wait_for(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.is_running() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            coroutine::sleep(POLL);
+        }
+        true
+    }
+
+    /// Wait until the server accepts connections
+    ///
+    /// The listener is bound when `start` returns, this also waits for the
+    /// accept loop to run, after
+    /// [`on_server_start`](crate::HttpServiceFactory::on_server_start) for a
+    /// factory. Returns an error if the server stopped before that.
+    pub fn wait_until_ready(&self) -> io::Result<()> {
+        while !self.state.is_ready() {
+            if !self.is_running() {
+                let msg = "the server stopped before accepting connections";
+                return Err(io::Error::other(msg));
+            }
+            coroutine::sleep(POLL);
+        }
+        Ok(())
+    }
+
+    /// The address the server listens on
+    ///
+    /// Start the server on port `0` to get a free port from the OS, e.g. for
+    /// tests running in parallel, and read the port from here.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Whether the server still accepts connections
+    pub fn is_running(&self) -> bool {
+        !self.accept.is_done()
+    }
+}
+
+/// cancel the accept loop, once
+fn cancel_accept(accept: &coroutine::Coroutine, stopped: &AtomicBool) {
+    if !stopped.swap(true, Ordering::AcqRel) {
+        // the accept loop only blocks in `accept`, where it can unwind safely
+        unsafe { accept.cancel() };
+    }
+}
@@ -0,0 +1,82 @@
+//! SIGTERM and SIGINT caught for a graceful shutdown, SIGHUP for a reload
+
+use std::io;
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+// written by the handlers, which may only touch atomics
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+static LAST: AtomicI32 = AtomicI32::new(0);
+static HANGUPS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    LAST.store(signal, Ordering::Relaxed);
+    RECEIVED.fetch_add(1, Ordering::Release);
+}
+
+extern "C" fn on_hangup(_signal: libc::c_int) {
+    HANGUPS.fetch_add(1, Ordering::Release);
+}
+
+/// replace the action of `signal` with `handler`
+fn catch(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) -> io::Result<()> {
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = handler as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The SIGTERM and SIGINT received since it was installed, and the SIGHUP
+/// once caught
+pub(crate) struct Signals {
+    seen: usize,
+    // the SIGHUP seen, `None` while SIGHUP is not caught
+    hangups: Option<usize>,
+}
+
+impl Signals {
+    /// catch SIGTERM and SIGINT, which then no longer end the process
+    pub(crate) fn install() -> io::Result<Self> {
+        let seen = RECEIVED.load(Ordering::Acquire);
+        for signal in [libc::SIGTERM, libc::SIGINT] {
+            catch(signal, on_signal)?;
+        }
+        Ok(Signals {
+            seen,
+            hangups: None,
+        })
+    }
+
+    /// catch SIGHUP too, which then no longer ends the process
+    pub(crate) fn catch_hangup(&mut self) -> io::Result<()> {
+        self.hangups = Some(HANGUPS.load(Ordering::Acquire));
+        catch(libc::SIGHUP, on_hangup)
+    }
+
+    /// the last signal received since the previous call, if any
+    pub(crate) fn poll(&mut self) -> Option<libc::c_int> {
+        let received = RECEIVED.load(Ordering::Acquire);
+        if received == self.seen {
+            return None;
+        }
+        self.seen = received;
+        Some(LAST.load(Ordering::Relaxed))
+    }
+
+    /// whether a SIGHUP was received since the previous call, several of
+    /// them count as one
+    pub(crate) fn hangup(&mut self) -> bool {
+        let Some(seen) = self.hangups.as_mut() else {
+            return false;
+        };
+        let received = HANGUPS.load(Ordering::Acquire);
+        if received == *seen {
+            return false;
+        }
+        *seen = received;
+        true
+    }
+}
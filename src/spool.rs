@@ -0,0 +1,121 @@
+//! request bodies kept in a temporary file once they are large
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A whole request body, in memory or in a temporary file
+///
+/// Returned by [`Request::spooled_body`](crate::Request::spooled_body).
+/// Reading starts at the beginning of the body and it can be read again
+/// after seeking back. A temporary file is removed when this is dropped,
+/// also when the handler returns early with an error or panics.
+#[derive(Debug)]
+pub struct SpooledBody {
+    inner: Spooled,
+}
+
+#[derive(Debug)]
+enum Spooled {
+    Memory(Cursor<Vec<u8>>),
+    File(TempFile, u64),
+}
+
+impl SpooledBody {
+    /// Read `len` bytes of `body`, into a temporary file if there are more
+    /// than `threshold`
+    pub(crate) fn read(mut body: impl Read, len: usize, threshold: usize) -> io::Result<Self> {
+        if len <= threshold {
+            let mut data = Vec::with_capacity(len);
+            body.read_to_end(&mut data)?;
+            return Ok(SpooledBody {
+                inner: Spooled::Memory(Cursor::new(data)),
+            });
+        }
+        // removed again if the copy fails
+        let mut tmp = TempFile::create()?;
+        let written = io::copy(&mut body, &mut tmp.file)?;
+        tmp.file.sync_data()?;
+        tmp.file.seek(SeekFrom::Start(0))?;
+        Ok(SpooledBody {
+            inner: Spooled::File(tmp, written),
+        })
+    }
+
+    /// The body length in bytes
+    pub fn len(&self) -> u64 {
+        match &self.inner {
+            Spooled::Memory(data) => data.get_ref().len() as u64,
+            Spooled::File(_, len) => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The temporary file holding the body, `None` while it is in memory
+    pub fn path(&self) -> Option<&Path> {
+        match &self.inner {
+            Spooled::Memory(_) => None,
+            Spooled::File(tmp, _) => Some(&tmp.path),
+        }
+    }
+}
+
+impl Read for SpooledBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Spooled::Memory(data) => data.read(buf),
+            Spooled::File(tmp, _) => tmp.file.read(buf),
+        }
+    }
+}
+
+impl Seek for SpooledBody {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match &mut self.inner {
+            Spooled::Memory(data) => data.seek(pos),
+            Spooled::File(tmp, _) => tmp.file.seek(pos),
+        }
+    }
+}
+
+/// a file in the temp directory, removed on drop
+#[derive(Debug)]
+struct TempFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create() -> io::Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let name = format!(
+            "may_minihttp-{}-{}-{nanos}.body",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        // never reuse a file someone else put there
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(TempFile { file, path })
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("can't remove {}: {e}", self.path.display());
+        }
+    }
+}
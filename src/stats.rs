@@ -0,0 +1,208 @@
+//! server wide counters kept by the connection loops
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Connection and traffic counters of a server
+///
+/// Pass one to [`HttpConfig::with_stats`](crate::HttpConfig::with_stats) and
+/// read it through a clone of the `Arc` from anywhere, e.g. a dashboard
+/// endpoint. Several servers may share one. The counters are updated with relaxed atomics, so a snapshot of
+/// several of them is not taken at a single instant.
+///
+/// # Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use may_minihttp::{HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response, ServerStats};
+///
+/// #[derive(Clone)]
+/// struct Status(Arc<ServerStats>);
+///
+/// impl HttpService for Status {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         let text = format!(
+///             "active {} requests {} out {}",
+///             self.0.active_connections(),
+///             self.0.requests(),
+///             self.0.bytes_written()
+///         );
+///         rsp.body_vec(text.into_bytes());
+///         Ok(())
+///     }
+/// }
+///
+/// let stats = Arc::new(ServerStats::new());
+/// let config = HttpConfig::new().with_stats(stats.clone());
+/// let server = HttpServer(Status(stats)).start_with_config("0.0.0.0:8080", config).unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    accepted: AtomicU64,
+    accept_errors: AtomicU64,
+    active: AtomicUsize,
+    keep_alive: AtomicUsize,
+    requests: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    next_conn: AtomicU64,
+    open: Mutex<BTreeMap<u64, Arc<OpenConn>>>,
+}
+
+/// An open connection, as listed by [`ServerStats::connections`]
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ConnectionStats {
+    /// the remote address of the client, `None` for [`serve_stream`](crate::serve_stream)
+    pub peer_addr: Option<SocketAddr>,
+    /// how long the connection has been open
+    pub age: Duration,
+    /// the requests answered on the connection
+    pub requests: u64,
+}
+
+/// the table entry of an open connection
+#[derive(Debug)]
+struct OpenConn {
+    peer_addr: Option<SocketAddr>,
+    opened: Instant,
+    requests: AtomicU64,
+}
+
+impl ServerStats {
+    /// Create zeroed counters, usable in a `static`
+    pub const fn new() -> Self {
+        ServerStats {
+            accepted: AtomicU64::new(0),
+            accept_errors: AtomicU64::new(0),
+            active: AtomicUsize::new(0),
+            keep_alive: AtomicUsize::new(0),
+            requests: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            next_conn: AtomicU64::new(0),
+            open: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Connections served since the start
+    pub fn accepted(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Accepts that failed since the start
+    pub fn accept_errors(&self) -> u64 {
+        self.accept_errors.load(Ordering::Relaxed)
+    }
+
+    /// Connections open right now
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Open connections that were kept alive after answering a request
+    pub fn keep_alive_connections(&self) -> usize {
+        self.keep_alive.load(Ordering::Relaxed)
+    }
+
+    /// Requests answered since the start
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Bytes read from the connections
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written to the connections
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// The open connections, oldest first
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        let open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.values()
+            .map(|c| ConnectionStats {
+                peer_addr: c.peer_addr,
+                age: c.opened.elapsed(),
+                requests: c.requests.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    pub(crate) fn add_accept_error(&self) {
+        self.accept_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_read(&self, n: usize) {
+        self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_written(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// counts a connection as active and lists it until dropped
+pub(crate) struct ConnStats {
+    stats: Option<Arc<ServerStats>>,
+    kept_alive: bool,
+    entry: Option<(u64, Arc<OpenConn>)>,
+}
+
+impl ConnStats {
+    pub(crate) fn open(stats: Option<Arc<ServerStats>>, peer_addr: Option<SocketAddr>) -> Self {
+        let entry = stats.as_ref().map(|stats| {
+            stats.accepted.fetch_add(1, Ordering::Relaxed);
+            stats.active.fetch_add(1, Ordering::Relaxed);
+            let id = stats.next_conn.fetch_add(1, Ordering::Relaxed);
+            let conn = Arc::new(OpenConn {
+                peer_addr,
+                opened: Instant::now(),
+                requests: AtomicU64::new(0),
+            });
+            let mut open = stats.open.lock().unwrap_or_else(|e| e.into_inner());
+            open.insert(id, conn.clone());
+            (id, conn)
+        });
+        ConnStats {
+            stats,
+            kept_alive: false,
+            entry,
+        }
+    }
+
+    /// count an answered request, `keep_alive` if the connection stays open
+    pub(crate) fn served(&mut self, keep_alive: bool) {
+        if let Some(stats) = &self.stats {
+            stats.requests.fetch_add(1, Ordering::Relaxed);
+            if let Some((_, conn)) = &self.entry {
+                conn.requests.fetch_add(1, Ordering::Relaxed);
+            }
+            if keep_alive && !self.kept_alive {
+                self.kept_alive = true;
+                stats.keep_alive.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for ConnStats {
+    fn drop(&mut self) {
+        if let Some(stats) = &self.stats {
+            stats.active.fetch_sub(1, Ordering::Relaxed);
+            if self.kept_alive {
+                stats.keep_alive.fetch_sub(1, Ordering::Relaxed);
+            }
+            if let Some((id, _)) = self.entry.take() {
+                let mut open = stats.open.lock().unwrap_or_else(|e| e.into_inner());
+                open.remove(&id);
+            }
+        }
+    }
+}
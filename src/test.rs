@@ -0,0 +1,324 @@
+//! in-process test client, no sockets involved
+//!
+//! Requests are fed through the same decode/serve/encode path that a real
+//! connection uses, over an in-memory [`MockStream`].
+//!
+//! # Example
+//! ```
+//! use may_minihttp::test::TestClient;
+//! use may_minihttp::{HttpService, Request, Response};
+//!
+//! #[derive(Clone)]
+//! struct Hello;
+//!
+//! impl HttpService for Hello {
+//!     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+//!         rsp.header("Content-Type: text/plain").body("Hello, World!");
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let client = TestClient::new(Hello);
+//! let rsp = client.get("/").send().unwrap();
+//! assert_eq!(rsp.status(), 200);
+//! assert_eq!(rsp.header("content-type"), Some("text/plain"));
+//! assert_eq!(rsp.text(), "Hello, World!");
+//! ```
+
+use std::io::{self, Cursor, Read, Write};
+
+use crate::config::HttpConfig;
+use crate::http_server::{serve_stream, HttpService};
+
+/// In-memory stream that reads from a fixed input and collects the output
+#[derive(Debug, Default)]
+pub struct MockStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl MockStream {
+    /// Create a stream that yields `input` and then reports EOF
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        MockStream {
+            input: Cursor::new(input.into()),
+            output: Vec::new(),
+        }
+    }
+
+    /// Everything written to the stream so far
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Consume the stream and return what was written to it
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Drives an `HttpService` with requests built in memory
+///
+/// Each request runs on a fresh clone of the service, like a new connection.
+pub struct TestClient<T> {
+    service: T,
+    config: HttpConfig,
+}
+
+impl<T: HttpService + Clone> TestClient<T> {
+    /// Create a client for `service` with the default `HttpConfig`
+    pub fn new(service: T) -> Self {
+        TestClient {
+            service,
+            config: HttpConfig::default(),
+        }
+    }
+
+    /// Use `config` when decoding requests
+    pub fn config(mut self, config: HttpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Start building a `GET` request for `path`
+    pub fn get(&self, path: &str) -> TestRequest<'_, T> {
+        self.request("GET", path)
+    }
+
+    /// Start building a `POST` request for `path`
+    pub fn post(&self, path: &str) -> TestRequest<'_, T> {
+        self.request("POST", path)
+    }
+
+    /// Start building a request with any method
+    pub fn request(&self, method: &str, path: &str) -> TestRequest<'_, T> {
+        TestRequest {
+            client: self,
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Feed raw request bytes and return the first response
+    pub fn send_raw(&self, raw: &[u8]) -> io::Result<TestResponse> {
+        self.send_raw_all(raw)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "no response"))
+    }
+
+    /// Feed raw (possibly pipelined) request bytes and return all responses
+    pub fn send_raw_all(&self, raw: &[u8]) -> io::Result<Vec<TestResponse>> {
+        TestResponse::parse_all(&self.serve(raw)?)
+    }
+
+    fn serve(&self, raw: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream = MockStream::new(raw);
+        serve_stream(&mut stream, self.service.clone(), &self.config)?;
+        Ok(stream.into_output())
+    }
+}
+
+/// Request under construction, see [`TestClient::request`]
+pub struct TestRequest<'a, T> {
+    client: &'a TestClient<T>,
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl<T: HttpService + Clone> TestRequest<'_, T> {
+    /// Add a request header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set the request body, `Content-Length` is added automatically
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// The request as it would appear on the wire
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(128 + self.body.len());
+        buf.extend_from_slice(format!("{} {} HTTP/1.1\r\n", self.method, self.path).as_bytes());
+        let has = |name: &str| {
+            self.headers
+                .iter()
+                .any(|(n, _)| n.eq_ignore_ascii_case(name))
+        };
+        if !has("host") {
+            buf.extend_from_slice(b"Host: localhost\r\n");
+        }
+        for (name, value) in self.headers.iter() {
+            buf.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        if !self.body.is_empty() && !has("content-length") {
+            buf.extend_from_slice(format!("Content-Length: {}\r\n", self.body.len()).as_bytes());
+        }
+        buf.extend_from_slice(b"\r\n");
+        buf.extend_from_slice(&self.body);
+        buf
+    }
+
+    /// Run the request through the service and return the response
+    pub fn send(self) -> io::Result<TestResponse> {
+        if self.method != "HEAD" {
+            return self.client.send_raw(&self.to_bytes());
+        }
+        // the Content-Length of a `HEAD` response is that of the body not sent
+        let out = self.client.serve(&self.to_bytes())?;
+        TestResponse::parse(&out, true).map(|(rsp, _)| rsp)
+    }
+}
+
+/// Parsed response returned by [`TestClient`]
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl TestResponse {
+    fn parse_all(mut buf: &[u8]) -> io::Result<Vec<TestResponse>> {
+        let mut rsps = Vec::new();
+        while !buf.is_empty() {
+            let (rsp, len) = Self::parse(buf, false)?;
+            rsps.push(rsp);
+            buf = &buf[len..];
+        }
+        Ok(rsps)
+    }
+
+    // a response to a `HEAD` request, `head`, has no body
+    fn parse(buf: &[u8], head: bool) -> io::Result<(TestResponse, usize)> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut rsp = httparse::Response::new(&mut headers);
+        let head_len = match rsp.parse(buf) {
+            Ok(httparse::Status::Complete(n)) => n,
+            Ok(httparse::Status::Partial) => return Err(invalid("partial response")),
+            Err(e) => return Err(invalid(&format!("failed to parse response: {e}"))),
+        };
+
+        let headers: Vec<(String, String)> = rsp
+            .headers
+            .iter()
+            .map(|h| {
+                let value = String::from_utf8_lossy(h.value).into_owned();
+                (h.name.to_owned(), value)
+            })
+            .collect();
+        let chunked = headers.iter().any(|(n, v)| {
+            n.eq_ignore_ascii_case("transfer-encoding") && v.eq_ignore_ascii_case("chunked")
+        });
+        let (body, end) = if head {
+            (Vec::new(), head_len)
+        } else if chunked {
+            Self::parse_chunked(buf, head_len)?
+        } else {
+            let body_len = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.trim().parse::<usize>())
+                .transpose()
+                .map_err(|_| invalid("invalid Content-Length"))?
+                .unwrap_or(0);
+            let end = head_len + body_len;
+            if buf.len() < end {
+                return Err(invalid("truncated response body"));
+            }
+            (buf[head_len..end].to_vec(), end)
+        };
+
+        let rsp = TestResponse {
+            status: rsp.code.unwrap_or_default(),
+            reason: rsp.reason.unwrap_or_default().to_owned(),
+            headers,
+            body,
+        };
+        Ok((rsp, end))
+    }
+
+    // join the chunks starting at `pos`, returns the body and where it ends
+    fn parse_chunked(buf: &[u8], mut pos: usize) -> io::Result<(Vec<u8>, usize)> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid chunked body");
+        let mut body = Vec::new();
+        loop {
+            let line_len = buf[pos..]
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .ok_or_else(invalid)?;
+            let size = std::str::from_utf8(&buf[pos..pos + line_len])
+                .ok()
+                .and_then(|s| usize::from_str_radix(s.trim(), 16).ok())
+                .ok_or_else(invalid)?;
+            pos += line_len + 2;
+            let end = pos + size + 2;
+            if buf.len() < end {
+                return Err(invalid());
+            }
+            if size == 0 {
+                return Ok((body, end));
+            }
+            body.extend_from_slice(&buf[pos..pos + size]);
+            pos = end;
+        }
+    }
+
+    /// The status code
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The reason phrase
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// All the headers in wire order
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The first header matching `name`, case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The response body, with a chunked body already joined
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The response body as text, invalid UTF-8 is replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
@@ -0,0 +1,90 @@
+//! per-request deadline on top of any `HttpService`
+
+use std::io;
+use std::time::Duration;
+
+use may::sync::mpsc;
+
+use crate::dispatch::spawn_call;
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+
+/// `HttpService` wrapper that bounds how long the inner service may take
+///
+/// The request is read into memory and handed to a clone of the inner
+/// service on a coroutine of its own. If the deadline passes first, that
+/// coroutine is cancelled, which unwinds it at its next blocking call (io,
+/// sleep, channels, ...), and the client gets a `504 Gateway Timeout`. Code
+/// that never blocks can't be interrupted, it runs on in the background but
+/// the connection is answered in time.
+///
+/// Request bodies over [`max_body`](Self::max_body) bytes get a `413 Payload
+/// Too Large`. [`Response::flush`] has no effect in the inner service, the
+/// response is sent once it returns.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use may_minihttp::{HttpServer, HttpService, Request, Response, TimeoutService};
+///
+/// #[derive(Clone)]
+/// struct Upstream;
+///
+/// impl HttpService for Upstream {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         // stands in for a call to a slow backend
+///         may::coroutine::sleep(Duration::from_secs(10));
+///         rsp.body("late");
+///         Ok(())
+///     }
+/// }
+///
+/// let service = TimeoutService::new(Upstream, Duration::from_secs(2));
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct TimeoutService<S> {
+    inner: S,
+    timeout: Duration,
+    max_body: usize,
+}
+
+impl<S> TimeoutService<S> {
+    /// Wrap `inner`, answering with `504` when a request takes longer than `timeout`
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        TimeoutService {
+            inner,
+            timeout,
+            max_body: 1024 * 1024,
+        }
+    }
+
+    /// Set the largest request body read into memory, default is 1MB
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+}
+
+impl<S: HttpService + Clone + Send + 'static> HttpService for TimeoutService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let req = req.into_owned(self.max_body)?;
+        let (method, path) = (req.method().to_owned(), req.path().to_owned());
+        let (worker, rx) = spawn_call(self.inner.clone(), req, ());
+        match rx.recv_timeout(self.timeout) {
+            Ok(answer) => answer?.apply(rsp),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // unwinds the worker at its next blocking call
+                unsafe { worker.coroutine().cancel() };
+                warn!("{method} {path} timed out after {:?}", self.timeout);
+                rsp.status_code(504, "Gateway Timeout");
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::other("service panicked"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+//! trace context propagation headers
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The trace a request is part of, as passed on by the caller
+///
+/// Get it from an incoming request with
+/// [`Request::trace_context`](crate::Request::trace_context), which reads the
+/// W3C `traceparent` and `tracestate` headers or, failing that, the Zipkin B3
+/// ones. Calls made while serving the request carry on the trace with a
+/// [`child`](Self::child) context, sent in the headers given by
+/// [`traceparent`](Self::traceparent) and [`tracestate`](Self::tracestate),
+/// or [`b3`](Self::b3) and [`b3_headers`](Self::b3_headers) for services
+/// that only know B3.
+///
+/// # Example
+/// ```no_run
+/// use may_minihttp::{HttpService, Request, Response, TraceContext};
+///
+/// #[derive(Clone)]
+/// struct Frontend;
+///
+/// impl HttpService for Frontend {
+///     fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         let span = match req.trace_context() {
+///             Some(parent) => parent.child(),
+///             None => TraceContext::new_root(),
+///         };
+///         // headers for a call to another service
+///         let outgoing = format!("traceparent: {}\r\n", span.traceparent());
+///         rsp.body_vec(outgoing.into_bytes());
+///         Ok(())
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    flags: u8,
+    state: Option<String>,
+}
+
+/// the `sampled` bit of the trace flags
+const SAMPLED: u8 = 0x01;
+
+impl TraceContext {
+    /// Start a new sampled trace, for requests that don't belong to one
+    pub fn new_root() -> Self {
+        let mut trace_id = [0; 16];
+        trace_id[..8].copy_from_slice(&random_id().to_be_bytes());
+        trace_id[8..].copy_from_slice(&random_id().to_be_bytes());
+        TraceContext {
+            trace_id,
+            span_id: random_id().to_be_bytes(),
+            flags: SAMPLED,
+            state: None,
+        }
+    }
+
+    /// Parse a `traceparent` header value, `None` if it is not valid
+    ///
+    /// Versions after `00` are read as far as `00` defines them.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let value = value.trim().as_bytes();
+        if value.len() < 55 {
+            return None;
+        }
+        let version = parse_hex::<1>(&value[..2])?[0];
+        // version 00 has nothing after the flags, later ones may add fields
+        let rest_ok = match value.get(55) {
+            None => true,
+            Some(&b'-') => version != 0,
+            Some(_) => false,
+        };
+        if version == 0xff || !rest_ok || [value[2], value[35], value[52]] != [b'-'; 3] {
+            return None;
+        }
+        let trace_id = parse_hex::<16>(&value[3..35])?;
+        let span_id = parse_hex::<8>(&value[36..52])?;
+        let flags = parse_hex::<1>(&value[53..55])?[0];
+        Self::from_ids(trace_id, span_id, flags)
+    }
+
+    /// Parse a single header `b3` value, `None` if it is not valid
+    ///
+    /// A value with only a sampling decision, such as `b3: 0`, has no trace
+    /// to carry on and gives `None` too. Debug sampling counts as sampled and
+    /// a deferred decision as not sampled. 64 bit trace ids are padded to
+    /// 128 bits.
+    pub fn from_b3(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let trace_id = parse_b3_trace_id(parts.next()?)?;
+        let span_id = parse_hex::<8>(parts.next()?.as_bytes())?;
+        let flags = match parts.next() {
+            Some("1" | "d") => SAMPLED,
+            Some("0") | None => 0,
+            Some(_) => return None,
+        };
+        // the parent span id is not needed to carry on the trace
+        if let Some(parent) = parts.next() {
+            parse_hex::<8>(parent.as_bytes())?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Self::from_ids(trace_id, span_id, flags)
+    }
+
+    fn from_ids(trace_id: [u8; 16], span_id: [u8; 8], flags: u8) -> Option<Self> {
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(TraceContext {
+            trace_id,
+            span_id,
+            flags,
+            state: None,
+        })
+    }
+
+    /// Set the vendor specific `tracestate` passed along with the trace
+    pub fn with_trace_state(mut self, state: impl Into<String>) -> Self {
+        self.state = Some(state.into()).filter(|s| !s.is_empty());
+        self
+    }
+
+    /// A context for a call made on behalf of this one
+    ///
+    /// It keeps the trace id, flags and trace state and gets a new random
+    /// span id.
+    pub fn child(&self) -> Self {
+        TraceContext {
+            span_id: random_id().to_be_bytes(),
+            ..self.clone()
+        }
+    }
+
+    /// The id of the whole trace
+    pub fn trace_id(&self) -> [u8; 16] {
+        self.trace_id
+    }
+
+    /// The id of the calling span, or of this one for a `child`
+    pub fn span_id(&self) -> [u8; 8] {
+        self.span_id
+    }
+
+    /// The trace flags, of which only `sampled` is defined
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Whether the caller records this trace
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    /// The trace id in lowercase hex, as logged by most tracing systems
+    pub fn trace_id_hex(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// The span id in lowercase hex
+    pub fn span_id_hex(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// The `traceparent` header value for this context
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id_hex(),
+            self.span_id_hex(),
+            self.flags
+        )
+    }
+
+    /// The vendor specific `tracestate` header value, if any
+    pub fn tracestate(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    /// The single header `b3` value for this context
+    pub fn b3(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.trace_id_hex(),
+            self.span_id_hex(),
+            self.b3_sampled()
+        )
+    }
+
+    /// The multi header B3 form, as `X-B3-TraceId`, `X-B3-SpanId` and
+    /// `X-B3-Sampled` name and value pairs
+    pub fn b3_headers(&self) -> [(&'static str, String); 3] {
+        [
+            ("X-B3-TraceId", self.trace_id_hex()),
+            ("X-B3-SpanId", self.span_id_hex()),
+            ("X-B3-Sampled", self.b3_sampled().to_owned()),
+        ]
+    }
+
+    fn b3_sampled(&self) -> &'static str {
+        if self.is_sampled() {
+            "1"
+        } else {
+            "0"
+        }
+    }
+}
+
+/// the trace context in `headers`, see `Request::trace_context`
+pub(crate) fn extract(headers: &[httparse::Header]) -> Option<TraceContext> {
+    // W3C wins when a proxy sends both, a bad traceparent starts a new trace
+    if headers
+        .iter()
+        .any(|h| h.name.eq_ignore_ascii_case("traceparent"))
+    {
+        let parent = TraceContext::from_traceparent(single(headers, "traceparent")?)?;
+        return Some(parent.with_trace_state(trace_state(headers)));
+    }
+    if let Some(b3) = single(headers, "b3") {
+        return TraceContext::from_b3(b3);
+    }
+    let trace_id = parse_b3_trace_id(single(headers, "x-b3-traceid")?)?;
+    let span_id = parse_hex::<8>(single(headers, "x-b3-spanid")?.as_bytes())?;
+    // debug implies sampled
+    let flags = match (
+        single(headers, "x-b3-flags"),
+        single(headers, "x-b3-sampled"),
+    ) {
+        (Some("1"), _) | (_, Some("1" | "true")) => SAMPLED,
+        (_, Some("0" | "false") | None) => 0,
+        _ => return None,
+    };
+    TraceContext::from_ids(trace_id, span_id, flags)
+}
+
+/// the value of the `name` header, `None` if it is missing, repeated or not utf-8
+fn single<'h>(headers: &[httparse::Header<'h>], name: &str) -> Option<&'h str> {
+    let mut found = headers.iter().filter(|h| h.name.eq_ignore_ascii_case(name));
+    let h = found.next()?;
+    if found.next().is_some() {
+        return None;
+    }
+    std::str::from_utf8(h.value).ok().map(str::trim)
+}
+
+/// all the `tracestate` lines as one list
+fn trace_state(headers: &[httparse::Header]) -> String {
+    let mut state = String::new();
+    for h in headers {
+        if !h.name.eq_ignore_ascii_case("tracestate") {
+            continue;
+        }
+        if let Ok(value) = std::str::from_utf8(h.value) {
+            let value = value.trim();
+            if !value.is_empty() {
+                if !state.is_empty() {
+                    state.push(',');
+                }
+                state.push_str(value);
+            }
+        }
+    }
+    state
+}
+
+/// a 64 or 128 bit B3 trace id, as 128 bits
+fn parse_b3_trace_id(s: &str) -> Option<[u8; 16]> {
+    match s.len() {
+        16 => {
+            let mut id = [0; 16];
+            id[8..].copy_from_slice(&parse_hex::<8>(s.as_bytes())?);
+            Some(id)
+        }
+        _ => parse_hex::<16>(s.as_bytes()),
+    }
+}
+
+/// a random non zero id, not for cryptographic use
+pub(crate) fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    // each RandomState has fresh random keys
+    let id = RandomState::new().hash_one(COUNTER.fetch_add(1, Ordering::Relaxed));
+    id.max(1)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for &b in bytes {
+        s.push(DIGITS[(b >> 4) as usize] as char);
+        s.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+/// decode `2 * N` lowercase hex digits
+fn parse_hex<const N: usize>(s: &[u8]) -> Option<[u8; N]> {
+    fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            _ => None,
+        }
+    }
+    if s.len() != 2 * N {
+        return None;
+    }
+    let mut out = [0; N];
+    for (o, pair) in out.iter_mut().zip(s.chunks(2)) {
+        *o = (digit(pair[0])? << 4) | digit(pair[1])?;
+    }
+    Some(out)
+}
@@ -0,0 +1,334 @@
+//! resumable uploads with the tus protocol
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::http_server::HttpService;
+use crate::request::Request;
+use crate::response::Response;
+use crate::trace::{hex, random_id};
+
+/// the protocol version spoken
+const TUS_VERSION: &str = "1.0.0";
+
+/// `HttpService` wrapper taking resumable uploads, as in the
+/// [tus protocol](https://tus.io/protocols/resumable-upload) 1.0.0
+///
+/// Requests under `base_path` are uploads, the rest go to the inner service.
+/// A client creates an upload with a `POST` to `base_path` giving its
+/// `Upload-Length`, and gets its URL in `Location`. It sends the bytes with
+/// `PATCH` requests, each starting at the `Upload-Offset` the server has. After
+/// a broken connection it asks for that offset with `HEAD` and goes on from
+/// there, nothing received is sent twice. `OPTIONS` tells the protocol
+/// versions, extensions and the [`max_size`](Self::max_size), `DELETE` drops
+/// an upload.
+///
+/// The body is streamed to a file in `dir`, named by the upload id, next to
+/// an `.info` file holding its length and `Upload-Metadata`. When the last
+/// byte is in, the [`on_complete`](Self::on_complete) hook gets the id and
+/// the file. Nothing is removed on its own: move the file away in the hook
+/// and clean up uploads clients gave up on. The ids are hard to guess but
+/// not secrets, put authentication in front of this service.
+///
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use may_minihttp::{HttpServer, HttpService, Request, Response, TusService};
+///
+/// #[derive(Clone)]
+/// struct Hello;
+///
+/// impl HttpService for Hello {
+///     fn call(&mut self, _req: Request, rsp: &mut Response) -> std::io::Result<()> {
+///         rsp.body("hello");
+///         Ok(())
+///     }
+/// }
+///
+/// fn uploaded(id: &str, file: &Path) {
+///     std::fs::rename(file, Path::new("/srv/videos").join(id)).unwrap();
+/// }
+///
+/// let service = TusService::new(Hello, "/files", "/var/tmp/uploads")
+///     .unwrap()
+///     .max_size(4 << 30)
+///     .on_complete(uploaded);
+/// let server = HttpServer(service).start("0.0.0.0:8080").unwrap();
+/// server.wait().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct TusService<S> {
+    inner: S,
+    base_path: String,
+    dir: PathBuf,
+    max_size: u64,
+    on_complete: Option<fn(&str, &Path)>,
+    // uploads a PATCH is writing to, across all the clones
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+impl<S> TusService<S> {
+    /// Wrap `inner`, taking uploads under `base_path` into `dir`
+    ///
+    /// Uploads are limited to 1 GiB, change that with
+    /// [`max_size`](Self::max_size).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error creating `dir` if it doesn't exist.
+    pub fn new(inner: S, base_path: &str, dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(TusService {
+            inner,
+            base_path: base_path.trim_end_matches('/').to_owned(),
+            dir,
+            max_size: 1 << 30,
+            on_complete: None,
+            busy: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    /// Set the largest `Upload-Length` accepted, in bytes
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Call `hook` with the id and file of each upload once it is complete
+    ///
+    /// The hook runs before the last `PATCH` is answered, so the client
+    /// knows the upload was handed over when it sees the final offset.
+    pub fn on_complete(mut self, hook: fn(&str, &Path)) -> Self {
+        self.on_complete = Some(hook);
+        self
+    }
+
+    /// the part of `path` after the base path, `None` if it is not an upload path
+    fn upload_path<'p>(&self, path: &'p str) -> Option<&'p str> {
+        let path = path.split('?').next().unwrap_or_default();
+        let rest = path.strip_prefix(self.base_path.as_str())?;
+        match rest {
+            "" | "/" => Some(""),
+            _ => rest.strip_prefix('/'),
+        }
+    }
+
+    fn data_file(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn info_file(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.info"))
+    }
+
+    /// the length and metadata of an upload, `None` if there is no such upload
+    fn info(&self, id: &str) -> io::Result<Option<(u64, String)>> {
+        // ids are made here, anything else can't name a file in `dir`
+        if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        let info = match fs::read_to_string(self.info_file(id)) {
+            Ok(info) => info,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let (length, metadata) = info.split_once('\n').unwrap_or((&info, ""));
+        let length = length.parse().map_err(|_| {
+            let msg = format!("corrupt upload info in {}", self.info_file(id).display());
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        })?;
+        Ok(Some((length, metadata.to_owned())))
+    }
+
+    fn offset(&self, id: &str) -> io::Result<u64> {
+        Ok(fs::metadata(self.data_file(id))?.len())
+    }
+
+    fn create(&self, req: &Request, rsp: &mut Response) -> io::Result<()> {
+        let Some(length) = header(req, "upload-length").and_then(|v| v.parse::<u64>().ok()) else {
+            rsp.status_code(400, "Bad Request");
+            return Ok(());
+        };
+        if length > self.max_size {
+            rsp.status_code(413, "Payload Too Large");
+            return Ok(());
+        }
+        let metadata = header(req, "upload-metadata").unwrap_or_default();
+        if metadata.contains(['\r', '\n']) {
+            rsp.status_code(400, "Bad Request");
+            return Ok(());
+        }
+        let id = new_id();
+        // never reuse a file someone else put there
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.data_file(&id))?;
+        fs::write(self.info_file(&id), format!("{length}\n{metadata}"))?;
+        debug!("upload {id} of {length} bytes created");
+        rsp.status_code(201, "Created")
+            .header_owned(format!("Location: {}/{id}", self.base_path));
+        if length == 0 {
+            self.complete(&id);
+        }
+        Ok(())
+    }
+
+    fn patch(&self, id: &str, length: u64, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let content_type = header(&req, "content-type");
+        if content_type != Some("application/offset+octet-stream") {
+            rsp.status_code(415, "Unsupported Media Type");
+            return Ok(());
+        }
+        let Some(client_offset) = header(&req, "upload-offset").and_then(|v| v.parse::<u64>().ok())
+        else {
+            rsp.status_code(400, "Bad Request");
+            return Ok(());
+        };
+        let Some(_writing) = Writing::start(&self.busy, id) else {
+            // another PATCH is still writing, its offset isn't final
+            rsp.status_code(409, "Conflict");
+            return Ok(());
+        };
+        let offset = self.offset(id)?;
+        if client_offset != offset {
+            rsp.status_code(409, "Conflict");
+            return Ok(());
+        }
+        let sent = header(&req, "content-length").and_then(|v| v.parse::<u64>().ok());
+        if sent.is_some_and(|sent| sent > length - offset) {
+            rsp.status_code(413, "Payload Too Large");
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().append(true).open(self.data_file(id))?;
+        // what arrives before a broken connection is kept, the client resumes after it
+        let copied = io::copy(&mut req.body().take(length - offset), &mut file);
+        file.flush()?;
+        file.sync_data()?;
+        let offset = offset + copied?;
+        rsp.status_code(204, "No Content")
+            .header_kv("Upload-Offset", offset.to_string());
+        if offset == length {
+            self.complete(id);
+        }
+        Ok(())
+    }
+
+    fn complete(&self, id: &str) {
+        debug!("upload {id} complete");
+        if let Some(hook) = self.on_complete {
+            hook(id, &self.data_file(id));
+        }
+    }
+
+    fn serve(&self, upload: &str, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.method() == "OPTIONS" {
+            rsp.status_code(204, "No Content")
+                .header_kv("Tus-Version", TUS_VERSION)
+                .header("Tus-Extension: creation,termination")
+                .header_kv("Tus-Max-Size", self.max_size.to_string());
+            return Ok(());
+        }
+        if header(&req, "tus-resumable") != Some(TUS_VERSION) {
+            rsp.status_code(412, "Precondition Failed")
+                .header_kv("Tus-Version", TUS_VERSION);
+            return Ok(());
+        }
+        if upload.is_empty() {
+            return match req.method() {
+                "POST" => self.create(&req, rsp),
+                _ => {
+                    rsp.status_code(405, "Method Not Allowed")
+                        .header("Allow: OPTIONS, POST");
+                    Ok(())
+                }
+            };
+        }
+        let Some((length, metadata)) = self.info(upload)? else {
+            rsp.status_code(404, "Not Found");
+            return Ok(());
+        };
+        match req.method() {
+            "HEAD" => {
+                rsp.header_kv("Upload-Offset", self.offset(upload)?.to_string())
+                    .header_kv("Upload-Length", length.to_string())
+                    .header("Cache-Control: no-store");
+                if !metadata.is_empty() {
+                    rsp.header_kv("Upload-Metadata", metadata);
+                }
+            }
+            "PATCH" => return self.patch(upload, length, req, rsp),
+            "DELETE" => {
+                let Some(_writing) = Writing::start(&self.busy, upload) else {
+                    rsp.status_code(409, "Conflict");
+                    return Ok(());
+                };
+                fs::remove_file(self.info_file(upload))?;
+                fs::remove_file(self.data_file(upload))?;
+                rsp.status_code(204, "No Content");
+            }
+            _ => {
+                rsp.status_code(405, "Method Not Allowed")
+                    .header("Allow: OPTIONS, HEAD, PATCH, DELETE");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: HttpService> HttpService for TusService<S> {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match self.upload_path(req.path()) {
+            Some(upload) => {
+                let upload = upload.to_owned();
+                rsp.header_kv("Tus-Resumable", TUS_VERSION);
+                self.serve(&upload, req, rsp)
+            }
+            None => self.inner.call(req, rsp),
+        }
+    }
+}
+
+/// an upload taken by a request, given back when it is done or unwinds
+struct Writing<'a> {
+    busy: &'a Mutex<HashSet<String>>,
+    id: String,
+}
+
+impl<'a> Writing<'a> {
+    fn start(busy: &'a Mutex<HashSet<String>>, id: &str) -> Option<Self> {
+        let mut taken = busy.lock().unwrap_or_else(|e| e.into_inner());
+        taken.insert(id.to_owned()).then(|| Writing {
+            busy,
+            id: id.to_owned(),
+        })
+    }
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        let mut taken = self.busy.lock().unwrap_or_else(|e| e.into_inner());
+        taken.remove(&self.id);
+    }
+}
+
+/// a random 128 bit upload id in hex
+fn new_id() -> String {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&random_id().to_be_bytes());
+    bytes[8..].copy_from_slice(&random_id().to_be_bytes());
+    hex(&bytes)
+}
+
+/// the first value of a header as text
+fn header<'r>(req: &'r Request, name: &str) -> Option<&'r str> {
+    req.headers()
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case(name))
+        .and_then(|h| std::str::from_utf8(h.value).ok())
+        .map(str::trim)
+}
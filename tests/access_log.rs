@@ -0,0 +1,106 @@
+//! Tests for the access log service and its sinks
+#![cfg(feature = "access-log")]
+
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{
+    AccessLogRecord, AccessLogService, AccessLogSink, ChannelSink, FileSink, HttpService, Request,
+    Response,
+};
+
+#[derive(Clone)]
+struct App;
+
+impl HttpService for App {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/hello" => rsp.body("hello"),
+            "/fail" => return Err(io::Error::other("broken")),
+            _ => {
+                rsp.status_code(404, "Not Found");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn record(path: &str) -> AccessLogRecord {
+    AccessLogRecord {
+        time: SystemTime::UNIX_EPOCH,
+        method: "GET".to_owned(),
+        path: path.to_owned(),
+        status: 200,
+        body_len: 5,
+        duration: Duration::from_micros(120),
+        user_agent: None,
+    }
+}
+
+#[test]
+fn test_channel_sink() {
+    let (sink, records) = ChannelSink::new();
+    let client = TestClient::new(AccessLogService::new(App, sink));
+    let rsp = client
+        .get("/hello")
+        .header("User-Agent", "curl/8.0")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), "hello");
+    let logged = records.try_recv().unwrap();
+    assert_eq!(logged.method, "GET");
+    assert_eq!(logged.path, "/hello");
+    assert_eq!(logged.status, 200);
+    assert_eq!(logged.body_len, 5);
+    assert_eq!(logged.user_agent.as_deref(), Some("curl/8.0"));
+
+    client.get("/missing?page=2").send().unwrap();
+    let logged = records.try_recv().unwrap();
+    assert_eq!(logged.path, "/missing?page=2");
+    assert_eq!(logged.status, 404);
+    assert_eq!(logged.user_agent, None);
+
+    // the error still reaches the connection, the log shows a 500
+    let _ = client.get("/fail").send();
+    assert_eq!(records.try_recv().unwrap().status, 500);
+}
+
+#[test]
+fn test_record_line() {
+    let line = record("/a\"b").to_string();
+    assert_eq!(
+        line,
+        "[Thu, 01 Jan 1970 00:00:00 GMT] \"GET /a\\\"b\" 200 5 0.120ms \"-\""
+    );
+}
+
+#[test]
+fn test_file_sink_rotates() {
+    let dir = std::env::temp_dir().join(format!("may_minihttp-access-log-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("access.log");
+    let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+
+    let line_len = record("/0").to_string().len() + 1;
+    let sink = FileSink::new(&path, 3 * line_len as u64, 2).unwrap();
+    for i in 0..10 {
+        sink.write(&record(&format!("/{i}")));
+    }
+    sink.flush();
+
+    // 3 lines per file, the oldest files are gone
+    let current = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(current.lines().count(), 1);
+    assert!(current.contains("GET /9"));
+    let previous = std::fs::read_to_string(rotated(1)).unwrap();
+    assert_eq!(previous.lines().count(), 3);
+    assert!(previous.contains("GET /6"));
+    assert!(std::fs::read_to_string(rotated(2))
+        .unwrap()
+        .contains("GET /3"));
+    assert!(!rotated(3).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,132 @@
+//! Tests for the admin endpoint
+#![cfg(feature = "admin")]
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{
+    AdminService, HeaderValidation, HttpConfig, HttpServer, HttpService, HttpServiceFactory,
+    Request, Response, ServerStats,
+};
+use serde_json::Value;
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn json(client: &TestClient<AdminService>, path: &str) -> Value {
+    let rsp = client.get(path).send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.header("content-type"), Some("application/json"));
+    serde_json::from_slice(rsp.body()).unwrap()
+}
+
+fn wait_for(cond: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        if cond() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn test_stats_and_config() {
+    let stats = Arc::new(ServerStats::new());
+    let config = HttpConfig::new()
+        .with_stats(stats.clone())
+        .with_max_in_flight(7)
+        .with_header_validation(HeaderValidation::Sanitize);
+    let app = TestClient::new(Hello).config(config.clone());
+    app.get("/").send().unwrap();
+    app.get("/").send().unwrap();
+
+    let admin = TestClient::new(AdminService::new(stats.clone(), config));
+    let counters = json(&admin, "/stats");
+    assert_eq!(counters["accepted"], 2);
+    assert_eq!(counters["requests"], 2);
+    assert_eq!(counters["active_connections"], 0);
+    assert_eq!(counters["bytes_read"], stats.bytes_read());
+
+    let config = json(&admin, "/config");
+    assert_eq!(config["max_in_flight"], 7);
+    assert_eq!(config["max_headers"], 16);
+    assert_eq!(config["header_validation"], "sanitize");
+    assert_eq!(config["parse_error_log"], "warn");
+    assert_eq!(config["on_request_complete"], false);
+}
+
+#[test]
+fn test_connection_table() {
+    let stats = Arc::new(ServerStats::new());
+    let config = HttpConfig::new().with_stats(stats.clone());
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config.clone())
+        .unwrap();
+    server.wait_until_ready().unwrap();
+
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    let mut buf = [0u8; 1024];
+    for _ in 0..2 {
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let n = client.read(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+    }
+
+    // the request is counted once its response is out
+    assert!(wait_for(|| stats.requests() == 2));
+    let admin = TestClient::new(AdminService::new(stats.clone(), config));
+    let conns = json(&admin, "/connections");
+    let conns = conns.as_array().unwrap();
+    assert_eq!(conns.len(), 1);
+    assert_eq!(conns[0]["peer"], client.local_addr().unwrap().to_string());
+    assert_eq!(conns[0]["requests"], 2);
+    assert!(conns[0]["age_ms"].is_u64());
+
+    server.stop();
+}
+
+#[test]
+fn test_log_level() {
+    let stats = Arc::new(ServerStats::new());
+    let admin = TestClient::new(AdminService::new(stats, HttpConfig::new()));
+
+    let rsp = admin
+        .request("PUT", "/log-level")
+        .body("debug")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(log::max_level(), log::LevelFilter::Debug);
+    assert_eq!(json(&admin, "/log-level")["level"], "debug");
+
+    let rsp = admin
+        .request("PUT", "/log-level")
+        .body("loud")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 400);
+    assert_eq!(log::max_level(), log::LevelFilter::Debug);
+}
+
+#[test]
+fn test_unknown_routes() {
+    let stats = Arc::new(ServerStats::new());
+    let admin = TestClient::new(AdminService::new(stats, HttpConfig::new()));
+    assert_eq!(admin.get("/nope").send().unwrap().status(), 404);
+    assert_eq!(admin.post("/stats").send().unwrap().status(), 405);
+}
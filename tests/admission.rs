@@ -0,0 +1,79 @@
+//! Tests for the bounded admission queue
+#![cfg(feature = "admission")]
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{AdmissionService, HttpService, Request, Response};
+
+/// Holds its slot for a while
+#[derive(Clone)]
+struct Slow;
+
+impl HttpService for Slow {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        thread::sleep(Duration::from_millis(300));
+        rsp.body("done");
+        Ok(())
+    }
+}
+
+/// send a request on its own thread after `delay`, return its status
+fn request_after(service: &AdmissionService<Slow>, delay: Duration) -> thread::JoinHandle<u16> {
+    let client = TestClient::new(service.clone());
+    thread::spawn(move || {
+        thread::sleep(delay);
+        client.get("/").send().unwrap().status()
+    })
+}
+
+#[test]
+fn test_full_queue_gets_429() {
+    let service = AdmissionService::new(Slow, 1)
+        .queue_depth(1)
+        .max_wait(Duration::from_secs(3));
+    let running = request_after(&service, Duration::ZERO);
+    let queued = request_after(&service, Duration::from_millis(50));
+
+    thread::sleep(Duration::from_millis(100));
+    let start = Instant::now();
+    let rsp = TestClient::new(service).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 429);
+    assert_eq!(rsp.header("retry-after"), Some("1"));
+    assert!(start.elapsed() < Duration::from_millis(200));
+
+    assert_eq!(running.join().unwrap(), 200);
+    // the queued request got the slot once it was free
+    assert_eq!(queued.join().unwrap(), 200);
+}
+
+#[test]
+fn test_wait_deadline_gets_503() {
+    let service = AdmissionService::new(Slow, 1)
+        .queue_depth(4)
+        .max_wait(Duration::from_millis(50));
+    let running = request_after(&service, Duration::ZERO);
+
+    thread::sleep(Duration::from_millis(50));
+    let rsp = TestClient::new(service.clone()).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 503);
+    assert_eq!(running.join().unwrap(), 200);
+
+    // the slot is given back
+    let rsp = TestClient::new(service).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.text(), "done");
+}
+
+#[test]
+fn test_no_queue() {
+    let service = AdmissionService::new(Slow, 1).queue_depth(0);
+    let running = request_after(&service, Duration::ZERO);
+
+    thread::sleep(Duration::from_millis(50));
+    let rsp = TestClient::new(service).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 429);
+    assert_eq!(running.join().unwrap(), 200);
+}
@@ -0,0 +1,77 @@
+//! Tests for noticing a client that went away during a long request
+#![cfg(unix)]
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpServer, HttpService, Request, Response};
+
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone)]
+struct Patient;
+
+impl HttpService for Patient {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/wait" {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_secs(5) {
+                if !req.is_client_connected() {
+                    GAVE_UP.store(true, Ordering::SeqCst);
+                    return Err(io::ErrorKind::ConnectionAborted.into());
+                }
+                may::coroutine::sleep(Duration::from_millis(10));
+            }
+        }
+        rsp.body(if req.is_client_connected() {
+            "here"
+        } else {
+            "gone"
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_connected_client() {
+    let server = HttpServer(Patient).start("127.0.0.1:0").unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    // a pipelined request waiting in the socket is no disconnect
+    client
+        .write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    assert_eq!(rsp.matches("here").count(), 2, "{rsp}");
+
+    // nothing to peek at without a connection
+    let rsp = TestClient::new(Patient).get("/").send().unwrap();
+    assert_eq!(rsp.text(), "here");
+}
+
+#[test]
+fn test_handler_notices_the_client_leaving() {
+    let server = HttpServer(Patient).start("127.0.0.1:0").unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client.write_all(b"GET /wait HTTP/1.1\r\n\r\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!GAVE_UP.load(Ordering::SeqCst));
+    drop(client);
+
+    let start = Instant::now();
+    while !GAVE_UP.load(Ordering::SeqCst) {
+        assert!(
+            start.elapsed() < Duration::from_secs(3),
+            "the handler kept going"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,64 @@
+//! Helpers shared by the integration tests
+//!
+//! Servers listen on a free port picked by the OS, so the test binaries can
+//! run in parallel; use `ServerHandle::local_addr` to reach them.
+#![allow(dead_code)]
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Once;
+use std::time::Duration;
+
+use may_minihttp::{HttpServer, HttpService, ServerHandle};
+
+static INIT: Once = Once::new();
+
+/// Initialize MAY runtime once for all tests
+pub fn init_may_runtime() {
+    INIT.call_once(|| {
+        may::config().set_stack_size(0x8000);
+    });
+}
+
+/// Wait until a started server accepts connections
+pub fn ready(handle: io::Result<ServerHandle>) -> ServerHandle {
+    let handle = handle.expect("Failed to start server");
+    handle.wait_until_ready().expect("server not ready");
+    handle
+}
+
+/// Start `service` on a free port and wait until it accepts connections
+pub fn start_server<T>(service: T) -> ServerHandle
+where
+    T: HttpService + Clone + Send + Sync + 'static,
+{
+    init_may_runtime();
+    ready(HttpServer(service).start("127.0.0.1:0"))
+}
+
+/// Connect to `addr`, reads time out after 2 seconds
+pub fn connect(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+}
+
+/// Send `req` on a new connection and return the first read of the answer
+pub fn send(addr: SocketAddr, req: &str) -> String {
+    let mut stream = connect(addr);
+    stream.write_all(req.as_bytes()).unwrap();
+
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+/// `GET path` on a new connection, see [`send`]
+pub fn get(addr: SocketAddr, path: &str) -> String {
+    send(
+        addr,
+        &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+    )
+}
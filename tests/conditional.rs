@@ -0,0 +1,148 @@
+//! Tests for If-Modified-Since handling
+#![cfg(feature = "conditional")]
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use may_minihttp::date::HttpDate;
+use may_minihttp::test::TestClient;
+use may_minihttp::{ConditionalService, HttpService, Request, Response};
+
+fn modified() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+}
+
+#[derive(Clone)]
+struct Doc;
+
+impl HttpService for Doc {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/missing" {
+            rsp.status_code(404, "Not Found").last_modified(modified());
+            return Ok(());
+        }
+        rsp.header("Content-Type: text/plain")
+            .last_modified(modified())
+            .body("document");
+        Ok(())
+    }
+}
+
+fn client() -> TestClient<ConditionalService<Doc>> {
+    TestClient::new(ConditionalService::new(Doc))
+}
+
+fn date(t: SystemTime) -> String {
+    HttpDate::from(t).to_string()
+}
+
+#[test]
+fn test_last_modified_header_is_sent() {
+    let rsp = client().get("/").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(
+        rsp.header("last-modified"),
+        Some("Tue, 14 Nov 2023 22:13:20 GMT")
+    );
+    assert_eq!(rsp.text(), "document");
+}
+
+#[test]
+fn test_not_modified_since() {
+    for since in [modified(), modified() + Duration::from_secs(60)] {
+        let rsp = client()
+            .get("/")
+            .header("If-Modified-Since", &date(since))
+            .send()
+            .unwrap();
+        assert_eq!(rsp.status(), 304);
+        assert_eq!(rsp.reason(), "Not Modified");
+        assert!(rsp.header("content-length").is_none());
+        assert!(rsp.header("last-modified").is_some());
+        assert!(rsp.body().is_empty());
+    }
+}
+
+#[test]
+fn test_modified_since() {
+    let rsp = client()
+        .get("/")
+        .header(
+            "If-Modified-Since",
+            &date(modified() - Duration::from_secs(1)),
+        )
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.text(), "document");
+}
+
+#[test]
+fn test_precondition_ignored() {
+    let since = date(modified());
+    let client = client();
+    let cases = [
+        client.post("/").header("If-Modified-Since", &since),
+        client.get("/").header("If-Modified-Since", "not a date"),
+        client
+            .get("/")
+            .header("If-Modified-Since", &since)
+            .header("If-None-Match", "\"abc\""),
+        client.get("/missing").header("If-Modified-Since", &since),
+    ];
+    for req in cases {
+        assert_ne!(req.send().unwrap().status(), 304);
+    }
+}
+
+/// Updates only when the client's `If-Match` / `If-Unmodified-Since` hold
+#[derive(Clone)]
+struct Update;
+
+impl HttpService for Update {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if !req.preconditions_hold(Some("\"v2\""), Some(modified())) {
+            rsp.status_code(412, "Precondition Failed");
+            return Ok(());
+        }
+        rsp.status_code(204, "No Content");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_if_match() {
+    let client = TestClient::new(Update);
+    let status = |value: &str| {
+        let req = client.request("PUT", "/").header("If-Match", value);
+        req.send().unwrap().status()
+    };
+    assert_eq!(status("\"v2\""), 204);
+    assert_eq!(status("\"v1\", \"v2\""), 204);
+    assert_eq!(status("*"), 204);
+    assert_eq!(status("\"v1\""), 412);
+    // weak tags never match
+    assert_eq!(status("W/\"v2\""), 412);
+}
+
+#[test]
+fn test_if_unmodified_since() {
+    let client = TestClient::new(Update);
+    let status = |since: SystemTime| {
+        let req = client
+            .request("DELETE", "/")
+            .header("If-Unmodified-Since", &date(since));
+        req.send().unwrap().status()
+    };
+    assert_eq!(status(modified()), 204);
+    assert_eq!(status(modified() - Duration::from_secs(1)), 412);
+
+    // If-Match takes precedence
+    let rsp = client
+        .request("DELETE", "/")
+        .header("If-Match", "\"v2\"")
+        .header("If-Unmodified-Since", &date(UNIX_EPOCH))
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 204);
+}
@@ -0,0 +1,67 @@
+//! Tests for checking request bodies against their digest
+#![cfg(feature = "digest")]
+
+use std::io::{self, Read};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{DigestService, HttpService, Request, Response};
+
+const BODY: &str = r#"{"event":"push"}"#;
+const SHA256: &str = "6EXikwqv8huOLQmnhCSlgLuwDPdod+dc/tHGNyV3Giw=";
+const SHA512: &str =
+    "YY0xxP7a0Ba3rp3kiphbV2hIPk0TJRrJdamqLPKGasuFBYalte5Wnb7k9grxAfCOyeJVcrEYrDMWipCG4UOg6g==";
+
+#[derive(Clone)]
+struct Echo;
+
+impl HttpService for Echo {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        rsp.body_vec(body);
+        Ok(())
+    }
+}
+
+fn send(client: &TestClient<DigestService<Echo>>, name: &str, value: &str) -> (u16, String) {
+    let rsp = client
+        .post("/hook")
+        .header(name, value)
+        .body(BODY)
+        .send()
+        .unwrap();
+    (rsp.status(), rsp.text())
+}
+
+#[test]
+fn test_matching_digests_reach_the_service() {
+    let client = TestClient::new(DigestService::new(Echo));
+    let content_digest = format!("sha-256=:{SHA256}:, sha-512=:{SHA512}:");
+    assert_eq!(
+        send(&client, "Content-Digest", &content_digest),
+        (200, BODY.to_owned())
+    );
+    let digest = format!("SHA-256={SHA256}, unixsum=30637");
+    assert_eq!(send(&client, "Digest", &digest), (200, BODY.to_owned()));
+}
+
+#[test]
+fn test_mismatch_is_rejected() {
+    let client = TestClient::new(DigestService::new(Echo));
+    // the sha-256 of "hello"
+    let wrong = "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:";
+    assert_eq!(send(&client, "Content-Digest", wrong).0, 400);
+    // not wrapped in colons
+    let malformed = format!("sha-256={SHA256}");
+    assert_eq!(send(&client, "Content-Digest", &malformed).0, 400);
+}
+
+#[test]
+fn test_missing_digest() {
+    let client = TestClient::new(DigestService::new(Echo));
+    assert_eq!(send(&client, "X-Other", "1").0, 200);
+
+    let client = TestClient::new(DigestService::new(Echo).require_digest());
+    assert_eq!(send(&client, "X-Other", "1").0, 400);
+    assert_eq!(send(&client, "Digest", "md5=abc").0, 400);
+}
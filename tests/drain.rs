@@ -0,0 +1,161 @@
+//! Tests for draining a server before it stops
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use may_minihttp::{
+    Drain, HttpConfig, HttpServer, HttpServerConcurrent, HttpService, HttpServiceFactory, Request,
+    Response, ServerHandle,
+};
+
+/// Fails its readiness check once the drain begins
+#[derive(Clone)]
+struct App(Arc<Drain>);
+
+impl HttpService for App {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/ready" if self.0.is_draining() => {
+                rsp.status_code(503, "Service Unavailable");
+            }
+            "/ready" => rsp.body("ready"),
+            "/fail" => return Err(io::Error::other("failed")),
+            "/slow" => {
+                may::coroutine::sleep(Duration::from_millis(300));
+                rsp.body("slow");
+            }
+            _ => rsp.body("hello"),
+        }
+        Ok(())
+    }
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let client = TcpStream::connect(addr).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+}
+
+fn get(client: &mut TcpStream, path: &str) -> String {
+    let req = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    client.write_all(req.as_bytes()).unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+fn is_closed(client: &mut TcpStream) -> bool {
+    let mut buf = [0u8; 16];
+    matches!(client.read(&mut buf), Ok(0))
+}
+
+fn check_drain(server: ServerHandle, drain: &Drain) {
+    server.wait_until_ready().unwrap();
+    let mut client = connect(server.local_addr());
+    let rsp = get(&mut client, "/ready");
+    assert!(rsp.starts_with("HTTP/1.1 200"));
+    assert!(!rsp.contains("Connection: close"));
+
+    server.begin_drain(Duration::from_millis(300));
+    assert!(drain.is_draining());
+
+    // the open connection closes after its next response
+    let rsp = get(&mut client, "/ready");
+    assert!(rsp.starts_with("HTTP/1.1 503"));
+    assert!(rsp.contains("Connection: close"));
+    assert!(is_closed(&mut client));
+
+    // new clients are still accepted during the grace period
+    let mut late = connect(server.local_addr());
+    let rsp = get(&mut late, "/");
+    assert!(rsp.ends_with("hello"));
+    assert!(rsp.contains("Connection: close"));
+
+    assert!(server.wait_for(Duration::from_secs(3)));
+    server.wait().unwrap();
+}
+
+#[test]
+fn test_drain() {
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServer(App(drain.clone()))
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    check_drain(server, &drain);
+}
+
+#[test]
+fn test_drain_during_a_call_closes_after_it() {
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServer(App(drain.clone()))
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = connect(server.local_addr());
+    let req = "GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    client.write_all(req.as_bytes()).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    server.begin_drain(Duration::from_secs(2));
+
+    // the response of the running call already tells about the drain
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    assert!(rsp.ends_with("slow"), "{rsp}");
+    assert!(rsp.contains("\r\nConnection: close\r\n"), "{rsp}");
+    assert!(server.wait_for(Duration::from_secs(3)));
+}
+
+fn check_error_during_drain(server: ServerHandle) {
+    server.wait_until_ready().unwrap();
+    let mut client = connect(server.local_addr());
+    server.begin_drain(Duration::from_millis(300));
+
+    // the 500 of a failed call closes the connection like any other response
+    let rsp = get(&mut client, "/fail");
+    assert!(rsp.starts_with("HTTP/1.1 500"), "{rsp}");
+    assert!(rsp.contains("\r\nConnection: close\r\n"), "{rsp}");
+    assert!(is_closed(&mut client));
+    assert!(server.wait_for(Duration::from_secs(3)));
+}
+
+#[test]
+fn test_drain_error_response_closes() {
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServer(App(drain.clone()))
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    check_error_during_drain(server);
+
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServerConcurrent::new(App(drain))
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    check_error_during_drain(server);
+}
+
+#[test]
+fn test_drain_concurrent() {
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServerConcurrent::new(App(drain.clone()))
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    check_drain(server, &drain);
+}
+
+#[test]
+fn test_drain_without_state() {
+    let drain = Arc::new(Drain::new());
+    let server = HttpServer(App(drain.clone())).start("127.0.0.1:0").unwrap();
+    server.begin_drain(Duration::ZERO);
+    assert!(!drain.is_draining());
+    assert!(server.wait_for(Duration::from_secs(3)));
+}
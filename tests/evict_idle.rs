@@ -0,0 +1,78 @@
+//! Tests for closing idle connections when the process runs out of fds
+//!
+//! The test takes up all the file descriptors of the process, so it is the
+//! only one in this file.
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use may_minihttp::{HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let client = TcpStream::connect(addr).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+}
+
+fn get(client: &mut TcpStream) -> String {
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+/// open files until the process has no file descriptor left
+fn exhaust_fds() -> Vec<File> {
+    let mut files = Vec::new();
+    loop {
+        match File::open("/dev/null") {
+            Ok(file) => files.push(file),
+            Err(e) if matches!(e.raw_os_error(), Some(23 | 24)) => return files,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+}
+
+#[test]
+fn test_idle_connection_evicted_for_new_client() {
+    let config = HttpConfig::new().with_evict_idle(true);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let addr = server.local_addr();
+
+    let mut idle = connect(addr);
+    assert!(get(&mut idle).ends_with("hello"));
+    // let the connection go back to waiting
+    std::thread::sleep(Duration::from_millis(50));
+
+    let mut files = exhaust_fds();
+    // room for the client socket but not for the server side of it
+    files.pop();
+    let mut fresh = connect(addr);
+    let rsp = get(&mut fresh);
+    drop(files);
+    assert!(rsp.ends_with("hello"), "{rsp}");
+
+    // the idle client sees its connection close
+    let mut buf = [0u8; 64];
+    assert_eq!(idle.read(&mut buf).unwrap(), 0);
+}
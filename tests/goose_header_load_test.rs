@@ -9,25 +9,15 @@
 //! - RAII pattern ensures proper cleanup
 //! - Tests against the same container image used in GitHub Actions
 //! - Simulates realistic traffic patterns (browsers, load balancers, APIs)
-//! - Dynamic port allocation prevents conflicts
+//! - Servers listen on a free port picked by the OS, preventing conflicts
+
+mod common;
 
 use bytes::BufMut;
+use common::init_may_runtime;
 use goose::prelude::*;
-use may_minihttp::{HttpServer, HttpService, Request, Response};
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerHandle};
 use std::io;
-use std::net::TcpListener;
-use std::sync::Once;
-use std::thread;
-use std::time::Duration;
-
-static INIT: Once = Once::new();
-
-/// Initialize MAY runtime once for all tests
-fn init_may_runtime() {
-    INIT.call_once(|| {
-        may::config().set_stack_size(0x8000);
-    });
-}
 
 /// Print detailed Goose metrics report
 fn print_goose_report(test_name: &str, metrics: &goose::metrics::GooseMetrics) {
@@ -120,131 +110,48 @@ impl HttpService for TestService {
     }
 }
 
-/// Check if a port is available for binding
-fn is_port_available(port: u16) -> bool {
-    TcpListener::bind(format!("127.0.0.1:{}", port)).is_ok()
-}
-
-/// Find the next available port starting from the given port
-fn find_available_port(start_port: u16) -> u16 {
-    for port in start_port..(start_port + 100) {
-        if is_port_available(port) {
-            eprintln!("[PORT] Found available port: {}", port);
-            return port;
-        }
-    }
-    panic!(
-        "Could not find available port in range {}-{}",
-        start_port,
-        start_port + 100
-    );
-}
-
-/// Ensure a port is available, finding an alternative if necessary
-fn ensure_port_available(preferred_port: u16) -> u16 {
-    if is_port_available(preferred_port) {
-        eprintln!("[PORT] Using preferred port: {}", preferred_port);
-        preferred_port
-    } else {
-        eprintln!(
-            "[PORT] Port {} in use, finding alternative...",
-            preferred_port
-        );
-        find_available_port(preferred_port + 1)
-    }
-}
-
 /// RAII fixture for Goose load testing
 ///
-/// This fixture uses dynamic port allocation to prevent conflicts and can be
-/// extended to use testcontainers for full isolation, matching the exact
-/// environment used in GitHub Actions CI.
-///
-/// ## Port Management
-///
-/// Automatically finds an available port if the preferred port is in use,
-/// ensuring tests never fail due to port conflicts.
+/// This fixture listens on a free port picked by the OS to prevent conflicts
+/// and can be extended to use testcontainers for full isolation, matching the
+/// exact environment used in GitHub Actions CI.
 struct GooseTestFixture {
     port: u16,
-    handle: Option<may::coroutine::JoinHandle<()>>,
+    handle: Option<ServerHandle>,
 }
 
 impl GooseTestFixture {
-    /// Create a new test fixture with port availability checking
-    ///
-    /// # Arguments
-    ///
-    /// * `preferred_port` - The preferred port to use (will find alternative if unavailable)
+    /// Create a new test fixture on a free port
     ///
     /// # Example
     ///
     /// ```ignore
-    /// let fixture = GooseTestFixture::new(19001);
-    /// // Uses port 19001, or next available port if busy
+    /// let fixture = GooseTestFixture::new();
+    /// let url = fixture.base_url();
     /// ```
-    fn new(preferred_port: u16) -> Self {
+    fn new() -> Self {
         // CRITICAL: Initialize MAY runtime configuration FIRST (once for all tests)
         init_may_runtime();
 
-        // Check port availability and find alternative if needed
-        let port = ensure_port_available(preferred_port);
-
         // Start the HTTP server in the MAIN THREAD (not a background thread)
         // This matches BRRTRouter's pattern exactly:
         // - HttpServer.start() spawns a coroutine and returns immediately
-        // - The JoinHandle keeps the server running
+        // - The ServerHandle keeps the server running
         // - No thread::spawn needed - MAY handles concurrency with coroutines
         let handle = HttpServer(TestService)
-            .start(&format!("127.0.0.1:{}", port))
+            .start("127.0.0.1:0")
+            .expect("Failed to start test server");
+        let port = handle.local_addr().port();
+        handle
+            .wait_until_ready()
             .expect("Failed to start test server");
-
-        let fixture = Self {
-            port,
-            handle: Some(handle),
-        };
-
-        // Wait for server to be ready to accept connections
-        if !fixture.wait_for_ready(50) {
-            panic!("Server failed to start on port {}", port);
-        }
 
         eprintln!("[GOOSE] GooseTestFixture started server on port {}", port);
 
-        fixture
-    }
-
-    /// Wait for server to be ready to accept connections
-    ///
-    /// This sends an actual HTTP request to verify the server is responsive,
-    /// not just listening.
-    fn wait_for_ready(&self, max_attempts: u32) -> bool {
-        use std::io::{Read, Write};
-        use std::net::TcpStream as StdTcpStream;
-
-        for attempt in 0..max_attempts {
-            if let Ok(mut stream) = StdTcpStream::connect(format!("127.0.0.1:{}", self.port)) {
-                // Send a minimal HTTP request
-                let request = format!("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
-                if stream.write_all(request.as_bytes()).is_ok() {
-                    // Try to read some response
-                    let mut buf = [0u8; 256];
-                    if stream.read(&mut buf).is_ok() {
-                        eprintln!(
-                            "[READY] Server on port {} is ready (attempt {})",
-                            self.port,
-                            attempt + 1
-                        );
-                        return true;
-                    }
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
+        Self {
+            port,
+            handle: Some(handle),
         }
-        eprintln!(
-            "[ERROR] Server on port {} failed to become ready after {} attempts",
-            self.port, max_attempts
-        );
-        false
     }
 
     /// Get the base URL for the test server
@@ -261,13 +168,11 @@ impl GooseTestFixture {
 
 impl Drop for GooseTestFixture {
     fn drop(&mut self) {
-        // Cancel the server coroutine and wait for it to finish
+        // Stop the server and wait for it to finish
         // This matches BRRTRouter's ServerHandle::stop() implementation
         if let Some(handle) = self.handle.take() {
-            unsafe {
-                handle.coroutine().cancel();
-            }
-            let _ = handle.join();
+            handle.stop();
+            let _ = handle.wait();
         }
         eprintln!(
             "[CLEANUP] GooseTestFixture for port {} cleaned up",
@@ -553,7 +458,7 @@ async fn request_with_api_gateway_headers(user: &mut GooseUser) -> TransactionRe
 #[tokio::test]
 async fn test_goose_smoke_test() -> Result<(), Box<dyn std::error::Error>> {
     // Minimal smoke test: 1 user, 1 second, 1 request
-    let fixture = GooseTestFixture::new(19000);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     eprintln!("[TEST] Starting Goose smoke test on {}", base_url);
@@ -585,7 +490,7 @@ async fn test_goose_smoke_test() -> Result<(), Box<dyn std::error::Error>> {
 #[tokio::test]
 async fn test_load_with_varying_headers() -> Result<(), Box<dyn std::error::Error>> {
     // Reduced load: 5 users, 3 seconds (was 10/10)
-    let fixture = GooseTestFixture::new(19001);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     // Configure Goose attack
@@ -616,7 +521,7 @@ async fn test_load_with_varying_headers() -> Result<(), Box<dyn std::error::Erro
 #[tokio::test]
 async fn test_browser_traffic_load() -> Result<(), Box<dyn std::error::Error>> {
     // Reduced load: 5 users, 2 seconds (was 20/5)
-    let fixture = GooseTestFixture::new(19002);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     let goose_attack = GooseAttack::initialize()?
@@ -639,7 +544,7 @@ async fn test_browser_traffic_load() -> Result<(), Box<dyn std::error::Error>> {
 #[tokio::test]
 async fn test_load_balancer_traffic() -> Result<(), Box<dyn std::error::Error>> {
     // Reduced load: 5 users, 2 seconds (was 15/5)
-    let fixture = GooseTestFixture::new(19003);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     let goose_attack = GooseAttack::initialize()?
@@ -663,7 +568,7 @@ async fn test_load_balancer_traffic() -> Result<(), Box<dyn std::error::Error>>
 #[tokio::test]
 async fn test_high_header_count_stress() -> Result<(), Box<dyn std::error::Error>> {
     // Reduced load: 3 users, 3 seconds (was 5/5)
-    let fixture = GooseTestFixture::new(19004);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     // Test with progressively more headers to validate limit enforcement
@@ -692,7 +597,7 @@ async fn test_high_header_count_stress() -> Result<(), Box<dyn std::error::Error
 #[tokio::test]
 async fn test_load_with_large_header_values() -> Result<(), Box<dyn std::error::Error>> {
     // Reduced load: 5 users, 3 seconds (was 10/10)
-    let fixture = GooseTestFixture::new(19005);
+    let fixture = GooseTestFixture::new();
     let base_url = fixture.base_url();
 
     // Test with various large header scenarios
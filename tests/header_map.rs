@@ -0,0 +1,56 @@
+//! Tests for the owned HeaderMap
+#![cfg(feature = "header-map")]
+
+use may_minihttp::HeaderMap;
+
+#[test]
+fn test_case_insensitive_lookup() {
+    let mut headers = HeaderMap::new();
+    headers.append("Content-Type", "application/json");
+
+    assert_eq!(headers.get("content-type"), Some(&b"application/json"[..]));
+    assert_eq!(headers.get_str("CONTENT-TYPE"), Some("application/json"));
+    assert!(headers.contains_key("Content-type"));
+    assert!(!headers.contains_key("Content-Length"));
+}
+
+#[test]
+fn test_repeated_headers_keep_order() {
+    let mut headers = HeaderMap::new();
+    headers.append("Via", "1.1 a");
+    headers.append("Host", "example.com");
+    headers.append("via", "1.1 b");
+
+    let vias: Vec<_> = headers.get_all("VIA").collect();
+    assert_eq!(vias, [&b"1.1 a"[..], &b"1.1 b"[..]]);
+    assert_eq!(headers.len(), 3);
+
+    let names: Vec<_> = headers.iter().map(|(n, _)| n).collect();
+    assert_eq!(names, ["Via", "Host", "via"]);
+}
+
+#[test]
+fn test_insert_and_remove() {
+    let mut headers = HeaderMap::new();
+    headers.append("X-Id", "1");
+    headers.append("x-id", "2");
+    headers.insert("X-ID", "3");
+    assert_eq!(headers.get_all("x-id").count(), 1);
+    assert_eq!(headers.get_str("x-id"), Some("3"));
+
+    assert_eq!(headers.remove("X-Id"), Some(b"3".to_vec()));
+    assert_eq!(headers.remove("X-Id"), None);
+    assert!(headers.is_empty());
+}
+
+#[test]
+fn test_header_map_is_send_and_static() {
+    fn assert_send_static<T: Send + 'static>(_: &T) {}
+
+    let mut headers = HeaderMap::with_capacity(1);
+    headers.append("Host", "example.com");
+    assert_send_static(&headers);
+
+    let handle = std::thread::spawn(move || headers.get_str("host").map(str::to_owned));
+    assert_eq!(handle.join().unwrap().as_deref(), Some("example.com"));
+}
@@ -10,28 +10,19 @@
 //!
 //! ## Port Management
 //!
-//! Tests use dynamic port allocation to prevent conflicts:
-//! - Check if port is available before binding
-//! - Automatically find next available port
-//! - Safe for parallel test execution
+//! Each server binds port 0 and reads the port the OS picked from its
+//! `ServerHandle`, so tests can run in parallel without conflicts.
+
+mod common;
 
 use bytes::BufMut;
-use may_minihttp::{HttpServer, HttpService, Request, Response};
+use common::init_may_runtime;
+use may_minihttp::{HttpServer, HttpService, Request, Response, ServerHandle};
 use std::io::{self, Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::Once;
+use std::net::TcpStream;
 use std::thread;
 use std::time::Duration;
 
-static INIT: Once = Once::new();
-
-/// Initialize MAY runtime once for all tests
-fn init_may_runtime() {
-    INIT.call_once(|| {
-        may::config().set_stack_size(0x8000);
-    });
-}
-
 #[derive(Clone)]
 struct TestService;
 
@@ -54,49 +45,6 @@ impl HttpService for TestService {
     }
 }
 
-/// Check if a port is available for binding
-///
-/// Returns `true` if the port is free, `false` if it's already in use.
-fn is_port_available(port: u16) -> bool {
-    TcpListener::bind(format!("127.0.0.1:{}", port)).is_ok()
-}
-
-/// Find the next available port starting from the given port
-///
-/// Tries up to 100 consecutive ports before giving up.
-/// Returns the first available port found, or panics if none are available.
-fn find_available_port(start_port: u16) -> u16 {
-    for port in start_port..(start_port + 100) {
-        if is_port_available(port) {
-            eprintln!("[PORT] Found available port: {}", port);
-            return port;
-        }
-    }
-    panic!(
-        "Could not find available port in range {}-{}",
-        start_port,
-        start_port + 100
-    );
-}
-
-/// Ensure a port is available, finding an alternative if necessary
-///
-/// First tries the preferred port. If unavailable, finds the next free port.
-/// This prevents test failures from port conflicts while maintaining determinism
-/// when possible.
-fn ensure_port_available(preferred_port: u16) -> u16 {
-    if is_port_available(preferred_port) {
-        eprintln!("[PORT] Using preferred port: {}", preferred_port);
-        preferred_port
-    } else {
-        eprintln!(
-            "[PORT] Port {} in use, finding alternative...",
-            preferred_port
-        );
-        find_available_port(preferred_port + 1)
-    }
-}
-
 /// RAII test fixture for HTTP server
 ///
 /// Ensures the server is properly shut down when the fixture is dropped,
@@ -104,91 +52,44 @@ fn ensure_port_available(preferred_port: u16) -> u16 {
 ///
 /// ## Port Management
 ///
-/// The fixture uses `ensure_port_available()` to check if the preferred port
-/// is free. If not, it automatically finds the next available port. This prevents
-/// test failures from port conflicts during parallel execution or when other
-/// services are running.
+/// The server listens on a free port picked by the OS, see `port()`.
 struct HeaderTestServer {
     port: u16,
-    handle: Option<may::coroutine::JoinHandle<()>>,
+    handle: Option<ServerHandle>,
 }
 
 impl HeaderTestServer {
-    /// Create and start a new test server with port availability checking
-    ///
-    /// # Arguments
-    ///
-    /// * `preferred_port` - The preferred port to use (will find alternative if unavailable)
+    /// Create and start a new test server on a free port
     ///
     /// # Example
     ///
     /// ```ignore
-    /// let server = HeaderTestServer::new(18001);
-    /// // Server automatically uses port 18001, or next available port if busy
+    /// let server = HeaderTestServer::new();
+    /// let stream = TcpStream::connect(("127.0.0.1", server.port()));
     /// ```
-    fn new(preferred_port: u16) -> Self {
+    fn new() -> Self {
         // CRITICAL: Initialize MAY runtime configuration FIRST (once for all tests)
         init_may_runtime();
 
-        // Check port availability and find alternative if needed
-        let port = ensure_port_available(preferred_port);
-
         // Start the HTTP server in the MAIN THREAD (not a background thread)
         // This matches BRRTRouter's pattern exactly:
         // - HttpServer.start() spawns a coroutine and returns immediately
-        // - The JoinHandle keeps the server running
+        // - The ServerHandle keeps the server running
         // - No thread::spawn needed - MAY handles concurrency with coroutines
         let handle = HttpServer(TestService)
-            .start(&format!("127.0.0.1:{}", port))
+            .start("127.0.0.1:0")
+            .expect("Failed to start test server");
+        let port = handle.local_addr().port();
+        handle
+            .wait_until_ready()
             .expect("Failed to start test server");
-
-        let fixture = Self {
-            port,
-            handle: Some(handle),
-        };
-
-        // Wait for server to be ready
-        assert!(
-            fixture.wait_for_ready(50),
-            "Server failed to start on port {}",
-            port
-        );
-        thread::sleep(Duration::from_millis(200));
 
         eprintln!("[SERVER] HeaderTestServer started on port {}", port);
 
-        fixture
-    }
-
-    /// Wait for server to be ready to accept connections
-    ///
-    /// This sends an actual HTTP request to verify the server is responsive,
-    /// not just listening.
-    fn wait_for_ready(&self, max_attempts: u32) -> bool {
-        for attempt in 0..max_attempts {
-            if let Ok(mut stream) = TcpStream::connect(format!("127.0.0.1:{}", self.port)) {
-                // Send a minimal HTTP request
-                let request = format!("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
-                if stream.write_all(request.as_bytes()).is_ok() {
-                    // Try to read some response
-                    let mut buf = [0u8; 256];
-                    if stream.read(&mut buf).is_ok() {
-                        eprintln!(
-                            "[READY] Server on port {} is ready (attempt {})",
-                            self.port,
-                            attempt + 1
-                        );
-                        return true;
-                    }
-                }
-            }
-            thread::sleep(Duration::from_millis(100));
+        Self {
+            port,
+            handle: Some(handle),
         }
-        eprintln!(
-            "[ERROR] Server on port {} failed to become ready after {} attempts",
-            self.port, max_attempts
-        );
-        false
     }
 
     /// Get the port number for this server
@@ -199,13 +100,11 @@ impl HeaderTestServer {
 
 impl Drop for HeaderTestServer {
     fn drop(&mut self) {
-        // Cancel the server coroutine and wait for it to finish
+        // Stop the server and wait for it to finish
         // This matches BRRTRouter's ServerHandle::stop() implementation
         if let Some(handle) = self.handle.take() {
-            unsafe {
-                handle.coroutine().cancel();
-            }
-            let _ = handle.join();
+            handle.stop();
+            let _ = handle.wait();
         }
         eprintln!("[CLEANUP] HeaderTestServer on port {} shut down", self.port);
     }
@@ -249,7 +148,7 @@ fn send_request_with_headers(port: u16, num_headers: usize) -> io::Result<String
 
 #[test]
 fn test_request_with_5_headers() {
-    let server = HeaderTestServer::new(18001);
+    let server = HeaderTestServer::new();
 
     let response = send_request_with_headers(server.port(), 5).expect("Failed to send request");
 
@@ -259,7 +158,7 @@ fn test_request_with_5_headers() {
 
 #[test]
 fn test_request_with_10_headers() {
-    let server = HeaderTestServer::new(18002);
+    let server = HeaderTestServer::new();
 
     let response = send_request_with_headers(server.port(), 10).expect("Failed to send request");
 
@@ -271,7 +170,7 @@ fn test_request_with_10_headers() {
 
 #[test]
 fn test_request_with_16_headers_at_default_limit() {
-    let server = HeaderTestServer::new(18003);
+    let server = HeaderTestServer::new();
 
     let response = send_request_with_headers(server.port(), 16).expect("Failed to send request");
 
@@ -284,7 +183,7 @@ fn test_request_with_16_headers_at_default_limit() {
 #[test]
 fn test_default_limit_accepts_16_headers() {
     // Test that Default (16) accepts exactly 16 headers
-    let server = HeaderTestServer::new(18004);
+    let server = HeaderTestServer::new();
 
     let response = send_request_with_headers(server.port(), 16)
         .expect("Failed to send request with 16 headers");
@@ -298,7 +197,7 @@ fn test_default_limit_accepts_16_headers() {
 #[test]
 fn test_default_limit_rejects_17_headers() {
     // Test that Default (16) rejects 17 headers
-    let server = HeaderTestServer::new(18005);
+    let server = HeaderTestServer::new();
 
     let result = send_request_with_headers(server.port(), 17);
 
@@ -336,7 +235,7 @@ fn test_default_limit_rejects_17_headers() {
 
 #[test]
 fn test_buffering_check_with_fragmented_headers() {
-    let server = HeaderTestServer::new(18008);
+    let server = HeaderTestServer::new();
 
     let mut stream =
         TcpStream::connect(format!("127.0.0.1:{}", server.port())).expect("Failed to connect");
@@ -381,7 +280,7 @@ fn test_buffering_check_with_fragmented_headers() {
 
 #[test]
 fn test_browser_like_request() {
-    let server = HeaderTestServer::new(18009);
+    let server = HeaderTestServer::new();
 
     let mut stream =
         TcpStream::connect(format!("127.0.0.1:{}", server.port())).expect("Failed to connect");
@@ -430,7 +329,7 @@ Sec-Fetch-User: ?1\r\n\
 
 #[test]
 fn test_load_balancer_headers() {
-    let server = HeaderTestServer::new(18010);
+    let server = HeaderTestServer::new();
 
     let mut stream =
         TcpStream::connect(format!("127.0.0.1:{}", server.port())).expect("Failed to connect");
@@ -477,9 +376,9 @@ Accept: application/json\r\n\
 #[test]
 fn test_large_user_agent_header() {
     // Realistic: very long User-Agent from modern browsers with extensions
-    let _server = HeaderTestServer::new(18700);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18700").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     // 500-character User-Agent (realistic for browsers with many extensions)
     let long_user_agent = format!(
@@ -519,9 +418,9 @@ fn test_large_user_agent_header() {
 #[test]
 fn test_large_cookie_header() {
     // Realistic: large Cookie header with many session values
-    let _server = HeaderTestServer::new(18701);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18701").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     // Build a large cookie header (1KB+)
     let mut cookies = Vec::new();
@@ -560,9 +459,9 @@ fn test_large_cookie_header() {
 #[test]
 fn test_large_referer_header() {
     // Realistic: very long Referer URL with query parameters
-    let _server = HeaderTestServer::new(18702);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18702").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     // Build a long URL with many query parameters (800+ chars)
     let mut params = Vec::new();
@@ -601,9 +500,9 @@ fn test_large_referer_header() {
 #[test]
 fn test_large_authorization_header() {
     // Realistic: large JWT token or OAuth bearer token
-    let _server = HeaderTestServer::new(18703);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18703").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     // Simulate a large JWT token (1.5KB+)
     let large_jwt = format!(
@@ -641,9 +540,9 @@ fn test_large_authorization_header() {
 #[test]
 fn test_multiple_large_headers_combined() {
     // Stress test: multiple large headers in one request
-    let _server = HeaderTestServer::new(18704);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18704").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     let large_user_agent = format!(
         "Mozilla/5.0 (Windows NT 10.0; Win64; x64) {}",
@@ -696,9 +595,9 @@ fn test_multiple_large_headers_combined() {
 #[test]
 fn test_extremely_large_single_header() {
     // Edge case: single header value that's extremely large (4KB+)
-    let _server = HeaderTestServer::new(18705);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18705").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     // 4KB header value
     let extremely_large_value = "X".repeat(4096);
@@ -734,9 +633,9 @@ fn test_extremely_large_single_header() {
 #[test]
 fn test_realistic_api_gateway_headers() {
     // Realistic: headers from API Gateway with tracing, correlation, forwarding
-    let _server = HeaderTestServer::new(18706);
+    let server = HeaderTestServer::new();
 
-    let mut stream = TcpStream::connect("127.0.0.1:18706").unwrap();
+    let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
 
     let trace_id = format!("trace-{}", "0123456789abcdef".repeat(8)); // 128-char trace ID
     let correlation_id = format!("correlation-{}", "fedcba9876543210".repeat(8));
@@ -0,0 +1,84 @@
+//! Tests for rejecting and sanitizing response headers with CR/LF
+
+use std::io;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HeaderValidation, HttpConfig, HttpService, Request, Response};
+
+/// copies the `name` query value into a header, like a careless handler
+#[derive(Clone)]
+struct Careless;
+
+impl HttpService for Careless {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let name = req
+            .path()
+            .trim_start_matches("/?name=")
+            .replace("%0D%0A", "\r\n");
+        rsp.header("X-Static: a\r\nInjected: yes")
+            .header("Content-Type: text/plain")
+            .header_owned(format!("Content-Disposition: attachment; filename={name}"));
+        rsp.body("ok");
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Clean;
+
+impl HttpService for Clean {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.header("Content-Type: text/plain")
+            .header_owned(format!("X-Tab: a\t{}", 1));
+        rsp.body("ok");
+        Ok(())
+    }
+}
+
+fn client(mode: HeaderValidation) -> TestClient<Careless> {
+    TestClient::new(Careless).config(HttpConfig::new().with_header_validation(mode))
+}
+
+#[test]
+fn test_injected_header_is_rejected_by_default() {
+    let rsp = TestClient::new(Careless)
+        .get("/?name=x%0D%0ASet-Cookie:%20a=b")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 500);
+    assert!(rsp.header("injected").is_none());
+    assert!(rsp.header("set-cookie").is_none());
+
+    // tabs are fine
+    let rsp = TestClient::new(Clean).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.header("x-tab"), Some("a\t1"));
+}
+
+#[test]
+fn test_sanitize_keeps_header_order() {
+    let client = client(HeaderValidation::Sanitize);
+    let rsp = client
+        .get("/?name=x%0D%0ASet-Cookie:%20a=b")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert!(rsp.header("set-cookie").is_none());
+    let names: Vec<&str> = rsp.headers().iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(
+        &names[names.len() - 3..],
+        ["X-Static", "Content-Type", "Content-Disposition"]
+    );
+    assert_eq!(rsp.header("x-static"), Some("a  Injected: yes"));
+    assert_eq!(
+        rsp.header("content-disposition"),
+        Some("attachment; filename=x  Set-Cookie:%20a=b")
+    );
+}
+
+#[test]
+fn test_off_sends_headers_as_is() {
+    let client = client(HeaderValidation::Off);
+    let rsp = client.get("/?name=report.txt").send().unwrap();
+    assert_eq!(rsp.header("injected"), Some("yes"));
+}
@@ -0,0 +1,141 @@
+//! Tests for taking over a connection from the http server
+
+mod common;
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, Request, Response};
+
+/// Switches upgrade requests to a line protocol that shouts back
+#[derive(Clone)]
+struct Shout;
+
+impl HttpService for Shout {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if !req.is_upgrade() {
+            rsp.body("plain http");
+            return Ok(());
+        }
+        rsp.status_code(101, "Switching Protocols")
+            .header("Connection: Upgrade")
+            .header("Upgrade: shout");
+        rsp.hijack(|mut conn| {
+            let mut buf = [0; 64];
+            while let Ok(n @ 1..) = conn.read(&mut buf) {
+                if conn.write_all(&buf[..n].to_ascii_uppercase()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// read until `end` was received, returns everything read
+fn read_until(client: &mut TcpStream, end: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut buf = [0; 256];
+    while !out.windows(end.len()).any(|w| w == end) {
+        let n = client.read(&mut buf).unwrap();
+        assert!(n > 0, "closed after {:?}", String::from_utf8_lossy(&out));
+        out.extend_from_slice(&buf[..n]);
+    }
+    out
+}
+
+#[test]
+fn test_hijack_hands_over_the_connection() {
+    let server = common::start_server(Shout);
+    let mut client = common::connect(server.local_addr());
+
+    // the bytes sent along with the request are handed over too
+    client
+        .write_all(b"GET / HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: shout\r\n\r\nearly;")
+        .unwrap();
+    let out = read_until(&mut client, b"EARLY;");
+    let out = String::from_utf8_lossy(&out);
+    assert!(
+        out.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+        "{out}"
+    );
+    assert!(out.contains("Upgrade: shout\r\n"));
+
+    // no longer http
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let out = read_until(&mut client, b"\r\n\r\n");
+    assert_eq!(out, b"GET / HTTP/1.1\r\n\r\n".to_ascii_uppercase());
+
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_serve_stream_closes_instead() {
+    let client = TestClient::new(Shout);
+    let raw = b"GET / HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: shout\r\n\r\n\
+                GET / HTTP/1.1\r\n\r\n";
+    // the response is sent, then the connection ends without serving more
+    let rsps = client.send_raw_all(raw).unwrap();
+    assert_eq!(rsps.len(), 1);
+    assert_eq!(rsps[0].status(), 101);
+}
+
+static TUNNELED: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+
+/// Tunnels `CONNECT` requests to the address in the path
+#[derive(Clone)]
+struct Tunnel;
+
+impl HttpService for Tunnel {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let mut upstream = may::net::TcpStream::connect(req.path())?;
+        rsp.hijack(move |conn| {
+            let (client, buffered) = conn.into_parts();
+            upstream.write_all(&buffered).unwrap();
+            let copied = may_minihttp::io::copy_bidirectional(&client, &upstream).unwrap();
+            // the bytes sent with the request went past the copy
+            *TUNNELED.lock().unwrap() = Some((copied.0 + buffered.len() as u64, copied.1));
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tunnel_passes_half_close_on() {
+    // answers once the client is done sending
+    let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let upstream = thread::spawn(move || {
+        let (mut conn, _) = upstream.accept().unwrap();
+        let mut data = Vec::new();
+        conn.read_to_end(&mut data).unwrap();
+        conn.write_all(&data.to_ascii_uppercase()).unwrap();
+    });
+
+    let server = common::start_server(Tunnel);
+    let mut client = common::connect(server.local_addr());
+    let req = format!("CONNECT {upstream_addr} HTTP/1.1\r\n\r\nearly,");
+    client.write_all(req.as_bytes()).unwrap();
+    read_until(&mut client, b"\r\n\r\n");
+    client.write_all(b"late").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut rest = Vec::new();
+    client.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"EARLY,LATE");
+    upstream.join().unwrap();
+    for _ in 0..100 {
+        if TUNNELED.lock().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*TUNNELED.lock().unwrap(), Some((10, 10)));
+}
@@ -0,0 +1,299 @@
+//! Tests for HttpConfig construction
+
+use std::io;
+
+use may_minihttp::{
+    BufGrowth, HeaderValidation, HttpConfig, HttpServer, HttpServerBuilder, HttpService,
+    MaxHeaders, Request, Response,
+};
+
+#[derive(Clone)]
+struct Ok200;
+
+impl HttpService for Ok200 {
+    fn call(&mut self, _req: Request, _rsp: &mut Response) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_default_config() {
+    let config = HttpConfig::new();
+    assert_eq!(config.max_headers, MaxHeaders::Default);
+    assert!(config.batch_writes);
+    assert_eq!(config.write_high_watermark, 1024 * 1024);
+    assert_eq!(config.max_conn_memory, 0);
+    assert_eq!(config.buf_initial_capacity, 32 * 1024);
+    assert_eq!(config.buf_grow_by, 32 * 1024);
+    assert_eq!(config.buf_growth, BufGrowth::Fixed);
+    assert_eq!(config.buf_max_capacity, 0);
+    assert_eq!(config.max_body, 0);
+    assert_eq!(config.max_in_flight, 1024);
+    assert_eq!(config.header_validation, HeaderValidation::Reject);
+    assert_eq!(config.max_accept_backoff_ms, 1000);
+    assert_eq!(config.backlog, 0);
+    assert_eq!(config.defer_accept_secs, 0);
+    assert_eq!(config.fastopen_queue, 0);
+    assert_eq!(config.ipv6_only, None);
+    assert_eq!(config.linger_secs, None);
+    assert!(!config.abortive_close);
+    assert_eq!(config.tcp_keepalive_idle_secs, 0);
+    assert_eq!(config.tcp_keepalive_interval_secs, 0);
+    assert_eq!(config.tcp_keepalive_retries, 0);
+    assert_eq!(config.keep_alive_timeout_secs, 0);
+    assert_eq!(config.max_requests_per_conn, 0);
+    assert!(!config.evict_idle);
+    assert_eq!(config.parse_error_log, log::LevelFilter::Warn);
+    assert!(!config.parse_error_hints);
+    assert!(config.on_parse_error.is_none());
+}
+
+#[test]
+fn test_performance_config() {
+    let config = HttpConfig::performance();
+    assert_eq!(config.max_headers, MaxHeaders::Default);
+    assert!(config.batch_writes);
+    assert_eq!(config.header_validation, HeaderValidation::Off);
+    assert_eq!(config.parse_error_log, log::LevelFilter::Off);
+    assert!(config.on_request_complete.is_none());
+    assert!(config.stats.is_none());
+}
+
+// environment variables are process wide, keep all env cases in one test
+#[test]
+fn test_from_env() {
+    std::env::remove_var("MINIHTTP_MAX_HEADERS");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_headers, MaxHeaders::Default);
+
+    std::env::set_var("MINIHTTP_MAX_HEADERS", "xlarge");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_headers, MaxHeaders::XLarge);
+
+    std::env::set_var("MINIHTTP_MAX_HEADERS", "48");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_headers, MaxHeaders::Custom(48));
+
+    std::env::set_var("MINIHTTP_MAX_HEADERS", "many");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_MAX_HEADERS");
+
+    std::env::set_var("MINIHTTP_BATCH_WRITES", "off");
+    let config = HttpConfig::from_env().unwrap();
+    assert!(!config.batch_writes);
+
+    std::env::set_var("MINIHTTP_BATCH_WRITES", "maybe");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_BATCH_WRITES");
+
+    std::env::set_var("MINIHTTP_WRITE_HIGH_WATERMARK", "65536");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.write_high_watermark, 65536);
+
+    std::env::set_var("MINIHTTP_WRITE_HIGH_WATERMARK", "-1");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_WRITE_HIGH_WATERMARK");
+
+    std::env::set_var("MINIHTTP_MAX_CONN_MEMORY", "4194304");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_conn_memory, 4 * 1024 * 1024);
+
+    std::env::remove_var("MINIHTTP_MAX_CONN_MEMORY");
+
+    std::env::set_var("MINIHTTP_BUF_INITIAL_CAPACITY", "4096");
+    std::env::set_var("MINIHTTP_BUF_GROW_BY", "8192");
+    std::env::set_var("MINIHTTP_BUF_GROWTH", "Doubling");
+    std::env::set_var("MINIHTTP_BUF_MAX_CAPACITY", "1048576");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.buf_initial_capacity, 4096);
+    assert_eq!(config.buf_grow_by, 8192);
+    assert_eq!(config.buf_growth, BufGrowth::Doubling);
+    assert_eq!(config.buf_max_capacity, 1024 * 1024);
+
+    std::env::set_var("MINIHTTP_BUF_GROWTH", "exponential");
+    let e = HttpConfig::from_env().unwrap_err();
+    assert!(e.to_string().contains("MINIHTTP_BUF_GROWTH"));
+
+    std::env::remove_var("MINIHTTP_BUF_INITIAL_CAPACITY");
+    std::env::remove_var("MINIHTTP_BUF_GROW_BY");
+    std::env::remove_var("MINIHTTP_BUF_GROWTH");
+    std::env::remove_var("MINIHTTP_BUF_MAX_CAPACITY");
+
+    std::env::set_var("MINIHTTP_MAX_BODY", "10485760");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_body, 10 * 1024 * 1024);
+
+    std::env::set_var("MINIHTTP_MAX_BODY", "10M");
+    let e = HttpConfig::from_env().unwrap_err();
+    assert!(e.to_string().contains("MINIHTTP_MAX_BODY"));
+
+    std::env::remove_var("MINIHTTP_MAX_BODY");
+
+    std::env::set_var("MINIHTTP_BACKLOG", "4096");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.backlog, 4096);
+
+    std::env::remove_var("MINIHTTP_BACKLOG");
+
+    std::env::set_var("MINIHTTP_DEFER_ACCEPT_SECS", "5");
+    std::env::set_var("MINIHTTP_FASTOPEN_QUEUE", "256");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.defer_accept_secs, 5);
+    assert_eq!(config.fastopen_queue, 256);
+
+    std::env::set_var("MINIHTTP_FASTOPEN_QUEUE", "-1");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_DEFER_ACCEPT_SECS");
+    std::env::remove_var("MINIHTTP_FASTOPEN_QUEUE");
+
+    std::env::set_var("MINIHTTP_IPV6_ONLY", "false");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.ipv6_only, Some(false));
+
+    std::env::remove_var("MINIHTTP_IPV6_ONLY");
+
+    std::env::set_var("MINIHTTP_LINGER_SECS", "0");
+    std::env::set_var("MINIHTTP_ABORTIVE_CLOSE", "on");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.linger_secs, Some(0));
+    assert!(config.abortive_close);
+
+    std::env::remove_var("MINIHTTP_LINGER_SECS");
+    std::env::remove_var("MINIHTTP_ABORTIVE_CLOSE");
+
+    std::env::set_var("MINIHTTP_TCP_KEEPALIVE_IDLE_SECS", "60");
+    std::env::set_var("MINIHTTP_TCP_KEEPALIVE_INTERVAL_SECS", "10");
+    std::env::set_var("MINIHTTP_TCP_KEEPALIVE_RETRIES", "5");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.tcp_keepalive_idle_secs, 60);
+    assert_eq!(config.tcp_keepalive_interval_secs, 10);
+    assert_eq!(config.tcp_keepalive_retries, 5);
+
+    std::env::remove_var("MINIHTTP_TCP_KEEPALIVE_IDLE_SECS");
+    std::env::remove_var("MINIHTTP_TCP_KEEPALIVE_INTERVAL_SECS");
+    std::env::remove_var("MINIHTTP_TCP_KEEPALIVE_RETRIES");
+
+    std::env::set_var("MINIHTTP_KEEP_ALIVE_TIMEOUT_SECS", "5");
+    std::env::set_var("MINIHTTP_MAX_REQUESTS_PER_CONN", "1000");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.keep_alive_timeout_secs, 5);
+    assert_eq!(config.max_requests_per_conn, 1000);
+
+    std::env::remove_var("MINIHTTP_KEEP_ALIVE_TIMEOUT_SECS");
+    std::env::remove_var("MINIHTTP_MAX_REQUESTS_PER_CONN");
+
+    std::env::set_var("MINIHTTP_EVICT_IDLE", "yes");
+    let config = HttpConfig::from_env().unwrap();
+    assert!(config.evict_idle);
+
+    std::env::remove_var("MINIHTTP_EVICT_IDLE");
+
+    std::env::set_var("MINIHTTP_MAX_IN_FLIGHT", "16");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_in_flight, 16);
+
+    std::env::remove_var("MINIHTTP_MAX_IN_FLIGHT");
+
+    std::env::set_var("MINIHTTP_HEADER_VALIDATION", "Sanitize");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.header_validation, HeaderValidation::Sanitize);
+
+    std::env::set_var("MINIHTTP_HEADER_VALIDATION", "strip");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_HEADER_VALIDATION");
+
+    std::env::set_var("MINIHTTP_MAX_ACCEPT_BACKOFF_MS", "0");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_accept_backoff_ms, 0);
+
+    std::env::set_var("MINIHTTP_MAX_ACCEPT_BACKOFF_MS", "18446744073709551615");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.max_accept_backoff_ms, u64::MAX);
+
+    std::env::set_var("MINIHTTP_MAX_ACCEPT_BACKOFF_MS", "1s");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_MAX_ACCEPT_BACKOFF_MS");
+
+    std::env::set_var("MINIHTTP_PARSE_ERROR_LOG", "Debug");
+    let config = HttpConfig::from_env().unwrap();
+    assert_eq!(config.parse_error_log, log::LevelFilter::Debug);
+
+    std::env::set_var("MINIHTTP_PARSE_ERROR_LOG", "loud");
+    assert!(HttpConfig::from_env().is_err());
+
+    std::env::remove_var("MINIHTTP_PARSE_ERROR_LOG");
+
+    std::env::set_var("MINIHTTP_PARSE_ERROR_HINTS", "on");
+    let config = HttpConfig::from_env().unwrap();
+    assert!(config.parse_error_hints);
+
+    std::env::remove_var("MINIHTTP_PARSE_ERROR_HINTS");
+
+    std::env::remove_var("MINIHTTP_LISTEN");
+    let builder = HttpServerBuilder::from_env(HttpServer(Ok200)).unwrap();
+    assert!(builder.start().is_err());
+
+    std::env::set_var("MINIHTTP_LISTEN", "127.0.0.1:0, 127.0.0.1:0,");
+    let builder = HttpServerBuilder::from_env(HttpServer(Ok200)).unwrap();
+    let servers = builder.start().unwrap();
+    assert_eq!(servers.len(), 2);
+    for server in servers {
+        server.wait_until_ready().unwrap();
+        server.stop();
+    }
+
+    std::env::set_var("MINIHTTP_MAX_BODY", "-1");
+    assert!(HttpServerBuilder::from_env(HttpServer(Ok200)).is_err());
+
+    std::env::remove_var("MINIHTTP_LISTEN");
+    std::env::remove_var("MINIHTTP_MAX_BODY");
+}
+
+#[cfg(feature = "config-file")]
+#[test]
+fn test_server_config_from_toml() {
+    use may_minihttp::ServerConfig;
+
+    let config = ServerConfig::from_toml(
+        r#"
+        listen = ["127.0.0.1:8080", "127.0.0.1:8081"]
+        max_headers = "large"
+        header_validation = "sanitize"
+        max_accept_backoff_ms = 250
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.listen, ["127.0.0.1:8080", "127.0.0.1:8081"]);
+    assert_eq!(config.http.max_headers, MaxHeaders::Large);
+    assert_eq!(config.http.header_validation, HeaderValidation::Sanitize);
+    assert_eq!(config.http.max_accept_backoff_ms, 250);
+
+    let config = ServerConfig::from_toml("max_headers = 96").unwrap();
+    assert!(config.listen.is_empty());
+    assert_eq!(config.http.max_headers, MaxHeaders::Custom(96));
+
+    assert!(ServerConfig::from_toml("max_headers = \"huge\"").is_err());
+    assert!(ServerConfig::from_toml("listen = \"127.0.0.1:8080\"").is_err());
+}
+
+#[cfg(feature = "config-file")]
+#[test]
+fn test_server_config_rejects_unknown_keys() {
+    use may_minihttp::ServerConfig;
+
+    let e = ServerConfig::from_toml("max_header = 64").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert!(e.to_string().contains("max_header"), "{e}");
+
+    let e = ServerConfig::from_toml("listen = [\"127.0.0.1:8080\"]\nbacklogg = 4096").unwrap_err();
+    assert!(e.to_string().contains("backlogg"), "{e}");
+
+    // skipped fields can't be set from a file either
+    assert!(ServerConfig::from_toml("stats = true").is_err());
+}
@@ -0,0 +1,83 @@
+//! Tests for the public HTTP date API
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::BytesMut;
+use may_minihttp::date::HttpDate;
+use may_minihttp::{encode, Response};
+
+#[test]
+fn test_format_and_parse_round_trip() {
+    let t = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let date = HttpDate::from(t);
+    let s = date.to_string();
+    assert_eq!(s, "Tue, 14 Nov 2023 22:13:20 GMT");
+    assert_eq!(s.parse::<HttpDate>().unwrap(), date);
+    assert_eq!(SystemTime::from(date), t);
+}
+
+#[test]
+fn test_subsecond_precision_is_dropped() {
+    let t = UNIX_EPOCH + Duration::from_millis(1_700_000_000_750);
+    let date = HttpDate::from(t);
+    assert_eq!(
+        SystemTime::from(date),
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    );
+    assert!(HttpDate::from(t + Duration::from_secs(1)) > date);
+}
+
+#[test]
+fn test_invalid_dates_are_rejected() {
+    for s in [
+        "",
+        "yesterday",
+        "Sun, 32 Nov 1994 08:49:37 GMT",
+        "1994-11-06",
+    ] {
+        let e = s.parse::<HttpDate>().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
+
+#[cfg(feature = "date-header")]
+#[test]
+fn test_now_http_date() {
+    let now = may_minihttp::date::now_http_date();
+    assert_eq!(now.len(), 29);
+    let parsed: SystemTime = now.parse::<HttpDate>().unwrap().into();
+    let diff = SystemTime::now()
+        .duration_since(parsed)
+        .unwrap_or(Duration::ZERO);
+    assert!(diff < Duration::from_secs(5));
+}
+
+#[test]
+fn test_last_modified_header_matches_display() {
+    let mut secs = vec![
+        0,
+        // 1994-11-06 08:49:37
+        784_111_777,
+        // around 2000-02-29 and 2100-02-28
+        951_782_399,
+        951_782_400,
+        951_868_800,
+        4_107_542_399,
+        4_107_542_400,
+        // 9999-12-31 23:59:59
+        253_402_300_799,
+    ];
+    // a day and a bit at a time over a few centuries
+    secs.extend((0..40_000u64).map(|i| i * 90_061 * 3));
+    for secs in secs {
+        let t = UNIX_EPOCH + Duration::from_secs(secs);
+        let mut body = BytesMut::new();
+        let mut rsp = Response::new(&mut body);
+        rsp.last_modified(t);
+        let mut buf = BytesMut::new();
+        encode(&rsp, &mut buf);
+        let expected = format!("\r\nLast-Modified: {}\r\n", HttpDate::from(t));
+        let encoded = String::from_utf8_lossy(&buf);
+        assert!(encoded.contains(&expected), "{secs}: {encoded}");
+    }
+}
@@ -0,0 +1,79 @@
+//! Tests for the https redirect and HSTS wrapper
+#![cfg(feature = "https-redirect")]
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, HttpsRedirect, Request, Response};
+
+#[derive(Clone)]
+struct Page;
+
+impl HttpService for Page {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        rsp.body("secret");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_plain_http_is_redirected() {
+    let client = TestClient::new(HttpsRedirect::new(Page));
+    let rsp = client
+        .get("/account?tab=2")
+        .header("Host", "example.com")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 301);
+    assert_eq!(
+        rsp.header("location"),
+        Some("https://example.com/account?tab=2")
+    );
+    assert!(rsp.body().is_empty());
+
+    let rsp = client
+        .post("/upload")
+        .header("Host", "example.com")
+        .header("X-Forwarded-Proto", "http")
+        .body("data")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 308);
+    assert_eq!(rsp.header("location"), Some("https://example.com/upload"));
+
+    let rsp = client.send_raw(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(rsp.status(), 400);
+}
+
+#[test]
+fn test_https_gets_hsts() {
+    let client = TestClient::new(HttpsRedirect::new(Page));
+    let rsp = client
+        .get("/")
+        .header("X-Forwarded-Proto", "HTTPS, http")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.text(), "secret");
+    assert_eq!(
+        rsp.header("strict-transport-security"),
+        Some("max-age=31536000")
+    );
+
+    let service = HttpsRedirect::new(Page)
+        .hsts_max_age(Duration::from_secs(600))
+        .include_subdomains()
+        .preload();
+    let rsp = TestClient::new(service)
+        .get("/")
+        .header("X-Forwarded-Proto", "https")
+        .send()
+        .unwrap();
+    assert_eq!(
+        rsp.header("strict-transport-security"),
+        Some("max-age=600; includeSubDomains; preload")
+    );
+}
@@ -0,0 +1,140 @@
+//! Tests for the client ip allow and deny lists
+#![cfg(feature = "ip-filter")]
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{
+    HttpConfig, HttpServer, HttpService, HttpServiceFactory, IpFilter, IpFilterService, Request,
+    Response,
+};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_allow_and_deny() {
+    let filter = IpFilter::new()
+        .allow("10.0.0.0/8")
+        .unwrap()
+        .allow("2001:db8::/32")
+        .unwrap()
+        .deny("10.1.0.0/16")
+        .unwrap()
+        .deny("10.2.3.4")
+        .unwrap();
+    assert!(filter.is_allowed(ip("10.0.0.1")));
+    assert!(filter.is_allowed(ip("10.2.3.5")));
+    assert!(!filter.is_allowed(ip("10.1.200.1")));
+    assert!(!filter.is_allowed(ip("10.2.3.4")));
+    assert!(!filter.is_allowed(ip("192.168.0.1")));
+    assert!(filter.is_allowed(ip("2001:db8::1")));
+    assert!(!filter.is_allowed(ip("2001:db9::1")));
+    // an IPv4 client of a dual stack listener
+    assert!(filter.is_allowed(ip("::ffff:10.0.0.1")));
+    assert!(!filter.is_allowed(ip("::ffff:10.1.0.1")));
+
+    let open = IpFilter::new().deny("0.0.0.0/0").unwrap();
+    assert!(!open.is_allowed(ip("8.8.8.8")));
+    assert!(open.is_allowed(ip("::1")));
+    assert!(IpFilter::new().is_allowed(ip("8.8.8.8")));
+}
+
+#[test]
+fn test_invalid_networks() {
+    for bad in [
+        "",
+        "10.0.0.0/33",
+        "::/129",
+        "10.0.0/8",
+        "10.0.0.0/x",
+        "host",
+    ] {
+        let err = IpFilter::new().allow(bad).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{bad}");
+    }
+}
+
+#[test]
+fn test_service_uses_forwarded_client() {
+    let filter = Arc::new(
+        IpFilter::new()
+            .deny("203.0.113.0/24")
+            .unwrap()
+            .trust_proxy("192.168.1.0/24")
+            .unwrap(),
+    );
+    let status = |peer: &str, forwarded: Option<&str>| {
+        let service = IpFilterService::new(Hello, filter.clone(), peer.parse().unwrap());
+        let client = TestClient::new(service);
+        let req = client.get("/");
+        let req = match forwarded {
+            Some(value) => req.header("X-Forwarded-For", value),
+            None => req,
+        };
+        req.send().unwrap().status()
+    };
+
+    assert_eq!(status("198.51.100.1:4000", None), 200);
+    assert_eq!(status("203.0.113.9:4000", None), 403);
+    assert_eq!(status("192.168.1.10:4000", Some("203.0.113.9")), 403);
+    assert_eq!(status("192.168.1.10:4000", Some("198.51.100.1:5555")), 200);
+    // skip trusted hops from the end, an untrusted hop can't be spoofed past
+    assert_eq!(
+        status("192.168.1.10:4000", Some("203.0.113.9, 192.168.1.11")),
+        403
+    );
+    assert_eq!(
+        status("192.168.1.10:4000", Some("203.0.113.9, 198.51.100.1")),
+        200
+    );
+    // a client sending the header directly is not trusted
+    assert_eq!(status("203.0.113.9:4000", Some("198.51.100.1")), 403);
+    assert_eq!(status("192.168.1.10:4000", Some("unknown")), 403);
+}
+
+#[test]
+fn test_listener_closes_denied_connections() {
+    let filter = Arc::new(IpFilter::new().deny("127.0.0.0/8").unwrap());
+    let config = HttpConfig::new().with_ip_filter(filter);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    // the write may already fail on the closed connection
+    let _ = client.write_all(b"GET / HTTP/1.1\r\n\r\n");
+    let mut buf = [0u8; 64];
+    assert!(matches!(client.read(&mut buf), Ok(0) | Err(_)));
+
+    // allowed clients are served as usual
+    let filter = Arc::new(IpFilter::new().deny("10.0.0.0/8").unwrap());
+    let config = HttpConfig::new().with_ip_filter(filter);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+}
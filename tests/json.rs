@@ -0,0 +1,48 @@
+//! Tests for deserializing JSON request bodies
+#![cfg(feature = "json")]
+
+use httparse::Status;
+use may_minihttp::{HttpError, Request};
+use serde_json::Value;
+
+fn request(body: &str) -> Vec<u8> {
+    format!(
+        "POST /events HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+fn parse_json(raw: &[u8], max_body: usize) -> std::io::Result<Value> {
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(raw, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    req.json_streaming(max_body)
+}
+
+#[test]
+fn test_json_body_is_deserialized() {
+    let value = parse_json(&request(r#"{"id": 7, "tags": ["a", "b"]}"#), 1024).unwrap();
+    assert_eq!(value["id"], 7);
+    assert_eq!(value["tags"][1], "b");
+}
+
+#[test]
+fn test_json_body_over_the_limit() {
+    let e = parse_json(&request(r#"{"id": 7}"#), 4).unwrap_err();
+    let expected = HttpError::BodyTooLarge {
+        length: 9,
+        limit: 4,
+    };
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+}
+
+#[test]
+fn test_invalid_json_body() {
+    let e = parse_json(&request(r#"{"id": "#), 1024).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    let e = parse_json(&request(r#"{"id": 7} trailing"#), 1024).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -233,3 +233,25 @@ fn test_size_selection_logic() {
     assert_eq!(select_size(100), 128, "100 headers -> XLarge");
     assert_eq!(select_size(200), 256, "200 headers -> Custom");
 }
+
+#[test]
+fn test_parse_variant_names() {
+    assert_eq!(
+        "default".parse::<MaxHeaders>().unwrap(),
+        MaxHeaders::Default
+    );
+    assert_eq!(
+        "Standard".parse::<MaxHeaders>().unwrap(),
+        MaxHeaders::Standard
+    );
+    assert_eq!(" large ".parse::<MaxHeaders>().unwrap(), MaxHeaders::Large);
+    assert_eq!("XLARGE".parse::<MaxHeaders>().unwrap(), MaxHeaders::XLarge);
+}
+
+#[test]
+fn test_parse_counts() {
+    assert_eq!("32".parse::<MaxHeaders>().unwrap(), MaxHeaders::Standard);
+    assert_eq!("128".parse::<MaxHeaders>().unwrap(), MaxHeaders::XLarge);
+    assert_eq!("96".parse::<MaxHeaders>().unwrap(), MaxHeaders::Custom(96));
+    assert!("lots".parse::<MaxHeaders>().is_err());
+}
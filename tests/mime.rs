@@ -0,0 +1,40 @@
+//! Tests for the extension to Content-Type mapping
+
+use may_minihttp::mime;
+
+#[test]
+fn test_common_extensions() {
+    assert_eq!(mime::from_path("index.html"), "text/html; charset=utf-8");
+    assert_eq!(mime::from_path("/a/b/style.css"), "text/css; charset=utf-8");
+    assert_eq!(mime::from_path("data.json"), "application/json");
+    assert_eq!(mime::from_path("logo.png"), "image/png");
+    assert_eq!(mime::from_path("font.woff2"), "font/woff2");
+}
+
+#[test]
+fn test_extension_is_case_insensitive() {
+    assert_eq!(mime::from_path("PHOTO.JPG"), "image/jpeg");
+    assert_eq!(mime::from_extension("Svg"), Some("image/svg+xml"));
+    assert_eq!(
+        mime::from_extension(".txt"),
+        Some("text/plain; charset=utf-8")
+    );
+}
+
+#[test]
+fn test_unknown_falls_back_to_octet_stream() {
+    assert_eq!(mime::from_path("binary.xyz123"), mime::OCTET_STREAM);
+    assert_eq!(mime::from_path("Makefile"), mime::OCTET_STREAM);
+    assert_eq!(mime::from_extension("xyz123"), None);
+}
+
+#[test]
+fn test_register_custom_mapping() {
+    mime::register(".Proto", "application/x-protobuf");
+    assert_eq!(mime::from_path("msg.proto"), "application/x-protobuf");
+    assert_eq!(mime::from_path("msg.PROTO"), "application/x-protobuf");
+
+    // custom mappings take precedence over the builtin table
+    mime::register("csv", "application/vnd.ms-excel");
+    assert_eq!(mime::from_path("report.csv"), "application/vnd.ms-excel");
+}
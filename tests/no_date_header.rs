@@ -0,0 +1,18 @@
+//! Tests for responses built without the `date-header` feature
+#![cfg(not(feature = "date-header"))]
+
+use bytes::BytesMut;
+use may_minihttp::{encode, Response};
+
+#[test]
+fn test_no_date_header() {
+    let mut body = BytesMut::new();
+    let mut rsp = Response::new(&mut body);
+    rsp.body("hi");
+    let mut buf = BytesMut::new();
+    encode(&rsp, &mut buf);
+    assert_eq!(
+        &buf[..],
+        b"HTTP/1.1 200 Ok\r\nServer: M\r\nContent-Length: 2\r\n\r\nhi"
+    );
+}
@@ -0,0 +1,133 @@
+//! Tests for the OpenTelemetry server spans
+#![cfg(feature = "otel")]
+
+use std::io;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, OtelService, Request, Response};
+use opentelemetry::trace::{SpanId, SpanKind, Status, TraceId, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::export::trace::SpanData;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+
+const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[derive(Clone)]
+struct App;
+
+impl HttpService for App {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/fail" => return Err(io::Error::other("broken")),
+            path if path.starts_with("/users/") => rsp.body("user"),
+            _ => {
+                rsp.status_code(404, "Not Found");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn route(path: &str) -> Option<&'static str> {
+    path.starts_with("/users/").then_some("/users/{id}")
+}
+
+/// a client tracing into a fresh exporter
+fn traced() -> (TestClient<OtelService<App, Tracer>>, InMemorySpanExporter) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let service = OtelService::new(App, provider.tracer("test")).route(route);
+    (TestClient::new(service), exporter)
+}
+
+fn only_span(exporter: &InMemorySpanExporter) -> SpanData {
+    let mut spans = exporter.get_finished_spans().unwrap();
+    assert_eq!(spans.len(), 1);
+    spans.remove(0)
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<KeyValue> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .cloned()
+}
+
+#[test]
+fn test_server_span_attributes() {
+    let (client, exporter) = traced();
+    assert_eq!(client.get("/users/7").send().unwrap().status(), 200);
+
+    let span = only_span(&exporter);
+    assert_eq!(span.name, "GET /users/{id}");
+    assert_eq!(span.span_kind, SpanKind::Server);
+    assert_eq!(
+        attribute(&span, "http.method"),
+        Some(KeyValue::new("http.method", "GET"))
+    );
+    assert_eq!(
+        attribute(&span, "http.route"),
+        Some(KeyValue::new("http.route", "/users/{id}"))
+    );
+    assert_eq!(
+        attribute(&span, "http.target"),
+        Some(KeyValue::new("http.target", "/users/7"))
+    );
+    assert_eq!(
+        attribute(&span, "http.status_code"),
+        Some(KeyValue::new("http.status_code", 200i64))
+    );
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[test]
+fn test_span_without_route() {
+    let (client, exporter) = traced();
+    assert_eq!(client.get("/nope").send().unwrap().status(), 404);
+
+    let span = only_span(&exporter);
+    assert_eq!(span.name, "GET");
+    assert_eq!(attribute(&span, "http.route"), None);
+    assert_eq!(
+        attribute(&span, "http.status_code"),
+        Some(KeyValue::new("http.status_code", 404i64))
+    );
+    // client errors are not server errors
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[test]
+fn test_span_joins_remote_trace() {
+    let (client, exporter) = traced();
+    client
+        .get("/users/7")
+        .header("traceparent", PARENT)
+        .send()
+        .unwrap();
+
+    let span = only_span(&exporter);
+    let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+    assert_eq!(span.span_context.trace_id(), trace_id);
+    assert_eq!(
+        span.parent_span_id,
+        SpanId::from_hex("00f067aa0ba902b7").unwrap()
+    );
+}
+
+#[test]
+fn test_service_error_marks_span() {
+    let (client, exporter) = traced();
+    let _ = client.get("/fail").send();
+
+    let span = only_span(&exporter);
+    assert_eq!(
+        attribute(&span, "http.status_code"),
+        Some(KeyValue::new("http.status_code", 500i64))
+    );
+    assert!(matches!(span.status, Status::Error { .. }));
+}
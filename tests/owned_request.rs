@@ -0,0 +1,95 @@
+//! Tests for detaching a request from the connection with into_owned
+//! and buffering a replayable body
+
+mod common;
+
+use common::send;
+use may_minihttp::{HttpService, OwnedRequest, Request, Response};
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Service that hands the request to a worker and answers 202
+#[derive(Clone)]
+struct EnqueueService(mpsc::Sender<OwnedRequest>);
+
+impl HttpService for EnqueueService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        match req.into_owned(16) {
+            Ok(owned) => {
+                self.0.send(owned).unwrap();
+                res.status_code(202, "Accepted");
+            }
+            Err(_) => {
+                res.status_code(413, "Payload Too Large");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_owned_request_is_sent_to_worker() {
+    let (tx, rx) = mpsc::channel();
+    let handle = common::start_server(EnqueueService(tx));
+    let addr = handle.local_addr();
+
+    let rsp = send(
+        addr,
+        "POST /jobs HTTP/1.1\r\nHost: localhost\r\nX-Job: resize\r\nContent-Length: 5\r\n\r\nhello",
+    );
+    assert!(rsp.starts_with("HTTP/1.1 202 Accepted"), "{rsp}");
+
+    let job = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+    assert_eq!(job.method(), "POST");
+    assert_eq!(job.path(), "/jobs");
+    assert_eq!(job.version(), 1);
+    assert_eq!(job.header("x-job"), Some(&b"resize"[..]));
+    assert_eq!(job.headers().len(), 3);
+    assert_eq!(job.body(), b"hello");
+
+    let rsp = send(
+        addr,
+        "POST /jobs HTTP/1.1\r\nHost: localhost\r\nContent-Length: 17\r\n\r\n0123456789abcdefg",
+    );
+    assert!(rsp.starts_with("HTTP/1.1 413"), "{rsp}");
+    assert!(rx.try_recv().is_err());
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+/// Service that reads the body twice and echoes both copies
+#[derive(Clone)]
+struct ReplayService;
+
+impl HttpService for ReplayService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let mut body = req.buffered_body(1024)?;
+        let mut out = Vec::new();
+        body.read_to_end(&mut out)?;
+        assert!(body.fill_buf()?.is_empty());
+
+        body.rewind();
+        let copy = body.clone();
+        body.read_to_end(&mut out)?;
+        out.extend_from_slice(copy.as_bytes());
+        res.body_vec(out);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_buffered_body_can_be_replayed() {
+    let handle = common::start_server(ReplayService);
+    let addr = handle.local_addr();
+
+    let rsp = send(
+        addr,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\r\nabc",
+    );
+    assert!(rsp.ends_with("\r\n\r\nabcabcabc"), "{rsp}");
+
+    handle.stop();
+    handle.wait().unwrap();
+}
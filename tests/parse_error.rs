@@ -0,0 +1,184 @@
+//! Tests for parse error reporting
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{
+    HttpConfig, HttpError, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+};
+
+#[derive(Clone)]
+struct Ok200;
+
+impl HttpService for Ok200 {
+    fn call(&mut self, _req: Request, _rsp: &mut Response) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn request_with_headers(n: usize) -> Vec<u8> {
+    let mut req = String::from("GET / HTTP/1.1\r\n");
+    for i in 0..n {
+        req.push_str(&format!("X-H-{i}: v\r\n"));
+    }
+    req.push_str("\r\n");
+    req.into_bytes()
+}
+
+static ERRORS: Mutex<Vec<(HttpError, Option<SocketAddr>)>> = Mutex::new(Vec::new());
+
+fn record(e: &HttpError, peer: Option<SocketAddr>) {
+    ERRORS.lock().unwrap().push((*e, peer));
+}
+
+#[test]
+fn test_too_many_headers_is_reported() {
+    let config = HttpConfig::new()
+        .with_parse_error_log(log::LevelFilter::Off)
+        .with_parse_error_hook(record);
+    let client = TestClient::new(Ok200).config(config);
+
+    let e = client.send_raw(&request_with_headers(20)).unwrap_err();
+    let expected = HttpError::TooManyHeaders {
+        received: 20,
+        limit: 16,
+    };
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+    assert_eq!(
+        e.to_string(),
+        "TooManyHeaders: received 20 headers, limit is 16 (over by 4)"
+    );
+    assert_eq!(*ERRORS.lock().unwrap(), [(expected, None)]);
+}
+
+#[test]
+fn test_too_many_headers_counts_one_request() {
+    // the headers of the pipelined request behind it are not counted
+    let mut buf = request_with_headers(20);
+    buf.extend_from_slice(&request_with_headers(10));
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Err(e) = Request::parse(&buf, &mut headers) else {
+        panic!("the request was parsed");
+    };
+    let expected = HttpError::TooManyHeaders {
+        received: 20,
+        limit: 16,
+    };
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+}
+
+static BAD_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+fn count(e: &HttpError, _peer: Option<SocketAddr>) {
+    assert!(matches!(e, HttpError::Parse(_)));
+    BAD_REQUESTS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn test_malformed_request_is_reported() {
+    let config = HttpConfig::new().with_parse_error_hook(count);
+    let client = TestClient::new(Ok200).config(config);
+
+    let e = client
+        .send_raw(b"GET / HTTP/1.1\r\nBad Header\r\n\r\n")
+        .unwrap_err();
+    assert!(matches!(HttpError::from_io(&e), Some(HttpError::Parse(_))));
+    assert_eq!(BAD_REQUESTS.load(Ordering::SeqCst), 1);
+
+    // valid requests don't reach the hook
+    client.get("/").send().unwrap();
+    assert_eq!(BAD_REQUESTS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_invalid_content_length_is_an_error() {
+    let client = TestClient::new(Ok200);
+    let e = client
+        .send_raw(b"POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\n")
+        .unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        HttpError::from_io(&e),
+        Some(&HttpError::InvalidContentLength)
+    );
+}
+
+#[test]
+fn test_body_over_max_body_is_an_error() {
+    let client = TestClient::new(Ok200).config(HttpConfig::new().with_max_body(4));
+    let rsp = client.post("/").body("1234").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+
+    let e = client.post("/").body("12345").send().unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let expected = HttpError::BodyTooLarge {
+        length: 5,
+        limit: 4,
+    };
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+}
+
+#[test]
+fn test_body_over_max_body_gets_413() {
+    let config = HttpConfig::new()
+        .with_max_body(4)
+        .with_parse_error_log(log::LevelFilter::Off);
+    let server = HttpServer(Ok200)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    // no body follows, so the close doesn't find unread bytes and reset
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\n")
+        .unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    assert!(
+        rsp.starts_with("HTTP/1.1 413 Payload Too Large\r\n"),
+        "{rsp}"
+    );
+    assert!(rsp.contains("\r\nConnection: close\r\n"), "{rsp}");
+}
+
+#[test]
+fn test_other_errors_are_not_http_errors() {
+    let e = io::Error::other("boom");
+    assert!(HttpError::from_io(&e).is_none());
+}
+
+/// what a client sending a malformed request sees after it
+fn read_after_garbage(config: HttpConfig) -> io::Result<usize> {
+    let config = config.with_parse_error_log(log::LevelFilter::Off);
+    let server = HttpServer(Ok200)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nBad Header\r\n\r\n")
+        .unwrap();
+    let mut buf = [0u8; 1024];
+    client.read(&mut buf)
+}
+
+#[test]
+fn test_abortive_close() {
+    // a normal close
+    assert_eq!(read_after_garbage(HttpConfig::new()).unwrap(), 0);
+
+    let config = HttpConfig::new().with_abortive_close(true);
+    let e = read_after_garbage(config).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+}
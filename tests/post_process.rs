@@ -0,0 +1,52 @@
+//! Tests for the response post-processing hook
+
+mod common;
+
+use common::get;
+use may_minihttp::{HttpService, PostProcess, Request, Response};
+use std::io;
+
+#[derive(Clone)]
+struct PathService;
+
+impl HttpService for PathService {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/missing" => {
+                res.status_code(404, "Not Found");
+            }
+            _ => res.body("hello"),
+        }
+        Ok(())
+    }
+}
+
+fn add_headers(rsp: &mut Response) {
+    rsp.header("X-Frame-Options: DENY");
+    if rsp.status().0 == 404 {
+        rsp.header("X-Not-Found: 1");
+    }
+    if rsp.body_len() > 0 {
+        rsp.header("X-Has-Body: 1");
+    }
+}
+
+#[test]
+fn test_hook_sees_final_response() {
+    let handle = common::start_server(PostProcess::new(PathService, add_headers));
+    let addr = handle.local_addr();
+
+    let ok = get(addr, "/");
+    assert!(ok.contains("X-Frame-Options: DENY"));
+    assert!(ok.contains("X-Has-Body: 1"));
+    assert!(!ok.contains("X-Not-Found"));
+
+    let missing = get(addr, "/missing");
+    assert!(missing.starts_with("HTTP/1.1 404"));
+    assert!(missing.contains("X-Frame-Options: DENY"));
+    assert!(missing.contains("X-Not-Found: 1"));
+    assert!(!missing.contains("X-Has-Body"));
+
+    handle.stop();
+    handle.wait().unwrap();
+}
@@ -6,11 +6,10 @@
 //! 3. Handles fragmented requests (buffering check)
 //! 4. Handles edge cases and malformed requests
 
-// Note: These imports are for future integration tests with the actual decode function
-// Currently we test the helpers that will be used with the decoder
+use std::io::Read;
 
-// We need to test the internal decode function, so we'll need to create a mock TcpStream
-// For now, let's create integration-style tests using the examples as reference
+use httparse::Status;
+use may_minihttp::{Charset, HttpError, Request};
 
 #[test]
 fn test_minimal_http_request() {
@@ -223,6 +222,252 @@ X-Special: !@#$%^&*()_+-=[]{}|;':\",./<>?\r\n\
     assert!(has_complete_headers(request));
 }
 
+#[test]
+fn test_parse_slice_without_stream() {
+    let request =
+        b"POST /submit?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let req = match Request::parse(request, &mut headers).unwrap() {
+        Status::Complete(req) => req,
+        Status::Partial => panic!("request should be complete"),
+    };
+    assert_eq!(req.method(), "POST");
+    assert_eq!(req.path(), "/submit?x=1");
+    assert_eq!(req.headers().len(), 2);
+
+    let mut body = Vec::new();
+    req.body().read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn test_parse_slice_body_stops_at_content_length() {
+    let request = b"PUT / HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET / HTTP/1.1\r\n\r\n";
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    let mut body = String::new();
+    req.body().read_to_string(&mut body).unwrap();
+    assert_eq!(body, "hi");
+}
+
+#[test]
+fn test_parse_slice_short_body_is_an_error() {
+    let request = b"PUT / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello";
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    let mut body = Vec::new();
+    let e = req.body().read_to_end(&mut body).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn test_parse_slice_partial_and_errors() {
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let status = Request::parse(b"GET / HTTP/1.1\r\nHost: exa", &mut headers).unwrap();
+    assert!(status.is_partial());
+
+    let request = create_request_with_n_headers(17);
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    assert!(Request::parse(&request, &mut headers).is_err());
+
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    assert!(Request::parse(b"GET / HTTP/1.1\r\nBad Header\r\n\r\n", &mut headers).is_err());
+}
+
+#[test]
+fn test_body_bytes_and_string_enforce_the_limit() {
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    assert_eq!(req.body_bytes(5).unwrap(), b"hello");
+
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    assert_eq!(req.body_string(16).unwrap(), "hello");
+
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    let e = req.body_string(4).unwrap_err();
+    let expected = HttpError::BodyTooLarge {
+        length: 5,
+        limit: 4,
+    };
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n\xff\xfe";
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    let e = req.body_string(16).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_body_text_follows_the_charset() {
+    let text = |content_type: &str, body: &[u8]| {
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        let mut headers = [httparse::EMPTY_HEADER; 16];
+        let Status::Complete(req) = Request::parse(&request, &mut headers).unwrap() else {
+            panic!("request should be complete");
+        };
+        req.body_text(64)
+            .map_err(|e| *HttpError::from_io(&e).expect("an HttpError"))
+    };
+    assert_eq!(text("text/plain", "grüß".as_bytes()).unwrap(), "grüß");
+    assert_eq!(
+        text("text/plain; charset=UTF-8", "grüß".as_bytes()).unwrap(),
+        "grüß"
+    );
+    assert_eq!(
+        text(
+            "application/x-www-form-urlencoded; charset=\"latin1\"",
+            b"a=gr\xfc\xdf"
+        )
+        .unwrap(),
+        "a=grüß"
+    );
+    assert_eq!(
+        text("text/plain;format=flowed; Charset=ISO-8859-1", b"\xe9").unwrap(),
+        "é"
+    );
+    assert_eq!(
+        text("text/plain; charset=us-ascii", b"plain").unwrap(),
+        "plain"
+    );
+
+    assert_eq!(
+        text("text/plain", b"ok\xff"),
+        Err(HttpError::InvalidText {
+            charset: Charset::Utf8,
+            valid_up_to: 2
+        })
+    );
+    assert_eq!(
+        text("text/plain; charset=ascii", b"caf\xe9"),
+        Err(HttpError::InvalidText {
+            charset: Charset::Ascii,
+            valid_up_to: 3
+        })
+    );
+    assert_eq!(
+        text("text/plain; charset=shift_jis", b"x"),
+        Err(HttpError::UnsupportedCharset)
+    );
+}
+
+/// Parse `request` and apply `check` to it
+fn with_parsed<R>(request: &[u8], check: impl FnOnce(&Request) -> R) -> R {
+    let mut headers = [httparse::EMPTY_HEADER; 16];
+    let Status::Complete(req) = Request::parse(request, &mut headers).unwrap() else {
+        panic!("request should be complete");
+    };
+    check(&req)
+}
+
+#[test]
+fn test_keep_alive_follows_version_and_connection() {
+    let keep_alive = |r: &[u8]| with_parsed(r, |req| req.is_keep_alive());
+    assert!(keep_alive(b"GET / HTTP/1.1\r\n\r\n"));
+    assert!(!keep_alive(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n"));
+    assert!(!keep_alive(
+        b"GET / HTTP/1.1\r\nConnection: TE, Close\r\n\r\n"
+    ));
+    assert!(!keep_alive(b"GET / HTTP/1.0\r\n\r\n"));
+    assert!(keep_alive(
+        b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"
+    ));
+}
+
+#[test]
+fn test_upgrade_and_expect_continue() {
+    let websocket =
+        b"GET /ws HTTP/1.1\r\nConnection: keep-alive, Upgrade\r\nUpgrade: websocket\r\n\r\n";
+    assert!(with_parsed(websocket, |req| req.is_upgrade()));
+    let no_upgrade = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\n";
+    assert!(!with_parsed(no_upgrade, |req| req.is_upgrade()));
+
+    let expect = b"PUT / HTTP/1.1\r\nExpect: 100-Continue\r\nContent-Length: 0\r\n\r\n";
+    assert!(with_parsed(expect, |req| req.expects_continue()));
+    let http10 = b"PUT / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n";
+    assert!(!with_parsed(http10, |req| req.expects_continue()));
+}
+
+#[test]
+fn test_headers_iter_and_raw_header_block() {
+    let request = b"GET / HTTP/1.1\r\nHost: a\r\nX-Sig:  v\xff \r\nhost: b\r\n\r\nbody";
+    with_parsed(request, |req| {
+        let pairs: Vec<_> = req.headers_iter().collect();
+        assert_eq!(
+            pairs,
+            [
+                ("Host", &b"a"[..]),
+                ("X-Sig", &b"v\xff"[..]),
+                ("host", &b"b"[..])
+            ]
+        );
+        // the spacing the parser trims is still there
+        assert_eq!(
+            req.raw_header_block(),
+            b"Host: a\r\nX-Sig:  v\xff \r\nhost: b\r\n"
+        );
+    });
+    with_parsed(b"GET / HTTP/1.1\r\n\r\n", |req| {
+        assert_eq!(req.headers_iter().count(), 0);
+        assert_eq!(req.raw_header_block(), b"");
+    });
+    with_parsed(b"GET / HTTP/1.1\nHost: a\n\n", |req| {
+        assert_eq!(req.raw_header_block(), b"Host: a\n");
+    });
+}
+
+#[test]
+fn test_parse_slice_rejects_invalid_content_length() {
+    let invalid: [&[u8]; 5] = [
+        b"Content-Length: abc",
+        b"Content-Length: \xff\xfe",
+        b"Content-Length: +5",
+        b"Content-Length: 99999999999999999999999",
+        b"Content-Length: 5\r\nContent-Length: 6",
+    ];
+    for header in invalid {
+        let mut request = b"POST / HTTP/1.1\r\n".to_vec();
+        request.extend_from_slice(header);
+        request.extend_from_slice(b"\r\n\r\nhello!");
+        let mut headers = [httparse::EMPTY_HEADER; 4];
+        let e = Request::parse(&request, &mut headers).err().unwrap();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            HttpError::from_io(&e),
+            Some(&HttpError::InvalidContentLength)
+        );
+    }
+
+    // repeated headers with the same length are fine
+    let request = b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello";
+    let mut headers = [httparse::EMPTY_HEADER; 4];
+    match Request::parse(request, &mut headers).unwrap() {
+        Status::Complete(req) => assert_eq!(req.body_bytes(16).unwrap(), b"hello"),
+        Status::Partial => panic!("request should be complete"),
+    }
+}
+
 // Helper functions
 
 /// Count the number of headers in an HTTP request
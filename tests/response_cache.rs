@@ -0,0 +1,260 @@
+//! Tests for the in-memory response cache
+//!
+//! These tests verify:
+//! 1. Repeated GET requests are served without invoking the inner service
+//! 2. Non-cacheable methods always reach the inner service
+//! 3. Entries expire after the configured TTL
+//! 4. Replaced and expired entries don't pile up
+//! 5. Cache-Control, Authorization, Cookie and Vary decide what is stored
+#![cfg(feature = "cache")]
+
+mod common;
+
+use bytes::BytesMut;
+use may_minihttp::{CacheService, HttpService, Request, Response, ResponseCache, ServerHandle};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Service that counts how many times it was invoked
+#[derive(Clone)]
+struct CountingService(Arc<AtomicUsize>);
+
+impl HttpService for CountingService {
+    fn call(&mut self, _req: Request, res: &mut Response) -> io::Result<()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        res.header("Content-Type: text/plain")
+            .header_owned(format!("X-Origin: {}", "counting"))
+            .body("cached");
+        Ok(())
+    }
+}
+
+fn start_server(cache: Arc<ResponseCache>) -> (ServerHandle, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let service = CacheService::new(CountingService(calls.clone()), cache);
+    (common::start_server(service), calls)
+}
+
+fn send(addr: SocketAddr, method: &str) -> String {
+    let req = format!("{method} /data HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n");
+    common::send(addr, &req)
+}
+
+#[test]
+fn test_repeated_get_is_served_from_cache() {
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60)));
+    let (handle, calls) = start_server(cache.clone());
+    let addr = handle.local_addr();
+
+    for _ in 0..3 {
+        let rsp = send(addr, "GET");
+        assert!(rsp.contains("200"));
+        assert!(rsp.contains("Content-Type: text/plain"));
+        assert!(rsp.contains("X-Origin: counting\r\n"));
+        assert!(rsp.ends_with("cached"));
+    }
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cache.hits(), 2);
+    assert_eq!(cache.len(), 1);
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+#[test]
+fn test_post_bypasses_cache() {
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60)));
+    let (handle, calls) = start_server(cache.clone());
+    let addr = handle.local_addr();
+
+    send(addr, "POST");
+    send(addr, "POST");
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(cache.is_empty());
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+#[test]
+fn test_entries_expire_after_ttl() {
+    let cache = Arc::new(ResponseCache::new(Duration::from_millis(50)));
+    let (handle, calls) = start_server(cache.clone());
+    let addr = handle.local_addr();
+
+    send(addr, "GET");
+    std::thread::sleep(Duration::from_millis(100));
+    send(addr, "GET");
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(cache.hits(), 0);
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+/// call `service` for a GET of `path`, outside of a connection
+fn call(service: &mut CacheService<CountingService>, path: &str) {
+    let raw = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let mut headers = [httparse::EMPTY_HEADER; 4];
+    let req = match Request::parse(raw.as_bytes(), &mut headers).unwrap() {
+        httparse::Status::Complete(req) => req,
+        httparse::Status::Partial => unreachable!(),
+    };
+    let mut body = BytesMut::new();
+    let mut rsp = Response::new(&mut body);
+    service.call(req, &mut rsp).unwrap();
+}
+
+#[test]
+fn test_reinserted_and_expired_entries_stay_bounded() {
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    // each lookup finds the entry expired and stores it again
+    let cache = Arc::new(ResponseCache::new(Duration::ZERO));
+    let mut service = CacheService::new(CountingService(calls.clone()), cache.clone());
+    for _ in 0..10_000 {
+        call(&mut service, "/same");
+    }
+    assert_eq!(cache.len(), 1);
+
+    // expired entries of other keys are swept by the next insert
+    for i in 0..10_000 {
+        call(&mut service, &format!("/key/{i}"));
+    }
+    assert_eq!(cache.len(), 1);
+    assert_eq!(calls.load(Ordering::SeqCst), 20_000);
+    assert_eq!(cache.hits(), 0);
+}
+
+/// Service that answers with a fixed set of response headers
+#[derive(Clone)]
+struct HeaderService {
+    calls: Arc<AtomicUsize>,
+    headers: &'static [&'static str],
+}
+
+impl HttpService for HeaderService {
+    fn call(&mut self, _req: Request, res: &mut Response) -> io::Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        for h in self.headers {
+            res.header(h);
+        }
+        res.body("cached");
+        Ok(())
+    }
+}
+
+fn header_service(
+    ttl: Duration,
+    headers: &'static [&'static str],
+) -> (CacheService<HeaderService>, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let inner = HeaderService {
+        calls: calls.clone(),
+        headers,
+    };
+    let service = CacheService::new(inner, Arc::new(ResponseCache::new(ttl)));
+    (service, calls)
+}
+
+/// call `service` for a GET of `/data` with the extra request header lines
+fn get(service: &mut CacheService<HeaderService>, headers: &str) {
+    let raw = format!("GET /data HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n");
+    let mut slots = [httparse::EMPTY_HEADER; 8];
+    let req = match Request::parse(raw.as_bytes(), &mut slots).unwrap() {
+        httparse::Status::Complete(req) => req,
+        httparse::Status::Partial => unreachable!(),
+    };
+    let mut body = BytesMut::new();
+    let mut rsp = Response::new(&mut body);
+    service.call(req, &mut rsp).unwrap();
+}
+
+#[test]
+fn test_authorized_requests_are_not_stored() {
+    let (mut service, calls) = header_service(Duration::from_secs(60), &[]);
+    get(&mut service, "Authorization: Bearer alice\r\n");
+    get(&mut service, "Authorization: Bearer alice\r\n");
+    get(&mut service, "Cookie: session=alice\r\n");
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert_eq!(service.cache().len(), 1);
+
+    // a public response may be shared
+    let (mut service, calls) = header_service(Duration::from_secs(60), &["Cache-Control: public"]);
+    get(&mut service, "Authorization: Bearer alice\r\n");
+    get(&mut service, "Authorization: Bearer bob\r\n");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_response_vary_is_part_of_the_key() {
+    let (mut service, calls) = header_service(Duration::from_secs(60), &["Vary: Accept-Language"]);
+    get(&mut service, "Accept-Language: en\r\n");
+    get(&mut service, "Accept-Language: en\r\n");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    get(&mut service, "Accept-Language: fr\r\n");
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(service.cache().hits(), 1);
+
+    let (mut service, calls) = header_service(Duration::from_secs(60), &["Vary: *"]);
+    get(&mut service, "");
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(service.cache().is_empty());
+}
+
+#[test]
+fn test_request_no_cache_and_no_store() {
+    let (mut service, calls) = header_service(Duration::from_secs(60), &[]);
+    get(&mut service, "Cache-Control: no-store\r\n");
+    assert!(service.cache().is_empty());
+
+    get(&mut service, "");
+    get(&mut service, "Cache-Control: no-cache\r\n");
+    get(&mut service, "Pragma: no-cache\r\n");
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert_eq!(service.cache().hits(), 0);
+
+    get(&mut service, "");
+    assert_eq!(service.cache().hits(), 1);
+}
+
+#[test]
+fn test_response_max_age_bounds_the_ttl() {
+    let (mut service, calls) =
+        header_service(Duration::from_secs(60), &["Cache-Control: max-age=0"]);
+    get(&mut service, "");
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(service.cache().is_empty());
+
+    let uncacheable: [&'static [&'static str]; 2] =
+        [&["Cache-Control: no-cache"], &["Cache-Control: private"]];
+    for headers in uncacheable {
+        let (mut service, calls) = header_service(Duration::from_secs(60), headers);
+        get(&mut service, "");
+        get(&mut service, "");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    let (mut service, calls) = header_service(
+        Duration::from_secs(60),
+        &["Cache-Control: public, max-age=1"],
+    );
+    get(&mut service, "");
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    std::thread::sleep(Duration::from_millis(1100));
+    get(&mut service, "");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
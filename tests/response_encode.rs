@@ -0,0 +1,161 @@
+//! Tests for the public response encoder
+#![cfg(feature = "date-header")]
+
+use bytes::{BufMut, BytesMut};
+use may_minihttp::{encode, CommonHeader, ContentType, Response};
+
+fn render(f: impl FnOnce(&mut Response)) -> String {
+    let mut body = BytesMut::new();
+    let mut rsp = Response::new(&mut body);
+    f(&mut rsp);
+    let mut buf = BytesMut::new();
+    encode(&rsp, &mut buf);
+    String::from_utf8(buf.to_vec()).unwrap()
+}
+
+/// Replace the variable Date value so the output can be compared exactly
+fn strip_date(s: &str) -> String {
+    let start = s.find("Date: ").unwrap() + "Date: ".len();
+    let end = start + s[start..].find("\r\n").unwrap();
+    format!("{}<date>{}", &s[..start], &s[end..])
+}
+
+#[test]
+fn test_default_response() {
+    let out = render(|_| {});
+    assert_eq!(
+        strip_date(&out),
+        "HTTP/1.1 200 Ok\r\nServer: M\r\nDate: <date>\r\nContent-Length: 0\r\n\r\n"
+    );
+}
+
+#[test]
+fn test_status_headers_and_body() {
+    let out = render(|rsp| {
+        rsp.status_code(201, "Created")
+            .header("Content-Type: application/json")
+            .header("Location: /items/1");
+        rsp.body_vec(b"{\"id\":1}".to_vec());
+    });
+    assert_eq!(
+        strip_date(&out),
+        "HTTP/1.1 201 Created\r\nServer: M\r\nDate: <date>\r\nContent-Length: 8\r\n\
+         Content-Type: application/json\r\nLocation: /items/1\r\n\r\n{\"id\":1}"
+    );
+}
+
+#[test]
+fn test_common_headers() {
+    let out = render(|rsp| {
+        rsp.content_type(ContentType::Text)
+            .common_header(CommonHeader::NoStore)
+            .common_header(ContentType::Json);
+    });
+    assert!(out.ends_with(
+        "\r\nContent-Type: text/plain; charset=utf-8\r\nCache-Control: no-store\r\n\
+         Content-Type: application/json\r\n\r\n"
+    ));
+    assert_eq!(ContentType::Html.as_str(), "text/html; charset=utf-8");
+    assert_eq!(CommonHeader::ConnectionClose.header(), "Connection: close");
+}
+
+#[test]
+fn test_body_mut_is_encoded() {
+    let out = render(|rsp| {
+        rsp.body_mut().put_slice(b"streamed ");
+        rsp.body_mut().put_slice(b"bytes");
+    });
+    assert!(out.contains("Content-Length: 14\r\n"));
+    assert!(out.ends_with("\r\n\r\nstreamed bytes"));
+}
+
+#[test]
+fn test_owned_headers_follow_static_ones() {
+    let tag = 42;
+    let out = render(|rsp| {
+        rsp.header_owned(format!("ETag: \"v{tag}\""))
+            .header("Content-Type: text/plain");
+        rsp.body("ok");
+    });
+    assert_eq!(
+        strip_date(&out),
+        "HTTP/1.1 200 Ok\r\nServer: M\r\nDate: <date>\r\nContent-Length: 2\r\n\
+         Content-Type: text/plain\r\nETag: \"v42\"\r\n\r\nok"
+    );
+}
+
+#[test]
+fn test_bodiless_statuses_drop_the_body() {
+    for (code, reason) in [
+        (204, "No Content"),
+        (304, "Not Modified"),
+        (103, "Early Hints"),
+    ] {
+        let out = render(|rsp| {
+            rsp.status_code(code, reason).header("ETag: \"v1\"");
+            rsp.body("stale bytes");
+        });
+        assert_eq!(
+            strip_date(&out),
+            format!(
+                "HTTP/1.1 {code} {reason}\r\nServer: M\r\nDate: <date>\r\nETag: \"v1\"\r\n\r\n"
+            )
+        );
+    }
+}
+
+fn status_line(f: impl FnOnce(&mut Response)) -> String {
+    let out = render(f);
+    out[..out.find("\r\n").unwrap()].to_owned()
+}
+
+#[test]
+fn test_custom_reason_phrases() {
+    let line = status_line(|rsp| {
+        rsp.status_with_reason(599, String::from("Upstream Scream"));
+    });
+    assert_eq!(line, "HTTP/1.1 599 Upstream Scream");
+
+    let line = status_line(|rsp| {
+        rsp.status_with_reason(200, "OK");
+    });
+    assert_eq!(line, "HTTP/1.1 200 OK");
+
+    // control characters can't end the status line early
+    let line = status_line(|rsp| {
+        rsp.status_with_reason(418, "Tea\r\nX-Injected: 1");
+    });
+    assert_eq!(line, "HTTP/1.1 418 Tea  X-Injected: 1");
+}
+
+#[test]
+fn test_empty_reason_and_unknown_codes() {
+    assert_eq!(
+        status_line(|rsp| {
+            rsp.status_with_reason(404, "");
+        }),
+        "HTTP/1.1 404 Not Found"
+    );
+    assert_eq!(
+        status_line(|rsp| {
+            rsp.status_with_reason(799, "");
+        }),
+        "HTTP/1.1 799 "
+    );
+    assert_eq!(
+        status_line(|rsp| {
+            rsp.status_code(42, "Answer");
+        }),
+        "HTTP/1.1 500 Internal Server Error"
+    );
+}
+
+#[test]
+fn test_header_kv() {
+    let id = 7u32.to_string();
+    let out = render(|rsp| {
+        rsp.header_kv("X-Request-Id", &id)
+            .header_kv("Cache-Control", "no-cache");
+    });
+    assert!(out.contains("\r\nX-Request-Id: 7\r\nCache-Control: no-cache\r\n\r\n"));
+}
@@ -0,0 +1,62 @@
+//! Tests for dropping root privileges after binding
+//!
+//! Switching user changes the whole process, so these tests have a file of
+//! their own.
+#![cfg(unix)]
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use may_minihttp::{HttpServer, HttpServerBuilder, HttpService, Request, Response};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_unknown_user() {
+    let started = HttpServerBuilder::new(HttpServer(Hello))
+        .run_as("no-such-user-minihttp", "no-such-group-minihttp")
+        .bind("127.0.0.1:0");
+    let Err(err) = started else {
+        panic!("the server started");
+    };
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_run_as_nobody() {
+    use std::os::unix::fs::MetadataExt;
+
+    // owned by the effective user of the process
+    let euid = || std::fs::metadata("/proc/self").unwrap().uid();
+    if euid() != 0 {
+        // only root can switch users
+        return;
+    }
+    let server = HttpServerBuilder::new(HttpServer(Hello))
+        .run_as("65534", "65534")
+        .bind("127.0.0.1:0")
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    assert_eq!(euid(), 65534);
+
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    assert!(rsp.ends_with("hello"), "{rsp}");
+}
@@ -0,0 +1,634 @@
+//! Tests for serving http over a generic Read + Write stream
+
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::BufMut;
+use may_minihttp::{
+    serve_stream, BufGrowth, HttpConfig, HttpError, HttpService, MaxHeaders, Request, Response,
+};
+
+/// In-memory stream: reads from a fixed input, collects everything written
+struct MockStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+    writes: usize,
+    // max bytes returned by one read
+    chunk: usize,
+    // fail all writes
+    broken: bool,
+}
+
+impl MockStream {
+    fn new(input: &[u8]) -> Self {
+        MockStream {
+            input: Cursor::new(input.to_vec()),
+            output: Vec::new(),
+            writes: 0,
+            chunk: usize::MAX,
+            broken: false,
+        }
+    }
+
+    /// Deliver the input `chunk` bytes at a time
+    fn chunked(input: &[u8], chunk: usize) -> Self {
+        MockStream {
+            chunk,
+            ..Self::new(input)
+        }
+    }
+
+    fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.chunk);
+        self.input.read(&mut buf[..n])
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.broken {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.writes += 1;
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Echo;
+
+impl HttpService for Echo {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let path = req.path().to_owned();
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        let mut w = rsp.body_mut().writer();
+        write!(w, "{path}:")?;
+        w.write_all(&body)
+    }
+}
+
+#[test]
+fn test_pipelined_requests_over_mock_stream() {
+    let mut stream = MockStream::new(
+        b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n\
+          POST /b HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nbody",
+    );
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+
+    let out = stream.output();
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 2, "{out}");
+    let first = out.find("/a:").unwrap();
+    let second = out.find("/b:body").unwrap();
+    assert!(first < second, "responses must keep request order");
+}
+
+struct RawHeaders;
+
+impl HttpService for RawHeaders {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body_vec(req.raw_header_block().to_vec());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_raw_header_block_of_each_request() {
+    let mut stream = MockStream::chunked(
+        b"GET /a HTTP/1.1\r\nHost: x\r\nX-Sig:  1 \r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+        7,
+    );
+    serve_stream(&mut stream, RawHeaders, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    assert!(
+        out.contains("\r\n\r\nHost: x\r\nX-Sig:  1 \r\nHTTP/1.1"),
+        "{out}"
+    );
+    assert!(out.ends_with("Content-Length: 0\r\n\r\n"), "{out}");
+}
+
+#[test]
+fn test_header_limit_from_config() {
+    let mut req = String::from("GET / HTTP/1.1\r\n");
+    for i in 0..20 {
+        req.push_str(&format!("X-H-{i}: v\r\n"));
+    }
+    req.push_str("\r\n");
+
+    let mut stream = MockStream::new(req.as_bytes());
+    assert!(serve_stream(&mut stream, Echo, &HttpConfig::default()).is_err());
+
+    let mut stream = MockStream::new(req.as_bytes());
+    let config = HttpConfig::new().with_max_headers(MaxHeaders::Standard);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    assert!(stream.output().ends_with("/:"));
+}
+
+#[test]
+fn test_custom_header_limit_over_128() {
+    let mut req = large_head(200);
+    req.extend_from_slice(b"POST /next HTTP/1.1\r\nContent-Length: 2\r\n\r\nok");
+    let config = HttpConfig::new().with_max_headers(MaxHeaders::Custom(200));
+    let mut stream = MockStream::new(&req);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    let out = stream.output();
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 2, "{out}");
+    assert!(out.contains("/large-head:"), "{out}");
+    assert!(out.ends_with("/next:ok"), "{out}");
+
+    let mut stream = MockStream::new(&large_head(201));
+    let e = serve_stream(&mut stream, Echo, &config).unwrap_err();
+    let expected = HttpError::TooManyHeaders {
+        received: 201,
+        limit: 200,
+    };
+    assert_eq!(HttpError::from_io(&e), Some(&expected));
+}
+
+const PIPELINED: &[u8] = b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\nGET /3 HTTP/1.1\r\n\r\n";
+
+#[test]
+fn test_pipelined_responses_are_batched() {
+    let mut stream = MockStream::new(PIPELINED);
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+    assert_eq!(stream.writes, 1);
+    assert_eq!(stream.output().matches("HTTP/1.1 200 Ok").count(), 3);
+}
+
+#[test]
+fn test_unbatched_writes_keep_order() {
+    let mut stream = MockStream::new(PIPELINED);
+    let config = HttpConfig::new().with_batch_writes(false);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    assert_eq!(stream.writes, 3);
+
+    let out = stream.output();
+    let pos: Vec<usize> = ["/1:", "/2:", "/3:"]
+        .iter()
+        .map(|p| out.find(p).unwrap())
+        .collect();
+    assert!(pos.windows(2).all(|w| w[0] < w[1]), "{out}");
+}
+
+#[test]
+fn test_truncated_body_is_an_error() {
+    // the client went away 5 bytes into a 10 byte body
+    let mut stream = MockStream::new(b"POST /a HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello");
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+    let output = stream.output();
+    assert!(output.starts_with("HTTP/1.1 500"), "{output}");
+    assert!(!output.contains("/a:hello"));
+}
+
+#[test]
+fn test_connection_close_ends_the_connection() {
+    let mut stream =
+        MockStream::new(b"GET /1 HTTP/1.1\r\nConnection: close\r\n\r\nGET /2 HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    assert!(out.ends_with("/1:"), "{out}");
+
+    let mut stream = MockStream::new(b"GET /1 HTTP/1.0\r\n\r\nGET /2 HTTP/1.0\r\n\r\n");
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+    assert_eq!(stream.output().matches("200 Ok").count(), 1);
+}
+
+#[test]
+fn test_keep_alive_header_and_request_limit() {
+    let input = b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\nGET /3 HTTP/1.1\r\n\r\n";
+    let config = HttpConfig::new()
+        .with_keep_alive_timeout_secs(5)
+        .with_max_requests_per_conn(2);
+    let mut stream = MockStream::new(input);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    let out = stream.output();
+    let responses: Vec<_> = out.split("HTTP/1.1 200 Ok").skip(1).collect();
+    assert_eq!(responses.len(), 2, "{out}");
+    assert!(responses[0].contains("\r\nKeep-Alive: timeout=5, max=1\r\n"));
+    assert!(!responses[0].contains("Connection: close"));
+    assert!(responses[1].contains("\r\nConnection: close\r\n"));
+    assert!(!responses[1].contains("Keep-Alive"));
+
+    // only the configured parts are announced
+    let config = HttpConfig::new().with_keep_alive_timeout_secs(5);
+    let mut stream = MockStream::new(input);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    let out = stream.output();
+    assert_eq!(out.matches("\r\nKeep-Alive: timeout=5\r\n").count(), 3);
+
+    // nothing by default
+    let mut stream = MockStream::new(input);
+    serve_stream(&mut stream, Echo, &HttpConfig::new()).unwrap();
+    assert!(!stream.output().contains("Keep-Alive"));
+}
+
+/// the largest body `Streaming` held before a write
+static MAX_BUFFERED: AtomicUsize = AtomicUsize::new(0);
+
+/// streams 4MB in 64KB writes
+struct Streaming;
+
+impl HttpService for Streaming {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        let chunk = [b'x'; 64 * 1024];
+        for _ in 0..64 {
+            let body = rsp.body_mut();
+            MAX_BUFFERED.fetch_max(body.len(), Ordering::Relaxed);
+            body.extend_from_slice(&chunk);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_streamed_body_is_bounded_by_the_high_watermark() {
+    let mut stream = MockStream::new(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let config = HttpConfig::new().with_write_high_watermark(256 * 1024);
+    serve_stream(&mut stream, Streaming, &config).unwrap();
+    assert!(MAX_BUFFERED.load(Ordering::Relaxed) <= (256 + 64) * 1024);
+    let out = stream.output();
+    assert!(out.contains("\r\nTransfer-Encoding: chunked\r\n"));
+    assert!(out.ends_with("\r\n0\r\n\r\n"));
+    assert_eq!(out.matches('x').count(), 64 * 64 * 1024);
+}
+
+/// fills all the static header slots
+struct FullHeaders;
+
+impl HttpService for FullHeaders {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        // the connection lines are not among the response headers
+        assert!(rsp.headers().is_empty() && rsp.owned_headers().is_empty());
+        for _ in 0..16 {
+            rsp.header("X-Slot: taken");
+        }
+        rsp.body("full");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_connection_lines_leave_the_header_slots_free() {
+    let input = b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\n";
+    let config = HttpConfig::new()
+        .with_keep_alive_timeout_secs(5)
+        .with_max_requests_per_conn(2);
+    let mut stream = MockStream::new(input);
+    serve_stream(&mut stream, FullHeaders, &config).unwrap();
+    let out = stream.output();
+    let responses: Vec<_> = out.split("HTTP/1.1 200 Ok").skip(1).collect();
+    assert_eq!(responses.len(), 2, "{out}");
+    assert_eq!(responses[0].matches("X-Slot: taken").count(), 16);
+    assert!(responses[0].contains("\r\nKeep-Alive: timeout=5, max=1\r\n"));
+    assert!(responses[1].contains("\r\nConnection: close\r\n"));
+}
+
+#[test]
+fn test_high_watermark_flushes_batched_responses() {
+    let mut stream = MockStream::new(PIPELINED);
+    let config = HttpConfig::new().with_write_high_watermark(1);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    assert_eq!(stream.writes, 3);
+    assert_eq!(stream.output().matches("HTTP/1.1 200 Ok").count(), 3);
+}
+
+#[derive(Clone)]
+struct Progress;
+
+impl HttpService for Progress {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.header("Content-Type: text/plain");
+        rsp.body("first,");
+        rsp.flush()?;
+        rsp.body_mut().extend_from_slice(b"second,");
+        rsp.flush()?;
+        rsp.body("last");
+        Ok(())
+    }
+}
+
+#[test]
+fn test_flush_streams_chunks_in_order() {
+    let mut stream = MockStream::new(b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Progress, &HttpConfig::default()).unwrap();
+    // the two flushes of each request, then the batched rest
+    assert_eq!(stream.writes, 5);
+
+    let out = stream.output();
+    let rsp = "Transfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n\
+               6\r\nfirst,\r\n7\r\nsecond,\r\n4\r\nlast\r\n0\r\n\r\n";
+    assert_eq!(out.matches(rsp).count(), 2, "{out}");
+    assert!(!out.contains("Content-Length"));
+}
+
+#[test]
+fn test_flush_to_http_1_0_ends_with_the_connection() {
+    let mut stream = MockStream::new(
+        b"GET /1 HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET /2 HTTP/1.0\r\n\r\n",
+    );
+    serve_stream(&mut stream, Progress, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    assert!(
+        out.ends_with("\r\nConnection: close\r\nContent-Type: text/plain\r\n\r\nfirst,second,last"),
+        "{out}"
+    );
+    assert!(!out.contains("Transfer-Encoding") && !out.contains("Content-Length"));
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 1);
+}
+
+#[test]
+fn test_flush_of_a_head_request_drops_the_body() {
+    let mut stream = MockStream::new(b"HEAD /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Progress, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    let head = "Transfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n";
+    let get = "6\r\nfirst,\r\n7\r\nsecond,\r\n4\r\nlast\r\n0\r\n\r\n";
+    let responses: Vec<_> = out.split("HTTP/1.1 200 Ok").skip(1).collect();
+    assert_eq!(responses.len(), 2, "{out}");
+    assert!(responses[0].ends_with(head), "{out}");
+    assert!(responses[1].ends_with(&format!("{head}{get}")), "{out}");
+}
+
+#[derive(Clone)]
+struct NotModified;
+
+impl HttpService for NotModified {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/cached" || req.path() == "/empty" {
+            match req.path() {
+                "/cached" => rsp.status_code(304, "Not Modified"),
+                _ => rsp.status_code(204, "No Content"),
+            };
+            rsp.body("stale");
+            rsp.flush()?;
+            rsp.body("more");
+        } else {
+            rsp.body("fresh");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_not_modified_keeps_the_connection_in_sync() {
+    let mut stream = MockStream::new(b"GET /cached HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, NotModified, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    let (first, second) = out.split_at(out.find("HTTP/1.1 200").unwrap());
+    assert!(first.starts_with("HTTP/1.1 304 Not Modified\r\n"), "{out}");
+    assert!(first.ends_with("\r\n\r\n"), "{out}");
+    assert!(!first.contains("Transfer-Encoding") && !first.contains("Content-Length"));
+    assert!(second.ends_with("\r\n\r\nfresh"), "{out}");
+}
+
+#[test]
+fn test_flushed_no_content_has_no_chunked_body() {
+    let mut stream = MockStream::new(b"GET /empty HTTP/1.1\r\n\r\nGET /next HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, NotModified, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    let (first, second) = out.split_at(out.find("HTTP/1.1 200").unwrap());
+    assert!(first.starts_with("HTTP/1.1 204 No Content\r\n"), "{out}");
+    assert!(first.ends_with("\r\n\r\n"), "{out}");
+    assert!(!first.contains("Transfer-Encoding") && !first.contains("0\r\n\r\n"));
+    assert!(second.ends_with("\r\n\r\nfresh"), "{out}");
+}
+
+#[derive(Clone)]
+struct Hints;
+
+impl HttpService for Hints {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let kind = |r: io::Result<()>| r.unwrap_err().kind();
+        assert_eq!(
+            kind(rsp.send_interim(200, &[])),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(rsp.send_interim(101, &[])),
+            io::ErrorKind::InvalidInput
+        );
+        let bad = rsp.send_interim(103, &["Link: </a>\r\nX-Evil: 1"]);
+        assert_eq!(kind(bad), io::ErrorKind::InvalidData);
+        if req.expects_continue() {
+            rsp.send_interim(100, &[])?;
+        }
+        rsp.send_interim(103, &["Link: </style.css>; rel=preload; as=style"])?;
+        if req.method() != "HEAD" {
+            rsp.body("final");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_interim_responses_precede_the_final_one() {
+    let req = b"POST /a HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 0\r\n\r\n\
+                HEAD /b HTTP/1.1\r\n\r\n";
+    let mut stream = MockStream::new(req);
+    serve_stream(&mut stream, Hints, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    let early = "HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload; as=style\r\n\r\n";
+    let first = format!("HTTP/1.1 100 Continue\r\n\r\n{early}HTTP/1.1 200 Ok\r\n");
+    assert!(out.starts_with(&first), "{out}");
+    // the head only response still follows its hints
+    let (_, second) = out.split_at(out.find("final").unwrap() + "final".len());
+    assert!(second.starts_with(early), "{out}");
+    assert!(second.ends_with("\r\n\r\n"), "{out}");
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 2, "{out}");
+}
+
+#[test]
+fn test_no_interim_responses_for_http_1_0() {
+    let mut stream = MockStream::new(b"GET / HTTP/1.0\r\n\r\n");
+    serve_stream(&mut stream, Hints, &HttpConfig::default()).unwrap();
+    let out = stream.output();
+    assert!(out.starts_with("HTTP/1.1 200 Ok\r\n"), "{out}");
+    assert!(!out.contains("103"), "{out}");
+}
+
+#[test]
+fn test_headers_arriving_byte_by_byte() {
+    let req = b"GET /slow HTTP/1.1\r\nHost: x\r\nX-Long: aaaaaaaaaaaaaaaa\r\n\r\n\
+                POST /next HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc";
+    let mut stream = MockStream::chunked(req, 1);
+    serve_stream(&mut stream, Echo, &HttpConfig::default()).unwrap();
+
+    let out = stream.output();
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 2, "{out}");
+    assert!(out.contains("/slow:"));
+    assert!(out.ends_with("/next:abc"));
+}
+
+static CONN_ERRORS: Mutex<Vec<io::ErrorKind>> = Mutex::new(Vec::new());
+
+fn record(peer: Option<SocketAddr>, e: &io::Error) {
+    assert!(peer.is_none());
+    CONN_ERRORS.lock().unwrap().push(e.kind());
+}
+
+#[test]
+fn test_connection_error_hook() {
+    let config = HttpConfig::new()
+        .with_connection_error_hook(record)
+        .with_parse_error_log(log::LevelFilter::Off);
+
+    let mut stream = MockStream::new(b"GET / HTTP/1.1\r\n\r\n");
+    stream.broken = true;
+    assert!(serve_stream(&mut stream, Echo, &config).is_err());
+
+    let mut stream = MockStream::new(b"GET / HTTP/1.1\r\nBad Header\r\n\r\n");
+    assert!(serve_stream(&mut stream, Echo, &config).is_err());
+
+    // a clean close is not reported
+    let mut stream = MockStream::new(b"GET / HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Echo, &config).unwrap();
+
+    assert_eq!(
+        *CONN_ERRORS.lock().unwrap(),
+        [io::ErrorKind::BrokenPipe, io::ErrorKind::InvalidData]
+    );
+}
+
+static COMPLETED: Mutex<Vec<(String, String, usize, usize)>> = Mutex::new(Vec::new());
+
+fn completed(method: &str, path: &str, status: usize, _elapsed: Duration, bytes: usize) {
+    let done = (method.to_owned(), path.to_owned(), status, bytes);
+    COMPLETED.lock().unwrap().push(done);
+}
+
+#[test]
+fn test_request_complete_hook() {
+    let config = HttpConfig::new().with_request_complete_hook(completed);
+
+    let req = b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nxy";
+    let mut stream = MockStream::new(req);
+    serve_stream(&mut stream, Echo, &config).unwrap();
+
+    // the flushed chunks count too
+    let mut stream = MockStream::new(b"GET /c HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Progress, &config).unwrap();
+
+    let done = COMPLETED.lock().unwrap();
+    let done: Vec<_> = done
+        .iter()
+        .map(|(m, p, status, bytes)| (m.as_str(), p.as_str(), *status, *bytes))
+        .collect();
+    assert_eq!(
+        done,
+        [
+            ("GET", "/a", 200, 3),
+            ("POST", "/b", 200, 5),
+            ("GET", "/c", 200, 17)
+        ]
+    );
+}
+
+#[derive(Clone)]
+struct Large;
+
+impl HttpService for Large {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/large" => rsp.body_vec(vec![b'x'; 1024 * 1024]),
+            _ => rsp.body("small"),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_buffers_shrink_after_large_response() {
+    // the 1MB response is over the limit only until its buffers are given back
+    let config = HttpConfig::new().with_max_conn_memory(256 * 1024);
+    let mut stream = MockStream::new(b"GET /large HTTP/1.1\r\n\r\nGET /small HTTP/1.1\r\n\r\n");
+    serve_stream(&mut stream, Large, &config).unwrap();
+    let out = stream.output();
+    assert_eq!(out.matches("HTTP/1.1 200 Ok").count(), 2);
+    assert!(out.ends_with("small"));
+}
+
+#[test]
+fn test_memory_limit_ends_endless_request_head() {
+    let mut head = b"GET / HTTP/1.1\r\n".to_vec();
+    while head.len() < 512 * 1024 {
+        head.extend_from_slice(b"X-Filler: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n");
+    }
+    let config = HttpConfig::new()
+        .with_max_headers(MaxHeaders::Custom(128))
+        .with_max_conn_memory(128 * 1024);
+    let e = serve_stream(&mut MockStream::new(&head), Echo, &config).unwrap_err();
+    match HttpError::from_io(&e) {
+        Some(HttpError::MemoryLimit { held, limit }) => {
+            assert_eq!(*limit, 128 * 1024);
+            assert!(*held > *limit);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    // without a limit the head is read on until the stream ends
+    assert!(serve_stream(&mut MockStream::new(&head), Echo, &HttpConfig::new()).is_ok());
+}
+
+/// a request with `count` filler headers, about 64 bytes each
+fn large_head(count: usize) -> Vec<u8> {
+    let mut head = b"GET /large-head HTTP/1.1\r\n".to_vec();
+    for i in 0..count {
+        write!(
+            head,
+            "X-Filler-{i:03}: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n"
+        )
+        .unwrap();
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+#[test]
+fn test_small_buffers_grow_for_large_head() {
+    let head = large_head(120);
+    assert!(head.len() > 4 * 1024);
+    for growth in [BufGrowth::Fixed, BufGrowth::Doubling] {
+        let config = HttpConfig::new()
+            .with_max_headers(MaxHeaders::Custom(128))
+            .with_buf_initial_capacity(512)
+            .with_buf_growth(growth, 1024);
+        let mut stream = MockStream::new(&head);
+        serve_stream(&mut stream, Echo, &config).unwrap();
+        assert!(stream.output().ends_with("/large-head:"), "{growth:?}");
+    }
+}
+
+#[test]
+fn test_buf_max_capacity_ends_large_head() {
+    let head = large_head(120);
+    let config = HttpConfig::new()
+        .with_max_headers(MaxHeaders::Custom(128))
+        .with_buf_initial_capacity(1024)
+        .with_buf_growth(BufGrowth::Doubling, 1024)
+        .with_buf_max_capacity(4096);
+    let e = serve_stream(&mut MockStream::new(&head), Echo, &config).unwrap_err();
+    match HttpError::from_io(&e) {
+        Some(HttpError::MemoryLimit { held, limit }) => {
+            assert_eq!(*limit, 4096);
+            assert_eq!(*held, 4096);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+
+    // a head under the limit is served
+    let mut stream = MockStream::new(&large_head(40));
+    serve_stream(&mut stream, Echo, &config).unwrap();
+    assert!(stream.output().ends_with("/large-head:"));
+}
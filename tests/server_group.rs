@@ -0,0 +1,69 @@
+//! Tests for running several servers with their own services as one
+#![cfg(feature = "server-group")]
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use may_minihttp::{HttpConfig, HttpServer, HttpService, Request, Response, ServerGroupBuilder};
+
+#[derive(Clone)]
+struct Says(&'static str);
+
+impl HttpService for Says {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body(self.0);
+        Ok(())
+    }
+}
+
+fn get(addr: SocketAddr) -> String {
+    let mut client = TcpStream::connect(addr).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    rsp
+}
+
+#[test]
+fn test_a_service_per_listener() {
+    let admin_config = HttpConfig::new().with_keep_alive_timeout_secs(5);
+    let servers = ServerGroupBuilder::new()
+        .serve("127.0.0.1:0", HttpServer(Says("api")))
+        .serve_with_config("127.0.0.1:0", HttpServer(Says("admin")), admin_config)
+        .start()
+        .unwrap();
+    servers.wait_until_ready().unwrap();
+    assert!(servers.is_running());
+    let addrs = servers.local_addrs();
+    assert_eq!(addrs.len(), 2);
+    assert_eq!(addrs[1], servers.servers()[1].local_addr());
+    assert!(get(addrs[0]).ends_with("\r\n\r\napi"));
+    assert!(get(addrs[1]).ends_with("\r\n\r\nadmin"));
+
+    servers.stop();
+    servers.wait().unwrap();
+}
+
+#[test]
+fn test_start_fails_as_a_whole() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let started = ServerGroupBuilder::new()
+        .serve("127.0.0.1:0", HttpServer(Says("api")))
+        .serve(
+            taken.local_addr().unwrap().to_string(),
+            HttpServer(Says("admin")),
+        )
+        .start();
+    assert!(started.is_err());
+
+    let Err(e) = ServerGroupBuilder::new().start() else {
+        panic!("an empty group started");
+    };
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
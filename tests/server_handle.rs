@@ -0,0 +1,272 @@
+//! Tests for the handle returned by the server start methods
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use may_minihttp::{
+    Drain, HttpConfig, HttpServer, HttpServerBuilder, HttpService, HttpServiceFactory, MaxHeaders,
+    Request, Response, ServerStats,
+};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn get(port: u16) -> String {
+    get_at(Ipv4Addr::LOCALHOST.into(), port)
+}
+
+fn get_at(ip: IpAddr, port: u16) -> String {
+    let mut client = TcpStream::connect((ip, port)).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client
+        .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    rsp
+}
+
+#[test]
+fn test_stop_and_wait() {
+    let server = HttpServer(Hello).start("127.0.0.1:0").unwrap();
+    assert!(server.is_running());
+    assert!(get(server.local_addr().port()).ends_with("hello"));
+
+    server.stop();
+    // stopping twice is fine
+    server.stop();
+    server.wait().unwrap();
+}
+
+#[test]
+fn test_ephemeral_port() {
+    let first = HttpServer(Hello).start("127.0.0.1:0").unwrap();
+    let second = HttpServer(Hello).start("127.0.0.1:0").unwrap();
+    let port = first.local_addr().port();
+    assert_ne!(port, 0);
+    assert_ne!(port, second.local_addr().port());
+    assert!(first.local_addr().ip().is_loopback());
+    assert!(get(port).ends_with("hello"));
+    assert!(get(second.local_addr().port()).ends_with("hello"));
+}
+
+#[test]
+fn test_backlog() {
+    let server = HttpServerBuilder::new(HttpServer(Hello))
+        .backlog(1024)
+        .bind("127.0.0.1:0")
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let port = server.local_addr().port();
+    // a burst of clients waits in the queue until the server takes them
+    let clients: Vec<_> = (0..64)
+        .map(|_| TcpStream::connect(("127.0.0.1", port)).unwrap())
+        .collect();
+    assert!(get(port).ends_with("hello"));
+    drop(clients);
+    server.stop();
+    server.wait().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_defer_accept_and_fastopen() {
+    let config = HttpConfig::new()
+        .with_defer_accept_secs(1)
+        .with_fastopen_queue(16);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    // the request comes right away, the connection is handed over with it
+    assert!(get(server.local_addr().port()).ends_with("hello"));
+}
+
+#[test]
+fn test_tcp_keepalive() {
+    let config = HttpConfig::new().with_tcp_keepalive(60, 10, 5);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    assert!(get(server.local_addr().port()).ends_with("hello"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_keep_alive_timeout() {
+    let config = HttpConfig::new().with_keep_alive_timeout_secs(1);
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    let rsp = String::from_utf8_lossy(&buf[..n]);
+    assert!(rsp.contains("\r\nKeep-Alive: timeout=1\r\n"), "{rsp}");
+
+    // the idle connection is closed, well before the read timeout
+    let idle = std::time::Instant::now();
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    assert!(idle.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn test_header_limit_over_128() {
+    let config = HttpConfig::new().with_max_headers(MaxHeaders::Custom(256));
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let mut req = String::from("GET / HTTP/1.1\r\nConnection: close\r\n");
+    for i in 0..250 {
+        req.push_str(&format!("X-H-{i}: v\r\n"));
+    }
+    req.push_str("\r\n");
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client.write_all(req.as_bytes()).unwrap();
+    let mut rsp = String::new();
+    client.read_to_string(&mut rsp).unwrap();
+    assert!(rsp.ends_with("hello"), "{rsp}");
+}
+
+#[derive(Clone)]
+struct Big;
+
+impl HttpService for Big {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body_vec(vec![b'x'; 4 << 20]);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_half_closed_client_gets_the_responses() {
+    let server = HttpServer(Big).start("127.0.0.1:0").unwrap();
+    server.wait_until_ready().unwrap();
+    let mut client = TcpStream::connect(server.local_addr()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    client
+        .write_all(b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\n")
+        .unwrap();
+    // nothing more to send, the responses are still wanted
+    client.shutdown(Shutdown::Write).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let mut rsp = Vec::new();
+    client.read_to_end(&mut rsp).unwrap();
+    assert_eq!(
+        rsp.len(),
+        2 * (4 << 20) + 2 * rsp.iter().position(|&b| b == b'x').unwrap()
+    );
+    assert!(rsp.ends_with(b"xxxx"));
+}
+
+#[test]
+fn test_dual_stack() {
+    let server = HttpServerBuilder::new(HttpServer(Hello))
+        .bind_dual_stack(0)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let port = server.local_addr().port();
+    assert!(get_at(Ipv6Addr::LOCALHOST.into(), port).ends_with("hello"));
+    assert!(get(port).ends_with("hello"));
+}
+
+#[test]
+fn test_ipv6_only() {
+    let config = HttpConfig::new().with_ipv6_only(true);
+    let v6 = HttpServer(Hello)
+        .start_with_config("[::]:0", config.clone())
+        .unwrap();
+    v6.wait_until_ready().unwrap();
+    let port = v6.local_addr().port();
+    // the port is free for IPv4
+    let v4 = HttpServer(Hello)
+        .start_with_config(("0.0.0.0", port), config)
+        .unwrap();
+    v4.wait_until_ready().unwrap();
+    assert!(get_at(Ipv6Addr::LOCALHOST.into(), port).ends_with("hello"));
+    assert!(get(port).ends_with("hello"));
+}
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Takes a while to start
+struct SlowStart;
+
+impl HttpServiceFactory for SlowStart {
+    type Service = Hello;
+
+    fn new_service(&self, _id: usize) -> Hello {
+        Hello
+    }
+
+    fn on_server_start(&self) {
+        std::thread::sleep(Duration::from_millis(100));
+        STARTED.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_wait_until_ready() {
+    let server = SlowStart.start("127.0.0.1:0").unwrap();
+    server.wait_until_ready().unwrap();
+    assert!(STARTED.load(Ordering::SeqCst));
+    assert!(get(server.local_addr().port()).ends_with("hello"));
+
+    // still running
+    assert!(!server.wait_for(Duration::from_millis(20)));
+}
+
+#[test]
+fn test_reload() {
+    let stats = Arc::new(ServerStats::new());
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new()
+        .with_stats(stats.clone())
+        .with_drain(drain.clone());
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let port = server.local_addr().port();
+    let rsp = get(port);
+    assert!(rsp.ends_with("hello"));
+    assert!(!rsp.contains("Connection: close"));
+
+    // only connections accepted after the reload use the new limits
+    server.reload(HttpConfig::new().with_max_requests_per_conn(1));
+    let rsp = get(port);
+    assert!(rsp.ends_with("hello"));
+    assert!(rsp.contains("Connection: close"));
+
+    // the stats and the drain the server started with stay
+    assert_eq!(stats.accepted(), 2);
+    assert_eq!(stats.requests(), 2);
+    server.begin_drain(Duration::ZERO);
+    assert!(drain.is_draining());
+    assert!(server.wait_for(Duration::from_secs(3)));
+}
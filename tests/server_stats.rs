@@ -0,0 +1,97 @@
+//! Tests for the server statistics counters
+
+mod common;
+
+use std::io::{self, Cursor, Read, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use may_minihttp::{
+    serve_stream, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+    ServerStats,
+};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+/// In-memory stream: reads from a fixed input, collects everything written
+struct MockStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stream_counters() {
+    let stats = Arc::new(ServerStats::new());
+    let input = b"GET /1 HTTP/1.1\r\n\r\nGET /2 HTTP/1.1\r\n\r\n";
+    let mut stream = MockStream {
+        input: Cursor::new(input.to_vec()),
+        output: Vec::new(),
+    };
+    let config = HttpConfig::new().with_stats(stats.clone());
+    serve_stream(&mut stream, Hello, &config).unwrap();
+
+    assert_eq!(stats.accepted(), 1);
+    assert_eq!(stats.requests(), 2);
+    assert_eq!(stats.bytes_read(), input.len() as u64);
+    assert_eq!(stats.bytes_written(), stream.output.len() as u64);
+    // the connection is over
+    assert_eq!(stats.active_connections(), 0);
+    assert_eq!(stats.keep_alive_connections(), 0);
+}
+
+fn wait_for(cond: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(3) {
+        if cond() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn test_live_connection_counters() {
+    let stats = Arc::new(ServerStats::new());
+    let config = HttpConfig::new().with_stats(stats.clone());
+    let server = common::ready(HttpServer(Hello).start_with_config("127.0.0.1:0", config));
+
+    let mut client = common::connect(server.local_addr());
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).ends_with("hello"));
+
+    assert!(wait_for(|| stats.keep_alive_connections() == 1));
+    assert_eq!(stats.active_connections(), 1);
+    assert_eq!(stats.requests(), 1);
+
+    drop(client);
+    assert!(wait_for(|| stats.active_connections() == 0));
+    assert_eq!(stats.keep_alive_connections(), 0);
+    assert_eq!(stats.accepted(), 1);
+}
@@ -0,0 +1,109 @@
+//! Tests for the HttpServiceFactory connection info and lifecycle hooks
+
+mod common;
+
+use common::init_may_runtime;
+use may_minihttp::{ConnectionInfo, HttpService, HttpServiceFactory, Request, Response};
+use std::io::{self, Read, Write};
+
+struct PeerService {
+    peer: String,
+}
+
+impl HttpService for PeerService {
+    fn call(&mut self, _req: Request, res: &mut Response) -> io::Result<()> {
+        res.body_vec(self.peer.clone().into_bytes());
+        Ok(())
+    }
+}
+
+struct PeerFactory;
+
+impl HttpServiceFactory for PeerFactory {
+    type Service = PeerService;
+
+    fn new_service(&self, _id: usize) -> PeerService {
+        unreachable!("new_service_with_info is overridden")
+    }
+
+    fn new_service_with_info(&self, info: &ConnectionInfo) -> PeerService {
+        assert!(!info.tls);
+        PeerService {
+            peer: format!("{} -> {}", info.peer_addr, info.local_addr),
+        }
+    }
+}
+
+#[test]
+fn test_service_sees_connection_addresses() {
+    init_may_runtime();
+    let handle = common::ready(PeerFactory.start("127.0.0.1:0"));
+    let mut stream = common::connect(handle.local_addr());
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).unwrap();
+    let rsp = String::from_utf8_lossy(&buf[..n]);
+    let expected = format!(
+        "{} -> {}",
+        stream.local_addr().unwrap(),
+        stream.peer_addr().unwrap()
+    );
+    assert!(rsp.ends_with(&expected), "unexpected response: {rsp}");
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+mod lifecycle {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Counters {
+        started: AtomicUsize,
+        stopped: AtomicUsize,
+    }
+
+    struct HookFactory(Arc<Counters>);
+
+    impl HttpServiceFactory for HookFactory {
+        type Service = PeerService;
+
+        fn new_service(&self, _id: usize) -> PeerService {
+            PeerService {
+                peer: String::new(),
+            }
+        }
+
+        fn on_server_start(&self) {
+            self.0.started.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_server_stop(&self) {
+            self.0.stopped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_start_and_stop_hooks() {
+        init_may_runtime();
+        let counters = Arc::new(Counters::default());
+        let handle = HookFactory(counters.clone())
+            .start("127.0.0.1:0")
+            .expect("Failed to start server");
+
+        // the accept loop runs after the start hook
+        handle.wait_until_ready().unwrap();
+        assert_eq!(counters.started.load(Ordering::SeqCst), 1);
+        assert_eq!(counters.stopped.load(Ordering::SeqCst), 0);
+
+        handle.stop();
+        handle.wait().unwrap();
+        assert_eq!(counters.started.load(Ordering::SeqCst), 1);
+        assert_eq!(counters.stopped.load(Ordering::SeqCst), 1);
+    }
+}
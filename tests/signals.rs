@@ -0,0 +1,115 @@
+//! Tests for draining a server on SIGTERM and SIGINT, and reloading it on
+//! SIGHUP
+#![cfg(unix)]
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use may_minihttp::{
+    Drain, HttpConfig, HttpServer, HttpService, HttpServiceFactory, Request, Response,
+};
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn get(addr: SocketAddr) -> String {
+    let mut client = TcpStream::connect(addr).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let n = client.read(&mut buf).unwrap();
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
+fn wait_for(mut f: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while !f() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    true
+}
+
+/// whether `signal` no longer has its default action
+fn is_caught(signal: libc::c_int) -> bool {
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) };
+    action.sa_sigaction != libc::SIG_DFL
+}
+
+// the signals reach every test of the process, one at a time
+static SIGNALS: Mutex<()> = Mutex::new(());
+
+fn send(signal: libc::c_int) {
+    assert_eq!(unsafe { libc::kill(libc::getpid(), signal) }, 0);
+}
+
+#[test]
+fn test_first_signal_drains_second_stops() {
+    let _one = SIGNALS.lock().unwrap_or_else(|e| e.into_inner());
+    let drain = Arc::new(Drain::new());
+    let config = HttpConfig::new().with_drain(drain.clone());
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", config)
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let addr = server.local_addr();
+
+    let signaler = thread::spawn(move || {
+        // a signal before the handlers are in place would end the test,
+        // SIGINT is caught last
+        assert!(wait_for(|| is_caught(libc::SIGINT)));
+        send(libc::SIGTERM);
+        assert!(wait_for(|| drain.is_draining()));
+        // still serving during the grace period, closing after each response
+        let rsp = get(addr);
+        assert!(rsp.contains("Connection: close"));
+        assert!(rsp.ends_with("hello"));
+        send(libc::SIGINT);
+    });
+
+    let started = Instant::now();
+    server.wait_for_signals(Duration::from_secs(60)).unwrap();
+    // the second signal cut the grace period short
+    assert!(started.elapsed() < Duration::from_secs(30));
+    signaler.join().unwrap();
+}
+
+#[test]
+fn test_hangup_reloads_the_config() {
+    let _one = SIGNALS.lock().unwrap_or_else(|e| e.into_inner());
+    let server = HttpServer(Hello)
+        .start_with_config("127.0.0.1:0", HttpConfig::new())
+        .unwrap();
+    server.wait_until_ready().unwrap();
+    let addr = server.local_addr();
+
+    let signaler = thread::spawn(move || {
+        assert!(wait_for(|| is_caught(libc::SIGHUP)));
+        assert!(!get(addr).contains("Connection: close"));
+        send(libc::SIGHUP);
+        // the reloaded config closes the connection after each request
+        assert!(wait_for(|| get(addr).contains("Connection: close")));
+        send(libc::SIGINT);
+    });
+
+    let config = || Ok(HttpConfig::new().with_max_requests_per_conn(1));
+    server
+        .wait_for_signals_with_reload(Duration::ZERO, config)
+        .unwrap();
+    signaler.join().unwrap();
+}
@@ -5,22 +5,14 @@
 //! - At limit boundary (should pass)
 //! - Above limit (should fail with TooManyHeaders)
 
+mod common;
+
 use bytes::BufMut;
-use may_minihttp::{HttpServer, HttpService, Request, Response};
+use may_minihttp::{HttpService, Request, Response, ServerHandle};
 use std::io::{self, Read, Write};
 use std::net::TcpStream;
-use std::sync::Once;
 use std::time::Duration;
 
-static INIT: Once = Once::new();
-
-/// Initialize MAY runtime once for all tests
-fn init_may_runtime() {
-    INIT.call_once(|| {
-        may::config().set_stack_size(0x8000);
-    });
-}
-
 /// Simple test service that echoes header count
 #[derive(Clone)]
 struct TestService;
@@ -37,23 +29,9 @@ impl HttpService for TestService {
     }
 }
 
-/// Start a test server and return its handle
-fn start_test_server(port: u16) -> may::coroutine::JoinHandle<()> {
-    init_may_runtime();
-
-    let handle = HttpServer(TestService)
-        .start(format!("127.0.0.1:{}", port))
-        .expect("Failed to start server");
-
-    // Wait for server to be ready
-    for _ in 0..50 {
-        if TcpStream::connect(format!("127.0.0.1:{}", port)).is_ok() {
-            break;
-        }
-        std::thread::sleep(Duration::from_millis(10));
-    }
-
-    handle
+/// Start a test server on a free port and return its handle
+fn start_test_server() -> ServerHandle {
+    common::start_server(TestService)
 }
 
 /// Send HTTP request with specified number of headers
@@ -100,9 +78,10 @@ fn send_request_with_headers(port: u16, num_headers: usize) -> io::Result<String
 
 #[test]
 fn test_3_headers_well_below_limit() {
-    let handle = start_test_server(18080);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let response = send_request_with_headers(18080, 3).expect("Failed to send request");
+    let response = send_request_with_headers(port, 3).expect("Failed to send request");
 
     println!("Response:\n{}", response);
 
@@ -110,17 +89,16 @@ fn test_3_headers_well_below_limit() {
     assert!(response.contains("Headers: 3"), "Should receive 3 headers");
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
 
 #[test]
 fn test_10_headers_below_limit() {
-    let handle = start_test_server(18081);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let response = send_request_with_headers(18081, 10).expect("Failed to send request");
+    let response = send_request_with_headers(port, 10).expect("Failed to send request");
 
     println!("10 headers response:\n{}", response);
 
@@ -134,17 +112,16 @@ fn test_10_headers_below_limit() {
     );
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
 
 #[test]
 fn test_16_headers_at_default_limit() {
-    let handle = start_test_server(18082);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let response = send_request_with_headers(18082, 16).expect("Failed to send request");
+    let response = send_request_with_headers(port, 16).expect("Failed to send request");
 
     println!("16 headers (at limit) response:\n{}", response);
 
@@ -158,17 +135,16 @@ fn test_16_headers_at_default_limit() {
     );
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
 
 #[test]
 fn test_17_headers_exceeds_default_limit() {
-    let handle = start_test_server(18083);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let result = send_request_with_headers(18083, 17);
+    let result = send_request_with_headers(port, 17);
 
     match result {
         Ok(response) => {
@@ -189,17 +165,16 @@ fn test_17_headers_exceeds_default_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
 
 #[test]
 fn test_20_headers_well_over_limit() {
-    let handle = start_test_server(18084);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let result = send_request_with_headers(18084, 20);
+    let result = send_request_with_headers(port, 20);
 
     match result {
         Ok(response) => {
@@ -221,17 +196,16 @@ fn test_20_headers_well_over_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
 
 #[test]
 fn test_32_headers_far_over_limit() {
-    let handle = start_test_server(18085);
+    let handle = start_test_server();
+    let port = handle.local_addr().port();
 
-    let result = send_request_with_headers(18085, 32);
+    let result = send_request_with_headers(port, 32);
 
     match result {
         Ok(response) => {
@@ -253,8 +227,6 @@ fn test_32_headers_far_over_limit() {
     }
 
     // Cleanup
-    unsafe {
-        handle.coroutine().cancel();
-    }
-    let _ = handle.join();
+    handle.stop();
+    handle.wait().unwrap();
 }
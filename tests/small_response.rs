@@ -0,0 +1,100 @@
+//! Tests for writing small and large responses on the same connection
+//!
+//! Small responses take the stack buffer path, large ones go through the
+//! response buffer; both must produce the same framing and keep order.
+
+mod common;
+
+use may_minihttp::{HttpService, Request, Response};
+use std::io::{self, BufRead, BufReader, Write};
+
+#[derive(Clone)]
+struct Sized;
+
+impl HttpService for Sized {
+    fn call(&mut self, req: Request, res: &mut Response) -> io::Result<()> {
+        let len: usize = req.path()[1..].parse().unwrap_or(0);
+        res.header("Content-Type: text/plain")
+            .body_vec(vec![b'x'; len]);
+        Ok(())
+    }
+}
+
+/// the head of a response and its `Content-Length`
+fn read_head(reader: &mut impl BufRead) -> (String, usize) {
+    let mut head = String::new();
+    let mut len = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(v) = line.strip_prefix("Content-Length: ") {
+            len = v.trim().parse().unwrap();
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            return (head, len);
+        }
+    }
+}
+
+fn read_response(reader: &mut impl BufRead) -> (String, Vec<u8>) {
+    let (head, len) = read_head(reader);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+    (head, body)
+}
+
+#[test]
+fn test_small_and_large_responses_on_one_connection() {
+    let handle = common::start_server(Sized);
+    let mut stream = common::connect(handle.local_addr());
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    for len in [0, 10, 300, 5000, 20, 100_000, 1] {
+        let req = format!("GET /{len} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        stream.write_all(req.as_bytes()).unwrap();
+        let (head, body) = read_response(&mut reader);
+        assert!(head.starts_with("HTTP/1.1 200 Ok\r\n"), "{head}");
+        assert!(head.contains("Content-Type: text/plain\r\n"));
+        assert_eq!(body, vec![b'x'; len]);
+    }
+
+    // pipelined mix, answered in order
+    let mut reqs = String::new();
+    for len in [5, 4000, 6] {
+        reqs.push_str(&format!("GET /{len} HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+    }
+    stream.write_all(reqs.as_bytes()).unwrap();
+    for len in [5, 4000, 6] {
+        let (_, body) = read_response(&mut reader);
+        assert_eq!(body.len(), len);
+    }
+
+    handle.stop();
+    handle.wait().unwrap();
+}
+
+#[test]
+fn test_head_response_has_no_body() {
+    let handle = common::start_server(Sized);
+    let mut stream = common::connect(handle.local_addr());
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // a body on the wire would be read as the start of the GET response
+    for len in [10, 100_000] {
+        let reqs = format!(
+            "HEAD /{len} HTTP/1.1\r\nHost: localhost\r\n\r\n\
+             GET /3 HTTP/1.1\r\nHost: localhost\r\n\r\n"
+        );
+        stream.write_all(reqs.as_bytes()).unwrap();
+        let (head, head_len) = read_head(&mut reader);
+        assert!(head.starts_with("HTTP/1.1 200 Ok\r\n"), "{head}");
+        assert_eq!(head_len, len);
+        let (head, body) = read_response(&mut reader);
+        assert!(head.starts_with("HTTP/1.1 200 Ok\r\n"), "{head}");
+        assert_eq!(body, b"xxx");
+    }
+
+    handle.stop();
+    handle.wait().unwrap();
+}
@@ -0,0 +1,42 @@
+//! Tests for the blocking thread pool
+#![cfg(feature = "blocking")]
+
+use std::panic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use may_minihttp::spawn_blocking;
+
+#[test]
+fn test_result_is_returned() {
+    let caller = thread::current().id();
+    let (sum, worker) = spawn_blocking(|| ((1..=100u32).sum::<u32>(), thread::current().id()));
+    assert_eq!(sum, 5050);
+    assert_ne!(worker, caller);
+}
+
+#[test]
+fn test_calls_run_in_parallel() {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..4)
+        .map(|i| {
+            may::go!(move || spawn_blocking(move || {
+                thread::sleep(Duration::from_millis(200));
+                i * 2
+            }))
+        })
+        .collect();
+    let results: Vec<i32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(results, [0, 2, 4, 6]);
+    assert!(start.elapsed() < Duration::from_millis(700));
+}
+
+#[test]
+fn test_panic_is_resumed_in_caller() {
+    let ret = panic::catch_unwind(|| spawn_blocking(|| panic!("boom")));
+    let payload = ret.unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+
+    // the pool keeps working afterwards
+    assert_eq!(spawn_blocking(|| 7), 7);
+}
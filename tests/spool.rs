@@ -0,0 +1,67 @@
+//! Tests for request bodies spooled to temporary files
+#![cfg(feature = "spool")]
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, Request, Response};
+
+/// where the last body was spooled to, `None` if it stayed in memory
+static SPOOLED_AT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Answers with the body length and its checksum, read twice
+#[derive(Clone)]
+struct Spool;
+
+impl HttpService for Spool {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        let mut body = req.spooled_body(16, 1024 * 1024)?;
+        let path = body.path().map(|p| p.to_owned());
+        if let Some(path) = &path {
+            assert!(path.exists());
+        }
+        *SPOOLED_AT.lock().unwrap() = path;
+
+        let mut first = Vec::new();
+        body.read_to_end(&mut first)?;
+        body.seek(SeekFrom::Start(0))?;
+        let mut second = Vec::new();
+        body.read_to_end(&mut second)?;
+        assert_eq!(first, second);
+
+        let sum: u64 = first.iter().map(|&b| b as u64).sum();
+        rsp.body_vec(format!("{} {sum}", body.len()).into_bytes());
+        Ok(())
+    }
+}
+
+fn upload(body: Vec<u8>) -> (String, Option<PathBuf>) {
+    let rsp = TestClient::new(Spool).post("/").body(body).send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    let spooled = SPOOLED_AT.lock().unwrap().take();
+    (rsp.text(), spooled)
+}
+
+#[test]
+fn test_spooled_body() {
+    let (text, path) = upload(b"small".to_vec());
+    assert_eq!(text, "5 537");
+    assert!(path.is_none());
+
+    let body: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let sum: u64 = body.iter().map(|&b| b as u64).sum();
+    let (text, path) = upload(body);
+    assert_eq!(text, format!("100000 {sum}"));
+    // the file is gone once the handler is done
+    let path = path.expect("a large body is spooled to a file");
+    assert!(!path.exists());
+
+    let rsp = TestClient::new(Spool)
+        .post("/")
+        .body(vec![0; 2 * 1024 * 1024])
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 500);
+}
@@ -0,0 +1,123 @@
+//! Tests for the in-process test client
+
+use std::io::{self, Read, Write};
+
+use bytes::BufMut;
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpConfig, HttpService, MaxHeaders, Request, Response};
+
+#[derive(Clone)]
+struct Echo;
+
+impl HttpService for Echo {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        match req.path() {
+            "/missing" => {
+                rsp.status_code(404, "Not Found");
+                return Ok(());
+            }
+            "/fail" => return Err(io::Error::other("boom")),
+            "/stream" => {
+                rsp.body("part one, ");
+                rsp.flush()?;
+                rsp.body("part two");
+                return Ok(());
+            }
+            _ => {}
+        }
+        let method = req.method().to_owned();
+        let headers = req.headers().len();
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        rsp.header("Content-Type: text/plain");
+        let mut w = rsp.body_mut().writer();
+        write!(w, "{method} {headers}:")?;
+        w.write_all(&body)
+    }
+}
+
+#[test]
+fn test_get_request() {
+    let rsp = TestClient::new(Echo).get("/").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.reason(), "Ok");
+    assert_eq!(rsp.header("CONTENT-TYPE"), Some("text/plain"));
+    assert_eq!(rsp.header("date").is_some(), cfg!(feature = "date-header"));
+    assert_eq!(rsp.text(), "GET 1:");
+}
+
+#[test]
+fn test_head_request() {
+    let rsp = TestClient::new(Echo).request("HEAD", "/").send().unwrap();
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.header("Content-Length"), Some("7"));
+    assert!(rsp.body().is_empty());
+}
+
+#[test]
+fn test_post_with_headers_and_body() {
+    let client = TestClient::new(Echo);
+    let rsp = client
+        .post("/submit")
+        .header("X-Trace", "abc")
+        .body("hello")
+        .send()
+        .unwrap();
+    // Host, X-Trace and the generated Content-Length
+    assert_eq!(rsp.text(), "POST 3:hello");
+    assert_eq!(rsp.header("content-length"), Some("12"));
+}
+
+#[test]
+fn test_status_and_service_error() {
+    let client = TestClient::new(Echo);
+    let rsp = client.get("/missing").send().unwrap();
+    assert_eq!(rsp.status(), 404);
+    assert_eq!(rsp.reason(), "Not Found");
+    assert!(rsp.body().is_empty());
+
+    let rsp = client.get("/fail").send().unwrap();
+    assert_eq!(rsp.status(), 500);
+    assert_eq!(rsp.text(), "boom");
+}
+
+#[test]
+fn test_raw_pipelined_requests() {
+    let rsps = TestClient::new(Echo)
+        .send_raw_all(
+            b"GET /a HTTP/1.1\r\n\r\n\
+              PUT /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi",
+        )
+        .unwrap();
+    assert_eq!(rsps.len(), 2);
+    assert_eq!(rsps[0].text(), "GET 0:");
+    assert_eq!(rsps[1].text(), "PUT 1:hi");
+}
+
+#[test]
+fn test_config_is_used_for_decoding() {
+    let client = TestClient::new(Echo);
+    let mut req = client.get("/");
+    for i in 0..20 {
+        req = req.header(&format!("X-H-{i}"), "v");
+    }
+    let raw = req.to_bytes();
+
+    assert!(client.send_raw(&raw).is_err());
+
+    let config = HttpConfig::new().with_max_headers(MaxHeaders::Standard);
+    let rsp = TestClient::new(Echo).config(config).send_raw(&raw).unwrap();
+    assert_eq!(rsp.text(), "GET 21:");
+}
+
+#[test]
+fn test_flushed_response_is_joined() {
+    let client = TestClient::new(Echo);
+    let rsps = client
+        .send_raw_all(b"GET /stream HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\n")
+        .unwrap();
+    assert_eq!(rsps.len(), 2);
+    assert_eq!(rsps[0].header("transfer-encoding"), Some("chunked"));
+    assert_eq!(rsps[0].text(), "part one, part two");
+    assert_eq!(rsps[1].status(), 404);
+}
@@ -0,0 +1,54 @@
+//! Tests for the per-request deadline
+#![cfg(feature = "timeout")]
+
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, Request, Response, TimeoutService};
+
+#[derive(Clone)]
+struct Sleepy;
+
+impl HttpService for Sleepy {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> io::Result<()> {
+        if req.path() == "/slow" {
+            may::coroutine::sleep(Duration::from_millis(500));
+        }
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body)?;
+        rsp.status_code(201, "Created")
+            .header("Content-Type: text/plain")
+            .body_vec(body);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_fast_request_passes_through() {
+    let client = TestClient::new(TimeoutService::new(Sleepy, Duration::from_secs(2)));
+    let rsp = client.post("/fast").body("payload").send().unwrap();
+    assert_eq!(rsp.status(), 201);
+    assert_eq!(rsp.reason(), "Created");
+    assert_eq!(rsp.header("content-type"), Some("text/plain"));
+    assert_eq!(rsp.text(), "payload");
+}
+
+#[test]
+fn test_slow_request_gets_gateway_timeout() {
+    let client = TestClient::new(TimeoutService::new(Sleepy, Duration::from_millis(50)));
+    let start = Instant::now();
+    let rsp = client.get("/slow").send().unwrap();
+    assert!(start.elapsed() < Duration::from_millis(400));
+    assert_eq!(rsp.status(), 504);
+    assert_eq!(rsp.reason(), "Gateway Timeout");
+    assert!(rsp.body().is_empty());
+}
+
+#[test]
+fn test_body_over_limit_is_rejected() {
+    let service = TimeoutService::new(Sleepy, Duration::from_secs(2)).max_body(4);
+    let client = TestClient::new(service);
+    let rsp = client.post("/fast").body("too long").send().unwrap();
+    assert_eq!(rsp.status(), 413);
+}
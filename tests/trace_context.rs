@@ -0,0 +1,202 @@
+//! Tests for trace context propagation headers
+#![cfg(feature = "trace")]
+
+use may_minihttp::test::TestClient;
+use may_minihttp::{HttpService, Request, Response, TraceContext};
+
+const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+/// Answers with the propagated context as `traceparent;tracestate`
+#[derive(Clone)]
+struct Context;
+
+impl HttpService for Context {
+    fn call(&mut self, req: Request, rsp: &mut Response) -> std::io::Result<()> {
+        let text = match req.trace_context() {
+            Some(ctx) => format!("{};{}", ctx.traceparent(), ctx.tracestate().unwrap_or("")),
+            None => "none".to_owned(),
+        };
+        rsp.body_vec(text.into_bytes());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_traceparent() {
+    let ctx = TraceContext::from_traceparent(PARENT).unwrap();
+    assert_eq!(ctx.trace_id_hex(), "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(ctx.span_id_hex(), "00f067aa0ba902b7");
+    assert_eq!(
+        ctx.span_id(),
+        [0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]
+    );
+    assert_eq!(ctx.flags(), 1);
+    assert!(ctx.is_sampled());
+    assert_eq!(ctx.traceparent(), PARENT);
+
+    let unsampled = PARENT.replace("-01", "-00");
+    assert!(!TraceContext::from_traceparent(&unsampled)
+        .unwrap()
+        .is_sampled());
+    // later versions may append fields
+    let future = format!("{}-extra", PARENT.replacen("00", "cc", 1));
+    assert!(TraceContext::from_traceparent(&future).is_some());
+}
+
+#[test]
+fn test_reject_invalid_traceparent() {
+    let invalid = [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        // uppercase hex
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        // all zero ids
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        // forbidden version, and trailing data on version 00
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "00_4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    ];
+    for value in invalid {
+        assert_eq!(TraceContext::from_traceparent(value), None, "{value}");
+    }
+}
+
+#[test]
+fn test_child_and_root() {
+    let parent = TraceContext::from_traceparent(PARENT)
+        .unwrap()
+        .with_trace_state("congo=t61rcWkgMzE");
+    let child = parent.child();
+    assert_eq!(child.trace_id(), parent.trace_id());
+    assert_ne!(child.span_id(), parent.span_id());
+    assert_eq!(child.flags(), parent.flags());
+    assert_eq!(child.tracestate(), Some("congo=t61rcWkgMzE"));
+    assert_ne!(child.span_id(), parent.child().span_id());
+
+    let root = TraceContext::new_root();
+    assert!(root.is_sampled());
+    assert_ne!(root.trace_id(), TraceContext::new_root().trace_id());
+    // a root context survives the round trip through its header
+    assert_eq!(
+        TraceContext::from_traceparent(&root.traceparent()),
+        Some(root)
+    );
+}
+
+#[test]
+fn test_request_trace_context() {
+    let client = TestClient::new(Context);
+    let rsp = client
+        .get("/")
+        .header("traceparent", PARENT)
+        .header("tracestate", "rojo=00f067aa0ba902b7")
+        .header("tracestate", "congo=t61rcWkgMzE")
+        .send()
+        .unwrap();
+    assert_eq!(
+        rsp.text(),
+        format!("{PARENT};rojo=00f067aa0ba902b7,congo=t61rcWkgMzE")
+    );
+
+    assert_eq!(client.get("/").send().unwrap().text(), "none");
+    let rsp = client.get("/").header("tracestate", "a=b").send().unwrap();
+    assert_eq!(rsp.text(), "none");
+    let rsp = client
+        .get("/")
+        .header("traceparent", "00-bad")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), "none");
+}
+
+#[test]
+fn test_parse_b3_single() {
+    let ctx = TraceContext::from_b3(
+        "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90",
+    )
+    .unwrap();
+    assert_eq!(ctx.trace_id_hex(), "80f198ee56343ba864fe8b2a57d3eff7");
+    assert_eq!(ctx.span_id_hex(), "e457b5a2e4d86bd1");
+    assert!(ctx.is_sampled());
+    assert_eq!(
+        ctx.b3(),
+        "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1"
+    );
+
+    // 64 bit trace id, debug and deferred sampling
+    let ctx = TraceContext::from_b3("64fe8b2a57d3eff7-e457b5a2e4d86bd1-d").unwrap();
+    assert_eq!(ctx.trace_id_hex(), "000000000000000064fe8b2a57d3eff7");
+    assert!(ctx.is_sampled());
+    let ctx = TraceContext::from_b3("64fe8b2a57d3eff7-e457b5a2e4d86bd1").unwrap();
+    assert!(!ctx.is_sampled());
+
+    for value in [
+        "0",
+        "1",
+        "d",
+        "64fe8b2a57d3eff7-e457b5a2e4d86bd1-x",
+        "64fe8b2a57d3eff7",
+    ] {
+        assert_eq!(TraceContext::from_b3(value), None, "{value}");
+    }
+}
+
+#[test]
+fn test_b3_shares_the_context() {
+    let ctx = TraceContext::from_traceparent(PARENT).unwrap();
+    let from_b3 = TraceContext::from_b3(&ctx.b3()).unwrap();
+    assert_eq!(from_b3, ctx);
+    assert_eq!(from_b3.traceparent(), PARENT);
+
+    let headers = ctx.b3_headers();
+    assert_eq!(headers[0], ("X-B3-TraceId", ctx.trace_id_hex()));
+    assert_eq!(headers[1], ("X-B3-SpanId", ctx.span_id_hex()));
+    assert_eq!(headers[2], ("X-B3-Sampled", "1".to_owned()));
+}
+
+#[test]
+fn test_request_b3_headers() {
+    let client = TestClient::new(Context);
+    let rsp = client
+        .get("/")
+        .header("b3", "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), format!("{PARENT};"));
+
+    let rsp = client
+        .get("/")
+        .header("X-B3-TraceId", "4bf92f3577b34da6a3ce929d0e0e4736")
+        .header("X-B3-SpanId", "00f067aa0ba902b7")
+        .header("X-B3-Sampled", "0")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), format!("{};", PARENT.replace("-01", "-00")));
+
+    // debug overrides the sampling decision
+    let rsp = client
+        .get("/")
+        .header("X-B3-TraceId", "4bf92f3577b34da6a3ce929d0e0e4736")
+        .header("X-B3-SpanId", "00f067aa0ba902b7")
+        .header("X-B3-Flags", "1")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), format!("{PARENT};"));
+
+    // traceparent wins over B3
+    let rsp = client
+        .get("/")
+        .header("b3", "64fe8b2a57d3eff7-e457b5a2e4d86bd1-1")
+        .header("traceparent", PARENT)
+        .send()
+        .unwrap();
+    assert_eq!(rsp.text(), format!("{PARENT};"));
+
+    let rsp = client
+        .get("/")
+        .header("X-B3-SpanId", "00f067aa0ba902b7")
+        .send();
+    assert_eq!(rsp.unwrap().text(), "none");
+}
@@ -0,0 +1,182 @@
+//! Tests for resumable uploads with the tus protocol
+#![cfg(feature = "tus")]
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use may_minihttp::test::{TestClient, TestResponse};
+use may_minihttp::{HttpService, Request, Response, TusService};
+
+/// the uploads handed over, with their content
+static COMPLETED: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+fn completed(id: &str, file: &Path) {
+    let data = std::fs::read(file).unwrap();
+    COMPLETED.lock().unwrap().push((id.to_owned(), data));
+}
+
+#[derive(Clone)]
+struct Hello;
+
+impl HttpService for Hello {
+    fn call(&mut self, _req: Request, rsp: &mut Response) -> io::Result<()> {
+        rsp.body("hello");
+        Ok(())
+    }
+}
+
+fn upload_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("may_minihttp-tus-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn client(dir: &Path) -> TestClient<TusService<Hello>> {
+    let service = TusService::new(Hello, "/files", dir)
+        .unwrap()
+        .max_size(1024)
+        .on_complete(completed);
+    TestClient::new(service)
+}
+
+fn create(client: &TestClient<TusService<Hello>>, length: &str) -> TestResponse {
+    client
+        .post("/files")
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Length", length)
+        .header("Upload-Metadata", "filename d29ybGQ=")
+        .send()
+        .unwrap()
+}
+
+fn patch(
+    client: &TestClient<TusService<Hello>>,
+    location: &str,
+    offset: u64,
+    body: &[u8],
+) -> TestResponse {
+    client
+        .request("PATCH", location)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Offset", &offset.to_string())
+        .header("Content-Type", "application/offset+octet-stream")
+        .body(body.to_vec())
+        .send()
+        .unwrap()
+}
+
+fn head(client: &TestClient<TusService<Hello>>, location: &str) -> TestResponse {
+    client
+        .request("HEAD", location)
+        .header("Tus-Resumable", "1.0.0")
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_options() {
+    let dir = upload_dir("options");
+    let rsp = client(&dir).request("OPTIONS", "/files").send().unwrap();
+    assert_eq!(rsp.status(), 204);
+    assert_eq!(rsp.header("Tus-Version"), Some("1.0.0"));
+    assert_eq!(rsp.header("Tus-Max-Size"), Some("1024"));
+    assert!(rsp.header("Tus-Extension").unwrap().contains("creation"));
+
+    // other paths go to the inner service
+    let rsp = client(&dir).get("/").send().unwrap();
+    assert_eq!(rsp.text(), "hello");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_resumed_upload() {
+    let dir = upload_dir("resume");
+    let client = client(&dir);
+    let rsp = create(&client, "11");
+    assert_eq!(rsp.status(), 201);
+    assert_eq!(rsp.header("Tus-Resumable"), Some("1.0.0"));
+    let location = rsp.header("Location").unwrap().to_owned();
+    let id = location.strip_prefix("/files/").unwrap().to_owned();
+
+    let rsp = head(&client, &location);
+    assert_eq!(rsp.status(), 200);
+    assert_eq!(rsp.header("Upload-Offset"), Some("0"));
+    assert_eq!(rsp.header("Upload-Length"), Some("11"));
+    assert_eq!(rsp.header("Upload-Metadata"), Some("filename d29ybGQ="));
+    assert_eq!(rsp.header("Cache-Control"), Some("no-store"));
+
+    let rsp = patch(&client, &location, 0, b"hello ");
+    assert_eq!(rsp.status(), 204);
+    assert_eq!(rsp.header("Upload-Offset"), Some("6"));
+
+    // the connection broke, the client asks where to go on
+    let rsp = head(&client, &location);
+    assert_eq!(rsp.header("Upload-Offset"), Some("6"));
+
+    // a stale offset is refused
+    let rsp = patch(&client, &location, 0, b"hello ");
+    assert_eq!(rsp.status(), 409);
+    assert!(!COMPLETED
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(done, _)| *done == id));
+
+    let rsp = patch(&client, &location, 6, b"world");
+    assert_eq!(rsp.status(), 204);
+    assert_eq!(rsp.header("Upload-Offset"), Some("11"));
+    let done = COMPLETED.lock().unwrap();
+    let (_, data) = done.iter().find(|(done, _)| *done == id).unwrap();
+    assert_eq!(data, b"hello world");
+    drop(done);
+
+    // nothing fits after the end
+    let rsp = patch(&client, &location, 11, b"!");
+    assert_eq!(rsp.status(), 413);
+
+    let rsp = client
+        .request("DELETE", &location)
+        .header("Tus-Resumable", "1.0.0")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 204);
+    assert_eq!(head(&client, &location).status(), 404);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_rejected_requests() {
+    let dir = upload_dir("rejected");
+    let client = client(&dir);
+
+    // the protocol version is required
+    let rsp = client
+        .post("/files")
+        .header("Upload-Length", "5")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 412);
+    assert_eq!(rsp.header("Tus-Version"), Some("1.0.0"));
+
+    assert_eq!(create(&client, "2048").status(), 413);
+    assert_eq!(create(&client, "many").status(), 400);
+
+    let location = create(&client, "5").header("Location").unwrap().to_owned();
+    let rsp = client
+        .request("PATCH", &location)
+        .header("Tus-Resumable", "1.0.0")
+        .header("Upload-Offset", "0")
+        .header("Content-Type", "text/plain")
+        .body("hello")
+        .send()
+        .unwrap();
+    assert_eq!(rsp.status(), 415);
+
+    assert_eq!(
+        head(&client, "/files/0123456789abcdef0123456789abcdef").status(),
+        404
+    );
+    assert_eq!(head(&client, "/files/..%2Fsecret").status(), 404);
+    std::fs::remove_dir_all(&dir).unwrap();
+}